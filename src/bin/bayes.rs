@@ -12,7 +12,7 @@ use std::string::String;
 use std::fmt::Debug;
 use std::f64::consts::PI;
 
-const ATTR: &'static [&str] = &["RI", "Na", "Mg", "Al", "Si", "K", "Ca", "Ba", "Fe"];
+const ATTR: &[&str] = &["RI", "Na", "Mg", "Al", "Si", "K", "Ca", "Ba", "Fe"];
 
 /// Represent a data row
 type Row = Vec<String>;
@@ -93,12 +93,12 @@ impl<'a> Data<'a> {
             let mut class_mean_variance = HashMap::new();
 
             for (i, attr) in ATTR.iter().enumerate() {
-                let mut mean = filtered
+                let mean = filtered
                     .iter()
                     .fold(0., |acc, &x| acc + x[i].parse::<f64>().unwrap())
                     / filtered.len() as f64;
 
-                let mut variance = filtered.iter().fold(0., |acc, &x| {
+                let variance = filtered.iter().fold(0., |acc, &x| {
                     acc + (x[i].parse::<f64>().unwrap() - mean).powi(2)
                 }) / filtered.len() as f64;
                 class_mean_variance.insert((*attr).into(), vec![mean, variance]);
@@ -144,16 +144,16 @@ impl<'a> Data<'a> {
     /// Read the input data and calculate prior and mean-variance on
     /// the trainingsdata.
     fn new(header: Vec<&'a str>, f: &str) -> io::Result<Data<'a>> {
-        let (train, test) = Data::read_data(&f)?;
+        let (train, test) = Data::read_data(f)?;
         let prior = Data::prior(&train);
         let mean_variance = Data::mean_variance(&train);
 
         Ok(Data {
-            header: header,
+            header,
             test_data: test,
             train_data: train,
-            prior: prior,
-            mean_variance: mean_variance,
+            prior,
+            mean_variance,
         })
     }
 }
@@ -25,7 +25,7 @@ static FG: u32 = 0xA99A84;
 /// Consolefonts psf2 format is stored in little endian ordering.
 /// The data is transformed from by 4 bytes from the buffer data.
 pub fn from_le(data: &[u8], index: usize) -> u32 {
-    ((data[index] as u32) <<  0) +
+    (data[index] as u32) +
     ((data[index+1] as u32) <<  8) +
     ((data[index+2] as u32) << 16) +
     ((data[index+3] as u32) << 24)
@@ -34,28 +34,20 @@ pub fn from_le(data: &[u8], index: usize) -> u32 {
 /// This is taken for compliance reasons.
 /// Also comments are kept from the c code
 #[repr(C)]
+#[derive(Default)]
 pub struct fb_bitfield {
 	pub offset: uint32_t, /* beginning of bitfield	*/
 	pub length: uint32_t, /* length of bitfield		*/
 	pub msb_right: uint32_t, /* != 0 : Most significant bit is right */
 }
 
-impl Default for fb_bitfield{
-    fn default() -> fb_bitfield {
-        fb_bitfield {
-            offset: 0,
-            length: 0,
-            msb_right: 0,
-        }
-    }
-}
-
 /// This struct represents the rust counter-part
 /// to the one defined in <kernel/fb.h>.
 /// Most of the fields are unused by here for
 /// compliance and completeness.
 /// The comments are kept from the original c code.
 #[repr(C)]
+#[derive(Default)]
 pub struct fb_var_screeninfo {
     pub xres: uint32_t, /* visible resolution	*/
 	  pub yres: uint32_t,
@@ -94,45 +86,9 @@ pub struct fb_var_screeninfo {
 	  pub reserved: [uint32_t; 4], /* Reserved for future compatibility */
 }
 
-impl Default for fb_var_screeninfo {
-    fn default() -> fb_var_screeninfo {
-        fb_var_screeninfo {
-            xres: 0,
-	          yres: 0,
-	          xres_virtual: 0,
-	          yres_virtual: 0,
-	          xoffset: 0,
-	          yoffset: 0,
-	          bits_per_pixel: 0,
-	          grayscale: 0,
-	          red: fb_bitfield::default(),
-	          green: fb_bitfield::default(),
-	          blue: fb_bitfield::default(),
-	          transp: fb_bitfield::default(),
-	          nonstd: 0,
-	          activate: 0,
-	          height: 0,
-	          width: 0,
-	          accel_flags: 0,
-	          pixclock: 0,
-	          left_margin: 0,
-	          right_margin: 0,
-	          upper_margin: 0,
-	          lower_margin: 0,
-	          hsync_len: 0,
-	          vsync_len: 0,
-	          sync: 0,
-	          vmode: 0,
-	          rotate: 0,
-	          colorspace: 0,
-	          reserved: [0,0,0,0],
-        }
-    }
-}
-
-/// Link external functions provided by the
-/// kernel which didn't need shared libs.
-extern {
+// Link external functions provided by the
+// kernel which didn't need shared libs.
+extern "C" {
     pub fn ioctl(fd: c_int, req: c_ulong, info: *mut fb_var_screeninfo) -> c_int;
 }
 
@@ -155,13 +111,13 @@ impl psf2_header {
     pub fn new(data: &[u8]) -> psf2_header {
         psf2_header {
             magic: [data[0], data[1], data[2], data[3]],
-            version: from_le(&data, 4),
-            headersize: from_le(&data, 8),
-            flags: from_le(&data, 12),
-            glyph_count: from_le(&data, 16),
-            glyph_size: from_le(&data, 20),
-            glyph_height: from_le(&data, 24),
-            glyph_width: from_le(&data, 28),
+            version: from_le(data, 4),
+            headersize: from_le(data, 8),
+            flags: from_le(data, 12),
+            glyph_count: from_le(data, 16),
+            glyph_size: from_le(data, 20),
+            glyph_height: from_le(data, 24),
+            glyph_width: from_le(data, 28),
         }
     }
 }
@@ -184,7 +140,7 @@ fn main() {
     // determine the framebuffer size and open an mmap region
     let len = 4 * info.xres * info.yres;
     let ptr: *mut u32 = unsafe { // create an mmap region with rust types
-        libc::mmap(0 as *mut libc::c_void, len as usize,
+        libc::mmap(std::ptr::null_mut::<libc::c_void>(), len as usize,
                    libc::PROT_READ | libc::PROT_WRITE,
                    libc::MAP_SHARED, fd, 0) as *mut u32
     };
@@ -238,7 +194,7 @@ fn main() {
 
             // get time string as byte array
             for s in 0..time_bytes.len() {
-                let mut glyph = &glyphs[time_bytes[s] as usize]; // use char code to index glyph
+                let glyph = &glyphs[time_bytes[s] as usize]; // use char code to index glyph
 
                 for y in 0..header.glyph_height {
                     for x in 0..header.glyph_width { // print glyph on screen
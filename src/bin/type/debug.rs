@@ -0,0 +1,123 @@
+//! An interactive stepper over the evaluation of a definition.
+//!
+//! Commands are read one per line:
+//!
+//! ```text
+//! step, s          do one reduction step
+//! next, n          step over, reduce the next redex to the end
+//! continue, c      run until a breakpoint or the end
+//! break x, b x     stop after a step which substitutes for x
+//! delete x, d x    remove the breakpoint on x
+//! print, p         show the current term
+//! quit, q          stop debugging
+//! ```
+//!
+//! Every step is printed with its number, rule and redex, after a
+//! command the current term or how the evaluation ended.
+use std::io::{self, BufRead, Write};
+
+use typecheck::eval::Step;
+use typecheck::trace::{Stepper, Stop};
+
+const HELP: &str = "commands: step, next, continue, break x, delete x, print, quit";
+
+/// Run the commands of `input` against the stepper.
+pub fn debug(input: impl BufRead, mut output: impl Write, mut stepper: Stepper) -> io::Result<()> {
+    writeln!(output, "{}", stepper.term())?;
+    for line in input.lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (steps, stop) = match words.as_slice() {
+            [] => continue,
+            ["step"] | ["s"] => stepper.step(),
+            ["next"] | ["n"] => stepper.step_over(),
+            ["continue"] | ["c"] => stepper.resume(),
+            ["break", x] | ["b", x] => {
+                stepper.add_breakpoint(x);
+                writeln!(output, "breakpoint on {}", x)?;
+                continue;
+            }
+            ["delete", x] | ["d", x] => {
+                match stepper.remove_breakpoint(x) {
+                    true => writeln!(output, "deleted the breakpoint on {}", x)?,
+                    false => writeln!(output, "no breakpoint on {}", x)?,
+                }
+                continue;
+            }
+            ["print"] | ["p"] => {
+                writeln!(output, "{}", stepper.term())?;
+                continue;
+            }
+            ["quit"] | ["q"] => break,
+            _ => {
+                writeln!(output, "{}", HELP)?;
+                continue;
+            }
+        };
+        let first = stepper.steps() - steps.len();
+        for (i, Step { rule, redex, .. }) in steps.iter().enumerate() {
+            writeln!(output, "{} {}: {}", first + i + 1, rule, redex)?;
+        }
+        match stop {
+            Stop::Done => writeln!(output, "  {}", stepper.term())?,
+            Stop::Breakpoint(x) => writeln!(output, "breakpoint on {}: {}", x, stepper.term())?,
+            Stop::Finished(outcome) => writeln!(output, "{}", outcome)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use typecheck::syntax::analyze;
+
+    fn session(source: &str, name: &str, commands: &str) -> String {
+        let program = analyze(source).program(name).unwrap();
+        let mut output = Vec::new();
+        let stepper = Stepper::new(program, 100);
+        debug(Cursor::new(commands), &mut output, stepper).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn step_next_and_breakpoints() {
+        let source = "
+            def id : Unit -> Unit = \\x. x
+            def twice : (Unit -> Unit) -> Unit -> Unit = \\f. \\y. f (f y)
+            def main : Unit = twice id ()
+        ";
+        let output = session(source, "main", "s\ns\nn\nb x\nc\nc\n");
+        let expected = [
+            "(\\f. \\y. f (f y)) (\\x. x) ()",
+            "1 beta f: (\\f. \\y. f (f y)) (\\x. x)",
+            "  (\\y. (\\x. x) ((\\x. x) y)) ()",
+            "2 beta y: (\\y. (\\x. x) ((\\x. x) y)) ()",
+            "  (\\x. x) ((\\x. x) ())",
+            // the argument is reduced to a value
+            "3 beta x: (\\x. x) ()",
+            "  (\\x. x) ()",
+            "breakpoint on x",
+            "4 beta x: (\\x. x) ()",
+            "breakpoint on x: ()",
+            "value ()",
+            "",
+        ];
+        assert_eq!(output, expected.join("\n"));
+    }
+
+    #[test]
+    fn diverging_terms_stop_at_the_budget() {
+        // r := \y. (!r) y; (!r) () loops through the store
+        let source = "
+            def loop : Unit =
+              (\\r : Ref (Unit -> Unit). (\\u : Unit. !r ()) (r := \\y. !r y)) (ref (\\z. z))
+        ";
+        let output = session(source, "loop", "c\nc\nprint\n");
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 1 + 100 + 2 + 1);
+        assert!(lines[101].starts_with("stopped after 100 steps, the term may diverge"));
+        assert_eq!(lines[102], lines[101]);
+    }
+}
@@ -0,0 +1,397 @@
+//! A language server for source files of the calculus.
+//!
+//! `type lsp` speaks the Language Server Protocol on stdin and stdout.
+//! Documents are synchronized in full and analyzed by `syntax::analyze`
+//! on every request. The server publishes the errors of the checker as
+//! diagnostics, shows the type of the subterm under the cursor on hover,
+//! jumps from a variable to the lambda, split or definition binding it
+//! and shows the goal of every hole as a code lens.
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+use typecheck::syntax::{analyze, Span};
+
+/// Read a message framed by a `Content-Length` header.
+/// Returns `None` at the end of the input.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(n) = line.strip_prefix("Content-Length:") {
+            length = n.trim().parse::<usize>().ok();
+        }
+    }
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let length = length.ok_or_else(|| invalid("missing Content-Length".into()))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| invalid(e.to_string()))
+}
+
+/// Write a message with its `Content-Length` header.
+pub fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// The protocol position of a byte offset, counting UTF-16 code units.
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let character: usize = before[start..].chars().map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+/// The byte offset of a protocol position.
+fn offset(text: &str, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let start = match line {
+        0 => 0,
+        _ => text.match_indices('\n').nth(line - 1)?.0 + 1,
+    };
+    let mut units = 0;
+    for (i, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return Some(start + i);
+        }
+        units += c.len_utf16();
+    }
+    Some(text.len())
+}
+
+fn range(text: &str, span: Span) -> Value {
+    json!({ "start": position(text, span.start), "end": position(text, span.end) })
+}
+
+/// The open documents by their uri.
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: HashMap<String, String>,
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server::default()
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let text = self.documents.get(uri).map(|t| t.as_str()).unwrap_or("");
+        let diagnostics: Vec<Value> = analyze(text)
+            .diagnostics
+            .iter()
+            .map(|d| {
+                json!({
+                    "range": range(text, d.span),
+                    "severity": 1,
+                    "source": "type",
+                    "message": d.message,
+                })
+            })
+            .collect();
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+
+    /// The document and offset a request refers to.
+    fn at(&self, params: &Value) -> Option<(&str, usize)> {
+        let text = self
+            .documents
+            .get(params["textDocument"]["uri"].as_str()?)?;
+        Some((text, offset(text, &params["position"])?))
+    }
+
+    fn hover(&self, params: &Value) -> Option<Value> {
+        let (text, offset) = self.at(params)?;
+        let analysis = analyze(text);
+        let (span, t) = analysis.type_at(offset)?;
+        Some(json!({
+            "contents": { "kind": "plaintext", "value": format!("{}", t) },
+            "range": range(text, *span),
+        }))
+    }
+
+    fn definition(&self, params: &Value) -> Option<Value> {
+        let (text, offset) = self.at(params)?;
+        let binder = analyze(text).binder_at(offset)?;
+        Some(json!({
+            "uri": params["textDocument"]["uri"],
+            "range": range(text, binder),
+        }))
+    }
+
+    fn code_lens(&self, params: &Value) -> Option<Value> {
+        let text = self
+            .documents
+            .get(params["textDocument"]["uri"].as_str()?)?;
+        let lenses: Vec<Value> = analyze(text)
+            .holes
+            .iter()
+            .map(|h| {
+                let context: Vec<String> = h
+                    .context
+                    .iter()
+                    .map(|b| format!("{} : {}", b.0, b.1))
+                    .collect();
+                let title = format!("?{} : {}  [{}]", h.name, h.goal, context.join(", "));
+                json!({
+                    "range": range(text, h.span),
+                    "command": { "title": title, "command": "" },
+                })
+            })
+            .collect();
+        Some(json!(lenses))
+    }
+
+    /// Handle a message and return the messages to send back,
+    /// or `None` if the server should exit.
+    pub fn handle(&mut self, message: &Value) -> Option<Vec<Value>> {
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+        let result = match message["method"].as_str().unwrap_or("") {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "codeLensProvider": { "resolveProvider": false },
+                },
+                "serverInfo": { "name": "type" },
+            }),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.documents.insert(uri.clone(), text.into());
+                return Some(vec![self.diagnostics(&uri)]);
+            }
+            "textDocument/didChange" => {
+                // the whole document is sent on every change
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    self.documents.insert(uri.clone(), text.into());
+                }
+                return Some(vec![self.diagnostics(&uri)]);
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return Some(vec![self.diagnostics(&uri)]);
+            }
+            "textDocument/hover" => self.hover(params).unwrap_or(Value::Null),
+            "textDocument/definition" => self.definition(params).unwrap_or(Value::Null),
+            "textDocument/codeLens" => self.code_lens(params).unwrap_or(Value::Null),
+            "shutdown" => Value::Null,
+            "exit" => return None,
+            method => {
+                if message["id"].is_null() {
+                    // notifications which are not understood are ignored
+                    return Some(Vec::new());
+                }
+                let error =
+                    json!({ "code": -32601, "message": format!("unknown method {}", method) });
+                return Some(vec![
+                    json!({ "jsonrpc": "2.0", "id": message["id"], "error": error }),
+                ]);
+            }
+        };
+        if message["id"].is_null() {
+            return Some(Vec::new());
+        }
+        Some(vec![
+            json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }),
+        ])
+    }
+}
+
+/// Serve requests until the client sends `exit` or closes the input.
+pub fn serve<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<()> {
+    let mut server = Server::new();
+    while let Some(message) = read_message(&mut input)? {
+        match server.handle(&message) {
+            Some(replies) => {
+                for reply in replies {
+                    write_message(&mut output, &reply)?;
+                }
+            }
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    /// An in-process client which queues messages and
+    /// collects the replies of a server.
+    struct Client {
+        input: Vec<u8>,
+        next_id: u64,
+    }
+
+    impl Client {
+        fn new() -> Client {
+            Client {
+                input: Vec::new(),
+                next_id: 0,
+            }
+        }
+
+        fn request(&mut self, method: &str, params: Value) -> u64 {
+            self.next_id += 1;
+            let message =
+                json!({ "jsonrpc": "2.0", "id": self.next_id, "method": method, "params": params });
+            write_message(&mut self.input, &message).unwrap();
+            self.next_id
+        }
+
+        fn notify(&mut self, method: &str, params: Value) {
+            let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+            write_message(&mut self.input, &message).unwrap();
+        }
+
+        /// Run the server on the queued messages.
+        fn run(self) -> Vec<Value> {
+            let mut output = Vec::new();
+            serve(Cursor::new(self.input), &mut output).unwrap();
+            let mut output = Cursor::new(output);
+            let mut replies = Vec::new();
+            while let Some(reply) = read_message(&mut output).unwrap() {
+                replies.push(reply);
+            }
+            replies
+        }
+    }
+
+    fn response(replies: &[Value], id: u64) -> &Value {
+        replies.iter().find(|r| r["id"] == json!(id)).unwrap()
+    }
+
+    fn diagnostics(replies: &[Value]) -> Vec<&Value> {
+        replies
+            .iter()
+            .filter(|r| r["method"] == "textDocument/publishDiagnostics")
+            .collect()
+    }
+
+    const URI: &str = "file:///example.tt";
+
+    /// The position of the n-th occurrence of a pattern.
+    fn at(text: &str, pattern: &str, n: usize) -> Value {
+        let offset = text.match_indices(pattern).nth(n).unwrap().0;
+        json!({ "textDocument": { "uri": URI }, "position": position(text, offset) })
+    }
+
+    #[test]
+    fn positions() {
+        let text = "ab\nλx. x\n";
+        assert_eq!(position(text, 5), json!({ "line": 1, "character": 1 }));
+        assert_eq!(offset(text, &json!({ "line": 1, "character": 1 })), Some(5));
+        assert_eq!(offset(text, &json!({ "line": 0, "character": 9 })), Some(2));
+        assert_eq!(offset(text, &json!({ "line": 5, "character": 0 })), None);
+    }
+
+    #[test]
+    fn session() {
+        let text = "def id : Foo -> Foo = \\x. x\n\
+                    def bad : Bar = id\n\
+                    def hole : Foo * Bar -> Bar = \\p. split p as (x, y) in ?goal\n";
+        let fixed = text.replace("def bad : Bar = id", "def bad : Foo -> Foo = id");
+        let mut client = Client::new();
+        let init = client.request("initialize", json!({ "capabilities": {} }));
+        client.notify("initialized", json!({}));
+        client.notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": URI, "languageId": "type", "version": 1, "text": text } }),
+        );
+        let hover = client.request("textDocument/hover", at(text, "x", 1));
+        let hover_id = client.request("textDocument/hover", at(text, "id", 1));
+        let definition = client.request("textDocument/definition", at(text, "x", 1));
+        let lens = client.request(
+            "textDocument/codeLens",
+            json!({ "textDocument": { "uri": URI } }),
+        );
+        client.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": URI, "version": 2 },
+                "contentChanges": [{ "text": fixed }],
+            }),
+        );
+        let unknown = client.request("textDocument/rename", json!({}));
+        let missing = client.request(
+            "textDocument/hover",
+            json!({ "textDocument": { "uri": "file:///missing.tt" }, "position": { "line": 0, "character": 0 } }),
+        );
+        let shutdown = client.request("shutdown", Value::Null);
+        client.notify("exit", Value::Null);
+        // nothing is answered after exit
+        let after_exit = client.request("shutdown", Value::Null);
+        let replies = client.run();
+
+        let capabilities = &response(&replies, init)["result"]["capabilities"];
+        assert_eq!(capabilities["hoverProvider"], json!(true));
+
+        // the error is reported at the offending subterm, and gone after the fix
+        let published = diagnostics(&replies);
+        assert_eq!(published.len(), 2);
+        let errors = published[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["message"], "expected Bar, found Foo -> Foo");
+        assert_eq!(
+            errors[0]["range"],
+            json!({ "start": { "line": 1, "character": 16 }, "end": { "line": 1, "character": 18 } })
+        );
+        assert_eq!(published[1]["params"]["diagnostics"], json!([]));
+
+        let hover = &response(&replies, hover)["result"];
+        assert_eq!(hover["contents"]["value"], "Foo");
+        let hover_id = &response(&replies, hover_id)["result"];
+        assert_eq!(hover_id["contents"]["value"], "Foo -> Foo");
+
+        // the second x is bound by the lambda
+        let definition = &response(&replies, definition)["result"];
+        assert_eq!(definition["uri"], URI);
+        assert_eq!(
+            definition["range"]["start"],
+            json!({ "line": 0, "character": 23 })
+        );
+
+        let lenses = response(&replies, lens)["result"]
+            .as_array()
+            .unwrap()
+            .clone();
+        assert_eq!(lenses.len(), 1);
+        assert_eq!(
+            lenses[0]["command"]["title"],
+            "?goal : Bar  [id : Foo -> Foo, bad : Bar, p : Foo * Bar, x : Foo, y : Bar]"
+        );
+
+        assert_eq!(response(&replies, unknown)["error"]["code"], json!(-32601));
+        assert_eq!(response(&replies, missing)["result"], Value::Null);
+        assert_eq!(response(&replies, shutdown)["result"], Value::Null);
+        assert!(replies.iter().all(|r| r["id"] != json!(after_exit)));
+    }
+}
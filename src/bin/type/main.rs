@@ -0,0 +1,287 @@
+//! The command line front-end of the `typecheck` library.
+//!
+//! Without arguments it checks the example terms, otherwise it runs
+//! one of the tools: `bench`, `codegen`, `prove`, `check-json`, `lsp`,
+//! `trace` or `debug`.
+#[macro_use]
+extern crate serde_json;
+extern crate typecheck;
+
+mod debug;
+mod lsp;
+
+use typecheck::eval::{self, Store, Strategy};
+use typecheck::{
+    aliases, church, codegen, counter_client, counter_context, counter_type, counters, cps,
+    dependent, examples, gradual, json, judgment, judgment_check, judgment_check_store, nbe,
+    pair_operator, prove, syntax, trace, type_app, vm, Context, Effects, Term, Type,
+};
+
+/// Check the example terms.
+fn check_examples() {
+    for (_, term, t) in examples() {
+        assert!(judgment_check(&Context::Empty(), &term, &t));
+
+        // the translations are well-typed as well
+        let translated = cps::cps(&term, &t, &Type::Baz).unwrap();
+        let cps_t = cps::cps_computation_type(&t, &Type::Baz);
+        assert!(judgment_check(&Context::Empty(), &translated, &cps_t));
+        let converted = cps::anf(&term, &t).unwrap();
+        assert!(judgment_check(&Context::Empty(), &converted, &t));
+        let embedded = dependent::embed(&term, &t).unwrap();
+        let embedded_t = dependent::embed_type(&t).unwrap();
+        assert!(dependent::judgment_check(
+            &dependent::embed_context(&Context::Empty()).unwrap(),
+            &embedded,
+            &embedded_t
+        ));
+        let encoded = church::translate(&term, &t).unwrap();
+        let encoded_t = church::translate_type(&t).unwrap();
+        assert!(dependent::judgment_check(
+            &church::base_context(),
+            &encoded,
+            &encoded_t
+        ));
+    }
+
+    // uncurry after curry is beta-eta equal to the identity
+    // \g. uncurry (curry g) = \g. g : Uncurried -> Uncurried
+    let ctx = Context::Empty();
+    let example = |name: &str| examples().into_iter().find(|e| e.0 == name).unwrap().1;
+    let (curry, uncurry) = (example("curry"), example("uncurry"));
+    let uncurried = aliases().get("Uncurried").unwrap();
+    let term = Term::Lam(
+        "g".into(),
+        Box::new(Term::App(
+            Box::new(uncurry),
+            Box::new(Term::App(
+                Box::new(curry),
+                Box::new(Term::Var("g".into())),
+                uncurried.clone(),
+            )),
+            aliases().get("Curried").unwrap(),
+        )),
+    );
+    let t = Type::Fn(
+        Box::new(uncurried.clone()),
+        Box::new(uncurried),
+        Effects::pure(),
+    );
+    let id = Term::Lam("g".into(), Box::new(Term::Var("g".into())));
+    assert_eq!(format!("{}", t), "Uncurried -> Uncurried");
+    assert!(judgment_check(&ctx, &term, &t));
+    assert!(nbe::terms_equal(&term, &id, &t));
+
+    // references
+    // !- (\r. (\u. !r) (r := \y. y)) (ref [Foo -> Foo] \x. x) : Foo -> Foo
+    let ctx = Context::Empty();
+    let id = Type::Fn(Box::new(Type::Foo), Box::new(Type::Foo), Effects::pure());
+    let term = Term::App(
+        Box::new(Term::Lam(
+            "r".into(),
+            Box::new(Term::App(
+                Box::new(Term::Lam(
+                    "u".into(),
+                    Box::new(Term::Deref(Box::new(Term::Var("r".into())))),
+                )),
+                Box::new(Term::Assign(
+                    Box::new(Term::Var("r".into())),
+                    Box::new(Term::Lam("y".into(), Box::new(Term::Var("y".into())))),
+                    id.clone(),
+                )),
+                Type::Unit,
+            )),
+        )),
+        Box::new(Term::Ref(
+            Box::new(Term::Lam("x".into(), Box::new(Term::Var("x".into())))),
+            id.clone(),
+        )),
+        Type::Ref(Box::new(id.clone())),
+    );
+    assert!(judgment_check(&ctx, &term, &id));
+    let mut store = Store::new();
+    let value = eval::eval(&term, &mut store);
+    assert!(judgment_check_store(&store.typing(), &ctx, &value, &id));
+
+    // existentials
+    // both counters are used through the same interface
+    let ctx = counter_context();
+    for counter in counters() {
+        assert!(judgment_check(&ctx, &counter, &counter_type()));
+        assert!(judgment_check(&ctx, &counter_client(counter), &Type::Foo));
+    }
+
+    // type operators
+    // !- \p. split p as (x, y) in (y, x) : Pair Foo Bar -> Pair Bar Foo
+    let term = Term::Lam(
+        "p".into(),
+        Box::new(Term::Split(
+            Box::new(Term::Var("p".into())),
+            "x".into(),
+            Type::Foo,
+            "y".into(),
+            Type::Bar,
+            Box::new(Term::Pair(
+                Box::new(Term::Var("y".into())),
+                Box::new(Term::Var("x".into())),
+            )),
+        )),
+    );
+    let t = Type::Fn(
+        Box::new(type_app(pair_operator(), vec![Type::Foo, Type::Bar])),
+        Box::new(type_app(pair_operator(), vec![Type::Bar, Type::Foo])),
+        Effects::pure(),
+    );
+    assert!(judgment(&t));
+    assert!(judgment_check(&Context::Empty(), &term, &t));
+    let translated = cps::cps(&term, &t, &Type::Baz).unwrap();
+    let cps_t = cps::cps_computation_type(&t, &Type::Baz);
+    assert!(judgment_check(&Context::Empty(), &translated, &cps_t));
+
+    // gradual typing
+    // (\x. x : Dyn -> Foo) () : Foo fails at runtime in the body
+    let term = Term::App(
+        Box::new(Term::Lam("x".into(), Box::new(Term::Var("x".into())))),
+        Box::new(Term::Unit),
+        Type::Dyn,
+    );
+    let casted = gradual::insert_casts(&Context::Empty(), &term, &Type::Foo).unwrap();
+    assert!(judgment_check(&Context::Empty(), &casted, &Type::Foo));
+    let blame = eval::run(&casted, &mut Store::new()).unwrap_err();
+    assert_eq!(blame.label, "/fun/body");
+
+    // exceptions
+    // \x. try raise Fail with Fail => x : Foo -> Foo
+    let term = Term::Lam(
+        "x".into(),
+        Box::new(Term::Try(
+            Box::new(Term::Raise("Fail".into())),
+            "Fail".into(),
+            Box::new(Term::Var("x".into())),
+        )),
+    );
+    let t = Type::Fn(Box::new(Type::Foo), Box::new(Type::Foo), Effects::pure());
+    assert!(judgment_check(&Context::Empty(), &term, &t));
+
+    // intersections
+    // \x. x : (Foo -> Foo) /\ (Bar -> Bar)
+    let term = Term::Lam("x".into(), Box::new(Term::Var("x".into())));
+    let arrow = |a: Type| Box::new(Type::Fn(Box::new(a.clone()), Box::new(a), Effects::pure()));
+    let t = Type::And(arrow(Type::Foo), arrow(Type::Bar));
+    assert!(judgment_check(&Context::Empty(), &term, &t));
+
+    // dependent types
+    // !- refl : 2 + 2 = 4
+    let ctx = dependent::Context::new();
+    let term = dependent::Expr::Refl;
+    let t = dependent::eq(
+        dependent::Expr::Nat,
+        dependent::app(
+            dependent::plus(),
+            vec![dependent::nat(2), dependent::nat(2)],
+        ),
+        dependent::nat(4),
+    );
+    assert!(dependent::judgment_check(&ctx, &term, &t));
+}
+
+/// The number of steps after which `trace` and `debug` give up.
+const BUDGET: usize = 10_000;
+
+/// The definition `name` of a source file as a closed term, or the
+/// last definition if no name is given. Exits with the errors of the file.
+fn load(path: &str, name: Option<String>) -> Term {
+    let source = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        std::process::exit(1)
+    });
+    let analysis = syntax::analyze(&source);
+    for d in &analysis.diagnostics {
+        let (line, column) = syntax::line_column(&source, d.span.start);
+        eprintln!("{}:{}:{}: {}", path, line, column, d.message);
+    }
+    let name = name.or_else(|| analysis.defs.last().map(|d| d.0.clone()));
+    match name.and_then(|name| analysis.program(&name)) {
+        Some(term) if analysis.diagnostics.is_empty() => term,
+        _ => {
+            eprintln!("{}: no definition to run", path);
+            std::process::exit(1)
+        }
+    }
+}
+
+fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("bench") => {
+            println!(
+                "{:<16} {:>8} {:>14} {:>14}",
+                "program", "result", "eval", "vm"
+            );
+            for m in vm::bench() {
+                println!(
+                    "{:<16} {:>8} {:>14?} {:>14?}",
+                    m.program, m.ticks, m.eval, m.vm
+                );
+            }
+        }
+        Some("codegen") => {
+            // the rust types for Foo, Bar and Baz
+            let base: Vec<String> = std::env::args().skip(2).collect();
+            let base = |i: usize, default: &str| base.get(i).cloned().unwrap_or(default.into());
+            let gen = codegen::Codegen::new(&base(0, "Foo"), &base(1, "Bar"), &base(2, "Baz"));
+            print!(
+                "{}",
+                gen.module(&examples()).expect("examples are well-typed")
+            );
+        }
+        Some("prove") => {
+            // prove the formulas given as arguments, or one per line on stdin
+            let formulas: Vec<String> = std::env::args().skip(2).collect();
+            let formulas = match formulas.is_empty() {
+                true => std::io::stdin().lines().map_while(Result::ok).collect(),
+                false => formulas,
+            };
+            for f in formulas.iter().filter(|f| !f.trim().is_empty()) {
+                print!("{}", prove::report(f));
+            }
+        }
+        Some("check-json") => {
+            let stdin = std::io::stdin();
+            let stdout = std::io::stdout();
+            json::check_documents(stdin.lock(), stdout.lock()).expect("stdout is writable");
+        }
+        Some(command @ "trace") | Some(command @ "debug") => {
+            // the file, optionally the definition to evaluate and the strategy
+            let usage = || -> ! {
+                eprintln!(
+                    "usage: type {} FILE [DEFINITION [value|name|need]]",
+                    command
+                );
+                std::process::exit(1)
+            };
+            let path = std::env::args().nth(2).unwrap_or_else(|| usage());
+            let strategy = match std::env::args().nth(4).as_deref() {
+                None | Some("value") => Strategy::CallByValue,
+                Some("name") => Strategy::CallByName,
+                Some("need") => Strategy::CallByNeed,
+                Some(_) => usage(),
+            };
+            let term = load(&path, std::env::args().nth(3));
+            if command == "trace" {
+                let store = &mut Store::new();
+                println!("{}", trace::trace_with(&term, store, BUDGET, strategy));
+            } else {
+                let stdin = std::io::stdin();
+                let stdout = std::io::stdout();
+                let stepper = trace::Stepper::new(term, BUDGET).with_strategy(strategy);
+                debug::debug(stdin.lock(), stdout.lock(), stepper).expect("stdout is writable");
+            }
+        }
+        Some("lsp") => {
+            let stdin = std::io::stdin();
+            let stdout = std::io::stdout();
+            lsp::serve(stdin.lock(), stdout.lock()).expect("the client sends valid messages");
+        }
+        _ => check_examples(),
+    }
+}
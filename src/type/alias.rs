@@ -0,0 +1,186 @@
+//! Type aliases `type Name = Type`.
+//!
+//! Declarations may refer to each other in any order, a reference to
+//! an alias is written as a free type variable of its name. Resolving
+//! the declarations replaces every reference by a `Type::Named` which
+//! carries the definition, so the checker can expand the alias while
+//! the printer keeps its name. An alias which refers to itself,
+//! directly or through others, has no expansion and is rejected.
+use std::fmt;
+
+use super::{free_in_type, subst_type, Type};
+
+/// Why a list of declarations can't be resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The aliases on a cycle, starting and ending with the same one.
+    Cycle(Vec<String>),
+    Duplicate(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Cycle(names) => write!(f, "type alias cycle {}", names.join(" -> ")),
+            Error::Duplicate(name) => write!(f, "'{}' is already defined", name),
+        }
+    }
+}
+
+/// Resolved aliases together with their definitions.
+#[derive(Debug, Clone, Default)]
+pub struct Aliases {
+    resolved: Vec<(String, Type)>,
+}
+
+impl Aliases {
+    /// Resolve a list of declarations `(name, definition)`.
+    pub fn new(declarations: &[(String, Type)]) -> Result<Aliases, Error> {
+        let mut aliases = Aliases::default();
+        for (i, (name, _)) in declarations.iter().enumerate() {
+            if declarations[..i].iter().any(|d| &d.0 == name) {
+                return Err(Error::Duplicate(name.clone()));
+            }
+        }
+        for (name, _) in declarations {
+            aliases.visit(declarations, name, &mut Vec::new())?;
+        }
+        Ok(aliases)
+    }
+
+    /// Resolve the declaration of `name` after the aliases it refers to,
+    /// the stack holds the aliases whose resolution is in progress.
+    fn visit(
+        &mut self,
+        declarations: &[(String, Type)],
+        name: &str,
+        stack: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        if self.get(name).is_some() {
+            return Ok(());
+        }
+        if let Some(start) = stack.iter().position(|n| n == name) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(name.into());
+            return Err(Error::Cycle(cycle));
+        }
+        let definition = match declarations.iter().find(|d| d.0 == name) {
+            Some(d) => &d.1,
+            None => return Ok(()),
+        };
+        stack.push(name.into());
+        for (other, _) in declarations {
+            if free_in_type(other, definition) {
+                self.visit(declarations, other, stack)?;
+            }
+        }
+        stack.pop();
+        let definition = self.resolve(definition);
+        self.resolved.push((name.into(), definition));
+        Ok(())
+    }
+
+    /// The alias `name` as a type.
+    pub fn get(&self, name: &str) -> Option<Type> {
+        self.resolved
+            .iter()
+            .find(|a| a.0 == name)
+            .map(|a| Type::Named(a.0.clone(), Box::new(a.1.clone())))
+    }
+
+    /// Replace the references to aliases within a type.
+    pub fn resolve(&self, t: &Type) -> Type {
+        self.resolved
+            .iter()
+            .fold(t.clone(), |t, (name, _)| match free_in_type(name, &t) {
+                true => subst_type(&t, name, &self.get(name).unwrap()),
+                false => t,
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{type_app, type_equality, Effects};
+    use super::*;
+
+    fn var(x: &str) -> Box<Type> {
+        Box::new(Type::Var(x.into()))
+    }
+
+    #[test]
+    fn aliases_refer_to_later_ones() {
+        // type Curried = Foo -> Bar -> Baz
+        // type Both = Curried * Pair
+        // type Pair = Foo * Bar
+        let curried = Type::Fn(
+            Box::new(Type::Foo),
+            Box::new(Type::Fn(
+                Box::new(Type::Bar),
+                Box::new(Type::Baz),
+                Effects::pure(),
+            )),
+            Effects::pure(),
+        );
+        let pair = Type::Sum(Box::new(Type::Foo), Box::new(Type::Bar));
+        let aliases = Aliases::new(&[
+            ("Curried".into(), curried.clone()),
+            ("Both".into(), Type::Sum(var("Curried"), var("Pair"))),
+            ("Pair".into(), pair.clone()),
+        ])
+        .unwrap();
+        let both = aliases.get("Both").unwrap();
+        assert_eq!(format!("{}", both), "Both");
+        let expanded = Type::Sum(Box::new(curried), Box::new(pair));
+        assert!(type_equality(&both, &expanded));
+        let t = aliases.resolve(&Type::Fn(var("Both"), var("Pair"), Effects::pure()));
+        assert_eq!(format!("{}", t), "Both -> Pair");
+        assert!(aliases.get("Baz").is_none());
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        // type A = Foo * B, type B = Unit -> C, type C = Ref A
+        let declarations = [
+            ("A".into(), Type::Sum(Box::new(Type::Foo), var("B"))),
+            (
+                "B".into(),
+                Type::Fn(Box::new(Type::Unit), var("C"), Effects::pure()),
+            ),
+            ("C".into(), Type::Ref(var("A"))),
+        ];
+        let error = Aliases::new(&declarations).unwrap_err();
+        assert_eq!(format!("{}", error), "type alias cycle A -> B -> C -> A");
+        // type List = Unit * List
+        let error = Aliases::new(&[("List".into(), Type::Sum(Box::new(Type::Unit), var("List")))]);
+        assert_eq!(
+            error.unwrap_err(),
+            Error::Cycle(vec!["List".into(), "List".into()])
+        );
+        let error = Aliases::new(&[("A".into(), Type::Foo), ("A".into(), Type::Bar)]);
+        assert_eq!(error.unwrap_err(), Error::Duplicate("A".into()));
+    }
+
+    #[test]
+    fn parameters_shadow_aliases() {
+        // type A = Foo
+        // type Swap = \A. \B. B * A
+        let swap = Type::Lam(
+            "A".into(),
+            super::super::Kind::Star,
+            Box::new(Type::Lam(
+                "B".into(),
+                super::super::Kind::Star,
+                Box::new(Type::Sum(var("B"), var("A"))),
+            )),
+        );
+        let aliases = Aliases::new(&[("A".into(), Type::Foo), ("Swap".into(), swap)]).unwrap();
+        let t = aliases.resolve(&type_app(
+            Type::Var("Swap".into()),
+            vec![Type::Bar, Type::Var("A".into())],
+        ));
+        assert_eq!(format!("{}", t), "Swap Bar A");
+        let expected = Type::Sum(Box::new(Type::Foo), Box::new(Type::Bar));
+        assert!(type_equality(&t, &expected));
+    }
+}
@@ -0,0 +1,453 @@
+//! Church encodings of products and sums in System F.
+//!
+//! System F is the fragment of the `dependent` calculus where Pi types
+//! only abstract over `Type0`. There the product `A*B` is encoded as
+//! `(R : Type0) -> (A -> B -> R) -> R`, a pair as the function which
+//! passes both components to its argument, and `split` as applying the
+//! pair to the result type and a function of both components. `Unit`
+//! becomes `(R : Type0) -> R -> R`.
+//!
+//! The simple calculus has no sum terms, so the encoded sum type
+//! `(R : Type0) -> (A -> R) -> (B -> R) -> R` comes with encoded
+//! constructors and a case eliminator instead. Together this shows
+//! that products and sums can be derived from functions alone.
+//!
+//! Existentials are encoded the same way, `exists X. A` becomes
+//! `(R : Type0) -> ((X : Type0) -> A -> R) -> R`. A package passes its
+//! hidden type and content to its argument and `unpack` applies the
+//! package to the result type and a function of both.
+//!
+//! Type operators become functions on types, their kinds are
+//! translated to function types between universes.
+use super::cps::Fresh;
+use super::dependent::{ann, app, arrow, free_vars, lam, pi, var, Context as FContext, Expr};
+use super::{normalize_annotations, normalize_type, subst_type, Effects, Kind, Term, Type};
+
+/// A context which declares the base types.
+pub fn base_context() -> FContext {
+    FContext::new()
+        .with("Foo", Expr::Universe(0))
+        .with("Bar", Expr::Universe(0))
+        .with("Baz", Expr::Universe(0))
+}
+
+/// A name for the answer type `R` of an encoding which captures no
+/// free variable of its parts.
+fn answer(parts: &[&Expr]) -> String {
+    let mut r = String::from("R");
+    while parts.iter().any(|p| free_vars(p).contains(&r)) {
+        r.push('\'');
+    }
+    r
+}
+
+/// The encoded product `(R : Type0) -> (A -> B -> R) -> R`.
+pub fn product_type(a: Expr, b: Expr) -> Expr {
+    let r = answer(&[&a, &b]);
+    pi(
+        &r,
+        Expr::Universe(0),
+        arrow(arrow(a, arrow(b, var(&r))), var(&r)),
+    )
+}
+
+/// The encoded sum `(R : Type0) -> (A -> R) -> (B -> R) -> R`.
+pub fn sum_type(a: Expr, b: Expr) -> Expr {
+    let r = answer(&[&a, &b]);
+    pi(
+        &r,
+        Expr::Universe(0),
+        arrow(arrow(a, var(&r)), arrow(arrow(b, var(&r)), var(&r))),
+    )
+}
+
+/// Quantify over the two type parameters `A` and `B`.
+fn for_all_a_b(body: Expr) -> Expr {
+    pi("A", Expr::Universe(0), pi("B", Expr::Universe(0), body))
+}
+
+/// inl : (A B : Type0) -> A -> A + B
+/// inl = \A. \B. \a. \R. \l. \r. l a
+pub fn inl() -> (Expr, Expr) {
+    let term = lam(
+        "A",
+        lam(
+            "B",
+            lam(
+                "a",
+                lam("R", lam("l", lam("r", app(var("l"), vec![var("a")])))),
+            ),
+        ),
+    );
+    let t = for_all_a_b(arrow(var("A"), sum_type(var("A"), var("B"))));
+    (term, t)
+}
+
+/// inr : (A B : Type0) -> B -> A + B
+/// inr = \A. \B. \b. \R. \l. \r. r b
+pub fn inr() -> (Expr, Expr) {
+    let term = lam(
+        "A",
+        lam(
+            "B",
+            lam(
+                "b",
+                lam("R", lam("l", lam("r", app(var("r"), vec![var("b")])))),
+            ),
+        ),
+    );
+    let t = for_all_a_b(arrow(var("B"), sum_type(var("A"), var("B"))));
+    (term, t)
+}
+
+/// case : (A B : Type0) -> A + B -> (C : Type0) -> (A -> C) -> (B -> C) -> C
+/// case = \A. \B. \s. \C. \l. \r. s C l r
+pub fn case() -> (Expr, Expr) {
+    let term = lam(
+        "A",
+        lam(
+            "B",
+            lam(
+                "s",
+                lam(
+                    "C",
+                    lam(
+                        "l",
+                        lam("r", app(var("s"), vec![var("C"), var("l"), var("r")])),
+                    ),
+                ),
+            ),
+        ),
+    );
+    let t = for_all_a_b(arrow(
+        sum_type(var("A"), var("B")),
+        pi(
+            "C",
+            Expr::Universe(0),
+            arrow(
+                arrow(var("A"), var("C")),
+                arrow(arrow(var("B"), var("C")), var("C")),
+            ),
+        ),
+    ));
+    (term, t)
+}
+
+/// The encoded existential `(R : Type0) -> ((X : Type0) -> A -> R) -> R`.
+pub fn exists_type(x: &str, a: Expr) -> Expr {
+    // the answer type must not capture the hidden type either
+    let r = answer(&[&var(x), &a]);
+    pi(
+        &r,
+        Expr::Universe(0),
+        arrow(pi(x, Expr::Universe(0), arrow(a, var(&r))), var(&r)),
+    )
+}
+
+/// The encoding of a kind, `*` is the universe of types.
+pub fn translate_kind(k: &Kind) -> Expr {
+    match k {
+        Kind::Star => Expr::Universe(0),
+        Kind::Arrow(k, l) => arrow(translate_kind(k), translate_kind(l)),
+    }
+}
+
+/// The encoding of a type. References, the dynamic type, intersections,
+/// unions, records and variants have no encoding.
+pub fn translate_type(t: &Type) -> Option<Expr> {
+    encode_type(&normalize_type(t))
+}
+
+fn encode_type(t: &Type) -> Option<Expr> {
+    Some(match t {
+        Type::Foo => var("Foo"),
+        Type::Bar => var("Bar"),
+        Type::Baz => var("Baz"),
+        Type::Unit => pi("R", Expr::Universe(0), arrow(var("R"), var("R"))),
+        Type::Fn(a, b, _) => arrow(encode_type(a)?, encode_type(b)?),
+        Type::Sum(a, b) => product_type(encode_type(a)?, encode_type(b)?),
+        Type::Ref(_)
+        | Type::Dyn
+        | Type::And(_, _)
+        | Type::Or(_, _)
+        | Type::Record(_)
+        | Type::Variant(_) => return None,
+        Type::Var(x) => var(x),
+        Type::Exists(x, a) => exists_type(x, encode_type(a)?),
+        // the terms of a polymorphic type have no encoding, the core
+        // leaves their type abstractions and applications implicit
+        Type::Forall(x, a) => pi(x, Expr::Universe(0), encode_type(a)?),
+        // in normal form no operator is applied to a lambda
+        Type::Lam(x, _, a) => lam(x, encode_type(a)?),
+        Type::App(f, a) => app(encode_type(f)?, vec![encode_type(a)?]),
+        Type::Named(_, t) => encode_type(t)?,
+    })
+}
+
+/// Lambdas have no inferable type, so annotate them
+/// when they are applied.
+fn inferable(e: Expr, t: &Type) -> Option<Expr> {
+    match e {
+        Expr::Lam(_, _) => Some(ann(e, translate_type(t)?)),
+        e => Some(e),
+    }
+}
+
+/// Translate a term of type `t` into its Church encoding.
+/// Returns `None` if the term uses references or doesn't fit its type.
+pub fn translate(term: &Term, t: &Type) -> Option<Expr> {
+    let (term, t) = (normalize_annotations(term), normalize_type(t));
+    encode(&Fresh::new(&term), &term, &t)
+}
+
+fn encode(fresh: &Fresh, term: &Term, t: &Type) -> Option<Expr> {
+    match (term, t) {
+        (Term::Var(x), _) => Some(var(x)),
+        (Term::Lam(x, body), Type::Fn(_, b, _)) => Some(lam(x, encode(fresh, body, b)?)),
+        (Term::App(f, a, type_arg), _) => {
+            let fun = Type::Fn(
+                Box::new(type_arg.clone()),
+                Box::new(t.clone()),
+                Effects::pure(),
+            );
+            let f = inferable(encode(fresh, f, &fun)?, &fun)?;
+            Some(app(f, vec![encode(fresh, a, type_arg)?]))
+        }
+        // (a, b) = \R. \k. k a b
+        (Term::Pair(a, b), Type::Sum(ta, tb)) => {
+            let (r, k) = (fresh.name("R"), fresh.name("k"));
+            let (a, b) = (encode(fresh, a, ta)?, encode(fresh, b, tb)?);
+            Some(lam(&r, lam(&k, app(var(&k), vec![a, b]))))
+        }
+        // split p as (x, y) in e = p T (\x. \y. e)
+        (Term::Split(p, x, ta, y, tb, body), _) => {
+            let pair = Type::Sum(Box::new(ta.clone()), Box::new(tb.clone()));
+            let p = inferable(encode(fresh, p, &pair)?, &pair)?;
+            let body = lam(x, lam(y, encode(fresh, body, t)?));
+            Some(app(p, vec![translate_type(t)?, body]))
+        }
+        // pack [W, e] as exists X. A = \R. \k. k W e
+        (Term::Pack(w, e, Type::Exists(x, a)), _) => {
+            let (r, k) = (fresh.name("R"), fresh.name("k"));
+            let e = encode(fresh, e, &subst_type(a, x, w))?;
+            Some(lam(&r, lam(&k, app(var(&k), vec![translate_type(w)?, e]))))
+        }
+        // unpack p as [X, x] in e = p T (\X. \x. e)
+        (Term::Unpack(p, package, x_type, x, body), _) => {
+            let p = inferable(encode(fresh, p, package)?, package)?;
+            let body = lam(x_type, lam(x, encode(fresh, body, t)?));
+            Some(app(p, vec![translate_type(t)?, body]))
+        }
+        // () = \R. \x. x
+        (&Term::Unit, &Type::Unit) => {
+            let (r, x) = (fresh.name("R"), fresh.name("x"));
+            Some(lam(&r, lam(&x, var(&x))))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::dependent::{definitional_equality, judgment_check, normalize};
+    use super::super::examples;
+    use super::*;
+
+    fn check(e: &Expr, t: &Expr) -> bool {
+        judgment_check(&base_context(), e, t)
+    }
+
+    #[test]
+    fn examples_type_check_after_translation() {
+        for (name, term, t) in examples() {
+            let e = translate(&term, &t).unwrap();
+            let et = translate_type(&t).unwrap();
+            assert!(check(&et, &Expr::Universe(0)));
+            assert!(check(&e, &et), "translated {} does not type check", name);
+        }
+    }
+
+    #[test]
+    fn pairs_and_unit() {
+        // split ((), ()) as (x, y) in (y, x) : Unit * Unit
+        let unit = Type::Unit;
+        let t = Type::Sum(Box::new(unit.clone()), Box::new(unit.clone()));
+        let term = Term::Split(
+            Box::new(Term::Pair(Box::new(Term::Unit), Box::new(Term::Unit))),
+            "x".into(),
+            unit.clone(),
+            "y".into(),
+            unit,
+            Box::new(Term::Pair(
+                Box::new(Term::Var("y".into())),
+                Box::new(Term::Var("x".into())),
+            )),
+        );
+        let e = translate(&term, &t).unwrap();
+        let et = translate_type(&t).unwrap();
+        assert!(check(&e, &et));
+        // the encoding computes the encoded pair
+        let expected =
+            translate(&Term::Pair(Box::new(Term::Unit), Box::new(Term::Unit)), &t).unwrap();
+        assert!(definitional_equality(&e, &expected));
+        // but not at a wrong type
+        assert!(!check(&e, &translate_type(&Type::Unit).unwrap()));
+    }
+
+    #[test]
+    fn answer_types_are_fresh() {
+        let t = Type::product(Type::var("R"), Type::Foo);
+        let r = || var("R'");
+        let expected = pi(
+            "R'",
+            Expr::Universe(0),
+            arrow(arrow(var("R"), arrow(var("Foo"), r())), r()),
+        );
+        assert!(definitional_equality(
+            &translate_type(&t).unwrap(),
+            &expected
+        ));
+        let expected = pi(
+            "R'",
+            Expr::Universe(0),
+            arrow(arrow(var("Foo"), r()), arrow(arrow(var("R"), r()), r())),
+        );
+        assert!(definitional_equality(
+            &sum_type(var("Foo"), var("R")),
+            &expected
+        ));
+    }
+
+    #[test]
+    fn sum_constructors_and_case() {
+        for (e, t) in [inl(), inr(), case()] {
+            assert!(check(&t, &Expr::Universe(0)));
+            assert!(check(&e, &t));
+        }
+    }
+
+    #[test]
+    fn swap_sums() {
+        // swap = \s. case Foo Bar s (Bar + Foo) (inr Bar Foo) (inl Bar Foo)
+        //  : Foo + Bar -> Bar + Foo
+        let (inl, inl_t) = inl();
+        let (inr, inr_t) = inr();
+        let (case, case_t) = case();
+        let swap = lam(
+            "s",
+            app(
+                ann(case, case_t),
+                vec![
+                    var("Foo"),
+                    var("Bar"),
+                    var("s"),
+                    sum_type(var("Bar"), var("Foo")),
+                    app(
+                        ann(inr.clone(), inr_t.clone()),
+                        vec![var("Bar"), var("Foo")],
+                    ),
+                    app(
+                        ann(inl.clone(), inl_t.clone()),
+                        vec![var("Bar"), var("Foo")],
+                    ),
+                ],
+            ),
+        );
+        let t = arrow(
+            sum_type(var("Foo"), var("Bar")),
+            sum_type(var("Bar"), var("Foo")),
+        );
+        assert!(check(&swap, &t));
+
+        // swapping a left injection yields a right injection
+        let g = base_context().with("a", var("Foo"));
+        let left = app(ann(inl, inl_t), vec![var("Foo"), var("Bar"), var("a")]);
+        let swapped = app(ann(swap, t), vec![left]);
+        let right = app(ann(inr, inr_t), vec![var("Bar"), var("Foo"), var("a")]);
+        assert!(judgment_check(
+            &g,
+            &swapped,
+            &sum_type(var("Bar"), var("Foo"))
+        ));
+        assert!(definitional_equality(&normalize(&swapped), &right));
+    }
+
+    #[test]
+    fn existentials() {
+        // unpack (pack [Unit, ((), \u. u)] as exists X. X * (X -> X))
+        //  as [X, p] in split p as (x, f) in (f x, ()) : exists X. X * Unit
+        let x = || Type::Var("X".into());
+        let x_to_x = Type::Fn(Box::new(x()), Box::new(x()), Effects::pure());
+        let package_type = Type::Exists(
+            "X".into(),
+            Box::new(Type::Sum(Box::new(x()), Box::new(x_to_x.clone()))),
+        );
+        let package = Term::Pack(
+            Type::Unit,
+            Box::new(Term::Pair(
+                Box::new(Term::Unit),
+                Box::new(Term::Lam("u".into(), Box::new(Term::Var("u".into())))),
+            )),
+            package_type.clone(),
+        );
+        let t = Type::Sum(Box::new(Type::Unit), Box::new(Type::Unit));
+        let body = Term::Split(
+            Box::new(Term::Var("p".into())),
+            "x".into(),
+            x(),
+            "f".into(),
+            x_to_x,
+            Box::new(Term::Pair(Box::new(Term::Unit), Box::new(Term::Unit))),
+        );
+        let term = Term::Unpack(
+            Box::new(package.clone()),
+            package_type.clone(),
+            "X".into(),
+            "p".into(),
+            Box::new(body),
+        );
+        let e = translate(&package, &package_type).unwrap();
+        assert!(check(&e, &translate_type(&package_type).unwrap()));
+        let e = translate(&term, &t).unwrap();
+        assert!(check(&e, &translate_type(&t).unwrap()));
+        // the answer type doesn't capture a variable called R
+        let t = Type::Exists("X".into(), Box::new(Type::Var("R".into())));
+        assert!(definitional_equality(
+            &translate_type(&t).unwrap(),
+            &pi(
+                "R'",
+                Expr::Universe(0),
+                arrow(
+                    pi("X", Expr::Universe(0), arrow(var("R"), var("R'"))),
+                    var("R'")
+                )
+            )
+        ));
+    }
+
+    #[test]
+    fn type_operators() {
+        use super::super::{kinding, pair_operator, type_app};
+        // Pair = \A. \B. A * B : Type0 -> Type0 -> Type0
+        let pair = pair_operator();
+        let kind = kinding(&mut Vec::new(), &pair).unwrap();
+        assert!(check(
+            &translate_type(&pair).unwrap(),
+            &translate_kind(&kind)
+        ));
+        // applications are reduced before the translation
+        let applied = type_app(pair, vec![Type::Foo, Type::Bar]);
+        let product = Type::Sum(Box::new(Type::Foo), Box::new(Type::Bar));
+        assert!(definitional_equality(
+            &translate_type(&applied).unwrap(),
+            &translate_type(&product).unwrap()
+        ));
+    }
+
+    #[test]
+    fn references_have_no_encoding() {
+        let t = Type::Ref(Box::new(Type::Unit));
+        assert!(translate_type(&t).is_none());
+        assert!(translate(&Term::Ref(Box::new(Term::Unit), Type::Unit), &t).is_none());
+    }
+}
@@ -0,0 +1,379 @@
+//! Generate rust source code from checked terms.
+//!
+//! The product is translated to a tuple and `Unit` to `()`. A term of
+//! function type on the top level becomes a function returning an
+//! `impl Fn`, nested function values are `Rc<dyn Fn>` since they may be
+//! used more than once and have to be cloned. References become
+//! `Rc<RefCell<T>>` and the base types are replaced with rust types
+//! given by the user, which must implement `Clone`.
+use std::collections::HashMap;
+
+use super::{judgment_check, normalize_annotations, normalize_type, Context, Effects, Term, Type};
+
+/// The rust identifiers of the variables of a term.
+type Names = HashMap<String, String>;
+
+/// The rust types used for the base types.
+#[derive(Debug, Clone)]
+pub struct Codegen {
+    foo_type: String,
+    bar_type: String,
+    baz_type: String,
+}
+
+/// Turn a variable name into a valid rust identifier.
+fn ident(x: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+        "return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe",
+        "use", "where", "while", "dyn", "async", "await", "abstract", "become", "box", "do",
+        "final", "macro", "override", "priv", "typeof", "unsized", "virtual", "yield", "try",
+    ];
+    let mut name = String::new();
+    for c in x.chars() {
+        match c {
+            '\'' => name.push_str("_p"),
+            c if c.is_alphanumeric() || c == '_' => name.push(c),
+            _ => name.push('_'),
+        }
+    }
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    if name == "_" || KEYWORDS.contains(&name.as_str()) {
+        name.push('_');
+    }
+    name
+}
+
+/// Give every variable of a term its own rust identifier. Names which
+/// are identifiers already are kept, the others are renamed by `ident`
+/// and get more underscores until they are distinct.
+fn identifiers(term: &Term) -> Names {
+    let mut vars = Vec::new();
+    variables(term, &mut vars);
+    vars.sort_by_key(|x| ident(x) != *x);
+    let mut names = HashMap::new();
+    let mut used: Vec<String> = Vec::new();
+    for x in vars {
+        let mut name = ident(&x);
+        while used.contains(&name) {
+            name.push('_');
+        }
+        used.push(name.clone());
+        names.insert(x, name);
+    }
+    names
+}
+
+/// Collect the variables of a term, bound or free.
+fn variables(t: &Term, acc: &mut Vec<String>) {
+    fn add(x: &String, acc: &mut Vec<String>) {
+        if !acc.contains(x) {
+            acc.push(x.clone());
+        }
+    }
+    match t {
+        Term::Var(x) => add(x, acc),
+        Term::Lam(x, body) => {
+            add(x, acc);
+            variables(body, acc);
+        }
+        Term::Split(p, x, _, y, _, body) => {
+            add(x, acc);
+            add(y, acc);
+            variables(p, acc);
+            variables(body, acc);
+        }
+        Term::Unpack(p, _, _, x, body) => {
+            add(x, acc);
+            variables(p, acc);
+            variables(body, acc);
+        }
+        Term::App(a, b, _) | Term::Pair(a, b) | Term::Assign(a, b, _) | Term::Try(a, _, b) => {
+            variables(a, acc);
+            variables(b, acc);
+        }
+        Term::Ref(a, _) | Term::Deref(a) | Term::Pack(_, a, _) | Term::Cast(a, _, _, _) => {
+            variables(a, acc)
+        }
+        Term::Unit | Term::Loc(_) | Term::Blame(_, _, _) | Term::Raise(_) => {}
+    }
+}
+
+/// Collect the free variables of a term.
+fn free_vars(t: &Term, bound: &mut Vec<String>, acc: &mut Vec<String>) {
+    match t {
+        Term::Var(x) => {
+            if !bound.contains(x) && !acc.contains(x) {
+                acc.push(x.clone());
+            }
+        }
+        Term::Lam(x, body) => {
+            bound.push(x.clone());
+            free_vars(body, bound, acc);
+            bound.pop();
+        }
+        Term::Split(p, x, _, y, _, body) => {
+            free_vars(p, bound, acc);
+            bound.push(x.clone());
+            bound.push(y.clone());
+            free_vars(body, bound, acc);
+            bound.truncate(bound.len() - 2);
+        }
+        Term::App(a, b, _) | Term::Pair(a, b) | Term::Assign(a, b, _) => {
+            free_vars(a, bound, acc);
+            free_vars(b, bound, acc);
+        }
+        Term::Ref(a, _) | Term::Deref(a) | Term::Pack(_, a, _) | Term::Cast(a, _, _, _) => {
+            free_vars(a, bound, acc)
+        }
+        Term::Unpack(p, _, _, x, body) => {
+            free_vars(p, bound, acc);
+            bound.push(x.clone());
+            free_vars(body, bound, acc);
+            bound.pop();
+        }
+        Term::Try(a, _, b) => {
+            free_vars(a, bound, acc);
+            free_vars(b, bound, acc);
+        }
+        Term::Unit | Term::Loc(_) | Term::Blame(_, _, _) | Term::Raise(_) => {}
+    }
+}
+
+impl Codegen {
+    pub fn new(foo_type: &str, bar_type: &str, baz_type: &str) -> Codegen {
+        Codegen {
+            foo_type: foo_type.into(),
+            bar_type: bar_type.into(),
+            baz_type: baz_type.into(),
+        }
+    }
+
+    /// The rust type of a value of type `t`.
+    /// Existential and polymorphic types, type operators, the dynamic type, intersections,
+    /// unions, records and variants have no counterpart and give `None`.
+    pub fn rust_type(&self, t: &Type) -> Option<String> {
+        Some(match t {
+            Type::Foo => self.foo_type.clone(),
+            Type::Bar => self.bar_type.clone(),
+            Type::Baz => self.baz_type.clone(),
+            Type::Unit => "()".into(),
+            Type::Sum(a, b) => format!("({}, {})", self.rust_type(a)?, self.rust_type(b)?),
+            Type::Fn(a, b, _) => format!(
+                "Rc<dyn Fn({}) -> {}>",
+                self.rust_type(a)?,
+                self.rust_type(b)?
+            ),
+            Type::Ref(a) => format!("Rc<RefCell<{}>>", self.rust_type(a)?),
+            Type::Named(_, t) => self.rust_type(t)?,
+            Type::Var(_)
+            | Type::Exists(_, _)
+            | Type::Forall(_, _)
+            | Type::Lam(_, _, _)
+            | Type::App(_, _)
+            | Type::Dyn
+            | Type::And(_, _)
+            | Type::Or(_, _)
+            | Type::Record(_)
+            | Type::Variant(_) => return None,
+        })
+    }
+
+    /// A closure `move |x: A| -> B { body }` for a lambda of type `A -> B`.
+    fn closure(&self, names: &Names, x: &str, body: &Term, a: &Type, b: &Type) -> Option<String> {
+        Some(format!(
+            "move |{}: {}| -> {} {{ {} }}",
+            names[x],
+            self.rust_type(a)?,
+            self.rust_type(b)?,
+            self.expr(names, body, b)?
+        ))
+    }
+
+    /// Translate a term of type `t` into a rust expression.
+    /// The term must be well-typed, otherwise `None` is returned.
+    fn expr(&self, names: &Names, term: &Term, t: &Type) -> Option<String> {
+        match (term, t) {
+            (Term::Var(x), _) => Some(format!("{}.clone()", names[x])),
+            (Term::Lam(x, body), Type::Fn(a, b, _)) => {
+                // the closure takes ownership, so hand it copies of the captured variables
+                let mut captured = Vec::new();
+                free_vars(body, &mut vec![x.clone()], &mut captured);
+                let clones: String = captured
+                    .iter()
+                    .map(|v| format!("let {0} = {0}.clone(); ", names[v]))
+                    .collect();
+                Some(format!(
+                    "{{ {}Rc::new({}) as {} }}",
+                    clones,
+                    self.closure(names, x, body, a, b)?,
+                    self.rust_type(t)?
+                ))
+            }
+            (Term::App(f, a, type_arg), _) => Some(format!(
+                "({})({})",
+                self.expr(
+                    names,
+                    f,
+                    &Type::Fn(
+                        Box::new(type_arg.clone()),
+                        Box::new(t.clone()),
+                        Effects::pure()
+                    )
+                )?,
+                self.expr(names, a, type_arg)?
+            )),
+            (Term::Pair(a, b), Type::Sum(l, r)) => Some(format!(
+                "({}, {})",
+                self.expr(names, a, l)?,
+                self.expr(names, b, r)?
+            )),
+            (Term::Split(p, x, a, y, b, body), _) => {
+                // the second binding shadows the first one if both have the same name
+                let x = if x == y { "_" } else { &names[x] };
+                let pair = Type::Sum(Box::new(a.clone()), Box::new(b.clone()));
+                Some(format!(
+                    "{{ let ({}, {}): {} = {}; {} }}",
+                    x,
+                    names[y],
+                    self.rust_type(&pair)?,
+                    self.expr(names, p, &pair)?,
+                    self.expr(names, body, t)?
+                ))
+            }
+            (&Term::Unit, &Type::Unit) => Some("()".into()),
+            (Term::Ref(init, a), Type::Ref(_)) => Some(format!(
+                "Rc::new(RefCell::new({}))",
+                self.expr(names, init, a)?
+            )),
+            (Term::Deref(cell), _) => Some(format!(
+                "{{ let c = {}; let v = c.borrow().clone(); v }}",
+                self.expr(names, cell, &Type::Ref(Box::new(t.clone())))?
+            )),
+            (Term::Assign(cell, value, a), &Type::Unit) => Some(format!(
+                "{{ let (c, v) = ({}, {}); *c.borrow_mut() = v; }}",
+                self.expr(names, cell, &Type::Ref(Box::new(a.clone())))?,
+                self.expr(names, value, a)?
+            )),
+            _ => None,
+        }
+    }
+
+    /// Generate a function `name` which returns the value of a closed term.
+    /// Functions on the top level are returned as `impl Fn`.
+    pub fn function(&self, name: &str, term: &Term, t: &Type) -> Option<String> {
+        if !judgment_check(&Context::Empty(), term, t) {
+            return None;
+        }
+        let (term, t) = (&normalize_annotations(term), &normalize_type(t));
+        let names = &identifiers(term);
+        let (ret, body) = match (term, t) {
+            (Term::Lam(x, body), Type::Fn(a, b, _)) => (
+                format!("impl Fn({}) -> {}", self.rust_type(a)?, self.rust_type(b)?),
+                self.closure(names, x, body, a, b)?,
+            ),
+            _ => (self.rust_type(t)?, self.expr(names, term, t)?),
+        };
+        Some(format!(
+            "#[allow(unused_variables)]\npub fn {}() -> {} {{\n    {}\n}}\n",
+            ident(name),
+            ret,
+            body
+        ))
+    }
+
+    /// Generate a module with one function for every named term.
+    pub fn module(&self, terms: &[(&str, Term, Type)]) -> Option<String> {
+        let mut out = String::from(
+            "// Generated from checked terms.\nuse std::cell::RefCell;\nuse std::rc::Rc;\n",
+        );
+        for (name, term, t) in terms {
+            out.push('\n');
+            out.push_str(&self.function(name, term, t)?);
+        }
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn var(x: &str) -> Box<Term> {
+        Box::new(Term::Var(x.into()))
+    }
+
+    #[test]
+    fn types() {
+        let gen = Codegen::new("i64", "String", "bool");
+        let t = Type::Fn(
+            Box::new(Type::Sum(Box::new(Type::Foo), Box::new(Type::Bar))),
+            Box::new(Type::Ref(Box::new(Type::Baz))),
+            Effects::pure(),
+        );
+        assert_eq!(
+            gen.rust_type(&t).unwrap(),
+            "Rc<dyn Fn((i64, String)) -> Rc<RefCell<bool>>>"
+        );
+        assert_eq!(gen.rust_type(&Type::Unit).unwrap(), "()");
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(ident("x"), "x");
+        assert_eq!(ident("x'"), "x_p");
+        assert_eq!(ident("1x"), "_1x");
+        assert_eq!(ident("const"), "const_");
+        assert_eq!(ident("_"), "__");
+
+        // \x'. \x_. \x_p. x' : Foo -> Bar -> Baz -> Foo
+        let gen = Codegen::new("i64", "String", "bool");
+        let term = Term::Lam(
+            "x'".into(),
+            Box::new(Term::Lam(
+                "x_".into(),
+                Box::new(Term::Lam("x_p".into(), var("x'"))),
+            )),
+        );
+        let arrow = |a: Type, b: Type| Type::Fn(Box::new(a), Box::new(b), Effects::pure());
+        let t = arrow(Type::Foo, arrow(Type::Bar, arrow(Type::Baz, Type::Foo)));
+        let code = gen.function("k", &term, &t).unwrap();
+        assert!(code.contains("move |x_p_: i64|"), "{}", code);
+        assert!(code.contains("move |x_: String|"), "{}", code);
+        assert!(
+            code.contains("move |x_p: bool| -> i64 { x_p_.clone() }"),
+            "{}",
+            code
+        );
+    }
+
+    #[test]
+    fn captured_variables_are_cloned() {
+        let gen = Codegen::new("i64", "String", "bool");
+        // \x. \y. x : Foo -> Bar -> Foo
+        let term = Term::Lam("x".into(), Box::new(Term::Lam("y".into(), var("x"))));
+        let t = Type::Fn(
+            Box::new(Type::Foo),
+            Box::new(Type::Fn(
+                Box::new(Type::Bar),
+                Box::new(Type::Foo),
+                Effects::pure(),
+            )),
+            Effects::pure(),
+        );
+        let code = gen.function("k", &term, &t).unwrap();
+        assert!(code.contains("pub fn k() -> impl Fn(i64) -> Rc<dyn Fn(String) -> i64>"));
+        assert!(code.contains("let x = x.clone(); Rc::new(move |y: String| -> i64 { x.clone() })"));
+    }
+
+    #[test]
+    fn ill_typed_terms_are_rejected() {
+        let gen = Codegen::new("i64", "String", "bool");
+        let t = Type::Fn(Box::new(Type::Foo), Box::new(Type::Bar), Effects::pure());
+        let term = Term::Lam("x".into(), var("x"));
+        assert!(gen.function("id", &term, &t).is_none());
+    }
+}
@@ -0,0 +1,562 @@
+//! Compiler passes over terms: the continuation-passing style
+//! transformation and the conversion into A-normal form.
+//!
+//! Both passes are type-directed like `judgment_check`, since every
+//! application they introduce needs the type of its argument.
+//!
+//! The CPS transformation is the call-by-value one. A term of type `A`
+//! becomes a term of type `([A] -> o) -> o` which passes its value to
+//! the continuation, where `o` is the answer type and `[A]` translates
+//! function types `A -> B` to `[A] -> ([B] -> o) -> o`.
+//!
+//! In A-normal form every argument of an application, a pair or an
+//! operation on references is a value and all intermediate results are
+//! bound with `let x : A = c in e`, which is written as `(\x. e) c`.
+use std::cell::Cell;
+use std::collections::HashSet;
+
+use super::eval::subst;
+use super::{
+    normalize_annotations, normalize_type, subst_type, subst_type_in_term, Effects, Term, Type,
+};
+
+/// Generates names which don't clash with the names of a term.
+pub struct Fresh {
+    used: HashSet<String>,
+    next: Cell<usize>,
+}
+
+impl Fresh {
+    pub fn new(t: &Term) -> Fresh {
+        fn collect(t: &Term, used: &mut HashSet<String>) {
+            match t {
+                Term::Var(x) => {
+                    used.insert(x.clone());
+                }
+                Term::Lam(x, body) => {
+                    used.insert(x.clone());
+                    collect(body, used);
+                }
+                Term::Split(p, x, _, y, _, body) => {
+                    used.insert(x.clone());
+                    used.insert(y.clone());
+                    collect(p, used);
+                    collect(body, used);
+                }
+                Term::App(a, b, _) | Term::Pair(a, b) | Term::Assign(a, b, _) => {
+                    collect(a, used);
+                    collect(b, used);
+                }
+                Term::Ref(a, _) | Term::Deref(a) | Term::Pack(_, a, _) | Term::Cast(a, _, _, _) => {
+                    collect(a, used)
+                }
+                Term::Unpack(p, _, x_type, x, body) => {
+                    used.insert(x_type.clone());
+                    used.insert(x.clone());
+                    collect(p, used);
+                    collect(body, used);
+                }
+                Term::Try(a, _, b) => {
+                    collect(a, used);
+                    collect(b, used);
+                }
+                Term::Unit | Term::Loc(_) | Term::Blame(_, _, _) | Term::Raise(_) => {}
+            }
+        }
+        let mut used = HashSet::new();
+        collect(t, &mut used);
+        Fresh {
+            used,
+            next: Cell::new(0),
+        }
+    }
+
+    pub fn name(&self, base: &str) -> String {
+        loop {
+            let n = self.next.get();
+            self.next.set(n + 1);
+            let name = format!("{}{}", base, n);
+            if !self.used.contains(&name) {
+                return name;
+            }
+        }
+    }
+}
+
+fn var(x: &str) -> Box<Term> {
+    Box::new(Term::Var(x.into()))
+}
+
+fn lam(x: &str, body: Term) -> Box<Term> {
+    Box::new(Term::Lam(x.into(), Box::new(body)))
+}
+
+fn arrow(a: Type, b: Type) -> Type {
+    Type::Fn(Box::new(a), Box::new(b), Effects::pure())
+}
+
+/// The translation `[A]` of value types.
+pub fn cps_type(t: &Type, o: &Type) -> Type {
+    match t {
+        Type::Fn(a, b, _) => arrow(cps_type(a, o), cps_computation_type(b, o)),
+        Type::Sum(a, b) => Type::Sum(Box::new(cps_type(a, o)), Box::new(cps_type(b, o))),
+        Type::And(a, b) => Type::And(Box::new(cps_type(a, o)), Box::new(cps_type(b, o))),
+        Type::Or(a, b) => Type::Or(Box::new(cps_type(a, o)), Box::new(cps_type(b, o))),
+        Type::Record(row) => Type::record(cps_fields(&row.fields, o), row.rest.clone()),
+        Type::Variant(row) => Type::variant(cps_fields(&row.fields, o), row.rest.clone()),
+        Type::Ref(a) => Type::Ref(Box::new(cps_type(a, o))),
+        Type::Exists(x, a) => Type::Exists(x.clone(), Box::new(cps_type(a, o))),
+        Type::Forall(x, a) => Type::Forall(x.clone(), Box::new(cps_type(a, o))),
+        Type::Lam(x, k, a) => Type::Lam(x.clone(), k.clone(), Box::new(cps_type(a, o))),
+        Type::App(f, a) => Type::App(Box::new(cps_type(f, o)), Box::new(cps_type(a, o))),
+        Type::Named(_, t) => cps_type(t, o),
+        Type::Foo | Type::Bar | Type::Baz | Type::Unit | Type::Var(_) | Type::Dyn => t.clone(),
+    }
+}
+
+fn cps_fields(fields: &[(String, Type)], o: &Type) -> Vec<(String, Type)> {
+    fields
+        .iter()
+        .map(|(l, t)| (l.clone(), cps_type(t, o)))
+        .collect()
+}
+
+/// The type `([A] -> o) -> o` of a translated term of type `A`.
+pub fn cps_computation_type(t: &Type, o: &Type) -> Type {
+    arrow(arrow(cps_type(t, o), o.clone()), o.clone())
+}
+
+/// Translate a term of type `t` into continuation-passing style
+/// with answer type `o`. Returns `None` if the term doesn't fit its type.
+pub fn cps(term: &Term, t: &Type, o: &Type) -> Option<Term> {
+    let (term, t) = (normalize_annotations(term), normalize_type(t));
+    cps_term(&Fresh::new(&term), &term, &t, &normalize_type(o))
+}
+
+fn cps_term(fresh: &Fresh, term: &Term, t: &Type, o: &Type) -> Option<Term> {
+    let k = fresh.name("k");
+    // the continuation receives a value of type [t]
+    let ret = |v: Term| Term::App(var(&k), Box::new(v), cps_type(t, o));
+    // run the translation of `m : a` and continue with its value bound to `x`
+    let bind = |m: &Term, a: &Type, x: &str, rest: Term| -> Option<Term> {
+        Some(Term::App(
+            Box::new(cps_term(fresh, m, a, o)?),
+            lam(x, rest),
+            arrow(cps_type(a, o), o.clone()),
+        ))
+    };
+    let body = match (term, t) {
+        (Term::Var(_), _) | (&Term::Unit, &Type::Unit) | (Term::Loc(_), _) => ret(term.clone()),
+        (Term::Lam(x, body), Type::Fn(_, b, _)) => {
+            ret(Term::Lam(x.clone(), Box::new(cps_term(fresh, body, b, o)?)))
+        }
+        (Term::App(f, a, type_arg), _) => {
+            let (vf, va) = (fresh.name("f"), fresh.name("a"));
+            // vf va k
+            let call = Term::App(
+                Box::new(Term::App(var(&vf), var(&va), cps_type(type_arg, o))),
+                var(&k),
+                arrow(cps_type(t, o), o.clone()),
+            );
+            let fun = arrow(type_arg.clone(), t.clone());
+            bind(f, &fun, &vf, bind(a, type_arg, &va, call)?)?
+        }
+        (Term::Pair(a, b), Type::Sum(ta, tb)) => {
+            let (va, vb) = (fresh.name("a"), fresh.name("b"));
+            let pair = ret(Term::Pair(var(&va), var(&vb)));
+            bind(a, ta, &va, bind(b, tb, &vb, pair)?)?
+        }
+        (Term::Split(p, x, ta, y, tb, body), _) => {
+            let vp = fresh.name("p");
+            let split = Term::Split(
+                var(&vp),
+                x.clone(),
+                cps_type(ta, o),
+                y.clone(),
+                cps_type(tb, o),
+                Box::new(Term::App(
+                    Box::new(cps_term(fresh, body, t, o)?),
+                    var(&k),
+                    arrow(cps_type(t, o), o.clone()),
+                )),
+            );
+            let pair = Type::Sum(Box::new(ta.clone()), Box::new(tb.clone()));
+            bind(p, &pair, &vp, split)?
+        }
+        (Term::Ref(init, a), Type::Ref(_)) => {
+            let v = fresh.name("v");
+            let alloc = ret(Term::Ref(var(&v), cps_type(a, o)));
+            bind(init, a, &v, alloc)?
+        }
+        (Term::Deref(cell), _) => {
+            let c = fresh.name("c");
+            bind(
+                cell,
+                &Type::Ref(Box::new(t.clone())),
+                &c,
+                ret(Term::Deref(var(&c))),
+            )?
+        }
+        (Term::Assign(cell, value, a), &Type::Unit) => {
+            let (c, v) = (fresh.name("c"), fresh.name("v"));
+            let assign = ret(Term::Assign(var(&c), var(&v), cps_type(a, o)));
+            let cell_type = Type::Ref(Box::new(a.clone()));
+            bind(cell, &cell_type, &c, bind(value, a, &v, assign)?)?
+        }
+        (Term::Pack(w, e, Type::Exists(x, a)), _) => {
+            let v = fresh.name("v");
+            let pack = ret(Term::Pack(cps_type(w, o), var(&v), cps_type(t, o)));
+            bind(e, &subst_type(a, x, w), &v, pack)?
+        }
+        (Term::Unpack(p, package, x_type, x, body), _) => {
+            let vp = fresh.name("p");
+            let unpack = Term::Unpack(
+                var(&vp),
+                cps_type(package, o),
+                x_type.clone(),
+                x.clone(),
+                Box::new(Term::App(
+                    Box::new(cps_term(fresh, body, t, o)?),
+                    var(&k),
+                    arrow(cps_type(t, o), o.clone()),
+                )),
+            );
+            bind(p, package, &vp, unpack)?
+        }
+        _ => return None,
+    };
+    Some(Term::Lam(k, Box::new(body)))
+}
+
+/// The continuation of the ANF conversion gets the converted term.
+type Cont<'a> = Box<dyn FnOnce(Term) -> Option<Term> + 'a>;
+
+/// Test if a term is a value in the sense of A-normal form.
+fn is_atom(t: &Term) -> bool {
+    matches!(
+        t,
+        Term::Var(_) | Term::Unit | Term::Lam(_, _) | Term::Loc(_)
+    )
+}
+
+/// Convert a term of type `t` into A-normal form.
+/// Returns `None` if the term doesn't fit its type.
+pub fn anf(term: &Term, t: &Type) -> Option<Term> {
+    let (term, t) = (normalize_annotations(term), normalize_type(t));
+    let fresh = Fresh::new(&term);
+    normalize(&fresh, &term, &t, Box::new(Some))
+}
+
+fn normalize<'a>(fresh: &'a Fresh, term: &Term, t: &Type, k: Cont<'a>) -> Option<Term> {
+    match (term, t) {
+        (Term::Var(_), _) | (&Term::Unit, &Type::Unit) | (Term::Loc(_), _) => k(term.clone()),
+        (Term::Lam(x, body), Type::Fn(_, b, _)) => {
+            let body = normalize(fresh, body, b, Box::new(Some))?;
+            k(Term::Lam(x.clone(), Box::new(body)))
+        }
+        (Term::App(f, a, type_arg), _) => {
+            let type_arg = type_arg.clone();
+            let fun = arrow(type_arg.clone(), t.clone());
+            normalize_name(
+                fresh,
+                f,
+                &fun,
+                Box::new(move |vf| {
+                    normalize_name(
+                        fresh,
+                        a,
+                        &type_arg.clone(),
+                        Box::new(move |va| k(Term::App(Box::new(vf), Box::new(va), type_arg))),
+                    )
+                }),
+            )
+        }
+        (Term::Pair(a, b), Type::Sum(ta, tb)) => {
+            let tb = (**tb).clone();
+            normalize_name(
+                fresh,
+                a,
+                ta,
+                Box::new(move |va| {
+                    normalize_name(
+                        fresh,
+                        b,
+                        &tb,
+                        Box::new(move |vb| k(Term::Pair(Box::new(va), Box::new(vb)))),
+                    )
+                }),
+            )
+        }
+        (Term::Split(p, x, ta, y, tb, body), _) => {
+            // rename the binders so they can't capture variables of the continuation
+            let (x2, y2) = (fresh.name(x), fresh.name(y));
+            let body = if x == y {
+                subst(body, y, &Term::Var(y2.clone()))
+            } else {
+                subst(
+                    &subst(body, x, &Term::Var(x2.clone())),
+                    y,
+                    &Term::Var(y2.clone()),
+                )
+            };
+            let (ta, tb, t) = (ta.clone(), tb.clone(), t.clone());
+            let pair = Type::Sum(Box::new(ta.clone()), Box::new(tb.clone()));
+            normalize_name(
+                fresh,
+                p,
+                &pair,
+                Box::new(move |vp| {
+                    let body = normalize(fresh, &body, &t, k)?;
+                    Some(Term::Split(Box::new(vp), x2, ta, y2, tb, Box::new(body)))
+                }),
+            )
+        }
+        (Term::Ref(init, a), Type::Ref(_)) => {
+            let a = a.clone();
+            normalize_name(
+                fresh,
+                init,
+                &a.clone(),
+                Box::new(move |v| k(Term::Ref(Box::new(v), a))),
+            )
+        }
+        (Term::Deref(cell), _) => normalize_name(
+            fresh,
+            cell,
+            &Type::Ref(Box::new(t.clone())),
+            Box::new(move |c| k(Term::Deref(Box::new(c)))),
+        ),
+        (Term::Assign(cell, value, a), &Type::Unit) => {
+            let a = a.clone();
+            normalize_name(
+                fresh,
+                cell,
+                &Type::Ref(Box::new(a.clone())),
+                Box::new(move |c| {
+                    normalize_name(
+                        fresh,
+                        value,
+                        &a.clone(),
+                        Box::new(move |v| k(Term::Assign(Box::new(c), Box::new(v), a))),
+                    )
+                }),
+            )
+        }
+        (Term::Pack(w, e, Type::Exists(x, a)), _) => {
+            let (w, package) = (w.clone(), t.clone());
+            normalize_name(
+                fresh,
+                e,
+                &subst_type(a, x, &w),
+                Box::new(move |v| k(Term::Pack(w, Box::new(v), package))),
+            )
+        }
+        (Term::Unpack(p, package, x_type, x, body), _) => {
+            // rename the binders so they can't capture names of the continuation
+            let (x_type2, x2) = (fresh.name(x_type), fresh.name(x));
+            let body = subst_type_in_term(body, x_type, &Type::Var(x_type2.clone()));
+            let body = subst(&body, x, &Term::Var(x2.clone()));
+            let (package, t) = (package.clone(), t.clone());
+            normalize_name(
+                fresh,
+                p,
+                &package.clone(),
+                Box::new(move |vp| {
+                    let body = normalize(fresh, &body, &t, k)?;
+                    Some(Term::Unpack(
+                        Box::new(vp),
+                        package,
+                        x_type2,
+                        x2,
+                        Box::new(body),
+                    ))
+                }),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Normalize a term and bind its result to a fresh name
+/// unless it already is a value.
+fn normalize_name<'a>(fresh: &'a Fresh, term: &Term, t: &Type, k: Cont<'a>) -> Option<Term> {
+    let t2 = t.clone();
+    normalize(
+        fresh,
+        term,
+        t,
+        Box::new(move |c| {
+            if is_atom(&c) {
+                k(c)
+            } else {
+                let x = fresh.name("t");
+                let rest = k(Term::Var(x.clone()))?;
+                Some(Term::App(lam(&x, rest), Box::new(c), t2))
+            }
+        }),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::eval::{self, Store};
+    use super::super::nbe::alpha_equal;
+    use super::super::{examples, judgment_check, Context};
+    use super::*;
+
+    /// Values in A-normal form.
+    fn is_anf_value(t: &Term) -> bool {
+        match t {
+            Term::Lam(_, body) => is_anf(body),
+            _ => is_atom(t),
+        }
+    }
+
+    /// Operations whose operands are all values.
+    fn is_computation(t: &Term) -> bool {
+        match t {
+            Term::App(a, b, _) | Term::Pair(a, b) | Term::Assign(a, b, _) => {
+                is_anf_value(a) && is_anf_value(b)
+            }
+            Term::Ref(v, _) | Term::Deref(v) => is_anf_value(v),
+            _ => false,
+        }
+    }
+
+    /// Test if a term is in A-normal form.
+    fn is_anf(t: &Term) -> bool {
+        match t {
+            // let x = c in e
+            Term::App(f, c, _) if is_computation(c) => match **f {
+                Term::Lam(_, ref body) => is_anf(body),
+                _ => false,
+            },
+            Term::Split(p, _, _, _, _, body) => is_anf_value(p) && is_anf(body),
+            _ => is_computation(t) || is_anf_value(t),
+        }
+    }
+
+    #[test]
+    fn cps_of_examples_type_checks() {
+        for o in &[Type::Baz, Type::Unit] {
+            for (name, term, t) in examples() {
+                let translated = cps(&term, &t, o).unwrap();
+                assert!(
+                    judgment_check(&Context::Empty(), &translated, &cps_computation_type(&t, o)),
+                    "cps of {} does not type check",
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn anf_of_examples_type_checks() {
+        for (name, term, t) in examples() {
+            let converted = anf(&term, &t).unwrap();
+            assert!(is_anf(&converted), "{} is not in anf", name);
+            assert!(
+                judgment_check(&Context::Empty(), &converted, &t),
+                "anf of {} does not type check",
+                name
+            );
+        }
+    }
+
+    /// (\r. (\u. !r) (r := (!r, ()))) (ref ((), ())) : Unit * Unit
+    fn program() -> (Term, Type) {
+        let pair = Type::Sum(Box::new(Type::Unit), Box::new(Type::Unit));
+        let term = Term::App(
+            lam(
+                "r",
+                Term::App(
+                    lam("u", Term::Deref(var("r"))),
+                    Box::new(Term::Assign(
+                        var("r"),
+                        Box::new(Term::Pair(
+                            Box::new(Term::Split(
+                                Box::new(Term::Deref(var("r"))),
+                                "x".into(),
+                                Type::Unit,
+                                "y".into(),
+                                Type::Unit,
+                                var("y"),
+                            )),
+                            Box::new(Term::Unit),
+                        )),
+                        pair.clone(),
+                    )),
+                    Type::Unit,
+                ),
+            ),
+            Box::new(Term::Ref(
+                Box::new(Term::Pair(Box::new(Term::Unit), Box::new(Term::Unit))),
+                pair.clone(),
+            )),
+            Type::Ref(Box::new(pair.clone())),
+        );
+        (term, pair)
+    }
+
+    #[test]
+    fn cps_program_computes_the_same_value() {
+        let (term, t) = program();
+        let translated = cps(&term, &t, &t).unwrap();
+        assert!(judgment_check(
+            &Context::Empty(),
+            &translated,
+            &cps_computation_type(&t, &t)
+        ));
+        // run it with the identity continuation
+        let run = Term::App(
+            Box::new(translated),
+            lam("x", Term::Var("x".into())),
+            arrow(t.clone(), t.clone()),
+        );
+        assert!(judgment_check(&Context::Empty(), &run, &t));
+        let (mut s1, mut s2) = (Store::new(), Store::new());
+        let expected = eval::eval(&term, &mut s1);
+        let value = eval::eval(&run, &mut s2);
+        assert!(alpha_equal(&expected, &value));
+        assert_eq!(s1.len(), s2.len());
+    }
+
+    #[test]
+    fn anf_program_computes_the_same_value() {
+        let (term, t) = program();
+        let converted = anf(&term, &t).unwrap();
+        assert!(is_anf(&converted));
+        assert!(!is_anf(&term));
+        assert!(judgment_check(&Context::Empty(), &converted, &t));
+        let (mut s1, mut s2) = (Store::new(), Store::new());
+        let expected = eval::eval(&term, &mut s1);
+        let value = eval::eval(&converted, &mut s2);
+        assert!(alpha_equal(&expected, &value));
+    }
+
+    #[test]
+    fn anf_renames_split_binders() {
+        // \x. split ((), ()) as (x, y) in x must not capture the outer x
+        let term = Term::Lam(
+            "x".into(),
+            Box::new(Term::Split(
+                Box::new(Term::Pair(Box::new(Term::Unit), Box::new(Term::Unit))),
+                "x".into(),
+                Type::Unit,
+                "y".into(),
+                Type::Unit,
+                var("x"),
+            )),
+        );
+        let t = arrow(Type::Foo, Type::Unit);
+        let converted = anf(&term, &t).unwrap();
+        assert!(judgment_check(&Context::Empty(), &converted, &t));
+    }
+
+    #[test]
+    fn ill_typed_terms_are_rejected() {
+        let term = Term::Pair(Box::new(Term::Unit), Box::new(Term::Unit));
+        assert!(cps(&term, &Type::Unit, &Type::Unit).is_none());
+        assert!(anf(&term, &Type::Unit).is_none());
+    }
+}
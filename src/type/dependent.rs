@@ -0,0 +1,1096 @@
+//! A small dependently typed core calculus.
+//!
+//! Terms and types share one syntax, a type is an expression whose type
+//! is a universe. The Pi type `(x : A) -> B` generalizes the function
+//! type to outputs which depend on the input, the Sigma type
+//! `(x : A) * B` generalizes the product to a second component whose
+//! type depends on the first one, and `split` is the eliminator of
+//! Sigma. The simply typed core is the fragment where no type depends on
+//! a term: `embed` maps `A -> B` and `A * B` to the Pi and Sigma types
+//! which don't use `x` and the core terms to the same terms here, so
+//! every example of the core checks again in this calculus. `church`
+//! encodes the core in System F instead, which also covers polymorphic
+//! and existential types.
+//!
+//! Type equality is definitional equality: two expressions are equal
+//! if their normal forms are equal up to renaming of bound variables.
+//! Together with natural numbers and an equality type this is enough
+//! to state simple propositions like `2 + 2 = 4` and check proofs of them.
+//!
+//! Types live in universes `Type0 : Type1 : Type2 ...`, a type of one
+//! universe is also a type of the larger ones. Like in the calculus of
+//! constructions `Type0` is impredicative: a function type into `Type0`
+//! is in `Type0` even if it quantifies over `Type0` itself, so the
+//! polymorphic types of System F stay types of `Type0`. Sigma types live
+//! in the universe of their components. Unlike with `Type : Type` the
+//! calculus is consistent as a logic and well-typed expressions have a
+//! normal form.
+//!
+//! Recursive functions `fix f. e` match on numbers with `case`. They
+//! are only accepted if the `termination` checker shows that every
+//! chain of recursive calls decreases, since a diverging function
+//! would prove anything and loop in the normalizer.
+use std::collections::HashSet;
+use std::fmt;
+
+use super::{normalize_annotations, normalize_type, termination, Effects, Term, Type};
+
+/// Expressions are terms and types at the same time.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Var(String),
+    Universe(usize),                                    // Type0, Type1, ...
+    Pi(String, Box<Expr>, Box<Expr>),                   // (x : A) -> B
+    Lam(String, Box<Expr>),                             // \x. e
+    App(Box<Expr>, Box<Expr>),                          // f a
+    Sigma(String, Box<Expr>, Box<Expr>),                // (x : A) * B
+    Pair(Box<Expr>, Box<Expr>),                         // (a, b)
+    Split(Box<Expr>, String, String, Box<Expr>),        // split p as (x, y) in e
+    Ann(Box<Expr>, Box<Expr>),                          // (e : A)
+    Nat,                                                // natural numbers
+    Zero,                                               // 0
+    Succ(Box<Expr>),                                    // succ n
+    NatRec(Box<Expr>, Box<Expr>, Box<Expr>, Box<Expr>), // natrec motive base step n
+    Eq(Box<Expr>, Box<Expr>, Box<Expr>),                // a =[A] b
+    Refl,                                               // refl : a =[A] a
+    Transport(Box<Expr>, Box<Expr>, Box<Expr>),         // transport P eq (p : P a) : P b
+    Case(Box<Expr>, Box<Expr>, String, Box<Expr>),      // case n of 0 => a | succ k => b
+    Fix(String, Box<Expr>),                             // fix f. e
+}
+
+/// The typing context binds variables to their types.
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    vars: Vec<(String, Expr)>,
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context::default()
+    }
+
+    /// Extend the context with a new variable.
+    pub fn with(&self, name: &str, t: Expr) -> Context {
+        let mut ctx = self.clone();
+        ctx.vars.push((name.into(), t));
+        ctx
+    }
+
+    /// Look up the type of the innermost variable with this name.
+    fn lookup(&self, name: &str) -> Option<&Expr> {
+        self.vars.iter().rev().find(|v| v.0 == name).map(|v| &v.1)
+    }
+
+    fn names(&self) -> HashSet<String> {
+        self.vars.iter().map(|v| v.0.clone()).collect()
+    }
+}
+
+pub fn var(x: &str) -> Expr {
+    Expr::Var(x.into())
+}
+
+pub fn pi(x: &str, a: Expr, b: Expr) -> Expr {
+    Expr::Pi(x.into(), Box::new(a), Box::new(b))
+}
+
+/// The non-dependent function type `A -> B`.
+pub fn arrow(a: Expr, b: Expr) -> Expr {
+    pi("_", a, b)
+}
+
+pub fn lam(x: &str, body: Expr) -> Expr {
+    Expr::Lam(x.into(), Box::new(body))
+}
+
+/// Apply a function to a list of arguments.
+pub fn app(f: Expr, args: Vec<Expr>) -> Expr {
+    args.into_iter()
+        .fold(f, |f, a| Expr::App(Box::new(f), Box::new(a)))
+}
+
+pub fn sigma(x: &str, a: Expr, b: Expr) -> Expr {
+    Expr::Sigma(x.into(), Box::new(a), Box::new(b))
+}
+
+/// The non-dependent product `A * B`.
+pub fn product(a: Expr, b: Expr) -> Expr {
+    sigma("_", a, b)
+}
+
+pub fn pair(a: Expr, b: Expr) -> Expr {
+    Expr::Pair(Box::new(a), Box::new(b))
+}
+
+pub fn split(p: Expr, x: &str, y: &str, body: Expr) -> Expr {
+    Expr::Split(Box::new(p), x.into(), y.into(), Box::new(body))
+}
+
+pub fn ann(e: Expr, t: Expr) -> Expr {
+    Expr::Ann(Box::new(e), Box::new(t))
+}
+
+/// The numeral `n` as `succ (succ ... 0)`.
+pub fn nat(n: usize) -> Expr {
+    (0..n).fold(Expr::Zero, |e, _| Expr::Succ(Box::new(e)))
+}
+
+pub fn nat_rec(motive: Expr, base: Expr, step: Expr, n: Expr) -> Expr {
+    Expr::NatRec(
+        Box::new(motive),
+        Box::new(base),
+        Box::new(step),
+        Box::new(n),
+    )
+}
+
+pub fn eq(t: Expr, a: Expr, b: Expr) -> Expr {
+    Expr::Eq(Box::new(t), Box::new(a), Box::new(b))
+}
+
+pub fn transport(motive: Expr, proof: Expr, e: Expr) -> Expr {
+    Expr::Transport(Box::new(motive), Box::new(proof), Box::new(e))
+}
+
+pub fn case(n: Expr, zero: Expr, k: &str, succ: Expr) -> Expr {
+    Expr::Case(Box::new(n), Box::new(zero), k.into(), Box::new(succ))
+}
+
+pub fn fix(f: &str, body: Expr) -> Expr {
+    Expr::Fix(f.into(), Box::new(body))
+}
+
+/// Collect all free variables of an expression.
+pub fn free_vars(e: &Expr) -> HashSet<String> {
+    fn under(names: &[&String], body: &Expr, bound: &mut Vec<String>, acc: &mut HashSet<String>) {
+        let n = bound.len();
+        bound.extend(names.iter().map(|s| (*s).clone()));
+        go(body, bound, acc);
+        bound.truncate(n);
+    }
+    fn go(e: &Expr, bound: &mut Vec<String>, acc: &mut HashSet<String>) {
+        match e {
+            Expr::Var(x) if !bound.contains(x) => {
+                acc.insert(x.clone());
+            }
+            Expr::Var(_) | Expr::Universe(_) | Expr::Nat | Expr::Zero | Expr::Refl => {}
+            Expr::Pi(x, a, b) | Expr::Sigma(x, a, b) => {
+                go(a, bound, acc);
+                under(&[x], b, bound, acc);
+            }
+            Expr::Lam(x, body) | Expr::Fix(x, body) => under(&[x], body, bound, acc),
+            Expr::Split(p, x, y, body) => {
+                go(p, bound, acc);
+                under(&[x, y], body, bound, acc);
+            }
+            Expr::Case(n, z, k, s) => {
+                go(n, bound, acc);
+                go(z, bound, acc);
+                under(&[k], s, bound, acc);
+            }
+            Expr::App(a, b) | Expr::Pair(a, b) | Expr::Ann(a, b) => {
+                go(a, bound, acc);
+                go(b, bound, acc);
+            }
+            Expr::Succ(n) => go(n, bound, acc),
+            Expr::NatRec(m, z, s, n) => {
+                for e in &[m, z, s, n] {
+                    go(e, bound, acc);
+                }
+            }
+            Expr::Eq(a, b, c) | Expr::Transport(a, b, c) => {
+                for e in &[a, b, c] {
+                    go(e, bound, acc);
+                }
+            }
+        }
+    }
+    let mut acc = HashSet::new();
+    go(e, &mut Vec::new(), &mut acc);
+    acc
+}
+
+/// Find a variant of `x` which is not contained in `avoid`.
+fn fresh(x: &str, avoid: &HashSet<String>) -> String {
+    let mut name = x.to_string();
+    while avoid.contains(&name) {
+        name.push('\'');
+    }
+    name
+}
+
+/// Substitute `v` for `x` below the binder `y`. The binder is renamed
+/// if it would capture a free variable of `v`.
+fn subst_binder(y: &str, body: &Expr, x: &str, v: &Expr) -> (String, Expr) {
+    if y == x {
+        return (y.into(), body.clone());
+    }
+    let fv = free_vars(v);
+    if fv.contains(y) {
+        let mut avoid = fv;
+        avoid.extend(free_vars(body));
+        avoid.insert(x.into());
+        let renamed = fresh(y, &avoid);
+        let body = subst(body, y, &var(&renamed));
+        (renamed.clone(), subst(&body, x, v))
+    } else {
+        (y.into(), subst(body, x, v))
+    }
+}
+
+/// Capture-avoiding substitution of `v` for the free variable `x`.
+pub fn subst(e: &Expr, x: &str, v: &Expr) -> Expr {
+    let s = |e: &Expr| Box::new(subst(e, x, v));
+    match e {
+        Expr::Var(y) if y == x => v.clone(),
+        Expr::Var(_) | Expr::Universe(_) | Expr::Nat | Expr::Zero | Expr::Refl => e.clone(),
+        Expr::Pi(y, a, b) => {
+            let (y, b) = subst_binder(y, b, x, v);
+            Expr::Pi(y, s(a), Box::new(b))
+        }
+        Expr::Sigma(y, a, b) => {
+            let (y, b) = subst_binder(y, b, x, v);
+            Expr::Sigma(y, s(a), Box::new(b))
+        }
+        Expr::Lam(y, body) => {
+            let (y, body) = subst_binder(y, body, x, v);
+            Expr::Lam(y, Box::new(body))
+        }
+        Expr::Fix(y, body) => {
+            let (y, body) = subst_binder(y, body, x, v);
+            Expr::Fix(y, Box::new(body))
+        }
+        Expr::Case(n, z, k, succ) => {
+            let (k, succ) = subst_binder(k, succ, x, v);
+            Expr::Case(s(n), s(z), k, Box::new(succ))
+        }
+        Expr::Split(p, a, b, body) => {
+            // treat the two binders as nested ones, but keep them apart
+            if a == x || b == x {
+                return Expr::Split(s(p), a.clone(), b.clone(), body.clone());
+            }
+            let mut avoid = free_vars(v);
+            avoid.extend(free_vars(body));
+            avoid.insert(x.into());
+            let rename = |n: &String, other: &String, body: Expr| {
+                if free_vars(v).contains(n) {
+                    let mut avoid = avoid.clone();
+                    avoid.insert(other.clone());
+                    let m = fresh(n, &avoid);
+                    let body = subst(&body, n, &var(&m));
+                    (m, body)
+                } else {
+                    (n.clone(), body)
+                }
+            };
+            let (a2, body) = rename(a, b, (**body).clone());
+            let (b2, body) = rename(b, &a2, body);
+            Expr::Split(s(p), a2, b2, Box::new(subst(&body, x, v)))
+        }
+        Expr::App(f, a) => Expr::App(s(f), s(a)),
+        Expr::Pair(a, b) => Expr::Pair(s(a), s(b)),
+        Expr::Ann(e, t) => Expr::Ann(s(e), s(t)),
+        Expr::Succ(n) => Expr::Succ(s(n)),
+        Expr::NatRec(m, z, st, n) => Expr::NatRec(s(m), s(z), s(st), s(n)),
+        Expr::Eq(t, a, b) => Expr::Eq(s(t), s(a), s(b)),
+        Expr::Transport(m, p, e) => Expr::Transport(s(m), s(p), s(e)),
+    }
+}
+
+/// Substitute `a` for `x` and `b` for `y` at the same time.
+fn subst2(e: &Expr, x: &str, a: &Expr, y: &str, b: &Expr) -> Expr {
+    if x == y {
+        return subst(e, y, b);
+    }
+    let mut avoid = free_vars(e);
+    avoid.extend(free_vars(a));
+    avoid.extend(free_vars(b));
+    avoid.insert(y.into());
+    let tmp = fresh(x, &avoid);
+    let e = subst(e, x, &var(&tmp));
+    subst(&subst(&e, y, b), &tmp, a)
+}
+
+/// Reduce an expression to its normal form. Well-typed expressions
+/// are expected to terminate.
+pub fn normalize(e: &Expr) -> Expr {
+    let n = |e: &Expr| Box::new(normalize(e));
+    match e {
+        Expr::Var(_) | Expr::Universe(_) | Expr::Nat | Expr::Zero | Expr::Refl => e.clone(),
+        Expr::Pi(x, a, b) => Expr::Pi(x.clone(), n(a), n(b)),
+        Expr::Sigma(x, a, b) => Expr::Sigma(x.clone(), n(a), n(b)),
+        Expr::Lam(x, body) => Expr::Lam(x.clone(), n(body)),
+        Expr::App(f, a) => match normalize(f) {
+            Expr::Lam(x, body) => normalize(&subst(&body, &x, a)),
+            Expr::Fix(x, body) => {
+                let unfolded = subst(&body, &x, &Expr::Fix(x.clone(), body.clone()));
+                normalize(&Expr::App(Box::new(unfolded), a.clone()))
+            }
+            f => Expr::App(Box::new(f), n(a)),
+        },
+        Expr::Pair(a, b) => Expr::Pair(n(a), n(b)),
+        Expr::Split(p, x, y, body) => match normalize(p) {
+            Expr::Pair(a, b) => normalize(&subst2(body, x, &a, y, &b)),
+            p => Expr::Split(Box::new(p), x.clone(), y.clone(), n(body)),
+        },
+        Expr::Ann(e, _) => normalize(e),
+        Expr::Succ(k) => Expr::Succ(n(k)),
+        Expr::NatRec(m, z, s, k) => match normalize(k) {
+            Expr::Zero => normalize(z),
+            Expr::Succ(k) => normalize(&app(
+                (**s).clone(),
+                vec![
+                    (*k).clone(),
+                    nat_rec((**m).clone(), (**z).clone(), (**s).clone(), *k),
+                ],
+            )),
+            k => Expr::NatRec(n(m), n(z), n(s), Box::new(k)),
+        },
+        Expr::Eq(t, a, b) => Expr::Eq(n(t), n(a), n(b)),
+        Expr::Transport(m, p, e) => match normalize(p) {
+            Expr::Refl => normalize(e),
+            p => Expr::Transport(n(m), Box::new(p), n(e)),
+        },
+        Expr::Case(k, z, x, s) => match normalize(k) {
+            Expr::Zero => normalize(z),
+            Expr::Succ(k) => normalize(&subst(s, x, &k)),
+            // a branch which calls an unfolded recursive function
+            // is left alone, normalizing it would unfold it forever
+            k => {
+                let branch = |e: &Expr| match unfolded(e) {
+                    true => Box::new(e.clone()),
+                    false => n(e),
+                };
+                Expr::Case(Box::new(k), branch(z), x.clone(), branch(s))
+            }
+        },
+        Expr::Fix(f, body) => Expr::Fix(f.clone(), n(body)),
+    }
+}
+
+/// Test if an expression contains a recursive function.
+fn unfolded(e: &Expr) -> bool {
+    match e {
+        Expr::Fix(_, _) => true,
+        Expr::Var(_) | Expr::Universe(_) | Expr::Nat | Expr::Zero | Expr::Refl => false,
+        Expr::Lam(_, a) | Expr::Succ(a) => unfolded(a),
+        Expr::Pi(_, a, b)
+        | Expr::Sigma(_, a, b)
+        | Expr::Split(a, _, _, b)
+        | Expr::App(a, b)
+        | Expr::Pair(a, b)
+        | Expr::Ann(a, b) => unfolded(a) || unfolded(b),
+        Expr::Eq(a, b, c) | Expr::Transport(a, b, c) | Expr::Case(a, b, _, c) => {
+            unfolded(a) || unfolded(b) || unfolded(c)
+        }
+        Expr::NatRec(a, b, c, d) => unfolded(a) || unfolded(b) || unfolded(c) || unfolded(d),
+    }
+}
+
+/// Test if two expressions are equal up to renaming of bound variables.
+pub fn alpha_equal(a: &Expr, b: &Expr) -> bool {
+    /// Variables are equal if they refer to the same binder
+    /// or are both free with the same name.
+    fn bound_at(env: &[(String, String)], x: &str, left: bool) -> Option<usize> {
+        env.iter()
+            .rposition(|p| if left { p.0 == x } else { p.1 == x })
+    }
+    fn under(
+        xs: &[(&String, &String)],
+        a: &Expr,
+        b: &Expr,
+        env: &mut Vec<(String, String)>,
+    ) -> bool {
+        let n = env.len();
+        env.extend(xs.iter().map(|p| (p.0.clone(), p.1.clone())));
+        let r = go(a, b, env);
+        env.truncate(n);
+        r
+    }
+    fn go(a: &Expr, b: &Expr, env: &mut Vec<(String, String)>) -> bool {
+        match (a, b) {
+            (Expr::Var(x), Expr::Var(y)) => {
+                match (bound_at(env, x, true), bound_at(env, y, false)) {
+                    (Some(i), Some(j)) => i == j,
+                    (None, None) => x == y,
+                    _ => false,
+                }
+            }
+            (Expr::Universe(i), Expr::Universe(j)) => i == j,
+            (&Expr::Nat, &Expr::Nat) | (&Expr::Zero, &Expr::Zero) | (&Expr::Refl, &Expr::Refl) => {
+                true
+            }
+            (Expr::Pi(x, a1, b1), Expr::Pi(y, a2, b2))
+            | (Expr::Sigma(x, a1, b1), Expr::Sigma(y, a2, b2)) => {
+                go(a1, a2, env) && under(&[(x, y)], b1, b2, env)
+            }
+            (Expr::Lam(x, a), Expr::Lam(y, b)) | (Expr::Fix(x, a), Expr::Fix(y, b)) => {
+                under(&[(x, y)], a, b, env)
+            }
+            (Expr::Case(n1, z1, x, s1), Expr::Case(n2, z2, y, s2)) => {
+                go(n1, n2, env) && go(z1, z2, env) && under(&[(x, y)], s1, s2, env)
+            }
+            (Expr::Split(p1, x1, y1, a), Expr::Split(p2, x2, y2, b)) => {
+                go(p1, p2, env) && under(&[(x1, x2), (y1, y2)], a, b, env)
+            }
+            (Expr::App(f1, a1), Expr::App(f2, a2))
+            | (Expr::Pair(f1, a1), Expr::Pair(f2, a2))
+            | (Expr::Ann(f1, a1), Expr::Ann(f2, a2)) => go(f1, f2, env) && go(a1, a2, env),
+            (Expr::Succ(a), Expr::Succ(b)) => go(a, b, env),
+            (Expr::NatRec(m1, z1, s1, n1), Expr::NatRec(m2, z2, s2, n2)) => {
+                go(m1, m2, env) && go(z1, z2, env) && go(s1, s2, env) && go(n1, n2, env)
+            }
+            (Expr::Eq(t1, a1, b1), Expr::Eq(t2, a2, b2))
+            | (Expr::Transport(t1, a1, b1), Expr::Transport(t2, a2, b2)) => {
+                go(t1, t2, env) && go(a1, a2, env) && go(b1, b2, env)
+            }
+            _ => false,
+        }
+    }
+    go(a, b, &mut Vec::new())
+}
+
+/// Definitional equality: both expressions have the same normal form.
+pub fn definitional_equality(a: &Expr, b: &Expr) -> bool {
+    alpha_equal(&normalize(a), &normalize(b))
+}
+
+/// Pick a name for a binder introduced while checking which
+/// doesn't clash with the context or the free variables of `e`.
+fn fresh_for(x: &str, g: &Context, e: &Expr) -> String {
+    let mut avoid = g.names();
+    avoid.extend(free_vars(e));
+    fresh(x, &avoid)
+}
+
+/// The universe a type lives in, `None` if the expression is no type.
+fn level(g: &Context, t: &Expr) -> Option<usize> {
+    match normalize(&judgment_infer(g, t)?) {
+        Expr::Universe(i) => Some(i),
+        _ => None,
+    }
+}
+
+/// Check that `m` is a family of types indexed by `a`, like the motive
+/// of an eliminator. The types may live in any universe.
+fn family(g: &Context, m: &Expr, a: &Expr) -> bool {
+    match m {
+        Expr::Lam(x, body) => {
+            let x2 = fresh_for(x, g, a);
+            level(&g.with(&x2, a.clone()), &subst(body, x, &var(&x2))).is_some()
+        }
+        _ => match judgment_infer(g, m).map(|t| normalize(&t)) {
+            Some(Expr::Pi(_, dom, cod)) => {
+                definitional_equality(&dom, a) && matches!(*cod, Expr::Universe(_))
+            }
+            _ => false,
+        },
+    }
+}
+
+/// Infer the type of an expression. Lambdas, pairs and `refl` have
+/// no inferable type and must be checked or annotated.
+pub fn judgment_infer(g: &Context, e: &Expr) -> Option<Expr> {
+    match e {
+        Expr::Var(x) => g.lookup(x).cloned(),
+        Expr::Universe(i) => Some(Expr::Universe(i + 1)),
+        Expr::Nat => Some(Expr::Universe(0)),
+        // a function type into `Type0` is in `Type0` whatever its domain
+        // is, the other types live in the larger universe of their parts
+        Expr::Pi(x, a, b) | Expr::Sigma(x, a, b) => {
+            let x2 = fresh_for(x, g, a);
+            let i = level(g, a)?;
+            let j = level(&g.with(&x2, (**a).clone()), &subst(b, x, &var(&x2)))?;
+            match e {
+                Expr::Pi(_, _, _) if j == 0 => Some(Expr::Universe(0)),
+                _ => Some(Expr::Universe(i.max(j))),
+            }
+        }
+        Expr::App(f, a) => match normalize(&judgment_infer(g, f)?) {
+            Expr::Pi(x, dom, cod) if judgment_check(g, a, &dom) => Some(subst(&cod, &x, a)),
+            _ => None,
+        },
+        Expr::Ann(e, t) if level(g, t).is_some() && judgment_check(g, e, t) => Some((**t).clone()),
+        Expr::Zero => Some(Expr::Nat),
+        Expr::Succ(n) if judgment_check(g, n, &Expr::Nat) => Some(Expr::Nat),
+        Expr::NatRec(m, z, s, n) => {
+            let k = fresh_for("k", g, m);
+            let motive_at = |n: Expr| app((**m).clone(), vec![n]);
+            let step = pi(
+                &k,
+                Expr::Nat,
+                arrow(motive_at(var(&k)), motive_at(Expr::Succ(Box::new(var(&k))))),
+            );
+            if family(g, m, &Expr::Nat)
+                && judgment_check(g, n, &Expr::Nat)
+                && judgment_check(g, z, &motive_at(Expr::Zero))
+                && judgment_check(g, s, &step)
+            {
+                Some(motive_at((**n).clone()))
+            } else {
+                None
+            }
+        }
+        Expr::Eq(t, a, b) => {
+            if level(g, t).is_some() && judgment_check(g, a, t) && judgment_check(g, b, t) {
+                Some(Expr::Universe(0))
+            } else {
+                None
+            }
+        }
+        // a case infers the type of its zero branch, which the other one
+        // must have as well
+        Expr::Case(n, z, k, s) if judgment_check(g, n, &Expr::Nat) => {
+            let t = judgment_infer(g, z)?;
+            let k2 = fresh_for(k, g, &t);
+            let s = subst(s, k, &var(&k2));
+            match judgment_check(&g.with(&k2, Expr::Nat), &s, &t) {
+                true => Some(t),
+                false => None,
+            }
+        }
+        Expr::Transport(m, p, e) => match normalize(&judgment_infer(g, p)?) {
+            Expr::Eq(t, a, b) => {
+                let motive_at = |x: Expr| app((**m).clone(), vec![x]);
+                if family(g, m, &t) && judgment_check(g, e, &motive_at(*a)) {
+                    Some(motive_at(*b))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Check an expression against a type.
+pub fn judgment_check(g: &Context, e: &Expr, t: &Expr) -> bool {
+    match (e, normalize(t)) {
+        (Expr::Lam(x, body), Expr::Pi(y, dom, cod)) => {
+            let x2 = fresh_for(x, g, &dom);
+            let body = subst(body, x, &var(&x2));
+            judgment_check(&g.with(&x2, *dom), &body, &subst(&cod, &y, &var(&x2)))
+        }
+        (Expr::Pair(a, b), Expr::Sigma(y, fst, snd)) => {
+            judgment_check(g, a, &fst) && judgment_check(g, b, &subst(&snd, &y, a))
+        }
+        (Expr::Refl, Expr::Eq(_, a, b)) => definitional_equality(&a, &b),
+        // Split is the eliminator of Sigma. If the pair is a variable
+        // the expected type may depend on it and learns its shape.
+        (Expr::Split(p, x, y, body), t) => match judgment_infer(g, p).map(|t| normalize(&t)) {
+            Some(Expr::Sigma(z, fst, snd)) => {
+                let x2 = fresh_for(x, g, &t);
+                let y2 = fresh_for(y, &g.with(&x2, Expr::Universe(0)), &t);
+                let body = subst2(body, x, &var(&x2), y, &var(&y2));
+                let ctx = g
+                    .with(&x2, (*fst).clone())
+                    .with(&y2, subst(&snd, &z, &var(&x2)));
+                let t = match **p {
+                    Expr::Var(ref v) => subst(&t, v, &pair(var(&x2), var(&y2))),
+                    _ => t,
+                };
+                judgment_check(&ctx, &body, &t)
+            }
+            _ => false,
+        },
+        // Case is the non-recursive eliminator of Nat, the expected type
+        // learns the shape of a variable like for Split.
+        (Expr::Case(n, z, k, s), t) => {
+            let k2 = fresh_for(k, g, &t);
+            let s = subst(s, k, &var(&k2));
+            let at = |shape: Expr| match **n {
+                Expr::Var(ref v) => subst(&t, v, &shape),
+                _ => t.clone(),
+            };
+            judgment_check(g, n, &Expr::Nat)
+                && judgment_check(g, z, &at(Expr::Zero))
+                && judgment_check(
+                    &g.with(&k2, Expr::Nat),
+                    &s,
+                    &at(Expr::Succ(Box::new(var(&k2)))),
+                )
+        }
+        // a recursive function is checked with itself in the context,
+        // after it is shown to terminate
+        (Expr::Fix(f, body), t) => {
+            let f2 = fresh_for(f, g, &t);
+            let body = subst(body, f, &var(&f2));
+            termination::check(e).is_ok() && judgment_check(&g.with(&f2, t.clone()), &body, &t)
+        }
+        (e, t) => match judgment_infer(g, e).map(|t| normalize(&t)) {
+            // universes are cumulative, a type in `Type0` is one in `Type1`
+            Some(Expr::Universe(i)) => matches!(t, Expr::Universe(j) if i <= j),
+            Some(inferred) => alpha_equal(&inferred, &t),
+            None => false,
+        },
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        /// Wrap everything except atoms into parens.
+        fn atom(e: &Expr) -> String {
+            match e {
+                Expr::Var(_) | Expr::Universe(_) | Expr::Nat | Expr::Zero | Expr::Refl => {
+                    format!("{}", e)
+                }
+                _ => format!("({})", e),
+            }
+        }
+        match self {
+            Expr::Var(x) => write!(f, "{}", x),
+            Expr::Universe(i) => write!(f, "Type{}", i),
+            Expr::Pi(x, a, b) if x == "_" => write!(f, "{} -> {}", atom(a), b),
+            Expr::Pi(x, a, b) => write!(f, "({} : {}) -> {}", x, a, b),
+            Expr::Lam(x, body) => write!(f, "\\{}. {}", x, body),
+            Expr::App(a, b) => match **a {
+                Expr::App(_, _) => write!(f, "{} {}", a, atom(b)),
+                _ => write!(f, "{} {}", atom(a), atom(b)),
+            },
+            Expr::Sigma(x, a, b) if x == "_" => write!(f, "{} * {}", atom(a), atom(b)),
+            Expr::Sigma(x, a, b) => write!(f, "({} : {}) * {}", x, a, atom(b)),
+            Expr::Pair(a, b) => write!(f, "({}, {})", a, b),
+            Expr::Split(p, x, y, body) => {
+                write!(f, "split {} as ({}, {}) in {}", p, x, y, body)
+            }
+            Expr::Ann(e, t) => write!(f, "{} : {}", atom(e), t),
+            Expr::Nat => write!(f, "Nat"),
+            Expr::Zero => write!(f, "0"),
+            Expr::Succ(n) => write!(f, "succ {}", atom(n)),
+            Expr::NatRec(m, z, s, n) => {
+                write!(f, "natrec {} {} {} {}", atom(m), atom(z), atom(s), atom(n))
+            }
+            Expr::Eq(t, a, b) => write!(f, "{} =[{}] {}", atom(a), t, atom(b)),
+            Expr::Refl => write!(f, "refl"),
+            Expr::Transport(m, p, e) => {
+                write!(f, "transport {} {} {}", atom(m), atom(p), atom(e))
+            }
+            Expr::Case(n, z, k, s) => {
+                write!(f, "case {} of 0 => {} | succ {} => {}", n, z, k, s)
+            }
+            Expr::Fix(x, body) => write!(f, "fix {}. {}", x, body),
+        }
+    }
+}
+
+/// Addition defined by recursion on the first argument.
+/// plus = \m. \n. natrec (\_. Nat) n (\k. \r. succ r) m
+pub fn plus() -> Expr {
+    ann(
+        lam(
+            "m",
+            lam(
+                "n",
+                nat_rec(
+                    lam("_", Expr::Nat),
+                    var("n"),
+                    lam("k", lam("r", Expr::Succ(Box::new(var("r"))))),
+                    var("m"),
+                ),
+            ),
+        ),
+        arrow(Expr::Nat, arrow(Expr::Nat, Expr::Nat)),
+    )
+}
+
+/// Congruence of equality derived from transport.
+/// cong : (f : Nat -> Nat) -> (a : Nat) -> (b : Nat) -> a = b -> f a = f b
+pub fn cong() -> Expr {
+    let nat_eq = |a: Expr, b: Expr| eq(Expr::Nat, a, b);
+    ann(
+        lam(
+            "f",
+            lam(
+                "a",
+                lam(
+                    "b",
+                    lam(
+                        "e",
+                        transport(
+                            lam(
+                                "y",
+                                nat_eq(
+                                    app(var("f"), vec![var("a")]),
+                                    app(var("f"), vec![var("y")]),
+                                ),
+                            ),
+                            var("e"),
+                            Expr::Refl,
+                        ),
+                    ),
+                ),
+            ),
+        ),
+        pi(
+            "f",
+            arrow(Expr::Nat, Expr::Nat),
+            pi(
+                "a",
+                Expr::Nat,
+                pi(
+                    "b",
+                    Expr::Nat,
+                    arrow(
+                        nat_eq(var("a"), var("b")),
+                        nat_eq(app(var("f"), vec![var("a")]), app(var("f"), vec![var("b")])),
+                    ),
+                ),
+            ),
+        ),
+    )
+}
+
+/// The base types of the simply typed core, its unit type and value,
+/// followed by the variables of a core context.
+pub fn embed_context(g: &super::Context) -> Option<Context> {
+    match g {
+        super::Context::Empty() => Some(
+            Context::new()
+                .with("Foo", Expr::Universe(0))
+                .with("Bar", Expr::Universe(0))
+                .with("Baz", Expr::Universe(0))
+                .with("Unit", Expr::Universe(0))
+                .with("unit", var("Unit")),
+        ),
+        super::Context::Snoc(rest, x, t) => Some(embed_context(rest)?.with(x, embed_type(t)?)),
+    }
+}
+
+/// A type of the simply typed core in this calculus, the function type
+/// is the Pi type and the product the Sigma type whose second part
+/// doesn't depend on the first one. Only pure functions, products and
+/// the base types have a counterpart.
+pub fn embed_type(t: &Type) -> Option<Expr> {
+    Some(match normalize_type(t) {
+        Type::Foo => var("Foo"),
+        Type::Bar => var("Bar"),
+        Type::Baz => var("Baz"),
+        Type::Unit => var("Unit"),
+        Type::Fn(a, b, e) if e == Effects::pure() => arrow(embed_type(&a)?, embed_type(&b)?),
+        Type::Sum(a, b) => product(embed_type(&a)?, embed_type(&b)?),
+        _ => return None,
+    })
+}
+
+/// A term of type `t` of the simply typed core in this calculus, where
+/// `split` is the eliminator of Sigma. `None` if the term leaves the
+/// fragment of `embed_type` or doesn't fit its type.
+pub fn embed(term: &Term, t: &Type) -> Option<Expr> {
+    embed_term(&normalize_annotations(term), &normalize_type(t))
+}
+
+fn embed_term(term: &Term, t: &Type) -> Option<Expr> {
+    // functions and pairs which are taken apart need their type
+    let inferable = |term: &Term, t: &Type| match term {
+        Term::Var(x) => Some(var(x)),
+        _ => Some(ann(embed_term(term, t)?, embed_type(t)?)),
+    };
+    match (term, t) {
+        (Term::Var(x), _) => Some(var(x)),
+        (Term::Lam(x, body), Type::Fn(_, b, _)) => Some(lam(x, embed_term(body, b)?)),
+        (Term::App(f, a, a_type), _) => {
+            let fun = Type::Fn(
+                Box::new(a_type.clone()),
+                Box::new(t.clone()),
+                Effects::pure(),
+            );
+            Some(app(inferable(f, &fun)?, vec![embed_term(a, a_type)?]))
+        }
+        (Term::Pair(a, b), Type::Sum(l, r)) => Some(pair(embed_term(a, l)?, embed_term(b, r)?)),
+        (Term::Split(p, x, a, y, b, body), _) => {
+            let pair = Type::Sum(Box::new(a.clone()), Box::new(b.clone()));
+            Some(split(inferable(p, &pair)?, x, y, embed_term(body, t)?))
+        }
+        (&Term::Unit, &Type::Unit) => Some(var("unit")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A context with the base types of the simple calculus.
+    fn base() -> Context {
+        Context::new()
+            .with("Foo", Expr::Universe(0))
+            .with("Bar", Expr::Universe(0))
+            .with("Baz", Expr::Universe(0))
+    }
+
+    fn nat_eq(a: Expr, b: Expr) -> Expr {
+        eq(Expr::Nat, a, b)
+    }
+
+    fn add(a: Expr, b: Expr) -> Expr {
+        app(plus(), vec![a, b])
+    }
+
+    #[test]
+    fn simple_types_are_special_cases() {
+        // the simply typed identity and uncurrying still check
+        let g = base();
+        let t = arrow(var("Foo"), var("Foo"));
+        assert!(judgment_check(&g, &lam("x", var("x")), &t));
+
+        let term = lam(
+            "f",
+            lam(
+                "p",
+                split(var("p"), "x", "y", app(var("f"), vec![var("x"), var("y")])),
+            ),
+        );
+        let t = arrow(
+            arrow(var("Foo"), arrow(var("Bar"), var("Baz"))),
+            arrow(product(var("Foo"), var("Bar")), var("Baz")),
+        );
+        assert!(judgment_check(&g, &term, &t));
+        assert!(!judgment_check(&g, &term, &arrow(var("Foo"), var("Foo"))));
+    }
+
+    #[test]
+    fn the_simply_typed_core_embeds() {
+        let g = embed_context(&super::super::Context::Empty()).unwrap();
+        for (name, term, t) in super::super::examples() {
+            let e = embed(&term, &t).unwrap();
+            let et = embed_type(&t).unwrap();
+            assert!(judgment_check(&g, &et, &Expr::Universe(0)), "{}", name);
+            assert!(judgment_check(&g, &e, &et), "{}: {}", name, e);
+        }
+        // split is the eliminator of the Sigma type
+        let swap = Term::lam(
+            "p",
+            Term::Split(
+                Box::new(Term::var("p")),
+                "x".into(),
+                Type::Foo,
+                "y".into(),
+                Type::Bar,
+                Box::new(Term::pair(Term::var("y"), Term::var("x"))),
+            ),
+        );
+        let t = Type::arrow(
+            Type::product(Type::Foo, Type::Bar),
+            Type::product(Type::Bar, Type::Foo),
+        );
+        let e = embed(&swap, &t).unwrap();
+        assert_eq!(e.to_string(), "\\p. split p as (x, y) in (y, x)");
+        assert_eq!(
+            embed_type(&t).unwrap().to_string(),
+            "(Foo * Bar) -> Bar * Foo"
+        );
+        assert!(judgment_check(&g, &e, &embed_type(&t).unwrap()));
+        let wrong = Type::arrow(
+            Type::product(Type::Foo, Type::Bar),
+            Type::product(Type::Foo, Type::Bar),
+        );
+        let e = embed(&swap, &wrong).unwrap();
+        assert!(!judgment_check(&g, &e, &embed_type(&wrong).unwrap()));
+    }
+
+    #[test]
+    fn polymorphic_identity() {
+        let t = pi("A", Expr::Universe(0), arrow(var("A"), var("A")));
+        let id = lam("A", lam("x", var("x")));
+        assert!(judgment_check(&Context::new(), &id, &t));
+
+        // instantiating it yields the simple identity type
+        let g = base();
+        let inst = app(ann(id, t), vec![var("Foo")]);
+        let inferred = judgment_infer(&g, &inst).unwrap();
+        assert!(definitional_equality(
+            &inferred,
+            &arrow(var("Foo"), var("Foo"))
+        ));
+    }
+
+    #[test]
+    fn dependent_projections() {
+        // (A : Type0) -> (B : A -> Type0) -> ((x : A) * B x) -> A
+        let family = |body| {
+            pi(
+                "A",
+                Expr::Universe(0),
+                pi("B", arrow(var("A"), Expr::Universe(0)), body),
+            )
+        };
+        let dep = sigma("x", var("A"), app(var("B"), vec![var("x")]));
+        let fst = lam("A", lam("B", lam("p", split(var("p"), "x", "y", var("x")))));
+        let fst_t = family(arrow(dep.clone(), var("A")));
+        assert!(judgment_check(&Context::new(), &fst, &fst_t));
+
+        // the type of the second projection mentions the first one
+        let snd = lam("A", lam("B", lam("p", split(var("p"), "x", "y", var("y")))));
+        let snd_t = family(pi(
+            "p",
+            dep,
+            app(
+                var("B"),
+                vec![app(
+                    ann(fst.clone(), fst_t.clone()),
+                    vec![var("A"), var("B"), var("p")],
+                )],
+            ),
+        ));
+        assert!(judgment_check(&Context::new(), &snd, &snd_t));
+
+        // a dependent pair of a number and a proof about it
+        let t = sigma("n", Expr::Nat, nat_eq(add(var("n"), nat(1)), nat(3)));
+        assert!(judgment_check(
+            &Context::new(),
+            &pair(nat(2), Expr::Refl),
+            &t
+        ));
+        assert!(!judgment_check(
+            &Context::new(),
+            &pair(nat(1), Expr::Refl),
+            &t
+        ));
+    }
+
+    #[test]
+    fn arithmetic_by_computation() {
+        let g = Context::new();
+        // 2 + 2 = 4
+        assert!(judgment_check(
+            &g,
+            &Expr::Refl,
+            &nat_eq(add(nat(2), nat(2)), nat(4))
+        ));
+        // 2 + 2 /= 5
+        assert!(!judgment_check(
+            &g,
+            &Expr::Refl,
+            &nat_eq(add(nat(2), nat(2)), nat(5))
+        ));
+        // (n : Nat) -> 0 + n = n holds definitionally
+        let t = pi("n", Expr::Nat, nat_eq(add(nat(0), var("n")), var("n")));
+        assert!(judgment_check(&g, &lam("n", Expr::Refl), &t));
+        // (n : Nat) -> n + 0 = n does not
+        let t = pi("n", Expr::Nat, nat_eq(add(var("n"), nat(0)), var("n")));
+        assert!(!judgment_check(&g, &lam("n", Expr::Refl), &t));
+    }
+
+    #[test]
+    fn proof_by_induction() {
+        // (n : Nat) -> n + 0 = n
+        // by natrec (\k. k + 0 = k) refl (\k. \ih. cong succ (k + 0) k ih) n
+        let motive = lam("k", nat_eq(add(var("k"), nat(0)), var("k")));
+        let step = lam(
+            "k",
+            lam(
+                "ih",
+                app(
+                    cong(),
+                    vec![
+                        lam("z", Expr::Succ(Box::new(var("z")))),
+                        add(var("k"), nat(0)),
+                        var("k"),
+                        var("ih"),
+                    ],
+                ),
+            ),
+        );
+        let proof = lam("n", nat_rec(motive, Expr::Refl, step, var("n")));
+        let t = pi("n", Expr::Nat, nat_eq(add(var("n"), nat(0)), var("n")));
+        assert!(judgment_check(&Context::new(), &proof, &t));
+    }
+
+    #[test]
+    fn transport_along_equality() {
+        // (A : Type0) -> (P : A -> Type0) -> (a : A) -> (b : A) -> a = b -> P a -> P b
+        let t = pi(
+            "A",
+            Expr::Universe(0),
+            pi(
+                "P",
+                arrow(var("A"), Expr::Universe(0)),
+                pi(
+                    "a",
+                    var("A"),
+                    pi(
+                        "b",
+                        var("A"),
+                        arrow(
+                            eq(var("A"), var("a"), var("b")),
+                            arrow(app(var("P"), vec![var("a")]), app(var("P"), vec![var("b")])),
+                        ),
+                    ),
+                ),
+            ),
+        );
+        let term = lam(
+            "A",
+            lam(
+                "P",
+                lam(
+                    "a",
+                    lam(
+                        "b",
+                        lam("e", lam("x", transport(var("P"), var("e"), var("x")))),
+                    ),
+                ),
+            ),
+        );
+        assert!(judgment_check(&Context::new(), &term, &t));
+    }
+
+    #[test]
+    fn universes() {
+        let g = base();
+        let (type0, type1) = (Expr::Universe(0), Expr::Universe(1));
+        assert!(judgment_check(&g, &type0, &type1));
+        assert!(!judgment_check(&g, &type0, &type0));
+        // a type of Type0 is one of Type1 as well
+        assert!(judgment_check(&g, &var("Foo"), &type1));
+        assert!(judgment_check(
+            &g,
+            &arrow(type0.clone(), type0.clone()),
+            &type1
+        ));
+        // polymorphic functions into Type0 are in Type0, pairs are not
+        let id = pi("A", type0.clone(), arrow(var("A"), var("A")));
+        assert!(judgment_check(&g, &id, &type0));
+        let packed = sigma("A", type0.clone(), var("A"));
+        assert!(!judgment_check(&g, &packed, &type0));
+        assert!(judgment_check(&g, &packed, &type1));
+        assert_eq!(judgment_infer(&g, &type1).unwrap().to_string(), "Type2");
+    }
+
+    #[test]
+    fn substitution_avoids_capture() {
+        // (\y. x)[x := y] = \y'. y
+        let e = subst(&lam("y", var("x")), "x", &var("y"));
+        assert!(alpha_equal(&e, &lam("z", var("y"))));
+        assert!(!alpha_equal(&e, &lam("z", var("z"))));
+        // split pairs simultaneously even if the components mention the binders
+        let e = normalize(&split(
+            pair(var("y"), var("x")),
+            "x",
+            "y",
+            pair(var("x"), var("y")),
+        ));
+        assert!(alpha_equal(&e, &pair(var("y"), var("x"))));
+    }
+
+    #[test]
+    fn ill_typed_expressions() {
+        let g = base();
+        // a variable which is not a type can't be used as one
+        let g = g.with("a", var("Foo"));
+        assert!(!judgment_check(
+            &g,
+            &arrow(var("a"), var("Foo")),
+            &Expr::Universe(0)
+        ));
+        // refl can't prove equality of different types
+        assert!(!judgment_check(
+            &g,
+            &Expr::Refl,
+            &eq(Expr::Universe(0), var("Foo"), var("Bar"))
+        ));
+        // unannotated lambdas can't be applied
+        assert!(judgment_infer(&g, &app(lam("x", var("x")), vec![var("a")])).is_none());
+    }
+}
@@ -0,0 +1,56 @@
+//! Effect sets on function types.
+//!
+//! The only effects are exceptions, named by a label. A function type
+//! `A -[E, F]-> B` lists the exceptions a call may raise, a pure
+//! function `A -> B` raises none. A term checks under the effects it
+//! is allowed to perform, so a function which raises `E` only has the
+//! types whose effects contain `E`.
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// A set of exception labels.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Effects(BTreeSet<String>);
+
+impl Effects {
+    /// The empty set of a term which raises nothing.
+    pub fn pure() -> Effects {
+        Effects::default()
+    }
+
+    pub fn of(labels: &[&str]) -> Effects {
+        Effects(labels.iter().map(|l| l.to_string()).collect())
+    }
+
+    pub fn is_pure(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, label: &str) -> bool {
+        self.0.contains(label)
+    }
+
+    /// Test if every effect of `self` is also one of `other`.
+    pub fn within(&self, other: &Effects) -> bool {
+        self.0.is_subset(&other.0)
+    }
+
+    /// The effects of `self` which are not in `other`.
+    pub fn without(&self, other: &Effects) -> Effects {
+        Effects(self.0.difference(&other.0).cloned().collect())
+    }
+
+    /// The effects together with another label.
+    pub fn with(&self, label: &str) -> Effects {
+        let mut effects = self.clone();
+        effects.0.insert(label.into());
+        effects
+    }
+}
+
+impl fmt::Display for Effects {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let labels: Vec<&str> = self.0.iter().map(|l| l.as_str()).collect();
+        write!(f, "{}", labels.join(", "))
+    }
+}
@@ -0,0 +1,819 @@
+//! A small-step call-by-value evaluator for closed terms.
+//!
+//! Terms are reduced by substitution. Since only closed values
+//! are ever substituted no renaming is needed to avoid capture.
+//! References live in a store which maps locations to values.
+//! Casts of the gradual cast calculus check the tag of a dynamic
+//! value when it is used and blame their label if it doesn't fit.
+use std::fmt;
+
+use super::gradual::{ground, is_ground};
+use super::{normalize_type, subst_type_in_term, type_equality, Term, Type};
+
+/// The store holds the values of all allocated cells
+/// together with the type each cell was allocated at.
+#[derive(Debug, Clone, Default)]
+pub struct Store {
+    cells: Vec<(Term, Type)>,
+}
+
+impl Store {
+    pub fn new() -> Store {
+        Store::default()
+    }
+
+    /// The number of allocated cells.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// The value held by a location.
+    pub fn get(&self, l: usize) -> Option<&Term> {
+        self.cells.get(l).map(|c| &c.0)
+    }
+
+    /// The store typing which assigns every location its cell type.
+    pub fn typing(&self) -> Vec<Type> {
+        self.cells.iter().map(|c| c.1.clone()).collect()
+    }
+
+    fn alloc(&mut self, v: Term, t: Type) -> usize {
+        self.cells.push((v, t));
+        self.cells.len() - 1
+    }
+
+    fn write(&mut self, l: usize, v: Term) -> bool {
+        match self.cells.get_mut(l) {
+            Some(cell) => {
+                cell.0 = v;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Test if a term is a value, i.e. it can't be reduced any further.
+pub fn is_value(t: &Term) -> bool {
+    match t {
+        Term::Lam(_, _) | Term::Unit | Term::Loc(_) => true,
+        Term::Pair(a, b) => is_value(a) && is_value(b),
+        Term::Pack(_, t, _) => is_value(t),
+        // a value tagged with its ground type and a wrapped function
+        Term::Cast(v, from, to, _) => {
+            is_value(v)
+                && match (normalize_type(from), normalize_type(to)) {
+                    (from, Type::Dyn) => is_ground(&from),
+                    (Type::Fn(_, _, _), Type::Fn(_, _, _)) => true,
+                    _ => false,
+                }
+        }
+        _ => false,
+    }
+}
+
+/// Replace every free occurrence of `x` in `t` with the closed value `v`.
+pub fn subst(t: &Term, x: &str, v: &Term) -> Term {
+    let s = |t: &Term| Box::new(subst(t, x, v));
+    match t {
+        Term::Var(n) if n == x => v.clone(),
+        Term::Var(_) | Term::Unit | Term::Loc(_) | Term::Blame(_, _, _) | Term::Raise(_) => {
+            t.clone()
+        }
+        Term::Lam(n, _) if n == x => t.clone(),
+        Term::Lam(n, body) => Term::Lam(n.clone(), s(body)),
+        Term::App(f, a, ty) => Term::App(s(f), s(a), ty.clone()),
+        Term::Pair(a, b) => Term::Pair(s(a), s(b)),
+        Term::Split(p, na, ta, nb, tb, body) => {
+            let body = if na == x || nb == x {
+                body.clone()
+            } else {
+                s(body)
+            };
+            Term::Split(s(p), na.clone(), ta.clone(), nb.clone(), tb.clone(), body)
+        }
+        Term::Ref(init, ty) => Term::Ref(s(init), ty.clone()),
+        Term::Deref(cell) => Term::Deref(s(cell)),
+        Term::Assign(cell, value, ty) => Term::Assign(s(cell), s(value), ty.clone()),
+        Term::Pack(w, t, ty) => Term::Pack(w.clone(), s(t), ty.clone()),
+        Term::Unpack(p, ty, x_type, n, body) => {
+            let body = if n == x { body.clone() } else { s(body) };
+            Term::Unpack(s(p), ty.clone(), x_type.clone(), n.clone(), body)
+        }
+        Term::Cast(e, from, to, l) => Term::Cast(s(e), from.clone(), to.clone(), l.clone()),
+        Term::Try(body, n, handler) => Term::Try(s(body), n.clone(), s(handler)),
+    }
+}
+
+/// The blame or exception in evaluation position of a term,
+/// a handler stops the search.
+fn aborted(t: &Term, strategy: Strategy) -> Option<&Term> {
+    fn first<'a>(ts: &[&'a Term], strategy: Strategy) -> Option<&'a Term> {
+        ts.iter()
+            .find(|t| !is_value(t))
+            .and_then(|t| aborted(t, strategy))
+    }
+    match t {
+        Term::Blame(_, _, _) | Term::Raise(_) => Some(t),
+        // a lazy argument is not evaluated before the call
+        Term::App(a, _, _) if strategy != Strategy::CallByValue => first(&[a], strategy),
+        Term::App(a, b, _) | Term::Pair(a, b) | Term::Assign(a, b, _) => first(&[a, b], strategy),
+        Term::Split(a, _, _, _, _, _)
+        | Term::Ref(a, _)
+        | Term::Deref(a)
+        | Term::Pack(_, a, _)
+        | Term::Unpack(a, _, _, _, _)
+        | Term::Cast(a, _, _, _) => first(&[a], strategy),
+        _ => None,
+    }
+}
+
+/// Reduce the cast of a value `v` from `from` to `to`.
+fn cast(v: &Term, from: &Type, to: &Type, l: &str) -> Option<Term> {
+    let (from, to) = (normalize_type(from), normalize_type(to));
+    let cast = |v: Term, from: &Type, to: &Type| {
+        Term::Cast(Box::new(v), from.clone(), to.clone(), l.into())
+    };
+    match (&from, &to) {
+        _ if type_equality(&from, &to) => Some(v.clone()),
+        // tag the value with its ground type first
+        (_, &Type::Dyn) => {
+            let g = ground(&from);
+            Some(cast(cast(v.clone(), &from, &g), &g, &to))
+        }
+        // check the tag, which is the only way a cast fails
+        (&Type::Dyn, _) if is_ground(&to) => match v {
+            Term::Cast(v, tag, _, _) if type_equality(tag, &to) => Some((**v).clone()),
+            Term::Cast(_, tag, _, _) => Some(Term::Blame(l.into(), to.clone(), tag.clone())),
+            _ => None,
+        },
+        (&Type::Dyn, _) => {
+            let g = ground(&to);
+            Some(cast(cast(v.clone(), &from, &g), &g, &to))
+        }
+        (Type::Sum(a1, b1), Type::Sum(a2, b2)) => match v {
+            Term::Pair(a, b) => Some(Term::Pair(
+                Box::new(cast((**a).clone(), a1, a2)),
+                Box::new(cast((**b).clone(), b1, b2)),
+            )),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The evaluation strategy decides when the argument of a call is
+/// evaluated. Pairs, cells and packages are evaluated eagerly by all
+/// of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    /// Before the call.
+    CallByValue,
+    /// Every time it is used.
+    CallByName,
+    /// The first time it is used, the value is shared with the other uses.
+    CallByNeed,
+}
+
+/// The rule a reduction step applies to its redex.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// `(\x. t) v` substitutes the argument for `x`.
+    Beta(String),
+    /// A wrapped function casts its argument and result.
+    CastApp,
+    Split(String, String),
+    Alloc,
+    Deref,
+    Assign,
+    Unpack(String),
+    Cast,
+    /// `try raise E with E => h` runs the handler.
+    Handle(String),
+    /// `try v with E => h` is done.
+    Return,
+    /// Blame or an exception aborts its surroundings.
+    Unwind,
+}
+
+impl Rule {
+    /// The variables the rule substitutes for.
+    pub fn binds(&self) -> Vec<&str> {
+        match self {
+            Rule::Beta(x) | Rule::Unpack(x) => vec![x],
+            Rule::Split(x, y) => vec![x, y],
+            _ => vec![],
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rule::Beta(x) => write!(f, "beta {}", x),
+            Rule::CastApp => write!(f, "cast application"),
+            Rule::Split(x, y) => write!(f, "split {}, {}", x, y),
+            Rule::Alloc => write!(f, "alloc"),
+            Rule::Deref => write!(f, "deref"),
+            Rule::Assign => write!(f, "assign"),
+            Rule::Unpack(x) => write!(f, "unpack {}", x),
+            Rule::Cast => write!(f, "cast"),
+            Rule::Handle(x) => write!(f, "handle {}", x),
+            Rule::Return => write!(f, "return"),
+            Rule::Unwind => write!(f, "unwind"),
+        }
+    }
+}
+
+/// A reduction step, the next term together with the redex, the rule
+/// which reduced it and the path of the redex within the term. Paths
+/// are written like the paths of the `json` module.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub term: Term,
+    pub redex: Term,
+    pub rule: Rule,
+    pub path: String,
+}
+
+impl Step {
+    /// The step of the part `field` put back into its surroundings.
+    fn within(self, field: &str, surround: impl FnOnce(Term) -> Term) -> Step {
+        Step {
+            term: surround(self.term),
+            path: format!("/{}{}", field, self.path),
+            ..self
+        }
+    }
+}
+
+/// Do a single reduction step. The evaluation order is left to right
+/// and `None` is returned if the term is a value or stuck. Blame
+/// and exceptions abort the program up to the next handler, they
+/// are returned as the next term.
+pub fn step(t: &Term, store: &mut Store) -> Option<Term> {
+    reduce(t, store).map(|s| s.term)
+}
+
+/// Do a single reduction step like `step` and tell which redex was
+/// reduced by which rule.
+pub fn reduce(t: &Term, store: &mut Store) -> Option<Step> {
+    reduce_with(t, store, Strategy::CallByValue)
+}
+
+/// Do a single reduction step under an evaluation strategy.
+pub fn reduce_with(t: &Term, store: &mut Store, strategy: Strategy) -> Option<Step> {
+    let here = |term: Term, rule: Rule| Step {
+        term,
+        redex: t.clone(),
+        rule,
+        path: String::new(),
+    };
+    match t {
+        Term::Blame(_, _, _) | Term::Raise(_) => return None,
+        _ => {
+            if let Some(abort) = aborted(t, strategy) {
+                return Some(here(abort.clone(), Rule::Unwind));
+            }
+        }
+    }
+    match t {
+        Term::App(f, a, ty) if !is_value(f) => reduce_with(f, store, strategy)
+            .map(|s| s.within("fun", |f| Term::App(Box::new(f), a.clone(), ty.clone()))),
+        Term::App(f, a, ty) if !is_value(a) && strategy == Strategy::CallByValue => {
+            reduce_with(a, store, strategy)
+                .map(|s| s.within("arg", |a| Term::App(f.clone(), Box::new(a), ty.clone())))
+        }
+        Term::App(f, a, ty) => match **f {
+            // the argument is shared through a thunk, which is
+            // a cell of the store evaluated the first time it is read
+            Term::Lam(ref x, ref body) if !is_value(a) && strategy == Strategy::CallByNeed => {
+                let thunk =
+                    Term::Deref(Box::new(Term::Loc(store.alloc((**a).clone(), ty.clone()))));
+                Some(here(subst(body, x, &thunk), Rule::Beta(x.clone())))
+            }
+            Term::Lam(ref x, ref body) => Some(here(subst(body, x, a), Rule::Beta(x.clone()))),
+            // a wrapped function casts its argument and result
+            Term::Cast(ref f, Type::Fn(ref a1, ref b1, _), Type::Fn(ref a2, ref b2, _), ref l) => {
+                let a = Term::Cast(a.clone(), (**a2).clone(), (**a1).clone(), l.clone());
+                let app = Term::App(f.clone(), Box::new(a), (**a1).clone());
+                let cast = Term::Cast(Box::new(app), (**b1).clone(), (**b2).clone(), l.clone());
+                Some(here(cast, Rule::CastApp))
+            }
+            _ => None,
+        },
+        Term::Pair(a, b) if !is_value(a) => reduce_with(a, store, strategy)
+            .map(|s| s.within("left", |a| Term::Pair(Box::new(a), b.clone()))),
+        Term::Pair(a, b) => reduce_with(b, store, strategy)
+            .map(|s| s.within("right", |b| Term::Pair(a.clone(), Box::new(b)))),
+        Term::Split(p, na, ta, nb, tb, body) if !is_value(p) => reduce_with(p, store, strategy)
+            .map(|s| {
+                s.within("pair", |p| {
+                    Term::Split(
+                        Box::new(p),
+                        na.clone(),
+                        ta.clone(),
+                        nb.clone(),
+                        tb.clone(),
+                        body.clone(),
+                    )
+                })
+            }),
+        Term::Split(p, na, _, nb, _, body) => match **p {
+            // substitute the second component first so it shadows the first
+            Term::Pair(ref a, ref b) => Some(here(
+                subst(&subst(body, nb, b), na, a),
+                Rule::Split(na.clone(), nb.clone()),
+            )),
+            _ => None,
+        },
+        Term::Ref(init, ty) if !is_value(init) => reduce_with(init, store, strategy)
+            .map(|s| s.within("init", |init| Term::Ref(Box::new(init), ty.clone()))),
+        Term::Ref(init, ty) => {
+            let l = store.alloc((**init).clone(), ty.clone());
+            Some(here(Term::Loc(l), Rule::Alloc))
+        }
+        Term::Deref(cell) if !is_value(cell) => reduce_with(cell, store, strategy)
+            .map(|s| s.within("cell", |cell| Term::Deref(Box::new(cell)))),
+        Term::Deref(cell) => match **cell {
+            Term::Loc(l) => match store.get(l).cloned()? {
+                // only a thunk holds a term which can step
+                Term::Raise(_) | Term::Blame(_, _, _) => {
+                    store.get(l).cloned().map(|v| here(v, Rule::Deref))
+                }
+                thunk if !is_value(&thunk) => {
+                    let s = reduce_with(&thunk, store, strategy)?;
+                    store.write(l, s.term);
+                    Some(Step {
+                        term: t.clone(),
+                        path: String::new(),
+                        ..s
+                    })
+                }
+                v => Some(here(v, Rule::Deref)),
+            },
+            _ => None,
+        },
+        Term::Assign(cell, value, ty) if !is_value(cell) => {
+            reduce_with(cell, store, strategy).map(|s| {
+                s.within("cell", |cell| {
+                    Term::Assign(Box::new(cell), value.clone(), ty.clone())
+                })
+            })
+        }
+        Term::Assign(cell, value, ty) if !is_value(value) => reduce_with(value, store, strategy)
+            .map(|s| {
+                s.within("value", |value| {
+                    Term::Assign(cell.clone(), Box::new(value), ty.clone())
+                })
+            }),
+        Term::Assign(cell, value, _) => match **cell {
+            Term::Loc(l) if store.write(l, (**value).clone()) => {
+                Some(here(Term::Unit, Rule::Assign))
+            }
+            _ => None,
+        },
+        Term::Pack(w, t, ty) => reduce_with(t, store, strategy)
+            .map(|s| s.within("body", |t| Term::Pack(w.clone(), Box::new(t), ty.clone()))),
+        Term::Unpack(p, ty, x_type, x, body) if !is_value(p) => reduce_with(p, store, strategy)
+            .map(|s| {
+                s.within("package", |p| {
+                    Term::Unpack(
+                        Box::new(p),
+                        ty.clone(),
+                        x_type.clone(),
+                        x.clone(),
+                        body.clone(),
+                    )
+                })
+            }),
+        Term::Unpack(p, _, x_type, x, body) => match **p {
+            // the hidden type becomes known again
+            Term::Pack(ref w, ref v, _) => Some(here(
+                subst(&subst_type_in_term(body, x_type, w), x, v),
+                Rule::Unpack(x.clone()),
+            )),
+            _ => None,
+        },
+        Term::Cast(e, from, to, l) if !is_value(e) => reduce_with(e, store, strategy).map(|s| {
+            s.within("term", |e| {
+                Term::Cast(Box::new(e), from.clone(), to.clone(), l.clone())
+            })
+        }),
+        Term::Cast(v, from, to, l) => cast(v, from, to, l).map(|v| here(v, Rule::Cast)),
+        Term::Try(body, x, handler) => match **body {
+            Term::Raise(ref y) if x == y => {
+                Some(here((**handler).clone(), Rule::Handle(x.clone())))
+            }
+            Term::Raise(_) | Term::Blame(_, _, _) => Some(here((**body).clone(), Rule::Unwind)),
+            _ if is_value(body) => Some(here((**body).clone(), Rule::Return)),
+            _ => reduce_with(body, store, strategy).map(|s| {
+                s.within("body", |b| {
+                    Term::Try(Box::new(b), x.clone(), handler.clone())
+                })
+            }),
+        },
+        Term::Var(_)
+        | Term::Lam(_, _)
+        | Term::Unit
+        | Term::Loc(_)
+        | Term::Blame(_, _, _)
+        | Term::Raise(_) => None,
+    }
+}
+
+/// A failed cast, its label is the location of the cast.
+#[derive(Debug, Clone)]
+pub struct Blame {
+    pub label: String,
+    pub expected: Type,
+    pub found: Type,
+}
+
+impl fmt::Display for Blame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "blame {}: expected {}, found {}",
+            self.label, self.expected, self.found
+        )
+    }
+}
+
+/// Reduce a term until no further step is possible.
+pub fn eval(t: &Term, store: &mut Store) -> Term {
+    eval_with(t, store, Strategy::CallByValue)
+}
+
+/// Reduce a term under an evaluation strategy until no further step is possible.
+pub fn eval_with(t: &Term, store: &mut Store, strategy: Strategy) -> Term {
+    let mut t = t.clone();
+    while let Some(next) = reduce_with(&t, store, strategy) {
+        t = next.term;
+    }
+    t
+}
+
+/// Reduce a term of the cast calculus to a value or the blame of a failed cast.
+pub fn run(t: &Term, store: &mut Store) -> Result<Term, Blame> {
+    match eval(t, store) {
+        Term::Blame(label, expected, found) => Err(Blame {
+            label,
+            expected,
+            found,
+        }),
+        v => Ok(v),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{judgment_check, judgment_check_store, judgment_store, Context, Effects};
+    use super::*;
+
+    fn var(x: &str) -> Box<Term> {
+        Box::new(Term::Var(x.into()))
+    }
+
+    fn lam(x: &str, body: Term) -> Term {
+        Term::Lam(x.into(), Box::new(body))
+    }
+
+    fn foo_to_foo() -> Type {
+        Type::Fn(Box::new(Type::Foo), Box::new(Type::Foo), Effects::pure())
+    }
+
+    /// Sequencing `a; b` where `a : Unit` encoded as `(\_. b) a`.
+    fn seq(a: Term, b: Term) -> Term {
+        Term::App(Box::new(lam("_", b)), Box::new(a), Type::Unit)
+    }
+
+    /// `let x : t = e in body` encoded as `(\x. body) e`.
+    fn let_in(x: &str, t: Type, e: Term, body: Term) -> Term {
+        Term::App(Box::new(lam(x, body)), Box::new(e), t)
+    }
+
+    /// Test if the store typing `b` extends `a`.
+    fn extends(a: &[Type], b: &[Type]) -> bool {
+        a.len() <= b.len()
+            && a.iter()
+                .zip(b.iter())
+                .all(|(a, b)| super::super::type_equality(a, b))
+    }
+
+    /// Run a closed well-typed program to the end and check progress
+    /// and preservation after every step. Returns the final value.
+    fn run_safely(term: Term, t: &Type) -> (Term, Store) {
+        run_safely_with(term, t, Strategy::CallByValue)
+    }
+
+    fn run_safely_with(term: Term, t: &Type, strategy: Strategy) -> (Term, Store) {
+        let ctx = Context::Empty();
+        let mut store = Store::new();
+        let mut term = term;
+        assert!(judgment_check(&ctx, &term, t));
+        for _ in 0..1000 {
+            let before = store.typing();
+            assert!(judgment_store(&before, &store));
+            assert!(judgment_check_store(&before, &ctx, &term, t));
+            if is_value(&term) {
+                return (term, store);
+            }
+            // progress: a well-typed term which is not a value can step
+            term = reduce_with(&term, &mut store, strategy)
+                .expect("well-typed term is stuck")
+                .term;
+            // preservation: the result has the same type under an extended store typing
+            let after = store.typing();
+            assert!(extends(&before, &after));
+            assert!(judgment_store(&after, &store));
+            assert!(judgment_check_store(&after, &ctx, &term, t));
+        }
+        panic!("program did not terminate");
+    }
+
+    #[test]
+    fn allocate_and_read() {
+        // !(ref [Unit] ()) : Unit
+        let term = Term::Deref(Box::new(Term::Ref(Box::new(Term::Unit), Type::Unit)));
+        let (value, store) = run_safely(term, &Type::Unit);
+        assert!(matches!(value, Term::Unit));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn update_reference() {
+        // let r = ref (\x. x) in r := (\y. (!r) y); !r  : Foo -> Foo
+        let cell = Type::Ref(Box::new(foo_to_foo()));
+        let term = let_in(
+            "r",
+            cell,
+            Term::Ref(Box::new(lam("x", Term::Var("x".into()))), foo_to_foo()),
+            seq(
+                Term::Assign(
+                    var("r"),
+                    Box::new(lam(
+                        "y",
+                        Term::App(Box::new(Term::Deref(var("r"))), var("y"), Type::Foo),
+                    )),
+                    foo_to_foo(),
+                ),
+                Term::Deref(var("r")),
+            ),
+        );
+        let (value, store) = run_safely(term, &foo_to_foo());
+        // the cell now holds the updated function which refers to itself
+        assert!(match (value, store.get(0)) {
+            (Term::Lam(ref y, _), Some(Term::Lam(z, _))) => y == "y" && z == "y",
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn references_to_references() {
+        // let r = ref (ref ()) in let s = !r in s := (); (!r, s) : Ref Unit * Ref Unit
+        let inner = Type::Ref(Box::new(Type::Unit));
+        let outer = Type::Ref(Box::new(inner.clone()));
+        let term = let_in(
+            "r",
+            outer,
+            Term::Ref(
+                Box::new(Term::Ref(Box::new(Term::Unit), Type::Unit)),
+                inner.clone(),
+            ),
+            let_in(
+                "s",
+                inner.clone(),
+                Term::Deref(var("r")),
+                seq(
+                    Term::Assign(var("s"), Box::new(Term::Unit), Type::Unit),
+                    Term::Pair(Box::new(Term::Deref(var("r"))), var("s")),
+                ),
+            ),
+        );
+        let t = Type::Sum(Box::new(inner.clone()), Box::new(inner));
+        let (value, store) = run_safely(term, &t);
+        assert!(match value {
+            Term::Pair(ref a, ref b) => matches!((&**a, &**b), (&Term::Loc(0), &Term::Loc(0))),
+            _ => false,
+        });
+        assert_eq!(store.len(), 2);
+    }
+
+    #[test]
+    fn split_stored_pair() {
+        // split !(ref (ref (), ())) as (x, y) in x := y : Unit
+        let t = Type::Sum(
+            Box::new(Type::Ref(Box::new(Type::Unit))),
+            Box::new(Type::Unit),
+        );
+        let term = Term::Split(
+            Box::new(Term::Deref(Box::new(Term::Ref(
+                Box::new(Term::Pair(
+                    Box::new(Term::Ref(Box::new(Term::Unit), Type::Unit)),
+                    Box::new(Term::Unit),
+                )),
+                t,
+            )))),
+            "x".into(),
+            Type::Ref(Box::new(Type::Unit)),
+            "y".into(),
+            Type::Unit,
+            Box::new(Term::Assign(var("x"), var("y"), Type::Unit)),
+        );
+        run_safely(term, &Type::Unit);
+    }
+
+    #[test]
+    fn ill_typed_references() {
+        let ctx = Context::Empty();
+        // ref [Unit] () : Ref (Foo -> Foo)
+        let term = Term::Ref(Box::new(Term::Unit), Type::Unit);
+        assert!(!judgment_check(
+            &ctx,
+            &term,
+            &Type::Ref(Box::new(foo_to_foo()))
+        ));
+        // () := () : Unit
+        let term = Term::Assign(Box::new(Term::Unit), Box::new(Term::Unit), Type::Unit);
+        assert!(!judgment_check(&ctx, &term, &Type::Unit));
+        // locations outside the store typing are rejected
+        let term = Term::Loc(0);
+        assert!(!judgment_check(
+            &ctx,
+            &term,
+            &Type::Ref(Box::new(Type::Unit))
+        ));
+        assert!(judgment_check_store(
+            &[Type::Unit],
+            &ctx,
+            &term,
+            &Type::Ref(Box::new(Type::Unit))
+        ));
+    }
+
+    #[test]
+    fn unpack_stored_package() {
+        // unpack (pack [Ref Unit, (ref (), \r. !r)] as exists X. X * (X -> Unit))
+        //  as [X, p] in split p as (c, get) in get c : Unit
+        let x = || Type::Var("X".into());
+        let content = |a: Type| {
+            Type::Sum(
+                Box::new(a.clone()),
+                Box::new(Type::Fn(Box::new(a), Box::new(Type::Unit), Effects::pure())),
+            )
+        };
+        let package_type = Type::Exists("X".into(), Box::new(content(x())));
+        let cell = Type::Ref(Box::new(Type::Unit));
+        let package = Term::Pack(
+            cell,
+            Box::new(Term::Pair(
+                Box::new(Term::Ref(Box::new(Term::Unit), Type::Unit)),
+                Box::new(lam("r", Term::Deref(var("r")))),
+            )),
+            package_type.clone(),
+        );
+        let body = Term::Split(
+            var("p"),
+            "c".into(),
+            x(),
+            "get".into(),
+            Type::Fn(Box::new(x()), Box::new(Type::Unit), Effects::pure()),
+            Box::new(Term::App(var("get"), var("c"), x())),
+        );
+        let term = Term::Unpack(
+            Box::new(package),
+            package_type,
+            "X".into(),
+            "p".into(),
+            Box::new(body),
+        );
+        let (value, store) = run_safely(term, &Type::Unit);
+        assert!(matches!(value, Term::Unit));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn exceptions_unwind_to_their_handler() {
+        // try (ref (), try (\u. raise Fail) () with Other => ()) with Fail => (ref (), ())
+        let fail = Term::App(
+            Box::new(lam("u", Term::Raise("Fail".into()))),
+            Box::new(Term::Unit),
+            Type::Unit,
+        );
+        let cell = || Box::new(Term::Ref(Box::new(Term::Unit), Type::Unit));
+        let term = Term::Try(
+            Box::new(Term::Pair(
+                cell(),
+                Box::new(Term::Try(
+                    Box::new(fail),
+                    "Other".into(),
+                    Box::new(Term::Unit),
+                )),
+            )),
+            "Fail".into(),
+            Box::new(Term::Pair(cell(), Box::new(Term::Unit))),
+        );
+        let t = Type::Sum(
+            Box::new(Type::Ref(Box::new(Type::Unit))),
+            Box::new(Type::Unit),
+        );
+        let (value, store) = run_safely(term, &t);
+        // the first cell stays allocated, the handler allocates the second
+        assert!(matches!(value, Term::Pair(ref l, _) if matches!(**l, Term::Loc(1))));
+        assert_eq!(store.len(), 2);
+    }
+
+    #[test]
+    fn strategies_agree_on_terminating_programs() {
+        let id = || lam("y", Term::Var("y".into()));
+        let unit_to_unit = || Type::Fn(Box::new(Type::Unit), Box::new(Type::Unit), Effects::pure());
+        let unit_pair = || Type::Sum(Box::new(Type::Unit), Box::new(Type::Unit));
+        let id_unit = || Term::App(Box::new(id()), Box::new(Term::Unit), Type::Unit);
+        let programs = vec![
+            // (\f. \x. f (f x)) (\y. y) ()
+            (
+                Term::App(
+                    Box::new(Term::App(
+                        Box::new(lam(
+                            "f",
+                            lam(
+                                "x",
+                                Term::App(
+                                    var("f"),
+                                    Box::new(Term::App(var("f"), var("x"), Type::Unit)),
+                                    Type::Unit,
+                                ),
+                            ),
+                        )),
+                        Box::new(id()),
+                        unit_to_unit(),
+                    )),
+                    Box::new(Term::Unit),
+                    Type::Unit,
+                ),
+                Type::Unit,
+            ),
+            // (\x. (x, x)) ((\y. y) ())
+            (
+                let_in("x", Type::Unit, id_unit(), Term::Pair(var("x"), var("x"))),
+                unit_pair(),
+            ),
+            // let p = ((\y. y) (), ()) in split p as (a, b) in (b, a)
+            (
+                let_in(
+                    "p",
+                    unit_pair(),
+                    Term::Pair(Box::new(id_unit()), Box::new(Term::Unit)),
+                    Term::Split(
+                        var("p"),
+                        "a".into(),
+                        Type::Unit,
+                        "b".into(),
+                        Type::Unit,
+                        Box::new(Term::Pair(var("b"), var("a"))),
+                    ),
+                ),
+                unit_pair(),
+            ),
+            // (\g. g ((\y. y) ())) (\x. (x, ()))
+            (
+                let_in(
+                    "g",
+                    Type::Fn(Box::new(Type::Unit), Box::new(unit_pair()), Effects::pure()),
+                    lam("x", Term::Pair(var("x"), Box::new(Term::Unit))),
+                    Term::App(var("g"), Box::new(id_unit()), Type::Unit),
+                ),
+                unit_pair(),
+            ),
+        ];
+        let strategies = [
+            Strategy::CallByValue,
+            Strategy::CallByName,
+            Strategy::CallByNeed,
+        ];
+        for (term, t) in programs {
+            // progress and preservation hold under every strategy, thunks included
+            let values: Vec<String> = strategies
+                .iter()
+                .map(|s| format!("{}", run_safely_with(term.clone(), &t, *s).0))
+                .collect();
+            assert_eq!(values[0], values[1], "{}", term);
+            assert_eq!(values[0], values[2], "{}", term);
+        }
+    }
+
+    #[test]
+    fn ill_typed_terms_get_stuck() {
+        // !() is not well-typed and can't step either
+        let mut store = Store::new();
+        let term = Term::Deref(Box::new(Term::Unit));
+        assert!(!judgment_check(&Context::Empty(), &term, &Type::Unit));
+        assert!(step(&term, &mut store).is_none());
+        assert!(!is_value(&term));
+    }
+}
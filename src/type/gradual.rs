@@ -0,0 +1,352 @@
+//! Gradual typing with the dynamic type `Dyn`.
+//!
+//! A partially annotated term uses `Dyn` where a type is unknown. It
+//! is checked like `judgment_check` but a subterm only needs a type
+//! consistent with the expected one, and wherever the two differ a cast
+//! `<A => B> t` is inserted. The result is a term of the cast calculus
+//! which the static checker accepts. Every cast is labelled with the
+//! path of the subterm it was inserted at, written like the paths of
+//! the `json` module, so a failing cast at runtime blames that place.
+//! Core terms carry no source positions, the elaboration of source files
+//! in `syntax` inserts its casts itself and labels them with the line
+//! and column of the subterm instead.
+use std::fmt;
+
+use super::{
+    consistent, free_in_context, free_in_type, judgment, normalize_type, subst_type, subtype,
+    type_equality, unfold, Context, Effects, Term, Type,
+};
+
+/// Why no casts can make a term fit its type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+fn error<T>(path: &str, message: String) -> Result<T, Error> {
+    Err(Error {
+        path: path.into(),
+        message,
+    })
+}
+
+/// The ground type a value of type `t` is tagged with when it is cast
+/// to `Dyn`, only the outermost constructor and the effects of a
+/// function are kept. References and existentials are invariant,
+/// their ground type is the type itself.
+pub fn ground(t: &Type) -> Type {
+    let dyn_ = || Box::new(Type::Dyn);
+    match unfold(t) {
+        Type::Fn(_, _, e) => Type::Fn(dyn_(), dyn_(), e),
+        Type::Sum(_, _) => Type::Sum(dyn_(), dyn_()),
+        t => t,
+    }
+}
+
+/// Test if a type other than `Dyn` is its own ground type.
+pub fn is_ground(t: &Type) -> bool {
+    match unfold(t) {
+        Type::Dyn => false,
+        Type::Fn(a, b, _) | Type::Sum(a, b) => {
+            matches!((*a, *b), (Type::Dyn, Type::Dyn))
+        }
+        _ => true,
+    }
+}
+
+fn lookup<'a>(g: &'a Context, x: &str) -> Option<&'a Type> {
+    match g {
+        Context::Empty() => None,
+        Context::Snoc(_, name, t) if name == x => Some(t),
+        Context::Snoc(rest, _, _) => lookup(rest, x),
+    }
+}
+
+fn snoc(g: &Context, x: &str, t: &Type) -> Context {
+    Context::Snoc(Box::new(g.clone()), x.into(), t.clone())
+}
+
+/// Turn a term of type `from` into one of type `to`.
+fn coerce(term: Term, from: &Type, to: &Type, path: &str) -> Result<Term, Error> {
+    if type_equality(from, to) {
+        Ok(term)
+    } else if consistent(from, to) {
+        let (from, to) = (normalize_type(from), normalize_type(to));
+        Ok(Term::Cast(Box::new(term), from, to, path.into()))
+    } else {
+        error(path, format!("expected {}, found {}", to, from))
+    }
+}
+
+/// Insert the casts which make a term check against `t` under `g`.
+pub fn insert_casts(g: &Context, term: &Term, t: &Type) -> Result<Term, Error> {
+    cast(g, term, t, &Effects::pure(), "")
+}
+
+fn cast(g: &Context, term: &Term, t: &Type, e: &Effects, path: &str) -> Result<Term, Error> {
+    let at = |field: &str| format!("{}/{}", path, field);
+    if !judgment(t) {
+        return error(path, format!("{} is not the type of a term", t));
+    }
+    let dyn_ = || Box::new(Type::Dyn);
+    let t = &unfold(t);
+    match (term, t) {
+        (Term::Pair(a, b), Type::Sum(l, r)) => Ok(Term::Pair(
+            Box::new(cast(g, a, l, e, &at("left"))?),
+            Box::new(cast(g, b, r, e, &at("right"))?),
+        )),
+        (Term::Pair(_, _), &Type::Dyn) => {
+            let pair = Type::Sum(dyn_(), dyn_());
+            coerce(cast(g, term, &pair, e, path)?, &pair, t, path)
+        }
+        (Term::Pair(_, _), _) => error(path, format!("expected {}, found a pair", t)),
+        (Term::Split(p, x, a, y, b, body), _) => {
+            let pair = Type::Sum(Box::new(a.clone()), Box::new(b.clone()));
+            Ok(Term::Split(
+                Box::new(cast(g, p, &pair, e, &at("pair"))?),
+                x.clone(),
+                a.clone(),
+                y.clone(),
+                b.clone(),
+                Box::new(cast(&snoc(&snoc(g, x, a), y, b), body, t, e, &at("body"))?),
+            ))
+        }
+        (Term::Lam(x, body), Type::Fn(i, o, f)) => Ok(Term::Lam(
+            x.clone(),
+            Box::new(cast(&snoc(g, x, i), body, o, f, &at("body"))?),
+        )),
+        (Term::Lam(_, _), &Type::Dyn) => {
+            let fun = Type::Fn(dyn_(), dyn_(), Effects::pure());
+            coerce(cast(g, term, &fun, e, path)?, &fun, t, path)
+        }
+        (Term::Lam(_, _), _) => error(path, format!("expected {}, found a function", t)),
+        (Term::App(f, a, type_arg), _) => {
+            let fun = Type::Fn(Box::new(type_arg.clone()), Box::new(t.clone()), e.clone());
+            Ok(Term::App(
+                Box::new(cast(g, f, &fun, e, &at("fun"))?),
+                Box::new(cast(g, a, type_arg, e, &at("arg"))?),
+                type_arg.clone(),
+            ))
+        }
+        (Term::Var(x), _) => match lookup(g, x) {
+            None => error(path, format!("unbound variable '{}'", x)),
+            Some(found) if subtype(found, t) => Ok(term.clone()),
+            Some(found) => coerce(term.clone(), found, t, path),
+        },
+        (&Term::Unit, _) => coerce(Term::Unit, &Type::Unit, t, path),
+        (Term::Ref(init, type_init), _) => {
+            let init = cast(g, init, type_init, e, &at("init"))?;
+            let cell = Type::Ref(Box::new(type_init.clone()));
+            coerce(Term::Ref(Box::new(init), type_init.clone()), &cell, t, path)
+        }
+        (Term::Deref(cell), _) => {
+            let cell_type = Type::Ref(Box::new(t.clone()));
+            Ok(Term::Deref(Box::new(cast(
+                g,
+                cell,
+                &cell_type,
+                e,
+                &at("cell"),
+            )?)))
+        }
+        (Term::Assign(cell, value, type_value), _) => {
+            let cell_type = Type::Ref(Box::new(type_value.clone()));
+            let assign = Term::Assign(
+                Box::new(cast(g, cell, &cell_type, e, &at("cell"))?),
+                Box::new(cast(g, value, type_value, e, &at("value"))?),
+                type_value.clone(),
+            );
+            coerce(assign, &Type::Unit, t, path)
+        }
+        (Term::Loc(l), _) => error(path, format!("unknown location {}", l)),
+        (Term::Pack(witness, body, package), _) => match judgment(package).then(|| unfold(package))
+        {
+            Some(Type::Exists(x, inner)) => {
+                let body = cast(g, body, &subst_type(&inner, &x, witness), e, &at("body"))?;
+                let pack = Term::Pack(witness.clone(), Box::new(body), package.clone());
+                coerce(pack, package, t, path)
+            }
+            _ => error(
+                path,
+                format!("a package must have an existential type, found {}", package),
+            ),
+        },
+        (Term::Unpack(package, package_type, x_type, x, body), _) => {
+            let (y, inner) = match judgment(package_type).then(|| unfold(package_type)) {
+                Some(Type::Exists(y, inner)) => (y, inner),
+                _ => {
+                    return error(
+                        path,
+                        format!(
+                            "a package must have an existential type, found {}",
+                            package_type
+                        ),
+                    )
+                }
+            };
+            if free_in_type(x_type, t) || free_in_context(x_type, g) {
+                return error(
+                    path,
+                    format!("the type variable {} escapes its scope", x_type),
+                );
+            }
+            let content = subst_type(&inner, &y, &Type::Var(x_type.clone()));
+            let package = cast(g, package, &Type::Exists(y, inner), e, &at("package"))?;
+            let body = cast(&snoc(g, x, &content), body, t, e, &at("body"))?;
+            Ok(Term::Unpack(
+                Box::new(package),
+                package_type.clone(),
+                x_type.clone(),
+                x.clone(),
+                Box::new(body),
+            ))
+        }
+        // explicit casts are kept, they may be followed by another one
+        (Term::Cast(inner, from, to, l), _) => {
+            if !consistent(from, to) {
+                return error(path, format!("{} is not consistent with {}", from, to));
+            }
+            let inner = cast(g, inner, from, e, &at("term"))?;
+            coerce(
+                Term::Cast(Box::new(inner), from.clone(), to.clone(), l.clone()),
+                to,
+                t,
+                path,
+            )
+        }
+        (Term::Blame(_, _, _), _) => Ok(term.clone()),
+        (Term::Raise(x), _) if e.contains(x) => Ok(term.clone()),
+        (Term::Raise(x), _) => error(path, format!("the exception {} may not be raised here", x)),
+        (Term::Try(body, x, handler), _) => Ok(Term::Try(
+            Box::new(cast(g, body, t, &e.with(x), &at("body"))?),
+            x.clone(),
+            Box::new(cast(g, handler, t, e, &at("handler"))?),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::eval::{run, Store};
+    use super::super::judgment_check;
+    use super::*;
+
+    fn var(x: &str) -> Box<Term> {
+        Box::new(Term::Var(x.into()))
+    }
+
+    fn lam(x: &str, body: Term) -> Term {
+        Term::Lam(x.into(), Box::new(body))
+    }
+
+    fn arrow(a: Type, b: Type) -> Type {
+        Type::Fn(Box::new(a), Box::new(b), Effects::pure())
+    }
+
+    /// Insert the casts, check the result statically and print the value it runs to.
+    fn run_gradually(term: &Term, t: &Type) -> Result<String, String> {
+        let ctx = Context::Empty();
+        let term = insert_casts(&ctx, term, t).map_err(|e| e.to_string())?;
+        assert!(judgment_check(&ctx, &term, t));
+        run(&term, &mut Store::new())
+            .map(|v| v.to_string())
+            .map_err(|b| b.to_string())
+    }
+
+    #[test]
+    fn consistency() {
+        let foo_to_dyn = arrow(Type::Foo, Type::Dyn);
+        assert!(consistent(&foo_to_dyn, &arrow(Type::Foo, Type::Bar)));
+        assert!(consistent(&foo_to_dyn, &Type::Dyn));
+        assert!(!consistent(&foo_to_dyn, &arrow(Type::Bar, Type::Bar)));
+        assert!(!consistent(&foo_to_dyn, &Type::Foo));
+        // references are invariant
+        let cell = |t| Type::Ref(Box::new(t));
+        assert!(!consistent(&cell(Type::Dyn), &cell(Type::Foo)));
+        assert!(!type_equality(&Type::Dyn, &Type::Foo));
+        assert!(is_ground(&arrow(Type::Dyn, Type::Dyn)));
+        assert!(!is_ground(&foo_to_dyn));
+        assert_eq!(format!("{}", ground(&foo_to_dyn)), "Dyn -> Dyn");
+    }
+
+    #[test]
+    fn casts_are_inserted_where_types_differ() {
+        // (\x. x : Dyn -> Foo) () : Foo
+        let term = Term::App(
+            Box::new(lam("x", Term::Var("x".into()))),
+            Box::new(Term::Unit),
+            Type::Dyn,
+        );
+        let casted = insert_casts(&Context::Empty(), &term, &Type::Foo).unwrap();
+        assert_eq!(
+            format!("{}", casted),
+            "(\\x. <Dyn => Foo> x) (<Unit => Dyn> ())"
+        );
+        // fully annotated terms stay as they are
+        let id = lam("x", Term::Var("x".into()));
+        let foo_to_foo = arrow(Type::Foo, Type::Foo);
+        assert_eq!(
+            format!(
+                "{}",
+                insert_casts(&Context::Empty(), &id, &foo_to_foo).unwrap()
+            ),
+            "\\x. x"
+        );
+        // inconsistent types can't be cast
+        let error = insert_casts(&Context::Empty(), &Term::Unit, &Type::Foo).unwrap_err();
+        assert_eq!(format!("{}", error), ": expected Foo, found Unit");
+    }
+
+    #[test]
+    fn failing_casts_blame_their_location() {
+        let term = Term::App(
+            Box::new(lam("x", Term::Var("x".into()))),
+            Box::new(Term::Unit),
+            Type::Dyn,
+        );
+        assert_eq!(
+            run_gradually(&term, &Type::Foo),
+            Err("blame /fun/body: expected Foo, found Unit".into())
+        );
+        assert_eq!(run_gradually(&term, &Type::Unit), Ok("()".into()));
+    }
+
+    #[test]
+    fn dynamic_functions_and_pairs() {
+        // (\f. split f (y, y) as (a, b) in b : Dyn -> Dyn) (\p. p) : Dyn
+        let apply = lam(
+            "f",
+            Term::Split(
+                Box::new(Term::App(
+                    var("f"),
+                    Box::new(Term::Pair(Box::new(Term::Unit), Box::new(Term::Unit))),
+                    Type::Dyn,
+                )),
+                "a".into(),
+                Type::Dyn,
+                "b".into(),
+                Type::Unit,
+                var("b"),
+            ),
+        );
+        let term = Term::App(
+            Box::new(apply.clone()),
+            Box::new(lam("p", Term::Var("p".into()))),
+            Type::Dyn,
+        );
+        assert_eq!(run_gradually(&term, &Type::Unit), Ok("()".into()));
+        // a function which doesn't return a pair is blamed where it is applied to one
+        let term = Term::App(Box::new(apply), Box::new(lam("p", Term::Unit)), Type::Dyn);
+        assert_eq!(
+            run_gradually(&term, &Type::Unit),
+            Err("blame /fun/body/pair/fun: expected Dyn * Dyn, found Unit".into())
+        );
+    }
+}
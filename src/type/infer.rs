@@ -0,0 +1,1835 @@
+//! Type inference and type classes for an implicitly typed language.
+//!
+//! The terms of the core carry their types, the language of this module
+//! leaves them out: lambdas, applications and splits have no annotations
+//! and `let` binds polymorphic definitions. Types are inferred by
+//! Hindley–Milner unification, a definition is generalized over the
+//! type variables its context doesn't mention, so `let id = \x. x` gets
+//! the type `forall a. a -> a`.
+//!
+//! Records and variants are extensible: their types end in a row
+//! variable which stands for further fields, so selecting a field
+//! `\r. r.x` has the type `forall a ρ. {x: a | ρ} -> a` and accepts
+//! every record with a field `x`. A tag `<x = e>` has the open type
+//! `<x: A | ρ>` and a `case` closes the variant to the labels it handles.
+//! Unifying two rows matches the common labels in any order and binds
+//! the row variables to the fields which the other side adds.
+//!
+//! Classes overload names, `class Eq a where eq : a -> a -> Bool`
+//! declares `eq : forall a. Eq a => a -> a -> Bool`, and instances give
+//! the methods at a type, possibly under constraints of their own like
+//! `instance (Eq a, Eq b) => Eq (a * b)`. A constraint on a generalized
+//! variable becomes part of the type of a definition, the others are
+//! solved by the instances.
+//!
+//! `elaborate` translates a program into a core term by dictionary
+//! passing. A dictionary holds the methods of an instance as a tuple, a
+//! constrained definition takes one dictionary argument per constraint
+//! and every use passes the dictionaries for its types. The core has no
+//! polymorphism, so a polymorphic definition is copied at every type it
+//! is used at and `judgment_check` checks the result like any other term.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use super::{free_in_type, subst_type, unfold, Context, Effects, Row, Term, Type};
+
+/// Expressions without type annotations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Var(String),
+    Lam(String, Box<Expr>),
+    App(Box<Expr>, Box<Expr>),
+    Let(String, Box<Expr>, Box<Expr>), // let x = e in body
+    Unit,
+    Pair(Box<Expr>, Box<Expr>),
+    Split(Box<Expr>, String, String, Box<Expr>), // split p as (x, y) in body
+    Record(Vec<(String, Expr)>),                 // {x = e, ...}
+    Field(Box<Expr>, String),                    // e.x
+    Tag(String, Box<Expr>),                      // <x = e>
+    Case(Box<Expr>, Vec<(String, String, Expr)>), // case e of <x = y> => body | ...
+}
+
+pub fn var(x: &str) -> Expr {
+    Expr::Var(x.into())
+}
+
+pub fn lam(x: &str, body: Expr) -> Expr {
+    Expr::Lam(x.into(), Box::new(body))
+}
+
+/// Apply a function to a list of arguments.
+pub fn app(f: Expr, args: Vec<Expr>) -> Expr {
+    args.into_iter()
+        .fold(f, |f, a| Expr::App(Box::new(f), Box::new(a)))
+}
+
+pub fn let_in(x: &str, e: Expr, body: Expr) -> Expr {
+    Expr::Let(x.into(), Box::new(e), Box::new(body))
+}
+
+pub fn pair(a: Expr, b: Expr) -> Expr {
+    Expr::Pair(Box::new(a), Box::new(b))
+}
+
+pub fn split(p: Expr, x: &str, y: &str, body: Expr) -> Expr {
+    Expr::Split(Box::new(p), x.into(), y.into(), Box::new(body))
+}
+
+pub fn record(fields: Vec<(&str, Expr)>) -> Expr {
+    Expr::Record(fields.into_iter().map(|(l, e)| (l.into(), e)).collect())
+}
+
+pub fn field(e: Expr, l: &str) -> Expr {
+    Expr::Field(Box::new(e), l.into())
+}
+
+pub fn tag(l: &str, e: Expr) -> Expr {
+    Expr::Tag(l.into(), Box::new(e))
+}
+
+/// Match a variant with one branch `(label, variable, body)` per label.
+pub fn case(e: Expr, branches: Vec<(&str, &str, Expr)>) -> Expr {
+    let branches = branches
+        .into_iter()
+        .map(|(l, x, body)| (l.into(), x.into(), body))
+        .collect();
+    Expr::Case(Box::new(e), branches)
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        /// Wrap everything except atoms into parens.
+        fn atom(e: &Expr) -> String {
+            match e {
+                Expr::Var(_)
+                | Expr::Unit
+                | Expr::Pair(_, _)
+                | Expr::Record(_)
+                | Expr::Field(_, _)
+                | Expr::Tag(_, _) => format!("{}", e),
+                _ => format!("({})", e),
+            }
+        }
+        match self {
+            Expr::Var(x) => write!(f, "{}", x),
+            Expr::Lam(x, body) => write!(f, "\\{}. {}", x, body),
+            Expr::App(a, b) => match **a {
+                Expr::App(_, _) => write!(f, "{} {}", a, atom(b)),
+                _ => write!(f, "{} {}", atom(a), atom(b)),
+            },
+            Expr::Let(x, e, body) => write!(f, "let {} = {} in {}", x, e, body),
+            Expr::Unit => write!(f, "()"),
+            Expr::Pair(a, b) => write!(f, "({}, {})", a, b),
+            Expr::Split(p, x, y, body) => write!(f, "split {} as ({}, {}) in {}", p, x, y, body),
+            Expr::Record(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(l, e)| format!("{} = {}", l, e))
+                    .collect();
+                write!(f, "{{{}}}", fields.join(", "))
+            }
+            Expr::Field(e, l) => write!(f, "{}.{}", atom(e), l),
+            Expr::Tag(l, e) => write!(f, "<{} = {}>", l, e),
+            Expr::Case(e, branches) => {
+                let branches: Vec<String> = branches
+                    .iter()
+                    .map(|(l, x, body)| format!("<{} = {}> => {}", l, x, body))
+                    .collect();
+                write!(f, "case {} of {}", e, branches.join(" | "))
+            }
+        }
+    }
+}
+
+/// A class constraint `Eq a` on a type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraint {
+    pub class: String,
+    pub t: Type,
+}
+
+impl Constraint {
+    pub fn new(class: &str, t: Type) -> Constraint {
+        Constraint {
+            class: class.into(),
+            t,
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.t {
+            Type::Foo
+            | Type::Bar
+            | Type::Baz
+            | Type::Unit
+            | Type::Var(_)
+            | Type::Named(_, _)
+            | Type::Record(_)
+            | Type::Variant(_) => write!(f, "{} {}", self.class, self.t),
+            _ => write!(f, "{} ({})", self.class, self.t),
+        }
+    }
+}
+
+/// A type generalized over some variables under class constraints,
+/// `forall a. Eq a => a -> a -> Bool`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheme {
+    pub vars: Vec<String>,
+    pub constraints: Vec<Constraint>,
+    pub t: Type,
+}
+
+impl Scheme {
+    fn mono(t: Type) -> Scheme {
+        Scheme {
+            vars: Vec::new(),
+            constraints: Vec::new(),
+            t,
+        }
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut types: Vec<&Type> = vec![&self.t];
+        types.extend(self.constraints.iter().map(|c| &c.t));
+        let names = readable(&types, &self.vars);
+        if !self.vars.is_empty() {
+            let vars: Vec<&str> = self.vars.iter().map(|x| names[x].as_str()).collect();
+            write!(f, "forall {}. ", vars.join(" "))?;
+        }
+        let constraints: Vec<String> = self
+            .constraints
+            .iter()
+            .map(|c| rename(&Constraint::new(&c.class, c.t.clone()), &names).to_string())
+            .collect();
+        match constraints.len() {
+            0 => {}
+            1 => write!(f, "{} => ", constraints[0])?,
+            _ => write!(f, "({}) => ", constraints.join(", "))?,
+        }
+        write!(f, "{}", rename_type(&self.t, &names))
+    }
+}
+
+/// A class declares the types of its methods in terms of its variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub name: String,
+    pub var: String,
+    pub methods: Vec<(String, Type)>,
+}
+
+/// An instance gives the methods of a class at the type `head`, whose
+/// variables may be constrained by the `context`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+    pub class: String,
+    pub context: Vec<Constraint>,
+    pub head: Type,
+    pub methods: Vec<(String, Expr)>,
+}
+
+/// Why a program has no type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Unbound(String),
+    Mismatch(Type, Type),
+    /// A type variable which would have to contain itself.
+    Infinite(String, Type),
+    NoInstance(Constraint),
+    /// A constraint on a type the program leaves open.
+    Ambiguous(Constraint),
+    UnknownClass(String),
+    Duplicate(String),
+    /// Two instances of a class with unifiable heads.
+    Overlap(String, Type, Type),
+    MissingMethod(Constraint, String),
+    UnknownMethod(Constraint, String),
+    /// A variant, which has no counterpart in the core.
+    Untranslatable(Type),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // unification variables are shown like the variables of a scheme
+        let types: Vec<&Type> = match self {
+            Error::Mismatch(a, b) => vec![a, b],
+            Error::Untranslatable(t) => vec![t],
+            Error::Infinite(_, t) => vec![t],
+            Error::NoInstance(c) | Error::Ambiguous(c) => vec![&c.t],
+            _ => vec![],
+        };
+        let mut vars = Vec::new();
+        for t in &types {
+            metas(t, &mut vars);
+        }
+        if let Error::Infinite(x, _) = self {
+            vars.retain(|y| y != x);
+            vars.insert(0, x.clone());
+        }
+        let names = readable(&types, &vars);
+        let t = |t: &Type| rename_type(t, &names);
+        match self {
+            Error::Unbound(x) => write!(f, "'{}' is not defined", x),
+            Error::Mismatch(a, b) => write!(f, "can't unify {} with {}", t(a), t(b)),
+            Error::Infinite(x, a) => write!(f, "{} occurs in {}", names[x], t(a)),
+            Error::NoInstance(c) => write!(f, "no instance for {}", rename(c, &names)),
+            Error::Ambiguous(c) => write!(f, "the constraint {} is ambiguous", rename(c, &names)),
+            Error::UnknownClass(c) => write!(f, "'{}' is not a class", c),
+            Error::Duplicate(x) => write!(f, "'{}' is already defined", x),
+            Error::Overlap(class, a, b) => {
+                let (a, b) = (
+                    Constraint::new(class, a.clone()),
+                    Constraint::new(class, b.clone()),
+                );
+                write!(f, "the instances {} and {} overlap", a, b)
+            }
+            Error::MissingMethod(i, m) => write!(f, "the instance {} lacks '{}'", i, m),
+            Error::UnknownMethod(i, m) => {
+                write!(f, "'{}' of the instance {} is no method of its class", m, i)
+            }
+            Error::Untranslatable(v) => write!(f, "the core has no variants like {}", t(v)),
+        }
+    }
+}
+
+/// A unification variable, solved by the substitution.
+fn is_meta(x: &str) -> bool {
+    x.starts_with('?')
+}
+
+/// Collect the unification variables of a type in order.
+fn metas(t: &Type, acc: &mut Vec<String>) {
+    match t {
+        Type::Var(x) if is_meta(x) && !acc.contains(x) => acc.push(x.clone()),
+        Type::Sum(a, b) | Type::Fn(a, b, _) => {
+            metas(a, acc);
+            metas(b, acc);
+        }
+        Type::Record(row) | Type::Variant(row) => {
+            for (_, t) in &row.fields {
+                metas(t, acc);
+            }
+            match &row.rest {
+                Some(r) if is_meta(r) && !acc.contains(r) => acc.push(r.clone()),
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Collect the row variables of a type.
+fn rows(t: &Type, acc: &mut Vec<String>) {
+    match t {
+        Type::Sum(a, b) | Type::Fn(a, b, _) => {
+            rows(a, acc);
+            rows(b, acc);
+        }
+        Type::Record(row) | Type::Variant(row) => {
+            for (_, t) in &row.fields {
+                rows(t, acc);
+            }
+            acc.extend(row.rest.iter().cloned());
+        }
+        _ => {}
+    }
+}
+
+/// Names `a`, `b`, ... for variables and `ρ`, `ρ1`, ... for row
+/// variables, which avoid the other variables of the types.
+fn readable(types: &[&Type], vars: &[String]) -> HashMap<String, String> {
+    let mut row_vars = Vec::new();
+    for t in types {
+        rows(t, &mut row_vars);
+    }
+    let free = |x: &String| types.iter().any(|t| free_in_type(x, t)) && !vars.contains(x);
+    let mut candidates = ('a'..='z')
+        .map(|c| c.to_string())
+        .chain((1..).map(|n| format!("t{}", n)))
+        .filter(|x| !free(x));
+    let mut row_candidates = Some("ρ".to_string())
+        .into_iter()
+        .chain((1..).map(|n| format!("ρ{}", n)))
+        .filter(|x| !free(x));
+    let mut names = HashMap::new();
+    for x in vars {
+        let name = match row_vars.contains(x) {
+            true => row_candidates.next(),
+            false => candidates.next(),
+        };
+        names.insert(x.clone(), name.expect("enough names"));
+    }
+    names
+}
+
+fn rename_type(t: &Type, names: &HashMap<String, String>) -> Type {
+    names.iter().fold(t.clone(), |t, (x, y)| {
+        subst_type(&t, x, &Type::Var(y.clone()))
+    })
+}
+
+fn rename(c: &Constraint, names: &HashMap<String, String>) -> Constraint {
+    Constraint::new(&c.class, rename_type(&c.t, names))
+}
+
+/// A term with its types, where dictionaries and the copies of
+/// polymorphic definitions are still to be made.
+#[derive(Debug, Clone)]
+enum Elab {
+    Var(String),
+    /// A polymorphic definition at types for its variables, and the
+    /// constraints on them which the dictionary arguments have to solve.
+    Inst(String, Vec<Type>, Vec<Constraint>),
+    /// The method at an index of the dictionary for the constraint.
+    Method(Constraint, usize),
+    Lam(String, Box<Elab>),
+    App(Box<Elab>, Box<Elab>, Type),
+    Unit,
+    Pair(Box<Elab>, Box<Elab>),
+    Split(Box<Elab>, String, Type, String, Type, Box<Elab>),
+    /// A polymorphic definition, monomorphic ones are applied lambdas.
+    Let(String, Rc<Def>, Box<Elab>),
+    Record(Vec<(String, Elab)>),
+    /// A field of a record of the type.
+    Field(Box<Elab>, String, Type),
+    /// A tag and a case, with the type of the variant.
+    Tag(String, Box<Elab>, Type),
+    Case(Box<Elab>, Vec<(String, String, Elab)>, Type),
+}
+
+#[derive(Debug, Clone)]
+struct Def {
+    scheme: Scheme,
+    body: Elab,
+}
+
+/// An instance with its methods in the order of the class.
+#[derive(Debug, Clone)]
+struct Elaborated {
+    class: String,
+    context: Vec<Constraint>,
+    head: Type,
+    methods: Vec<Elab>,
+}
+
+/// Checked class and instance declarations.
+#[derive(Debug, Clone, Default)]
+pub struct Classes {
+    classes: Vec<Class>,
+    instances: Vec<Elaborated>,
+}
+
+impl Classes {
+    /// Check the declarations, the methods of the instances may use
+    /// the primitives of the context.
+    pub fn new(
+        g: &Context,
+        classes: Vec<Class>,
+        instances: Vec<Instance>,
+    ) -> Result<Classes, Error> {
+        let mut names = HashSet::new();
+        for c in &classes {
+            for name in Some(&c.name)
+                .into_iter()
+                .chain(c.methods.iter().map(|m| &m.0))
+            {
+                if !names.insert(name.clone()) {
+                    return Err(Error::Duplicate(name.clone()));
+                }
+            }
+        }
+        let mut checked = Classes {
+            classes,
+            instances: Vec::new(),
+        };
+        for i in &instances {
+            for c in Some(&i.class)
+                .into_iter()
+                .chain(i.context.iter().map(|c| &c.class))
+            {
+                checked.class(c)?;
+            }
+            for other in checked.instances.iter().filter(|o| o.class == i.class) {
+                if Infer::new(g, &checked).overlap(&i.head, &other.head) {
+                    let heads = (other.head.clone(), i.head.clone());
+                    return Err(Error::Overlap(i.class.clone(), heads.0, heads.1));
+                }
+            }
+            checked.instances.push(Elaborated {
+                class: i.class.clone(),
+                context: i.context.clone(),
+                head: i.head.clone(),
+                methods: Vec::new(),
+            });
+        }
+        // the methods may use every instance, the one they define included
+        let mut methods = Vec::new();
+        for i in &instances {
+            methods.push(Infer::new(g, &checked).instance(i)?);
+        }
+        for (i, m) in checked.instances.iter_mut().zip(methods) {
+            i.methods = m;
+        }
+        Ok(checked)
+    }
+
+    fn class(&self, name: &str) -> Result<&Class, Error> {
+        self.classes
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| Error::UnknownClass(name.into()))
+    }
+
+    /// The class and index of a method.
+    fn method(&self, name: &str) -> Option<(&Class, usize)> {
+        self.classes
+            .iter()
+            .flat_map(|c| c.methods.iter().enumerate().map(move |(i, m)| (c, i, m)))
+            .find(|(_, _, m)| m.0 == name)
+            .map(|(c, i, _)| (c, i))
+    }
+
+    /// The types of the methods of a class at a type.
+    fn method_types(&self, c: &Constraint) -> Vec<Type> {
+        let class = self.class(&c.class).expect("constraints name classes");
+        class
+            .methods
+            .iter()
+            .map(|m| subst_type(&m.1, &class.var, &c.t))
+            .collect()
+    }
+
+    /// The instance for a type and the types of its variables.
+    fn instance(&self, c: &Constraint) -> Option<(&Elaborated, HashMap<String, Type>)> {
+        self.instances
+            .iter()
+            .filter(|i| i.class == c.class)
+            .find_map(|i| {
+                let mut bindings = HashMap::new();
+                match matches(&i.head, &c.t, &mut bindings) {
+                    true => Some((i, bindings)),
+                    false => None,
+                }
+            })
+    }
+}
+
+/// Match an instance head against a type, binding the variables of the head.
+fn matches(head: &Type, t: &Type, bindings: &mut HashMap<String, Type>) -> bool {
+    match (unfold(head), unfold(t)) {
+        (Type::Var(x), t) => match bindings.get(&x) {
+            Some(bound) => *bound == t,
+            None => {
+                bindings.insert(x, t);
+                true
+            }
+        },
+        (Type::Sum(a1, b1), Type::Sum(a2, b2)) | (Type::Fn(a1, b1, _), Type::Fn(a2, b2, _)) => {
+            matches(&a1, &a2, bindings) && matches(&b1, &b2, bindings)
+        }
+        (Type::Foo, Type::Foo)
+        | (Type::Bar, Type::Bar)
+        | (Type::Baz, Type::Baz)
+        | (Type::Unit, Type::Unit) => true,
+        _ => false,
+    }
+}
+
+/// The type of a tuple: `Unit` for no elements, the element itself for
+/// one and nested products for more. Dictionaries are tuples of methods.
+fn tuple_type(types: &[Type]) -> Type {
+    match types {
+        [] => Type::Unit,
+        [t] => t.clone(),
+        [t, rest @ ..] => Type::product(t.clone(), tuple_type(rest)),
+    }
+}
+
+/// The state of inference: the substitution of the unification variables
+/// and the constraints which are wanted.
+struct Infer<'a> {
+    g: &'a Context,
+    classes: &'a Classes,
+    subst: HashMap<String, Type>,
+    next: usize,
+    wanted: Vec<Constraint>,
+    /// The constraints an instance may use.
+    given: Vec<Constraint>,
+    generalized: HashSet<String>,
+}
+
+type Env = Vec<(String, Scheme)>;
+
+impl<'a> Infer<'a> {
+    fn new(g: &'a Context, classes: &'a Classes) -> Infer<'a> {
+        Infer {
+            g,
+            classes,
+            subst: HashMap::new(),
+            next: 0,
+            wanted: Vec::new(),
+            given: Vec::new(),
+            generalized: HashSet::new(),
+        }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.next += 1;
+        Type::Var(format!("?{}", self.next))
+    }
+
+    /// Apply the substitution to a type.
+    fn zonk(&self, t: &Type) -> Type {
+        match t {
+            Type::Var(x) => match self.subst.get(x) {
+                Some(t) => self.zonk(t),
+                None => t.clone(),
+            },
+            Type::Sum(a, b) => Type::product(self.zonk(a), self.zonk(b)),
+            Type::Fn(a, b, _) => Type::arrow(self.zonk(a), self.zonk(b)),
+            Type::Record(row) => Type::Record(Box::new(self.zonk_row(row))),
+            Type::Variant(row) => Type::Variant(Box::new(self.zonk_row(row))),
+            _ => t.clone(),
+        }
+    }
+
+    /// Apply the substitution to a row, a solved row variable adds the
+    /// fields it is bound to.
+    fn zonk_row(&self, row: &Row) -> Row {
+        let mut fields: Vec<(String, Type)> = row
+            .fields
+            .iter()
+            .map(|(l, t)| (l.clone(), self.zonk(t)))
+            .collect();
+        let bound = row.rest.as_ref().and_then(|r| self.subst.get(r));
+        let rest = match bound.map(|t| self.zonk(t)) {
+            Some(Type::Record(more)) | Some(Type::Variant(more)) => {
+                fields.extend(more.fields);
+                more.rest
+            }
+            _ => row.rest.clone(),
+        };
+        Row { fields, rest }
+    }
+
+    fn zonk_constraint(&self, c: &Constraint) -> Constraint {
+        Constraint::new(&c.class, self.zonk(&c.t))
+    }
+
+    fn unify(&mut self, a: &Type, b: &Type) -> Result<(), Error> {
+        // variables are bound to the types before unfolding, to keep their names
+        match (self.zonk(a), self.zonk(b)) {
+            (Type::Var(x), Type::Var(y)) if x == y => return Ok(()),
+            (Type::Var(x), t) | (t, Type::Var(x)) if is_meta(&x) => {
+                if free_in_type(&x, &t) {
+                    return Err(Error::Infinite(x, t));
+                }
+                self.subst.insert(x, t);
+                return Ok(());
+            }
+            _ => {}
+        }
+        match (unfold(&self.zonk(a)), unfold(&self.zonk(b))) {
+            (Type::Var(x), Type::Var(y)) if x == y => Ok(()),
+            (Type::Var(x), t) | (t, Type::Var(x)) if is_meta(&x) => {
+                if free_in_type(&x, &t) {
+                    return Err(Error::Infinite(x, t));
+                }
+                self.subst.insert(x, t);
+                Ok(())
+            }
+            (Type::Sum(a1, b1), Type::Sum(a2, b2)) | (Type::Fn(a1, b1, _), Type::Fn(a2, b2, _)) => {
+                self.unify(&a1, &a2)?;
+                self.unify(&b1, &b2)
+            }
+            (Type::Record(r1), Type::Record(r2)) | (Type::Variant(r1), Type::Variant(r2)) => {
+                match self.unify_rows(*r1, *r2)? {
+                    true => Ok(()),
+                    false => Err(Error::Mismatch(self.zonk(a), self.zonk(b))),
+                }
+            }
+            (ref a, ref b)
+                if a == b && matches!(a, Type::Foo | Type::Bar | Type::Baz | Type::Unit) =>
+            {
+                Ok(())
+            }
+            _ => Err(Error::Mismatch(self.zonk(a), self.zonk(b))),
+        }
+    }
+
+    /// Unify two rows: the common labels in any order, then each row
+    /// variable is bound to the fields only the other row has. Tells if
+    /// the rows have the same labels.
+    fn unify_rows(&mut self, a: Row, b: Row) -> Result<bool, Error> {
+        let (mut a, mut b) = (a, b);
+        // unifying fields may solve a row variable and bring new common labels
+        while let Some(l) = a
+            .fields
+            .iter()
+            .map(|f| &f.0)
+            .find(|l| b.fields.iter().any(|f| f.0 == **l))
+        {
+            let l = l.clone();
+            let take = |row: &mut Row| {
+                let i = row
+                    .fields
+                    .iter()
+                    .position(|f| f.0 == l)
+                    .expect("a common label");
+                row.fields.remove(i).1
+            };
+            let (ta, tb) = (take(&mut a), take(&mut b));
+            self.unify(&ta, &tb)?;
+            a = self.zonk_row(&a);
+            b = self.zonk_row(&b);
+        }
+        let meta = |r: &Option<String>| r.as_ref().is_some_and(|r| is_meta(r));
+        match (a.rest.clone(), b.rest.clone()) {
+            (Some(x), Some(y)) if x == y => Ok(a.fields.is_empty() && b.fields.is_empty()),
+            (Some(x), Some(y)) if meta(&a.rest) && meta(&b.rest) => {
+                let rest = self.fresh_row();
+                self.bind_row(
+                    &x,
+                    Row {
+                        fields: b.fields,
+                        rest: rest.clone(),
+                    },
+                )?;
+                self.bind_row(
+                    &y,
+                    Row {
+                        fields: a.fields,
+                        rest,
+                    },
+                )?;
+                Ok(true)
+            }
+            (Some(x), _) if meta(&a.rest) && a.fields.is_empty() => {
+                self.bind_row(&x, b)?;
+                Ok(true)
+            }
+            (_, Some(y)) if meta(&b.rest) && b.fields.is_empty() => {
+                self.bind_row(&y, a)?;
+                Ok(true)
+            }
+            _ => Ok(a.fields.is_empty() && b.fields.is_empty() && a.rest == b.rest),
+        }
+    }
+
+    fn fresh_row(&mut self) -> Option<String> {
+        self.next += 1;
+        Some(format!("?{}", self.next))
+    }
+
+    fn bind_row(&mut self, x: &str, row: Row) -> Result<(), Error> {
+        let t = Type::Record(Box::new(row));
+        if row_occurs(x, &t) {
+            return Err(Error::Infinite(x.into(), t));
+        }
+        self.subst.insert(x.into(), t);
+        Ok(())
+    }
+
+    /// Test if two instance heads have a common instance.
+    fn overlap(&mut self, a: &Type, b: &Type) -> bool {
+        let open = |t: &Type, infer: &mut Infer| {
+            let mut vars = Vec::new();
+            free_vars(t, &mut vars);
+            vars.iter()
+                .fold(t.clone(), |t, x| subst_type(&t, x, &infer.fresh()))
+        };
+        let (a, b) = (open(a, self), open(b, self));
+        self.unify(&a, &b).is_ok()
+    }
+
+    /// Replace the variables of a scheme by fresh unification variables
+    /// and want its constraints.
+    fn instantiate(&mut self, s: &Scheme) -> (Vec<Type>, Vec<Constraint>, Type) {
+        let types: Vec<Type> = s.vars.iter().map(|_| self.fresh()).collect();
+        let inst = |t: &Type| {
+            s.vars
+                .iter()
+                .zip(&types)
+                .fold(t.clone(), |t, (x, a)| subst_type(&t, x, a))
+        };
+        let constraints: Vec<Constraint> = s
+            .constraints
+            .iter()
+            .map(|c| Constraint::new(&c.class, inst(&c.t)))
+            .collect();
+        self.wanted.extend(constraints.iter().cloned());
+        let t = inst(&s.t);
+        (types, constraints, t)
+    }
+
+    /// Reduce a constraint by the instances until it only constrains variables.
+    fn reduce(&self, c: &Constraint) -> Result<Vec<Constraint>, Error> {
+        let c = self.zonk_constraint(c);
+        if let Type::Var(_) = unfold(&c.t) {
+            return Ok(vec![c]);
+        }
+        let (instance, bindings) = self
+            .classes
+            .instance(&c)
+            .ok_or_else(|| Error::NoInstance(c.clone()))?;
+        let mut reduced = Vec::new();
+        for k in &instance.context {
+            let t = bindings
+                .iter()
+                .fold(k.t.clone(), |t, (x, a)| subst_type(&t, x, a));
+            reduced.extend(self.reduce(&Constraint::new(&k.class, t))?);
+        }
+        Ok(reduced)
+    }
+
+    fn infer(&mut self, env: &Env, e: &Expr) -> Result<(Elab, Type), Error> {
+        match e {
+            Expr::Var(x) => {
+                if let Some((_, s)) = env.iter().rev().find(|b| b.0 == *x) {
+                    if s.vars.is_empty() {
+                        return Ok((Elab::Var(x.clone()), s.t.clone()));
+                    }
+                    let s = s.clone();
+                    let (types, constraints, t) = self.instantiate(&s);
+                    return Ok((Elab::Inst(x.clone(), types, constraints), t));
+                }
+                if let Some((class, i)) = self.classes.method(x) {
+                    let a = self.fresh();
+                    let c = Constraint::new(&class.name, a.clone());
+                    let t = subst_type(&class.methods[i].1, &class.var, &a);
+                    self.wanted.push(c.clone());
+                    return Ok((Elab::Method(c, i), t));
+                }
+                match lookup(self.g, x) {
+                    Some(t) => Ok((Elab::Var(x.clone()), t)),
+                    None => Err(Error::Unbound(x.clone())),
+                }
+            }
+            Expr::Lam(x, body) => {
+                let a = self.fresh();
+                let mut env = env.clone();
+                env.push((x.clone(), Scheme::mono(a.clone())));
+                let (body, t) = self.infer(&env, body)?;
+                Ok((Elab::Lam(x.clone(), Box::new(body)), Type::arrow(a, t)))
+            }
+            Expr::App(f, a) => {
+                let (f, f_type) = self.infer(env, f)?;
+                let (a, a_type) = self.infer(env, a)?;
+                let r = self.fresh();
+                self.unify(&f_type, &Type::arrow(a_type.clone(), r.clone()))?;
+                Ok((Elab::App(Box::new(f), Box::new(a), a_type), r))
+            }
+            Expr::Let(x, e, body) => {
+                let start = self.wanted.len();
+                let (e, t) = self.infer(env, e)?;
+                let s = self.generalize(env, start, &t)?;
+                let mut env = env.clone();
+                env.push((x.clone(), s.clone()));
+                let (body, body_type) = self.infer(&env, body)?;
+                let elab = match s.vars.is_empty() {
+                    true => Elab::App(
+                        Box::new(Elab::Lam(x.clone(), Box::new(body))),
+                        Box::new(e),
+                        t,
+                    ),
+                    false => Elab::Let(
+                        x.clone(),
+                        Rc::new(Def { scheme: s, body: e }),
+                        Box::new(body),
+                    ),
+                };
+                Ok((elab, body_type))
+            }
+            Expr::Unit => Ok((Elab::Unit, Type::Unit)),
+            Expr::Pair(a, b) => {
+                let (a, a_type) = self.infer(env, a)?;
+                let (b, b_type) = self.infer(env, b)?;
+                Ok((
+                    Elab::Pair(Box::new(a), Box::new(b)),
+                    Type::product(a_type, b_type),
+                ))
+            }
+            Expr::Split(p, x, y, body) => {
+                let (p, p_type) = self.infer(env, p)?;
+                let (a, b) = (self.fresh(), self.fresh());
+                self.unify(&p_type, &Type::product(a.clone(), b.clone()))?;
+                let mut env = env.clone();
+                env.push((x.clone(), Scheme::mono(a.clone())));
+                env.push((y.clone(), Scheme::mono(b.clone())));
+                let (body, t) = self.infer(&env, body)?;
+                let split = Elab::Split(Box::new(p), x.clone(), a, y.clone(), b, Box::new(body));
+                Ok((split, t))
+            }
+            Expr::Record(fields) => {
+                let (mut elabs, mut types) = (Vec::new(), Vec::new());
+                for (l, e) in fields {
+                    if types.iter().any(|f: &(String, Type)| f.0 == *l) {
+                        return Err(Error::Duplicate(l.clone()));
+                    }
+                    let (e, t) = self.infer(env, e)?;
+                    elabs.push((l.clone(), e));
+                    types.push((l.clone(), t));
+                }
+                Ok((Elab::Record(elabs), Type::record(types, None)))
+            }
+            Expr::Field(e, l) => {
+                let (e, t) = self.infer(env, e)?;
+                let a = self.fresh();
+                let rest = self.fresh_row();
+                self.unify(&t, &Type::record(vec![(l.clone(), a.clone())], rest))?;
+                Ok((Elab::Field(Box::new(e), l.clone(), t), a))
+            }
+            Expr::Tag(l, e) => {
+                let (e, t) = self.infer(env, e)?;
+                let t = Type::variant(vec![(l.clone(), t)], self.fresh_row());
+                Ok((Elab::Tag(l.clone(), Box::new(e), t.clone()), t))
+            }
+            Expr::Case(e, branches) => {
+                let (e, t) = self.infer(env, e)?;
+                let r = self.fresh();
+                let (mut elabs, mut cases) = (Vec::new(), Vec::new());
+                for (l, x, body) in branches {
+                    if cases.iter().any(|f: &(String, Type)| f.0 == *l) {
+                        return Err(Error::Duplicate(l.clone()));
+                    }
+                    let a = self.fresh();
+                    let mut env = env.clone();
+                    env.push((x.clone(), Scheme::mono(a.clone())));
+                    let (body, body_type) = self.infer(&env, body)?;
+                    self.unify(&body_type, &r)?;
+                    elabs.push((l.clone(), x.clone(), body));
+                    cases.push((l.clone(), a));
+                }
+                self.unify(&t, &Type::variant(cases, None))?;
+                Ok((Elab::Case(Box::new(e), elabs, t), r))
+            }
+        }
+    }
+
+    /// Generalize a type over the variables which the environment
+    /// doesn't mention, the wanted constraints since `start` on them
+    /// become constraints of the scheme.
+    fn generalize(&mut self, env: &Env, start: usize, t: &Type) -> Result<Scheme, Error> {
+        let t = self.zonk(t);
+        let mut fixed = Vec::new();
+        for (_, s) in env {
+            let mut vars = Vec::new();
+            metas(&self.zonk(&s.t), &mut vars);
+            fixed.extend(vars.into_iter().filter(|x| !s.vars.contains(x)));
+        }
+        let mut vars = Vec::new();
+        metas(&t, &mut vars);
+        vars.retain(|x| !fixed.contains(x));
+        let mut constraints: Vec<Constraint> = Vec::new();
+        for c in &self.wanted[start..] {
+            let c = self.zonk_constraint(c);
+            if !vars.iter().any(|x| free_in_type(x, &c.t)) {
+                continue;
+            }
+            for c in self.reduce(&c)? {
+                let on_var = matches!(c.t, Type::Var(ref x) if vars.contains(x));
+                if on_var && !constraints.contains(&c) {
+                    constraints.push(c);
+                }
+            }
+        }
+        self.generalized.extend(vars.iter().cloned());
+        Ok(Scheme {
+            vars,
+            constraints,
+            t,
+        })
+    }
+
+    /// Solve the wanted constraints which no scheme took: they must have
+    /// an instance or be given, and may not leave a variable open.
+    fn solve(&self) -> Result<(), Error> {
+        for c in &self.wanted {
+            for c in self.reduce(c)? {
+                let solved = match c.t {
+                    Type::Var(ref x) if is_meta(x) => self.generalized.contains(x),
+                    _ => self.given.contains(&c),
+                };
+                match c.t {
+                    _ if solved => {}
+                    Type::Var(ref x) if is_meta(x) => return Err(Error::Ambiguous(c)),
+                    _ => return Err(Error::NoInstance(c)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Infer the methods of an instance against the types of its class.
+    fn instance(&mut self, i: &Instance) -> Result<Vec<Elab>, Error> {
+        let head = Constraint::new(&i.class, i.head.clone());
+        let class = self.classes.class(&i.class)?.clone();
+        self.given = i.context.clone();
+        if let Some((m, _)) = i
+            .methods
+            .iter()
+            .find(|(m, _)| !class.methods.iter().any(|c| c.0 == *m))
+        {
+            return Err(Error::UnknownMethod(head, m.clone()));
+        }
+        let mut methods = Vec::new();
+        for (name, t) in &class.methods {
+            let e = match i.methods.iter().find(|m| m.0 == *name) {
+                Some((_, e)) => e,
+                None => return Err(Error::MissingMethod(head, name.clone())),
+            };
+            let (e, inferred) = self.infer(&Vec::new(), e)?;
+            self.unify(&inferred, &subst_type(t, &class.var, &i.head))?;
+            methods.push(e);
+        }
+        self.solve()?;
+        Ok(methods.iter().map(|e| self.finish(e)).collect())
+    }
+
+    /// Apply the substitution to the types of an elaborated term.
+    fn finish(&self, e: &Elab) -> Elab {
+        let f = |e: &Elab| Box::new(self.finish(e));
+        let c = |cs: &[Constraint]| cs.iter().map(|c| self.zonk_constraint(c)).collect();
+        match e {
+            Elab::Var(_) | Elab::Unit => e.clone(),
+            Elab::Inst(x, types, cs) => Elab::Inst(
+                x.clone(),
+                types.iter().map(|t| self.zonk(t)).collect(),
+                c(cs),
+            ),
+            Elab::Method(k, i) => Elab::Method(self.zonk_constraint(k), *i),
+            Elab::Lam(x, body) => Elab::Lam(x.clone(), f(body)),
+            Elab::App(a, b, t) => Elab::App(f(a), f(b), self.zonk(t)),
+            Elab::Pair(a, b) => Elab::Pair(f(a), f(b)),
+            Elab::Split(p, x, a, y, b, body) => Elab::Split(
+                f(p),
+                x.clone(),
+                self.zonk(a),
+                y.clone(),
+                self.zonk(b),
+                f(body),
+            ),
+            Elab::Let(x, def, body) => {
+                let scheme = Scheme {
+                    vars: def.scheme.vars.clone(),
+                    constraints: c(&def.scheme.constraints),
+                    t: self.zonk(&def.scheme.t),
+                };
+                let def = Def {
+                    scheme,
+                    body: self.finish(&def.body),
+                };
+                Elab::Let(x.clone(), Rc::new(def), f(body))
+            }
+            Elab::Record(fields) => Elab::Record(
+                fields
+                    .iter()
+                    .map(|(l, e)| (l.clone(), self.finish(e)))
+                    .collect(),
+            ),
+            Elab::Field(e, l, t) => Elab::Field(f(e), l.clone(), self.zonk(t)),
+            Elab::Tag(l, e, t) => Elab::Tag(l.clone(), f(e), self.zonk(t)),
+            Elab::Case(e, branches, t) => Elab::Case(
+                f(e),
+                branches
+                    .iter()
+                    .map(|(l, x, body)| (l.clone(), x.clone(), self.finish(body)))
+                    .collect(),
+                self.zonk(t),
+            ),
+        }
+    }
+}
+
+/// Test if a row variable occurs in the fields of a row.
+fn row_occurs(x: &str, row: &Type) -> bool {
+    match row {
+        Type::Record(row) => row.fields.iter().any(|f| free_in_type(x, &f.1)),
+        _ => false,
+    }
+}
+
+/// Collect the variables of a type in order.
+fn free_vars(t: &Type, acc: &mut Vec<String>) {
+    match t {
+        Type::Var(x) if !acc.contains(x) => acc.push(x.clone()),
+        Type::Sum(a, b) | Type::Fn(a, b, _) => {
+            free_vars(a, acc);
+            free_vars(b, acc);
+        }
+        _ => {}
+    }
+}
+
+/// The type of a primitive.
+fn lookup(g: &Context, x: &str) -> Option<Type> {
+    match g {
+        Context::Empty() => None,
+        Context::Snoc(_, y, t) if y == x => Some(t.clone()),
+        Context::Snoc(rest, _, _) => lookup(rest, x),
+    }
+}
+
+/// Infer the most general type of an expression, which may use the
+/// primitives of the context and the methods of the classes.
+pub fn infer(g: &Context, classes: &Classes, e: &Expr) -> Result<Scheme, Error> {
+    let mut infer = Infer::new(g, classes);
+    let (_, t) = infer.infer(&Vec::new(), e)?;
+    let s = infer.generalize(&Vec::new(), 0, &t)?;
+    infer.solve()?;
+    Ok(s)
+}
+
+/// Translate an expression into a core term of the returned type by
+/// dictionary passing. The type variables which the program leaves
+/// open are instantiated with `Unit`.
+pub fn elaborate(g: &Context, classes: &Classes, e: &Expr) -> Result<(Term, Type), Error> {
+    let mut infer = Infer::new(g, classes);
+    let (e, t) = infer.infer(&Vec::new(), e)?;
+    infer.solve()?;
+    let (e, t) = (infer.finish(&e), infer.zonk(&t));
+    let mut taken = HashSet::new();
+    let mut ctx = g;
+    while let Context::Snoc(rest, x, _) = ctx {
+        taken.insert(x.clone());
+        ctx = rest;
+    }
+    let mut lower = Lower { classes, taken };
+    let types = HashMap::new();
+    let term = lower.term(&e, &types, &Scope::default())?;
+    Ok((term, lower.ty(&t, &types)))
+}
+
+/// What a name of the program stands for while lowering.
+#[derive(Debug, Clone)]
+enum Bound {
+    /// A variable, renamed apart from all others.
+    Var(String),
+    /// A polymorphic definition and the scope it was defined in.
+    Def(Rc<Def>, Scope),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    names: Vec<(String, Bound)>,
+    /// The dictionary parameters for the constraints of the definitions.
+    dicts: Vec<(Constraint, String)>,
+}
+
+/// The translation of elaborated terms into core terms, which copies
+/// the polymorphic definitions and builds the dictionaries.
+struct Lower<'a> {
+    classes: &'a Classes,
+    /// Every name of the core term, a copy of a definition must not
+    /// capture a variable of the place it is used at.
+    taken: HashSet<String>,
+}
+
+impl<'a> Lower<'a> {
+    fn fresh(&mut self, x: &str) -> String {
+        let name = (0..)
+            .map(|n| match n {
+                0 => x.to_string(),
+                n => format!("{}{}", x, n),
+            })
+            .find(|name| !self.taken.contains(name))
+            .expect("a fresh name");
+        self.taken.insert(name.clone());
+        name
+    }
+
+    /// A type at the types of the variables of the copies, variables
+    /// which the program leaves open are `Unit` and rows are closed.
+    fn resolve(&self, t: &Type, types: &HashMap<String, Type>) -> Type {
+        let row = |row: &Row| {
+            let mut fields: Vec<(String, Type)> = row
+                .fields
+                .iter()
+                .map(|(l, t)| (l.clone(), self.resolve(t, types)))
+                .collect();
+            let rest = match row.rest.as_ref().map(|r| (r, types.get(r))) {
+                Some((_, Some(Type::Record(more)))) => {
+                    fields.extend(more.fields.iter().cloned());
+                    more.rest.clone()
+                }
+                Some((r, _)) if !is_meta(r) => Some(r.clone()),
+                _ => None,
+            };
+            Box::new(Row { fields, rest })
+        };
+        match t {
+            Type::Var(x) => match types.get(x) {
+                Some(t) => t.clone(),
+                None if is_meta(x) => Type::Unit,
+                None => t.clone(),
+            },
+            Type::Sum(a, b) => Type::product(self.resolve(a, types), self.resolve(b, types)),
+            Type::Fn(a, b, _) => Type::arrow(self.resolve(a, types), self.resolve(b, types)),
+            Type::Record(r) => Type::Record(row(r)),
+            Type::Variant(r) => Type::Variant(row(r)),
+            _ => t.clone(),
+        }
+    }
+
+    /// The core type of a type, records are tuples of their fields in
+    /// the order of the labels.
+    fn ty(&self, t: &Type, types: &HashMap<String, Type>) -> Type {
+        core_type(&self.resolve(t, types))
+    }
+
+    fn term(
+        &mut self,
+        e: &Elab,
+        types: &HashMap<String, Type>,
+        scope: &Scope,
+    ) -> Result<Term, Error> {
+        Ok(match e {
+            Elab::Var(x) => match scope.names.iter().rev().find(|b| b.0 == *x) {
+                Some((_, Bound::Var(name))) => Term::Var(name.clone()),
+                Some((_, Bound::Def(_, _))) => unreachable!("definitions are instantiated"),
+                None => Term::Var(x.clone()),
+            },
+            Elab::Inst(x, args, wanted) => {
+                let (def, def_scope) = match scope.names.iter().rev().find(|b| b.0 == *x) {
+                    Some((_, Bound::Def(def, def_scope))) => (def.clone(), def_scope.clone()),
+                    _ => unreachable!("instantiated names are definitions"),
+                };
+                // copy the definition at the types of its variables
+                let mut inner = types.clone();
+                for (v, a) in def.scheme.vars.iter().zip(args) {
+                    inner.insert(v.clone(), self.resolve(a, types));
+                }
+                let mut def_scope = def_scope;
+                let mut params = Vec::new();
+                for c in &def.scheme.constraints {
+                    let name = self.fresh(&format!("d{}", c.class));
+                    def_scope.dicts.push((c.clone(), name.clone()));
+                    params.push(name);
+                }
+                let body = self.term(&def.body, &inner, &def_scope)?;
+                let mut copy = params
+                    .into_iter()
+                    .rev()
+                    .fold(body, |body, p| Term::Lam(p, Box::new(body)));
+                // and pass the dictionaries
+                for c in wanted {
+                    let dict = self.dict(c, types, scope)?;
+                    let t = self.dict_type(c, types);
+                    copy = Term::App(Box::new(copy), Box::new(dict), t);
+                }
+                copy
+            }
+            Elab::Method(c, i) => {
+                let dict = self.dict(c, types, scope)?;
+                let c = Constraint::new(&c.class, self.ty(&c.t, types));
+                project(dict, &self.classes.method_types(&c), *i)
+            }
+            Elab::Lam(x, body) => {
+                let name = self.fresh(x);
+                let mut scope = scope.clone();
+                scope.names.push((x.clone(), Bound::Var(name.clone())));
+                Term::Lam(name, Box::new(self.term(body, types, &scope)?))
+            }
+            Elab::App(f, a, t) => Term::App(
+                Box::new(self.term(f, types, scope)?),
+                Box::new(self.term(a, types, scope)?),
+                self.ty(t, types),
+            ),
+            Elab::Unit => Term::Unit,
+            Elab::Pair(a, b) => Term::Pair(
+                Box::new(self.term(a, types, scope)?),
+                Box::new(self.term(b, types, scope)?),
+            ),
+            Elab::Split(p, x, a, y, b, body) => {
+                let p = self.term(p, types, scope)?;
+                let (nx, ny) = (self.fresh(x), self.fresh(y));
+                let mut scope = scope.clone();
+                scope.names.push((x.clone(), Bound::Var(nx.clone())));
+                scope.names.push((y.clone(), Bound::Var(ny.clone())));
+                let body = self.term(body, types, &scope)?;
+                Term::Split(
+                    Box::new(p),
+                    nx,
+                    self.ty(a, types),
+                    ny,
+                    self.ty(b, types),
+                    Box::new(body),
+                )
+            }
+            Elab::Let(x, def, body) => {
+                let mut inner = scope.clone();
+                inner
+                    .names
+                    .push((x.clone(), Bound::Def(def.clone(), scope.clone())));
+                self.term(body, types, &inner)?
+            }
+            Elab::Record(fields) => {
+                let mut fields: Vec<&(String, Elab)> = fields.iter().collect();
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                let mut terms = Vec::new();
+                for (_, e) in fields {
+                    terms.push(self.term(e, types, scope)?);
+                }
+                tuple(terms)
+            }
+            Elab::Field(e, l, t) => {
+                let record = self.term(e, types, scope)?;
+                let fields = match self.resolve(t, types) {
+                    Type::Record(row) => sorted(row.fields),
+                    _ => unreachable!("fields are selected from records"),
+                };
+                let i = fields.iter().position(|f| f.0 == *l).expect("the field");
+                let fields: Vec<Type> = fields.iter().map(|f| core_type(&f.1)).collect();
+                project(record, &fields, i)
+            }
+            Elab::Tag(_, _, t) | Elab::Case(_, _, t) => {
+                return Err(Error::Untranslatable(self.resolve(t, types)))
+            }
+        })
+    }
+
+    fn dict_type(&self, c: &Constraint, types: &HashMap<String, Type>) -> Type {
+        let c = Constraint::new(&c.class, self.ty(&c.t, types));
+        tuple_type(&self.classes.method_types(&c))
+    }
+
+    /// The dictionary for a constraint: a parameter of a definition, or
+    /// the dictionary of an instance applied to the dictionaries for
+    /// its own constraints.
+    fn dict(
+        &mut self,
+        c: &Constraint,
+        types: &HashMap<String, Type>,
+        scope: &Scope,
+    ) -> Result<Term, Error> {
+        if let Type::Var(_) = unfold(&c.t) {
+            return match scope.dicts.iter().rev().find(|d| d.0 == *c) {
+                Some((_, name)) => Ok(Term::Var(name.clone())),
+                None => Err(Error::Ambiguous(c.clone())),
+            };
+        }
+        let (instance, bindings) = self
+            .classes
+            .instance(c)
+            .ok_or_else(|| Error::NoInstance(c.clone()))?;
+        let instance = instance.clone();
+        // the methods at the types of the variables of the head
+        let inner: HashMap<String, Type> = bindings
+            .iter()
+            .map(|(x, t)| (x.clone(), self.resolve(t, types)))
+            .collect();
+        let mut inner_scope = Scope::default();
+        let mut params = Vec::new();
+        for k in &instance.context {
+            let name = self.fresh(&format!("d{}", k.class));
+            inner_scope.dicts.push((k.clone(), name.clone()));
+            params.push(name);
+        }
+        let mut methods = Vec::new();
+        for m in &instance.methods {
+            methods.push(self.term(m, &inner, &inner_scope)?);
+        }
+        let tuple = tuple(methods);
+        let mut dict = params
+            .into_iter()
+            .rev()
+            .fold(tuple, |body, p| Term::Lam(p, Box::new(body)));
+        for k in &instance.context {
+            let t = bindings
+                .iter()
+                .fold(k.t.clone(), |t, (x, a)| subst_type(&t, x, a));
+            let k = Constraint::new(&k.class, t);
+            let arg = self.dict(&k, types, scope)?;
+            let t = self.dict_type(&k, types);
+            dict = Term::App(Box::new(dict), Box::new(arg), t);
+        }
+        Ok(dict)
+    }
+}
+
+/// The fields of a record in the order of their labels.
+fn sorted(mut fields: Vec<(String, Type)>) -> Vec<(String, Type)> {
+    fields.sort_by(|a, b| a.0.cmp(&b.0));
+    fields
+}
+
+/// Replace closed records by the tuples of their fields.
+fn core_type(t: &Type) -> Type {
+    match t {
+        Type::Sum(a, b) => Type::product(core_type(a), core_type(b)),
+        Type::Fn(a, b, _) => Type::Fn(
+            Box::new(core_type(a)),
+            Box::new(core_type(b)),
+            Effects::pure(),
+        ),
+        Type::Record(row) if row.rest.is_none() => {
+            let fields: Vec<Type> = sorted(row.fields.clone())
+                .iter()
+                .map(|f| core_type(&f.1))
+                .collect();
+            tuple_type(&fields)
+        }
+        _ => t.clone(),
+    }
+}
+
+/// The tuple of the methods of a dictionary or the fields of a record.
+fn tuple(mut methods: Vec<Term>) -> Term {
+    match methods.len() {
+        0 => Term::Unit,
+        1 => methods.remove(0),
+        _ => {
+            let m = methods.remove(0);
+            Term::Pair(Box::new(m), Box::new(tuple(methods)))
+        }
+    }
+}
+
+/// Select the element at an index of a tuple with elements of the types.
+fn project(dict: Term, methods: &[Type], i: usize) -> Term {
+    if methods.len() == 1 {
+        return dict;
+    }
+    let rest = tuple_type(&methods[1..]);
+    let body = match i {
+        0 => Term::var("m"),
+        _ => project(Term::var("rest"), &methods[1..], i - 1),
+    };
+    Term::split(dict, "m", methods[0].clone(), "rest", rest, body)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::eval::{eval_with, Store, Strategy};
+    use super::super::judgment_check;
+    use super::*;
+
+    fn bool_type() -> Type {
+        Type::Named("Bool".into(), Box::new(Type::Baz))
+    }
+
+    fn a() -> Type {
+        Type::var("a")
+    }
+
+    fn binary(t: Type, r: Type) -> Type {
+        Type::arrow(t.clone(), Type::arrow(t, r))
+    }
+
+    /// Values and comparisons of the base types, and conjunction.
+    fn primitives() -> Context {
+        Context::new()
+            .with("foo1", Type::Foo)
+            .with("foo2", Type::Foo)
+            .with("bar1", Type::Bar)
+            .with("bar2", Type::Bar)
+            .with("eq_foo", binary(Type::Foo, bool_type()))
+            .with("neq_foo", binary(Type::Foo, bool_type()))
+            .with("eq_bar", binary(Type::Bar, bool_type()))
+            .with("neq_bar", binary(Type::Bar, bool_type()))
+            .with("true", bool_type())
+            .with("and", binary(bool_type(), bool_type()))
+            .with("or", binary(bool_type(), bool_type()))
+    }
+
+    /// The methods are lambdas, to make dictionaries values.
+    fn eta(f: &str) -> Expr {
+        lam("x", lam("y", app(var(f), vec![var("x"), var("y")])))
+    }
+
+    /// eq (a1, b1) (a2, b2) = and (eq a1 a2) (eq b1 b2)
+    fn componentwise(method: &str, combine: &str) -> Expr {
+        lam(
+            "p",
+            lam(
+                "q",
+                split(
+                    var("p"),
+                    "a1",
+                    "b1",
+                    split(
+                        var("q"),
+                        "a2",
+                        "b2",
+                        app(
+                            var(combine),
+                            vec![
+                                app(var(method), vec![var("a1"), var("a2")]),
+                                app(var(method), vec![var("b1"), var("b2")]),
+                            ],
+                        ),
+                    ),
+                ),
+            ),
+        )
+    }
+
+    /// class Eq a where eq, neq : a -> a -> Bool
+    /// with instances for Foo, Bar and pairs of instances
+    fn classes() -> Classes {
+        let eq = Class {
+            name: "Eq".into(),
+            var: "a".into(),
+            methods: vec![
+                ("eq".into(), binary(a(), bool_type())),
+                ("neq".into(), binary(a(), bool_type())),
+            ],
+        };
+        let base = |t: Type, eq: &str, neq: &str| Instance {
+            class: "Eq".into(),
+            context: vec![],
+            head: t,
+            methods: vec![("eq".into(), eta(eq)), ("neq".into(), eta(neq))],
+        };
+        let pairs = Instance {
+            class: "Eq".into(),
+            context: vec![
+                Constraint::new("Eq", a()),
+                Constraint::new("Eq", Type::var("b")),
+            ],
+            head: Type::product(a(), Type::var("b")),
+            methods: vec![
+                ("eq".into(), componentwise("eq", "and")),
+                ("neq".into(), componentwise("neq", "or")),
+            ],
+        };
+        let instances = vec![
+            base(Type::Foo, "eq_foo", "neq_foo"),
+            base(Type::Bar, "eq_bar", "neq_bar"),
+            pairs,
+        ];
+        Classes::new(&primitives(), vec![eq], instances).unwrap()
+    }
+
+    fn scheme(e: &Expr) -> String {
+        infer(&primitives(), &classes(), e).unwrap().to_string()
+    }
+
+    fn error(e: &Expr) -> String {
+        elaborate(&primitives(), &classes(), e)
+            .unwrap_err()
+            .to_string()
+    }
+
+    /// Elaborate a program, check the core term and run it by name,
+    /// since the primitives are free variables.
+    fn run(e: &Expr) -> (Type, String) {
+        let (term, t) = elaborate(&primitives(), &classes(), e).unwrap();
+        assert!(judgment_check(&primitives(), &term, &t), "{}", term);
+        let value = eval_with(&term, &mut Store::new(), Strategy::CallByName);
+        (t, value.to_string())
+    }
+
+    #[test]
+    fn principal_types() {
+        assert_eq!(scheme(&lam("x", var("x"))), "forall a. a -> a");
+        let twice = lam(
+            "f",
+            lam("x", app(var("f"), vec![app(var("f"), vec![var("x")])])),
+        );
+        assert_eq!(scheme(&twice), "forall a. (a -> a) -> a -> a");
+        let swap = lam("p", split(var("p"), "x", "y", pair(var("y"), var("x"))));
+        assert_eq!(scheme(&swap), "forall a b. a * b -> b * a");
+        // a let-bound definition is used at two types
+        let e = let_in(
+            "id",
+            lam("x", var("x")),
+            pair(
+                app(var("id"), vec![Expr::Unit]),
+                app(var("id"), vec![var("foo1")]),
+            ),
+        );
+        assert_eq!(scheme(&e), "Unit * Foo");
+        // a lambda-bound one is not
+        let e = lam(
+            "id",
+            pair(
+                app(var("id"), vec![Expr::Unit]),
+                app(var("id"), vec![var("foo1")]),
+            ),
+        );
+        assert_eq!(
+            infer(&primitives(), &classes(), &e)
+                .unwrap_err()
+                .to_string(),
+            "can't unify Unit with Foo"
+        );
+        let e = lam("x", app(var("x"), vec![var("x")]));
+        assert_eq!(
+            infer(&primitives(), &classes(), &e)
+                .unwrap_err()
+                .to_string(),
+            "a occurs in a -> b"
+        );
+    }
+
+    #[test]
+    fn constrained_types() {
+        let same = lam("x", lam("y", app(var("eq"), vec![var("x"), var("y")])));
+        assert_eq!(scheme(&same), "forall a. Eq a => a -> a -> Bool");
+        // the constraint on a pair is reduced to its components
+        let e = lam(
+            "x",
+            lam(
+                "y",
+                app(
+                    var("eq"),
+                    vec![pair(var("x"), var("y")), pair(var("x"), var("y"))],
+                ),
+            ),
+        );
+        assert_eq!(scheme(&e), "forall a b. (Eq a, Eq b) => a -> b -> Bool");
+        // constraints on known types are solved
+        let e = lam("x", app(var("neq"), vec![var("x"), var("foo1")]));
+        assert_eq!(scheme(&e), "Foo -> Bool");
+    }
+
+    #[test]
+    fn dictionary_passing() {
+        // let same = \x. \y. eq x y in same foo1 foo2
+        let same = lam("x", lam("y", app(var("eq"), vec![var("x"), var("y")])));
+        let e = let_in(
+            "same",
+            same.clone(),
+            app(var("same"), vec![var("foo1"), var("foo2")]),
+        );
+        let (term, t) = elaborate(&primitives(), &classes(), &e).unwrap();
+        assert_eq!(t, bool_type());
+        // the definition takes the dictionary, a pair of the methods
+        assert_eq!(
+            term.to_string(),
+            "(\\dEq. \\x. \\y. (split dEq as (m, rest) in m) x y) (\\x1. \\y1. eq_foo x1 y1, \\x2. \\y2. neq_foo x2 y2) foo1 foo2"
+        );
+        assert!(judgment_check(&primitives(), &term, &t));
+        assert_eq!(run(&e).1, "eq_foo foo1 foo2");
+
+        // the dictionary of pairs is built from the ones of the components
+        let pairs = app(
+            var("same"),
+            vec![
+                pair(var("foo1"), var("bar1")),
+                pair(var("foo2"), var("bar2")),
+            ],
+        );
+        let both = pair(
+            app(var("same"), vec![var("bar1"), var("bar2")]),
+            pairs.clone(),
+        );
+        let (term, t) = elaborate(
+            &primitives(),
+            &classes(),
+            &let_in("same", same.clone(), both),
+        )
+        .unwrap();
+        assert_eq!(t, Type::product(bool_type(), bool_type()));
+        assert!(judgment_check(&primitives(), &term, &t));
+
+        // a definition with a constraint passes its dictionary on
+        let e = let_in(
+            "differ",
+            lam("x", lam("y", app(var("neq"), vec![var("x"), var("y")]))),
+            let_in(
+                "both",
+                lam(
+                    "x",
+                    lam(
+                        "y",
+                        app(
+                            var("differ"),
+                            vec![pair(var("x"), var("x")), pair(var("y"), var("y"))],
+                        ),
+                    ),
+                ),
+                app(var("both"), vec![var("foo1"), var("foo2")]),
+            ),
+        );
+        let (term, t) = elaborate(&primitives(), &classes(), &e).unwrap();
+        assert!(judgment_check(&primitives(), &term, &t));
+        // both hands its dictionary twice to the instance for pairs
+        assert!(
+            term.to_string().contains("dEq dEq) (x, x) (y, y)"),
+            "{}",
+            term
+        );
+    }
+
+    #[test]
+    fn extensible_records() {
+        let get_x = lam("r", field(var("r"), "x"));
+        assert_eq!(scheme(&get_x), "forall a ρ. {x: a | ρ} -> a");
+        let both = lam("r", pair(field(var("r"), "x"), field(var("r"), "y")));
+        assert_eq!(scheme(&both), "forall a b ρ. {x: a, y: b | ρ} -> a * b");
+        // any record with the field will do
+        let e = let_in(
+            "get",
+            get_x.clone(),
+            pair(
+                app(
+                    var("get"),
+                    vec![record(vec![("y", var("bar1")), ("x", var("foo1"))])],
+                ),
+                app(var("get"), vec![record(vec![("x", Expr::Unit)])]),
+            ),
+        );
+        assert_eq!(scheme(&e), "Foo * Unit");
+        // the order of the fields doesn't matter
+        let twice = lam(
+            "f",
+            pair(
+                app(
+                    var("f"),
+                    vec![record(vec![("x", var("foo1")), ("y", var("bar1"))])],
+                ),
+                app(
+                    var("f"),
+                    vec![record(vec![("y", var("bar1")), ("x", var("foo1"))])],
+                ),
+            ),
+        );
+        assert_eq!(scheme(&twice), "forall a. ({x: Foo, y: Bar} -> a) -> a * a");
+        // but the labels do
+        let e = app(
+            lam("r", field(var("r"), "z")),
+            vec![record(vec![("x", var("foo1"))])],
+        );
+        assert_eq!(
+            infer(&primitives(), &classes(), &e)
+                .unwrap_err()
+                .to_string(),
+            "can't unify {z: a | ρ} with {x: Foo}"
+        );
+        let e = record(vec![("x", var("foo1")), ("x", var("foo2"))]);
+        assert_eq!(
+            infer(&primitives(), &classes(), &e)
+                .unwrap_err()
+                .to_string(),
+            "'x' is already defined"
+        );
+    }
+
+    #[test]
+    fn extensible_variants() {
+        assert_eq!(
+            scheme(&tag("some", var("foo1"))),
+            "forall ρ. <some: Foo | ρ>"
+        );
+        let either = lam(
+            "v",
+            case(
+                var("v"),
+                vec![("left", "x", var("x")), ("right", "y", var("y"))],
+            ),
+        );
+        assert_eq!(scheme(&either), "forall a. <left: a, right: a> -> a");
+        let e = app(either.clone(), vec![tag("right", var("foo1"))]);
+        assert_eq!(scheme(&e), "Foo");
+        let e = app(either, vec![tag("other", var("foo1"))]);
+        assert_eq!(
+            infer(&primitives(), &classes(), &e)
+                .unwrap_err()
+                .to_string(),
+            "can't unify <left: a, right: a> with <other: Foo | ρ>"
+        );
+    }
+
+    #[test]
+    fn records_become_tuples() {
+        // the copies of get select from records with different fields
+        let e = let_in(
+            "get",
+            lam("r", field(var("r"), "x")),
+            pair(
+                app(
+                    var("get"),
+                    vec![record(vec![("y", var("bar1")), ("x", var("foo1"))])],
+                ),
+                app(var("get"), vec![record(vec![("x", var("bar2"))])]),
+            ),
+        );
+        let (term, t) = elaborate(&primitives(), &classes(), &e).unwrap();
+        assert_eq!(t, Type::product(Type::Foo, Type::Bar));
+        assert!(judgment_check(&primitives(), &term, &t), "{}", term);
+        assert_eq!(
+            term.to_string(),
+            "((\\r. split r as (m, rest) in m) (foo1, bar1), (\\r1. r1) bar2)"
+        );
+        assert_eq!(
+            error(&tag("some", var("foo1"))),
+            "the core has no variants like <some: Foo>"
+        );
+    }
+
+    #[test]
+    fn unsolvable_constraints() {
+        // no instance for functions
+        let e = app(var("eq"), vec![lam("x", var("x")), lam("x", var("x"))]);
+        assert_eq!(error(&e), "no instance for Eq (a -> a)");
+        assert_eq!(
+            error(&app(var("eq"), vec![var("true")])),
+            "no instance for Eq Bool"
+        );
+        // nothing fixes the type of the method
+        let e = app(lam("f", Expr::Unit), vec![var("eq")]);
+        assert_eq!(error(&e), "the constraint Eq a is ambiguous");
+        assert_eq!(error(&var("equal")), "'equal' is not defined");
+    }
+
+    #[test]
+    fn invalid_declarations() {
+        let g = primitives();
+        let eq = classes().classes[0].clone();
+        let instance = |head: Type, methods: Vec<(&str, Expr)>| Instance {
+            class: "Eq".into(),
+            context: vec![],
+            head,
+            methods: methods.into_iter().map(|(m, e)| (m.into(), e)).collect(),
+        };
+        let foo = instance(
+            Type::Foo,
+            vec![("eq", var("eq_foo")), ("neq", var("neq_foo"))],
+        );
+        let error = |instances: Vec<Instance>| {
+            Classes::new(&g, vec![eq.clone()], instances)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error(vec![instance(Type::Foo, vec![("eq", var("eq_foo"))])]),
+            "the instance Eq Foo lacks 'neq'"
+        );
+        assert_eq!(
+            error(vec![instance(
+                Type::Bar,
+                vec![("eq", var("eq_foo")), ("neq", var("neq_bar"))]
+            )]),
+            "can't unify Foo with Bar"
+        );
+        let pairs = |t| {
+            instance(
+                Type::product(Type::Foo, t),
+                vec![
+                    ("eq", componentwise("eq", "and")),
+                    ("neq", componentwise("neq", "or")),
+                ],
+            )
+        };
+        assert_eq!(
+            error(vec![foo.clone(), pairs(a()), pairs(Type::var("b"))]),
+            "the instances Eq (Foo * a) and Eq (Foo * b) overlap"
+        );
+        // the methods of a pair of Foo and a need an instance for a
+        assert_eq!(error(vec![foo, pairs(a())]), "no instance for Eq a");
+        assert_eq!(
+            Classes::new(&g, vec![eq.clone(), eq], vec![])
+                .unwrap_err()
+                .to_string(),
+            "'Eq' is already defined"
+        );
+    }
+}
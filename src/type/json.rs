@@ -0,0 +1,1065 @@
+//! A JSON schema for terms, types and contexts.
+//!
+//! Every type and term is an object whose `tag` names the constructor,
+//! the other fields are named after the parts:
+//!
+//! ```text
+//! type := {"tag": "foo"} | {"tag": "bar"} | {"tag": "baz"} | {"tag": "unit"}
+//!       | {"tag": "product", "left": type, "right": type}
+//!       | {"tag": "fn", "input": type, "output": type, "effects": [string, ...]}
+//!       | {"tag": "ref", "content": type}
+//!       | {"tag": "var", "name": string}
+//!       | {"tag": "exists", "var": string, "body": type}
+//!       | {"tag": "forall", "var": string, "body": type}
+//!       | {"tag": "lam", "var": string, "kind": kind, "body": type}
+//!       | {"tag": "app", "fun": type, "arg": type}
+//!       | {"tag": "named", "name": string, "definition": type}
+//!       | {"tag": "dyn"}
+//!       | {"tag": "and", "left": type, "right": type}
+//!       | {"tag": "or", "left": type, "right": type}
+//!       | {"tag": "record", "fields": [field, ...], "rest": string?}
+//!       | {"tag": "variant", "fields": [field, ...], "rest": string?}
+//! field := {"label": string, "type": type}
+//! kind := {"tag": "star"} | {"tag": "arrow", "input": kind, "output": kind}
+//! term := {"tag": "var", "name": string}
+//!       | {"tag": "lam", "name": string, "body": term}
+//!       | {"tag": "app", "fun": term, "arg": term, "arg_type": type}
+//!       | {"tag": "pair", "left": term, "right": term}
+//!       | {"tag": "split", "pair": term, "left": string, "left_type": type,
+//!          "right": string, "right_type": type, "body": term}
+//!       | {"tag": "unit"}
+//!       | {"tag": "ref", "init": term, "type": type}
+//!       | {"tag": "deref", "cell": term}
+//!       | {"tag": "assign", "cell": term, "value": term, "type": type}
+//!       | {"tag": "loc", "index": number}
+//!       | {"tag": "pack", "witness": type, "body": term, "type": type}
+//!       | {"tag": "unpack", "package": term, "type": type, "type_var": string,
+//!          "name": string, "body": term}
+//!       | {"tag": "cast", "term": term, "from": type, "to": type, "label": string}
+//!       | {"tag": "blame", "label": string, "expected": type, "found": type}
+//!       | {"tag": "raise", "exception": string}
+//!       | {"tag": "try", "body": term, "exception": string, "handler": term}
+//! context := [{"name": string, "type": type}, ...]
+//! ```
+//!
+//! The context lists the outermost binding first. `type check-json`
+//! reads documents `{"context": context, "term": term, "type": type}`
+//! and writes one result `{"ok": bool, "errors": [...]}` per line. An
+//! error has a `kind`, a `message` and the `path` of the offending
+//! part of the document.
+use std::io::{self, Read, Write};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::eval::is_value;
+use super::{
+    consistent, free_in_context, free_in_type, judgment, judgment_ctx, subst_type, subtype,
+    type_equality, unfold, Context, Effects, Kind, Term, Type,
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "tag", rename_all = "lowercase", deny_unknown_fields)]
+pub enum TypeRepr {
+    Foo,
+    Bar,
+    Baz,
+    Unit,
+    Product {
+        left: Type,
+        right: Type,
+    },
+    Fn {
+        input: Type,
+        output: Type,
+        #[serde(default, skip_serializing_if = "Effects::is_pure")]
+        effects: Effects,
+    },
+    Ref {
+        content: Type,
+    },
+    Var {
+        name: String,
+    },
+    Exists {
+        var: String,
+        body: Type,
+    },
+    Forall {
+        var: String,
+        body: Type,
+    },
+    Lam {
+        var: String,
+        kind: Kind,
+        body: Type,
+    },
+    App {
+        fun: Type,
+        arg: Type,
+    },
+    Named {
+        name: String,
+        definition: Type,
+    },
+    Dyn,
+    And {
+        left: Type,
+        right: Type,
+    },
+    Or {
+        left: Type,
+        right: Type,
+    },
+    Record {
+        fields: Vec<Field>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rest: Option<String>,
+    },
+    Variant {
+        fields: Vec<Field>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rest: Option<String>,
+    },
+}
+
+/// A labelled field of a record or variant type.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Field {
+    label: String,
+    #[serde(rename = "type")]
+    t: Type,
+}
+
+fn from_fields(fields: Vec<Field>) -> Vec<(String, Type)> {
+    fields.into_iter().map(|f| (f.label, f.t)).collect()
+}
+
+fn into_fields(fields: Vec<(String, Type)>) -> Vec<Field> {
+    fields
+        .into_iter()
+        .map(|(label, t)| Field { label, t })
+        .collect()
+}
+
+impl From<TypeRepr> for Type {
+    fn from(t: TypeRepr) -> Type {
+        match t {
+            TypeRepr::Foo => Type::Foo,
+            TypeRepr::Bar => Type::Bar,
+            TypeRepr::Baz => Type::Baz,
+            TypeRepr::Unit => Type::Unit,
+            TypeRepr::Product { left, right } => Type::Sum(Box::new(left), Box::new(right)),
+            TypeRepr::Fn {
+                input,
+                output,
+                effects,
+            } => Type::Fn(Box::new(input), Box::new(output), effects),
+            TypeRepr::Ref { content } => Type::Ref(Box::new(content)),
+            TypeRepr::Var { name } => Type::Var(name),
+            TypeRepr::Exists { var, body } => Type::Exists(var, Box::new(body)),
+            TypeRepr::Forall { var, body } => Type::Forall(var, Box::new(body)),
+            TypeRepr::Lam { var, kind, body } => Type::Lam(var, kind, Box::new(body)),
+            TypeRepr::App { fun, arg } => Type::App(Box::new(fun), Box::new(arg)),
+            TypeRepr::Named { name, definition } => Type::Named(name, Box::new(definition)),
+            TypeRepr::Dyn => Type::Dyn,
+            TypeRepr::And { left, right } => Type::And(Box::new(left), Box::new(right)),
+            TypeRepr::Or { left, right } => Type::Or(Box::new(left), Box::new(right)),
+            TypeRepr::Record { fields, rest } => Type::record(from_fields(fields), rest),
+            TypeRepr::Variant { fields, rest } => Type::variant(from_fields(fields), rest),
+        }
+    }
+}
+
+impl From<Type> for TypeRepr {
+    fn from(t: Type) -> TypeRepr {
+        match t {
+            Type::Foo => TypeRepr::Foo,
+            Type::Bar => TypeRepr::Bar,
+            Type::Baz => TypeRepr::Baz,
+            Type::Unit => TypeRepr::Unit,
+            Type::Sum(left, right) => TypeRepr::Product {
+                left: *left,
+                right: *right,
+            },
+            Type::Fn(input, output, effects) => TypeRepr::Fn {
+                input: *input,
+                output: *output,
+                effects,
+            },
+            Type::Ref(content) => TypeRepr::Ref { content: *content },
+            Type::Var(name) => TypeRepr::Var { name },
+            Type::Exists(var, body) => TypeRepr::Exists { var, body: *body },
+            Type::Forall(var, body) => TypeRepr::Forall { var, body: *body },
+            Type::Lam(var, kind, body) => TypeRepr::Lam {
+                var,
+                kind,
+                body: *body,
+            },
+            Type::App(fun, arg) => TypeRepr::App {
+                fun: *fun,
+                arg: *arg,
+            },
+            Type::Named(name, definition) => TypeRepr::Named {
+                name,
+                definition: *definition,
+            },
+            Type::Dyn => TypeRepr::Dyn,
+            Type::And(left, right) => TypeRepr::And {
+                left: *left,
+                right: *right,
+            },
+            Type::Or(left, right) => TypeRepr::Or {
+                left: *left,
+                right: *right,
+            },
+            Type::Record(row) => TypeRepr::Record {
+                fields: into_fields(row.fields),
+                rest: row.rest,
+            },
+            Type::Variant(row) => TypeRepr::Variant {
+                fields: into_fields(row.fields),
+                rest: row.rest,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "tag", rename_all = "lowercase", deny_unknown_fields)]
+pub enum KindRepr {
+    Star,
+    Arrow { input: Kind, output: Kind },
+}
+
+impl From<KindRepr> for Kind {
+    fn from(k: KindRepr) -> Kind {
+        match k {
+            KindRepr::Star => Kind::Star,
+            KindRepr::Arrow { input, output } => Kind::Arrow(Box::new(input), Box::new(output)),
+        }
+    }
+}
+
+impl From<Kind> for KindRepr {
+    fn from(k: Kind) -> KindRepr {
+        match k {
+            Kind::Star => KindRepr::Star,
+            Kind::Arrow(input, output) => KindRepr::Arrow {
+                input: *input,
+                output: *output,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "tag", rename_all = "lowercase", deny_unknown_fields)]
+pub enum TermRepr {
+    Var {
+        name: String,
+    },
+    Lam {
+        name: String,
+        body: Term,
+    },
+    App {
+        fun: Term,
+        arg: Term,
+        arg_type: Type,
+    },
+    Pair {
+        left: Term,
+        right: Term,
+    },
+    Split {
+        pair: Term,
+        left: String,
+        left_type: Type,
+        right: String,
+        right_type: Type,
+        body: Term,
+    },
+    Unit,
+    Ref {
+        init: Term,
+        #[serde(rename = "type")]
+        init_type: Type,
+    },
+    Deref {
+        cell: Term,
+    },
+    Assign {
+        cell: Term,
+        value: Term,
+        #[serde(rename = "type")]
+        value_type: Type,
+    },
+    Loc {
+        index: usize,
+    },
+    Pack {
+        witness: Type,
+        body: Term,
+        #[serde(rename = "type")]
+        package_type: Type,
+    },
+    Unpack {
+        package: Term,
+        #[serde(rename = "type")]
+        package_type: Type,
+        type_var: String,
+        name: String,
+        body: Term,
+    },
+    Cast {
+        term: Term,
+        from: Type,
+        to: Type,
+        label: String,
+    },
+    Blame {
+        label: String,
+        expected: Type,
+        found: Type,
+    },
+    Raise {
+        exception: String,
+    },
+    Try {
+        body: Term,
+        exception: String,
+        handler: Term,
+    },
+}
+
+impl From<TermRepr> for Term {
+    fn from(t: TermRepr) -> Term {
+        match t {
+            TermRepr::Var { name } => Term::Var(name),
+            TermRepr::Lam { name, body } => Term::Lam(name, Box::new(body)),
+            TermRepr::App { fun, arg, arg_type } => {
+                Term::App(Box::new(fun), Box::new(arg), arg_type)
+            }
+            TermRepr::Pair { left, right } => Term::Pair(Box::new(left), Box::new(right)),
+            TermRepr::Split {
+                pair,
+                left,
+                left_type,
+                right,
+                right_type,
+                body,
+            } => Term::Split(
+                Box::new(pair),
+                left,
+                left_type,
+                right,
+                right_type,
+                Box::new(body),
+            ),
+            TermRepr::Unit => Term::Unit,
+            TermRepr::Ref { init, init_type } => Term::Ref(Box::new(init), init_type),
+            TermRepr::Deref { cell } => Term::Deref(Box::new(cell)),
+            TermRepr::Assign {
+                cell,
+                value,
+                value_type,
+            } => Term::Assign(Box::new(cell), Box::new(value), value_type),
+            TermRepr::Loc { index } => Term::Loc(index),
+            TermRepr::Pack {
+                witness,
+                body,
+                package_type,
+            } => Term::Pack(witness, Box::new(body), package_type),
+            TermRepr::Unpack {
+                package,
+                package_type,
+                type_var,
+                name,
+                body,
+            } => Term::Unpack(
+                Box::new(package),
+                package_type,
+                type_var,
+                name,
+                Box::new(body),
+            ),
+            TermRepr::Cast {
+                term,
+                from,
+                to,
+                label,
+            } => Term::Cast(Box::new(term), from, to, label),
+            TermRepr::Blame {
+                label,
+                expected,
+                found,
+            } => Term::Blame(label, expected, found),
+            TermRepr::Raise { exception } => Term::Raise(exception),
+            TermRepr::Try {
+                body,
+                exception,
+                handler,
+            } => Term::Try(Box::new(body), exception, Box::new(handler)),
+        }
+    }
+}
+
+impl From<Term> for TermRepr {
+    fn from(t: Term) -> TermRepr {
+        match t {
+            Term::Var(name) => TermRepr::Var { name },
+            Term::Lam(name, body) => TermRepr::Lam { name, body: *body },
+            Term::App(fun, arg, arg_type) => TermRepr::App {
+                fun: *fun,
+                arg: *arg,
+                arg_type,
+            },
+            Term::Pair(left, right) => TermRepr::Pair {
+                left: *left,
+                right: *right,
+            },
+            Term::Split(pair, left, left_type, right, right_type, body) => TermRepr::Split {
+                pair: *pair,
+                left,
+                left_type,
+                right,
+                right_type,
+                body: *body,
+            },
+            Term::Unit => TermRepr::Unit,
+            Term::Ref(init, init_type) => TermRepr::Ref {
+                init: *init,
+                init_type,
+            },
+            Term::Deref(cell) => TermRepr::Deref { cell: *cell },
+            Term::Assign(cell, value, value_type) => TermRepr::Assign {
+                cell: *cell,
+                value: *value,
+                value_type,
+            },
+            Term::Loc(index) => TermRepr::Loc { index },
+            Term::Pack(witness, body, package_type) => TermRepr::Pack {
+                witness,
+                body: *body,
+                package_type,
+            },
+            Term::Unpack(package, package_type, type_var, name, body) => TermRepr::Unpack {
+                package: *package,
+                package_type,
+                type_var,
+                name,
+                body: *body,
+            },
+            Term::Cast(term, from, to, label) => TermRepr::Cast {
+                term: *term,
+                from,
+                to,
+                label,
+            },
+            Term::Blame(label, expected, found) => TermRepr::Blame {
+                label,
+                expected,
+                found,
+            },
+            Term::Raise(exception) => TermRepr::Raise { exception },
+            Term::Try(body, exception, handler) => TermRepr::Try {
+                body: *body,
+                exception,
+                handler: *handler,
+            },
+        }
+    }
+}
+
+/// A binding of a context.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Binding {
+    name: String,
+    #[serde(rename = "type")]
+    t: Type,
+}
+
+impl From<Vec<Binding>> for Context {
+    fn from(bindings: Vec<Binding>) -> Context {
+        bindings.into_iter().fold(Context::Empty(), |g, b| {
+            Context::Snoc(Box::new(g), b.name, b.t)
+        })
+    }
+}
+
+impl From<Context> for Vec<Binding> {
+    fn from(g: Context) -> Vec<Binding> {
+        let mut bindings = Vec::new();
+        let mut g = g;
+        while let Context::Snoc(rest, name, t) = g {
+            bindings.push(Binding { name, t });
+            g = *rest;
+        }
+        bindings.reverse();
+        bindings
+    }
+}
+
+/// An error together with the path of the part of the document it is about.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Error {
+    pub kind: &'static str,
+    pub path: String,
+    pub message: String,
+}
+
+fn type_error(path: &str, message: String) -> Option<Error> {
+    Some(Error {
+        kind: "type",
+        path: path.into(),
+        message,
+    })
+}
+
+fn lookup<'a>(g: &'a Context, x: &str) -> Option<&'a Type> {
+    match g {
+        Context::Empty() => None,
+        Context::Snoc(_, name, t) if name == x => Some(t),
+        Context::Snoc(rest, _, _) => lookup(rest, x),
+    }
+}
+
+/// The variable and body of a well-kinded existential type.
+fn existential(t: &Type) -> Option<(String, Box<Type>)> {
+    match judgment(t).then(|| unfold(t)) {
+        Some(Type::Exists(x, inner)) => Some((x, inner)),
+        _ => None,
+    }
+}
+
+/// Check a term like `judgment_check` and explain the first failure
+/// with the path of the subterm where it happens.
+pub fn explain(g: &Context, term: &Term, t: &Type, path: &str) -> Option<Error> {
+    explain_effects(g, term, t, &Effects::pure(), path)
+}
+
+/// Explain the first failure of a term which may raise the exceptions `e`.
+fn explain_effects(g: &Context, term: &Term, t: &Type, e: &Effects, path: &str) -> Option<Error> {
+    let snoc =
+        |g: &Context, x: &str, t: &Type| Context::Snoc(Box::new(g.clone()), x.into(), t.clone());
+    let at = |field: &str| format!("{}/{}", path, field);
+    if !judgment(t) {
+        return Some(Error {
+            kind: "kind",
+            path: path.into(),
+            message: format!("{} is not the type of a term", t),
+        });
+    }
+    let t = &unfold(t);
+    match t {
+        Type::And(l, r) if is_value(term) => {
+            return explain_effects(g, term, l, e, path)
+                .or_else(|| explain_effects(g, term, r, e, path));
+        }
+        Type::Forall(x, _) if is_value(term) && free_in_context(x, g) => {
+            return type_error(path, format!("the type variable {} is already bound", x));
+        }
+        Type::Forall(_, body) if is_value(term) => {
+            return explain_effects(g, term, body, e, path);
+        }
+        Type::Or(l, r)
+            if explain_effects(g, term, l, e, path).is_none()
+                || explain_effects(g, term, r, e, path).is_none() =>
+        {
+            return None;
+        }
+        _ => (),
+    }
+    match (term, t) {
+        (Term::Pair(a, b), Type::Sum(l, r)) => explain_effects(g, a, l, e, &at("left"))
+            .or_else(|| explain_effects(g, b, r, e, &at("right"))),
+        (Term::Pair(_, _), _) => type_error(path, format!("expected {}, found a pair", t)),
+        (Term::Split(p, x, a, y, b, body), _) => {
+            let pair = Type::Sum(Box::new(a.clone()), Box::new(b.clone()));
+            explain_effects(g, p, &pair, e, &at("pair"))
+                .or_else(|| explain_effects(&snoc(&snoc(g, x, a), y, b), body, t, e, &at("body")))
+        }
+        (Term::Lam(x, body), Type::Fn(i, o, f)) => {
+            let error = explain_effects(&snoc(g, x, i), body, o, f, &at("body"))?;
+            match unfold(i) {
+                Type::Or(l, r) => [l, r]
+                    .iter()
+                    .map(|i| Type::Fn(i.clone(), o.clone(), f.clone()))
+                    .find_map(|case| explain_effects(g, term, &case, e, path)),
+                _ => Some(error),
+            }
+        }
+        (Term::Lam(_, _), _) => type_error(path, format!("expected {}, found a function", t)),
+        (Term::App(f, a, type_arg), _) => {
+            let fun = Type::Fn(Box::new(type_arg.clone()), Box::new(t.clone()), e.clone());
+            explain_effects(g, f, &fun, e, &at("fun"))
+                .or_else(|| explain_effects(g, a, type_arg, e, &at("arg")))
+        }
+        (Term::Var(x), _) => match lookup(g, x) {
+            None => type_error(path, format!("unbound variable '{}'", x)),
+            Some(found) if !subtype(found, t) => {
+                type_error(path, format!("expected {}, found {}", t, found))
+            }
+            Some(_) => None,
+        },
+        (&Term::Unit, &Type::Unit) => None,
+        (&Term::Unit, _) => type_error(path, format!("expected {}, found Unit", t)),
+        (Term::Ref(init, type_init), Type::Ref(inner)) => {
+            if !type_equality(type_init, inner) {
+                return type_error(path, format!("expected {}, found {}", inner, type_init));
+            }
+            explain_effects(g, init, inner, e, &at("init"))
+        }
+        (Term::Ref(_, _), _) => type_error(path, format!("expected {}, found a reference", t)),
+        (Term::Deref(cell), _) => {
+            explain_effects(g, cell, &Type::Ref(Box::new(t.clone())), e, &at("cell"))
+        }
+        (Term::Assign(cell, value, type_value), &Type::Unit) => {
+            let cell_type = Type::Ref(Box::new(type_value.clone()));
+            explain_effects(g, cell, &cell_type, e, &at("cell"))
+                .or_else(|| explain_effects(g, value, type_value, e, &at("value")))
+        }
+        (Term::Assign(_, _, _), _) => type_error(path, format!("expected {}, found Unit", t)),
+        // a closed program has no locations, they only appear during evaluation
+        (Term::Loc(l), _) => type_error(path, format!("unknown location {}", l)),
+        (Term::Pack(witness, body, package), _) => match existential(package) {
+            Some((x, inner)) => {
+                if !type_equality(package, t) {
+                    return type_error(path, format!("expected {}, found {}", t, package));
+                }
+                explain_effects(g, body, &subst_type(&inner, &x, witness), e, &at("body"))
+            }
+            None => type_error(
+                path,
+                format!("a package must have an existential type, found {}", package),
+            ),
+        },
+        (Term::Unpack(package, package_type, x_type, x, body), _) => {
+            let (y, inner) = match existential(package_type) {
+                Some(exists) => exists,
+                None => {
+                    return type_error(
+                        path,
+                        format!(
+                            "a package must have an existential type, found {}",
+                            package_type
+                        ),
+                    )
+                }
+            };
+            if free_in_type(x_type, t) || free_in_context(x_type, g) {
+                return type_error(
+                    path,
+                    format!("the type variable {} escapes its scope", x_type),
+                );
+            }
+            let content = subst_type(&inner, &y, &Type::Var(x_type.clone()));
+            explain_effects(g, package, &Type::Exists(y, inner), e, &at("package"))
+                .or_else(|| explain_effects(&snoc(g, x, &content), body, t, e, &at("body")))
+        }
+        (Term::Cast(inner, from, to, _), _) => {
+            if !type_equality(to, t) {
+                return type_error(path, format!("expected {}, found {}", t, to));
+            }
+            if !consistent(from, to) {
+                return type_error(path, format!("{} is not consistent with {}", from, to));
+            }
+            explain_effects(g, inner, from, e, &at("term"))
+        }
+        (Term::Blame(_, _, _), _) => None,
+        (Term::Raise(x), _) if e.contains(x) => None,
+        (Term::Raise(x), _) => Some(Error {
+            kind: "effect",
+            path: path.into(),
+            message: format!("the exception {} may not be raised here", x),
+        }),
+        (Term::Try(body, x, handler), _) => explain_effects(g, body, t, &e.with(x), &at("body"))
+            .or_else(|| explain_effects(g, handler, t, e, &at("handler"))),
+    }
+}
+
+/// Decode one field of a document.
+fn field<T>(doc: &Value, name: &str, errors: &mut Vec<Error>) -> Option<T>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    let value = match doc.get(name) {
+        Some(value) => value.clone(),
+        None if name == "context" => json!([]),
+        None => {
+            errors.push(Error {
+                kind: "schema",
+                path: format!("/{}", name),
+                message: format!("missing field `{}`", name),
+            });
+            return None;
+        }
+    };
+    match serde_json::from_value(value) {
+        Ok(t) => Some(t),
+        Err(e) => {
+            errors.push(Error {
+                kind: "schema",
+                path: format!("/{}", name),
+                message: e.to_string(),
+            });
+            None
+        }
+    }
+}
+
+/// Check a document `{context, term, type}` and return the errors.
+pub fn check_document(doc: &Value) -> Vec<Error> {
+    let mut errors = Vec::new();
+    if !doc.is_object() {
+        errors.push(Error {
+            kind: "schema",
+            path: "".into(),
+            message: "expected an object with context, term and type".into(),
+        });
+        return errors;
+    }
+    let g: Option<Context> = field(doc, "context", &mut errors);
+    let term: Option<Term> = field(doc, "term", &mut errors);
+    let t: Option<Type> = field(doc, "type", &mut errors);
+    if let (Some(g), Some(term), Some(t)) = (g, term, t) {
+        if !judgment_ctx(&g) {
+            errors.push(Error {
+                kind: "context",
+                path: "/context".into(),
+                message: "a name is bound more than once".into(),
+            });
+        }
+        errors.extend(explain(&g, &term, &t, "/term"));
+    }
+    errors
+}
+
+/// Read documents until the end of the input and write
+/// a result for every one of them.
+pub fn check_documents<R: Read, W: Write>(input: R, mut output: W) -> io::Result<()> {
+    for doc in serde_json::Deserializer::from_reader(input).into_iter::<Value>() {
+        let errors = match doc {
+            Ok(doc) => check_document(&doc),
+            Err(e) => {
+                // the rest of the input can't be read reliably
+                let error = Error {
+                    kind: "syntax",
+                    path: "".into(),
+                    message: e.to_string(),
+                };
+                writeln!(output, "{}", json!({ "ok": false, "errors": [error] }))?;
+                break;
+            }
+        };
+        writeln!(
+            output,
+            "{}",
+            json!({ "ok": errors.is_empty(), "errors": errors })
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::nbe::alpha_equal;
+    use super::super::{counter_context, counter_type, counters, examples, judgment_check};
+    use super::*;
+
+    #[test]
+    fn schema() {
+        let t = Type::Fn(
+            Box::new(Type::Sum(Box::new(Type::Foo), Box::new(Type::Unit))),
+            Box::new(Type::Ref(Box::new(Type::Bar))),
+            Effects::pure(),
+        );
+        let expected = json!({
+            "tag": "fn",
+            "input": { "tag": "product", "left": { "tag": "foo" }, "right": { "tag": "unit" } },
+            "output": { "tag": "ref", "content": { "tag": "bar" } },
+        });
+        assert_eq!(serde_json::to_value(&t).unwrap(), expected);
+        let term = Term::App(
+            Box::new(Term::Lam("x".into(), Box::new(Term::Var("x".into())))),
+            Box::new(Term::Unit),
+            Type::Unit,
+        );
+        let expected = json!({
+            "tag": "app",
+            "fun": { "tag": "lam", "name": "x", "body": { "tag": "var", "name": "x" } },
+            "arg": { "tag": "unit" },
+            "arg_type": { "tag": "unit" },
+        });
+        assert_eq!(serde_json::to_value(&term).unwrap(), expected);
+        let g = Context::Snoc(
+            Box::new(Context::Snoc(
+                Box::new(Context::Empty()),
+                "x".into(),
+                Type::Foo,
+            )),
+            "y".into(),
+            Type::Bar,
+        );
+        let expected = json!([
+            { "name": "x", "type": { "tag": "foo" } },
+            { "name": "y", "type": { "tag": "bar" } },
+        ]);
+        assert_eq!(serde_json::to_value(&g).unwrap(), expected);
+        let t = Type::record(vec![("x".into(), Type::Foo)], Some("r".into()));
+        let expected = json!({
+            "tag": "record",
+            "fields": [{ "label": "x", "type": { "tag": "foo" } }],
+            "rest": "r",
+        });
+        assert_eq!(serde_json::to_value(&t).unwrap(), expected);
+        assert_eq!(serde_json::from_value::<Type>(expected).unwrap(), t);
+    }
+
+    #[test]
+    fn examples_round_trip() {
+        for (name, term, t) in examples() {
+            let json = serde_json::to_string(&(&term, &t)).unwrap();
+            let (term2, t2): (Term, Type) = serde_json::from_str(&json).unwrap();
+            assert!(alpha_equal(&term, &term2), "{} changed", name);
+            assert!(type_equality(&t, &t2));
+            assert!(judgment_check(&Context::Empty(), &term2, &t2));
+            assert!(explain(&Context::Empty(), &term2, &t2, "/term").is_none());
+        }
+    }
+
+    /// The term with all its subterms.
+    fn subterms(term: &Term, acc: &mut Vec<Term>) {
+        acc.push(term.clone());
+        match term {
+            Term::Lam(_, t) | Term::Ref(t, _) | Term::Deref(t) | Term::Pack(_, t, _) => {
+                subterms(t, acc)
+            }
+            Term::Cast(t, _, _, _) => subterms(t, acc),
+            Term::Pair(a, b)
+            | Term::Split(a, _, _, _, _, b)
+            | Term::App(a, b, _)
+            | Term::Assign(a, b, _)
+            | Term::Unpack(a, _, _, _, b)
+            | Term::Try(a, _, b) => {
+                subterms(a, acc);
+                subterms(b, acc);
+            }
+            Term::Var(_) | Term::Unit | Term::Loc(_) | Term::Blame(_, _, _) | Term::Raise(_) => {}
+        }
+    }
+
+    #[test]
+    fn explain_agrees_with_judgment_check() {
+        let a = || Type::var("a");
+        let fail = Effects::of(&["Fail"]);
+        let mut types = vec![
+            Type::Foo,
+            Type::Bar,
+            Type::Unit,
+            Type::Dyn,
+            Type::arrow(Type::Foo, Type::Foo),
+            Type::arrow_raising(Type::Foo, Type::Foo, fail),
+            Type::product(Type::Foo, Type::Bar),
+            Type::reference(Type::Foo),
+            Type::forall("a", Type::arrow(a(), a())),
+            Type::exists("X", Type::var("X")),
+            Type::and(
+                Type::arrow(Type::Foo, Type::Foo),
+                Type::arrow(Type::Bar, Type::Bar),
+            ),
+            Type::or(Type::Foo, Type::Bar),
+            Type::record(vec![("x".into(), Type::Foo)], None),
+            Type::variant(vec![("x".into(), Type::Foo)], None),
+            counter_type(),
+        ];
+        let mut roots = vec![
+            Term::raise("Fail"),
+            Term::try_with(Term::raise("Fail"), "Fail", Term::var("x")),
+            Term::reference(Term::var("x"), Type::Foo),
+            Term::deref(Term::var("r")),
+            Term::assign(Term::var("r"), Term::var("x"), Type::Foo),
+            Term::app(Term::var("id"), Term::var("x"), Type::Foo),
+            Term::lam("x", Term::app(Term::var("f"), Term::var("x"), Type::Foo)),
+        ];
+        for (_, term, t) in examples() {
+            roots.push(term);
+            types.push(t);
+        }
+        roots.extend(counters());
+        let mut terms = Vec::new();
+        for term in &roots {
+            subterms(term, &mut terms);
+        }
+        let g = Context::new()
+            .with("x", Type::Foo)
+            .with("y", Type::Bar)
+            .with("p", Type::product(Type::Foo, Type::Bar))
+            .with("f", Type::arrow(Type::Foo, Type::Foo))
+            .with("r", Type::reference(Type::Foo))
+            .with("id", Type::forall("a", Type::arrow(a(), a())));
+        for g in &[Context::new(), counter_context(), g] {
+            for term in &terms {
+                for t in &types {
+                    assert_eq!(
+                        explain(g, term, t, "").is_none(),
+                        judgment_check(g, term, t),
+                        "{} : {}",
+                        term,
+                        t
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn structured_errors() {
+        let foo = json!({ "tag": "foo" });
+        let doc = json!({
+            "context": [{ "name": "f", "type": { "tag": "fn", "input": foo, "output": foo } }],
+            "term": { "tag": "lam", "name": "x", "body": {
+                "tag": "app", "fun": { "tag": "var", "name": "f" },
+                "arg": { "tag": "var", "name": "y" }, "arg_type": foo } },
+            "type": { "tag": "fn", "input": foo, "output": foo },
+        });
+        assert_eq!(
+            check_document(&doc),
+            vec![Error {
+                kind: "type",
+                path: "/term/body/arg".into(),
+                message: "unbound variable 'y'".into(),
+            }]
+        );
+
+        let doc = json!({ "term": { "tag": "lambda" }, "type": foo });
+        let errors = check_document(&doc);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            (errors[0].kind, errors[0].path.as_str()),
+            ("schema", "/term")
+        );
+        assert!(errors[0].message.contains("unknown variant `lambda`"));
+
+        let doc = json!({
+            "context": [{ "name": "x", "type": foo }, { "name": "x", "type": foo }],
+            "term": { "tag": "var", "name": "x" },
+        });
+        let kinds: Vec<&str> = check_document(&doc).iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, vec!["schema"]);
+
+        // \x. try raise Fail with Other => x : Foo -[Other]-> Foo
+        let raise = json!({ "tag": "raise", "exception": "Fail" });
+        let term = json!({ "tag": "lam", "name": "x", "body": {
+            "tag": "try", "body": raise, "exception": "Other",
+            "handler": { "tag": "var", "name": "x" } } });
+        let t = json!({ "tag": "fn", "input": foo, "output": foo, "effects": ["Other"] });
+        let doc = json!({ "term": term, "type": t });
+        assert_eq!(
+            check_document(&doc),
+            vec![Error {
+                kind: "effect",
+                path: "/term/body/body".into(),
+                message: "the exception Fail may not be raised here".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn packages() {
+        let package_type =
+            json!({ "tag": "exists", "var": "X", "body": { "tag": "var", "name": "X" } });
+        let package = json!({ "tag": "pack", "witness": { "tag": "unit" }, "body": { "tag": "unit" }, "type": package_type });
+        let doc = json!({ "term": package, "type": package_type });
+        assert!(check_document(&doc).is_empty());
+
+        // the hidden type escapes: unpack p as [Y, y] in y : Y
+        let term = json!({
+            "tag": "unpack", "package": package, "type": package_type,
+            "type_var": "Y", "name": "y", "body": { "tag": "var", "name": "y" },
+        });
+        let doc = json!({ "term": term, "type": { "tag": "var", "name": "Y" } });
+        let errors = check_document(&doc);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "/term");
+        assert!(errors[0].message.contains("escapes"));
+    }
+
+    #[test]
+    fn polymorphism() {
+        // \x. x : forall a. a -> a
+        let a = json!({ "tag": "var", "name": "a" });
+        let id_type = json!({
+            "tag": "forall", "var": "a",
+            "body": { "tag": "fn", "input": a, "output": a, "effects": [] },
+        });
+        let t: Type = serde_json::from_value(id_type.clone()).unwrap();
+        assert_eq!(format!("{}", t), "forall a. a -> a");
+        let id = json!({ "tag": "lam", "name": "x", "body": { "tag": "var", "name": "x" } });
+        assert!(check_document(&json!({ "term": id, "type": id_type })).is_empty());
+
+        // the variable of the quantifier is already bound by the context
+        let context = json!([{ "name": "y", "type": a }]);
+        let constant = json!({ "tag": "lam", "name": "x", "body": { "tag": "var", "name": "y" } });
+        let doc = json!({ "context": context, "term": constant, "type": id_type });
+        let errors = check_document(&doc);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "the type variable a is already bound");
+    }
+
+    #[test]
+    fn kinds() {
+        // Pair = \A. \B. A * B
+        let var = |x: &str| json!({ "tag": "var", "name": x });
+        let star = json!({ "tag": "star" });
+        let pair = json!({
+            "tag": "lam", "var": "A", "kind": star,
+            "body": { "tag": "lam", "var": "B", "kind": star,
+                      "body": { "tag": "product", "left": var("A"), "right": var("B") } },
+        });
+        let t: Type = serde_json::from_value(pair.clone()).unwrap();
+        assert_eq!(format!("{}", t), "\\A. \\B. A * B");
+        assert_eq!(serde_json::to_value(&t).unwrap(), pair);
+
+        // Pair Unit Unit
+        let applied = json!({ "tag": "app", "arg": { "tag": "unit" },
+                              "fun": { "tag": "app", "fun": pair, "arg": { "tag": "unit" } } });
+        let term = json!({ "tag": "pair", "left": { "tag": "unit" }, "right": { "tag": "unit" } });
+        assert!(check_document(&json!({ "term": term, "type": applied })).is_empty());
+
+        // Pair Unit is an operator, not a type
+        let partial = json!({ "tag": "app", "fun": pair, "arg": { "tag": "unit" } });
+        let errors = check_document(&json!({ "term": term, "type": partial }));
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].kind, errors[0].path.as_str()), ("kind", "/term"));
+    }
+
+    #[test]
+    fn documents() {
+        let input = r#"
+            {"term": {"tag": "unit"}, "type": {"tag": "unit"}}
+            {"context": [{"name": "x", "type": {"tag": "foo"}}, {"name": "x", "type": {"tag": "bar"}}],
+             "term": {"tag": "var", "name": "x"}, "type": {"tag": "bar"}}
+            {"term": {"tag": "unit"}, "type": {"tag": "foo"}}
+            {"term":
+        "#;
+        let mut output = Vec::new();
+        check_documents(input.as_bytes(), &mut output).unwrap();
+        let results: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0], json!({ "ok": true, "errors": [] }));
+        assert_eq!(results[1]["errors"][0]["kind"], "context");
+        assert_eq!(
+            results[2]["errors"][0],
+            json!({ "kind": "type", "path": "/term", "message": "expected Foo, found Unit" })
+        );
+        assert_eq!(results[3]["errors"][0]["kind"], "syntax");
+    }
+}
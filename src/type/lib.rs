@@ -0,0 +1,1704 @@
+//! A sample type checker written in rust.
+//!
+//! The calculus has the base types `Foo`, `Bar`, `Baz` and `Unit`,
+//! products, functions with effects, references, existentials, type
+//! operators, the dynamic type of gradual typing, intersections,
+//! unions, records, variants and polymorphic types. Terms carry the
+//! annotations which make their types checkable without inference,
+//! `judgment_check` decides if a term has a type in a context. The
+//! other passes live in their own modules, the `type` binary is a
+//! front-end over them.
+//!
+//! ```
+//! use typecheck::{judgment_check, Context, Term, Type};
+//!
+//! // \x. x : (Foo -> Foo) /\ (Bar -> Bar)
+//! let id = Term::lam("x", Term::var("x"));
+//! let both = Type::and(
+//!     Type::arrow(Type::Foo, Type::Foo),
+//!     Type::arrow(Type::Bar, Type::Bar),
+//! );
+//! assert!(judgment_check(&Context::new(), &id, &both));
+//!
+//! // foo : Foo |- id foo : Foo
+//! let g = Context::new().with("foo", Type::Foo);
+//! let app = Term::app(Term::var("id"), Term::var("foo"), Type::Foo);
+//! assert!(judgment_check(&g.with("id", both), &app, &Type::Foo));
+//! ```
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate serde_json;
+
+pub mod alias;
+pub mod church;
+pub mod codegen;
+pub mod cps;
+pub mod dependent;
+pub mod effect;
+pub mod eval;
+pub mod gradual;
+pub mod infer;
+pub mod json;
+pub mod nbe;
+pub mod omega;
+pub mod prove;
+pub mod rank;
+pub mod refine;
+pub mod session;
+pub mod syntax;
+pub mod termination;
+pub mod trace;
+pub mod vm;
+
+use std::fmt;
+
+pub use effect::Effects;
+
+use eval::Store;
+
+/// The types of terms and the type operators over them, from the base
+/// types and `Unit` to products `A * B`, functions `A -> B` and the
+/// extensions below. `judgment` tells the types of terms apart.
+///
+/// The derived equality compares the syntax, `type_equality`
+/// is the equality of the type system.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "json::TypeRepr", into = "json::TypeRepr")]
+pub enum Type {
+    Foo,
+    Bar,
+    Baz,
+    Unit,
+    Sum(Box<Type>, Box<Type>),         // the product left * right
+    Fn(Box<Type>, Box<Type>, Effects), // input -[effects]-> output
+    Ref(Box<Type>),                    // mutable cell holding a value
+    Var(String),                       // a type variable
+    Exists(String, Box<Type>),         // exists X. T
+    Forall(String, Box<Type>),         // forall X. T, also in argument positions
+    Lam(String, Kind, Box<Type>),      // a type operator \X :: K. T
+    App(Box<Type>, Box<Type>),         // a type operator applied to a type
+    Named(String, Box<Type>),          // an alias together with its closed definition
+    Dyn,                               // the dynamic type, consistent with every type
+    And(Box<Type>, Box<Type>),         // the intersection A /\ B of two types
+    Or(Box<Type>, Box<Type>),          // the union A \/ B of two types
+    Record(Box<Row>),                  // {l: A, ... | r} with the row variable r
+    Variant(Box<Row>),                 // <l: A, ... | r> one of the labelled types
+}
+
+/// The labelled fields of a record or variant type, open to more
+/// fields when they end in the row variable `rest`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Row {
+    pub fields: Vec<(String, Type)>,
+    pub rest: Option<String>,
+}
+
+impl Type {
+    /// The record type `{l: A, ... | r}`.
+    pub fn record(fields: Vec<(String, Type)>, rest: Option<String>) -> Type {
+        Type::Record(Box::new(Row { fields, rest }))
+    }
+
+    /// The variant type `<l: A, ... | r>`.
+    pub fn variant(fields: Vec<(String, Type)>, rest: Option<String>) -> Type {
+        Type::Variant(Box::new(Row { fields, rest }))
+    }
+}
+
+/// The kinds classify types, `*` is the kind of the types of terms
+/// and `K -> L` the kind of type operators.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "json::KindRepr", into = "json::KindRepr")]
+pub enum Kind {
+    Star,
+    Arrow(Box<Kind>, Box<Kind>),
+}
+
+/// Test if a type is the type of terms.
+pub fn judgment(t: &Type) -> bool {
+    kinding(&mut Vec::new(), t) == Some(Kind::Star)
+}
+
+/// The kinding judgment, infer the kind of a type under the kinds
+/// of the type variables bound on the way down. Free type variables
+/// stand for types, like the hidden type within `unpack`.
+pub fn kinding(env: &mut Vec<(String, Kind)>, t: &Type) -> Option<Kind> {
+    let star = |k: Option<Kind>| match k {
+        Some(Kind::Star) => Some(Kind::Star),
+        _ => None,
+    };
+    match t {
+        &Type::Foo | &Type::Bar | &Type::Baz | &Type::Unit | &Type::Dyn => Some(Kind::Star),
+        Type::Sum(l, r) | Type::Fn(l, r, _) | Type::And(l, r) | Type::Or(l, r) => {
+            star(kinding(env, l))?;
+            star(kinding(env, r))
+        }
+        Type::Ref(t) => star(kinding(env, t)),
+        Type::Record(row) | Type::Variant(row) => {
+            let fields = &row.fields;
+            for (i, (l, t)) in fields.iter().enumerate() {
+                if fields[..i].iter().any(|f| &f.0 == l) {
+                    return None;
+                }
+                star(kinding(env, t))?;
+            }
+            Some(Kind::Star)
+        }
+        Type::Var(x) => match env.iter().rev().find(|p| &p.0 == x) {
+            Some((_, k)) => Some(k.clone()),
+            None => Some(Kind::Star),
+        },
+        Type::Exists(x, t) | Type::Forall(x, t) => {
+            env.push((x.clone(), Kind::Star));
+            let k = star(kinding(env, t));
+            env.pop();
+            k
+        }
+        Type::Lam(x, k, t) => {
+            env.push((x.clone(), k.clone()));
+            let body = kinding(env, t);
+            env.pop();
+            Some(Kind::Arrow(Box::new(k.clone()), Box::new(body?)))
+        }
+        Type::Named(_, t) => kinding(&mut Vec::new(), t),
+        Type::App(f, a) => match kinding(env, f)? {
+            Kind::Arrow(k, l) if kinding(env, a).as_ref() == Some(&*k) => Some(*l),
+            _ => None,
+        },
+    }
+}
+
+/// Reduce the type operator applications within a type.
+/// Terminates for well-kinded types.
+pub fn normalize_type(t: &Type) -> Type {
+    let n = |t: &Type| Box::new(normalize_type(t));
+    match t {
+        &Type::Foo | &Type::Bar | &Type::Baz | &Type::Unit | &Type::Dyn | Type::Var(_) => t.clone(),
+        Type::Sum(a, b) => Type::Sum(n(a), n(b)),
+        Type::Fn(a, b, e) => Type::Fn(n(a), n(b), e.clone()),
+        Type::And(a, b) => Type::And(n(a), n(b)),
+        Type::Or(a, b) => Type::Or(n(a), n(b)),
+        Type::Record(row) => Type::record(normalize_fields(&row.fields), row.rest.clone()),
+        Type::Variant(row) => Type::variant(normalize_fields(&row.fields), row.rest.clone()),
+        Type::Ref(a) => Type::Ref(n(a)),
+        Type::Exists(x, a) => Type::Exists(x.clone(), n(a)),
+        Type::Forall(x, a) => Type::Forall(x.clone(), n(a)),
+        Type::Lam(x, k, a) => Type::Lam(x.clone(), k.clone(), n(a)),
+        Type::App(f, a) => match normalize_type(f) {
+            Type::Lam(x, _, body) => normalize_type(&subst_type(&body, &x, a)),
+            f => Type::App(Box::new(f), n(a)),
+        },
+        Type::Named(_, t) => normalize_type(t),
+    }
+}
+
+fn normalize_fields(fields: &[(String, Type)]) -> Vec<(String, Type)> {
+    fields
+        .iter()
+        .map(|(l, t)| (l.clone(), normalize_type(t)))
+        .collect()
+}
+
+/// Expand aliases and reduce operator applications only at the
+/// head of a type, the parts keep their alias names.
+pub fn unfold(t: &Type) -> Type {
+    match t {
+        Type::Named(_, t) => unfold(t),
+        Type::App(f, a) => match unfold(f) {
+            Type::Lam(x, _, body) => unfold(&subst_type(&body, &x, a)),
+            f => Type::App(Box::new(f), a.clone()),
+        },
+        _ => t.clone(),
+    }
+}
+
+/// Normalize the well-kinded types within the annotations of a term.
+pub fn normalize_annotations(term: &Term) -> Term {
+    let t = |t: &Term| Box::new(normalize_annotations(t));
+    let ty = |t: &Type| match judgment(t) {
+        true => normalize_type(t),
+        false => t.clone(),
+    };
+    match term {
+        Term::Var(_) | Term::Unit | Term::Loc(_) | Term::Raise(_) => term.clone(),
+        Term::Lam(n, body) => Term::Lam(n.clone(), t(body)),
+        Term::App(f, a, a_type) => Term::App(t(f), t(a), ty(a_type)),
+        Term::Pair(a, b) => Term::Pair(t(a), t(b)),
+        Term::Split(p, na, ta, nb, tb, body) => {
+            Term::Split(t(p), na.clone(), ty(ta), nb.clone(), ty(tb), t(body))
+        }
+        Term::Ref(init, init_type) => Term::Ref(t(init), ty(init_type)),
+        Term::Deref(cell) => Term::Deref(t(cell)),
+        Term::Assign(cell, value, value_type) => Term::Assign(t(cell), t(value), ty(value_type)),
+        Term::Pack(w, body, p_type) => Term::Pack(ty(w), t(body), ty(p_type)),
+        Term::Unpack(p, p_type, x_type, n, body) => {
+            Term::Unpack(t(p), ty(p_type), x_type.clone(), n.clone(), t(body))
+        }
+        Term::Cast(e, from, to, l) => Term::Cast(t(e), ty(from), ty(to), l.clone()),
+        Term::Blame(l, expected, found) => Term::Blame(l.clone(), ty(expected), ty(found)),
+        Term::Try(body, x, handler) => Term::Try(t(body), x.clone(), t(handler)),
+    }
+}
+
+/// Check if two well-kinded types are equal
+/// after reducing type operator applications.
+pub fn type_equality(a: &Type, b: &Type) -> bool {
+    let mut env = Vec::new();
+    match (kinding(&mut env, a), kinding(&mut env, b)) {
+        (Some(k), Some(l)) if k == l => {
+            type_equality_under(&normalize_type(a), &normalize_type(b), &mut Vec::new())
+        }
+        _ => false,
+    }
+}
+
+/// Check if two types are equal up to renaming of the
+/// type variables bound on the way down.
+fn type_equality_under(a: &Type, b: &Type, env: &mut Vec<(String, String)>) -> bool {
+    match (a, b) {
+        (&Type::Dyn, &Type::Dyn)
+        | (&Type::Foo, &Type::Foo)
+        | (&Type::Bar, &Type::Bar)
+        | (&Type::Baz, &Type::Baz)
+        | (&Type::Unit, &Type::Unit) => true,
+        (Type::Sum(al, ar), Type::Sum(bl, br)) => {
+            type_equality_under(al, bl, env) && type_equality_under(ar, br, env)
+        }
+        (Type::Fn(ai, ao, ae), Type::Fn(bi, bo, be)) => {
+            ae == be && type_equality_under(ai, bi, env) && type_equality_under(ao, bo, env)
+        }
+        (Type::And(al, ar), Type::And(bl, br)) | (Type::Or(al, ar), Type::Or(bl, br)) => {
+            type_equality_under(al, bl, env) && type_equality_under(ar, br, env)
+        }
+        (Type::Ref(a), Type::Ref(b)) => type_equality_under(a, b, env),
+        // the order of the fields doesn't matter
+        (Type::Record(a), Type::Record(b)) | (Type::Variant(a), Type::Variant(b)) => {
+            let (af, bf) = (&a.fields, &b.fields);
+            let rest = match (&a.rest, &b.rest) {
+                (Some(x), Some(y)) => {
+                    type_equality_under(&Type::Var(x.clone()), &Type::Var(y.clone()), env)
+                }
+                (None, None) => true,
+                _ => false,
+            };
+            rest && af.len() == bf.len()
+                && af.iter().all(|(l, a)| match bf.iter().find(|f| &f.0 == l) {
+                    Some((_, b)) => type_equality_under(a, b, env),
+                    None => false,
+                })
+        }
+        (Type::Var(x), Type::Var(y)) => {
+            match (
+                env.iter().rposition(|p| &p.0 == x),
+                env.iter().rposition(|p| &p.1 == y),
+            ) {
+                (Some(i), Some(j)) => i == j,
+                (None, None) => x == y,
+                _ => false,
+            }
+        }
+        (Type::Exists(x, a), Type::Exists(y, b)) | (Type::Forall(x, a), Type::Forall(y, b)) => {
+            env.push((x.clone(), y.clone()));
+            let equal = type_equality_under(a, b, env);
+            env.pop();
+            equal
+        }
+        (Type::Lam(x, k, a), Type::Lam(y, l, b)) if k == l => {
+            env.push((x.clone(), y.clone()));
+            let equal = type_equality_under(a, b, env);
+            env.pop();
+            equal
+        }
+        (Type::App(f, a), Type::App(g, b)) => {
+            type_equality_under(f, g, env) && type_equality_under(a, b, env)
+        }
+        _ => false,
+    }
+}
+
+/// The consistency relation of gradual typing, two well-kinded types
+/// are consistent if they are equal where neither of them is `Dyn`.
+/// References, existentials and the effects of functions are invariant,
+/// a cast between them would need a proxy.
+pub fn consistent(a: &Type, b: &Type) -> bool {
+    fn go(a: &Type, b: &Type) -> bool {
+        match (a, b) {
+            (&Type::Dyn, _) | (_, &Type::Dyn) => true,
+            (Type::Sum(al, ar), Type::Sum(bl, br)) => go(al, bl) && go(ar, br),
+            (Type::Fn(al, ar, ae), Type::Fn(bl, br, be)) => ae == be && go(al, bl) && go(ar, br),
+            _ => type_equality_under(a, b, &mut Vec::new()),
+        }
+    }
+    let mut env = Vec::new();
+    match (kinding(&mut env, a), kinding(&mut env, b)) {
+        (Some(k), Some(l)) if k == l => go(&normalize_type(a), &normalize_type(b)),
+        _ => false,
+    }
+}
+
+/// Test if a value of type `a` can be used at type `b`, which holds
+/// if they are equal except that the functions of `a` may raise fewer
+/// exceptions. Arguments are contravariant, references invariant.
+/// An intersection is below each of its parts and a union above them,
+/// the invertible rules are tried first. A polymorphic type is below
+/// its instances, which `rank::subsumes` finds.
+pub fn subtype(a: &Type, b: &Type) -> bool {
+    fn go(a: &Type, b: &Type) -> bool {
+        match (a, b) {
+            (Type::Forall(_, _), _) | (_, Type::Forall(_, _)) => rank::subsumes(a, b),
+            (_, Type::And(bl, br)) => go(a, bl) && go(a, br),
+            (Type::Or(al, ar), _) => go(al, b) && go(ar, b),
+            (Type::And(al, ar), _) if go(al, b) || go(ar, b) => true,
+            (_, Type::Or(bl, br)) if go(a, bl) || go(a, br) => true,
+            (Type::Sum(al, ar), Type::Sum(bl, br)) => go(al, bl) && go(ar, br),
+            (Type::Fn(ai, ao, ae), Type::Fn(bi, bo, be)) => {
+                ae.within(be) && go(bi, ai) && go(ao, bo)
+            }
+            _ => type_equality_under(a, b, &mut Vec::new()),
+        }
+    }
+    let mut env = Vec::new();
+    match (kinding(&mut env, a), kinding(&mut env, b)) {
+        (Some(k), Some(l)) if k == l => go(&normalize_type(a), &normalize_type(b)),
+        _ => false,
+    }
+}
+
+/// Test if a type variable occurs free in a type.
+pub fn free_in_type(x: &str, t: &Type) -> bool {
+    match t {
+        &Type::Foo | &Type::Bar | &Type::Baz | &Type::Unit | &Type::Dyn | Type::Named(_, _) => {
+            false
+        }
+        Type::Sum(a, b)
+        | Type::Fn(a, b, _)
+        | Type::App(a, b)
+        | Type::And(a, b)
+        | Type::Or(a, b) => free_in_type(x, a) || free_in_type(x, b),
+        Type::Ref(a) => free_in_type(x, a),
+        Type::Record(row) | Type::Variant(row) => {
+            row.rest.as_ref().is_some_and(|r| r == x)
+                || row.fields.iter().any(|f| free_in_type(x, &f.1))
+        }
+        Type::Var(y) => x == y,
+        Type::Exists(y, a) | Type::Forall(y, a) | Type::Lam(y, _, a) => {
+            x != y && free_in_type(x, a)
+        }
+    }
+}
+
+/// Test if a type variable occurs free in the types of a context.
+fn free_in_context(x: &str, g: &Context) -> bool {
+    match g {
+        &Context::Empty() => false,
+        Context::Snoc(rest, _, t) => free_in_type(x, t) || free_in_context(x, rest),
+    }
+}
+
+/// Substitute the closed type `s` for the type variable `x`
+/// within the annotations of a term.
+pub fn subst_type_in_term(term: &Term, x: &str, s: &Type) -> Term {
+    let t = |t: &Term| Box::new(subst_type_in_term(t, x, s));
+    let ty = |ty: &Type| subst_type(ty, x, s);
+    match term {
+        Term::Var(_) | Term::Unit | Term::Loc(_) | Term::Raise(_) => term.clone(),
+        Term::Lam(n, body) => Term::Lam(n.clone(), t(body)),
+        Term::App(f, a, a_type) => Term::App(t(f), t(a), ty(a_type)),
+        Term::Pair(a, b) => Term::Pair(t(a), t(b)),
+        Term::Split(p, na, ta, nb, tb, body) => {
+            Term::Split(t(p), na.clone(), ty(ta), nb.clone(), ty(tb), t(body))
+        }
+        Term::Ref(init, init_type) => Term::Ref(t(init), ty(init_type)),
+        Term::Deref(cell) => Term::Deref(t(cell)),
+        Term::Assign(cell, value, value_type) => Term::Assign(t(cell), t(value), ty(value_type)),
+        Term::Pack(w, body, p_type) => Term::Pack(ty(w), t(body), ty(p_type)),
+        Term::Unpack(p, p_type, x_type, n, body) => {
+            let body = if x_type == x { body.clone() } else { t(body) };
+            Term::Unpack(t(p), ty(p_type), x_type.clone(), n.clone(), body)
+        }
+        Term::Cast(e, from, to, l) => Term::Cast(t(e), ty(from), ty(to), l.clone()),
+        Term::Blame(l, expected, found) => Term::Blame(l.clone(), ty(expected), ty(found)),
+        Term::Try(body, x, handler) => Term::Try(t(body), x.clone(), t(handler)),
+    }
+}
+
+/// Substitute the type `s` for the variable `x` within `t`,
+/// renaming bound variables which would capture a variable of `s`.
+pub fn subst_type(t: &Type, x: &str, s: &Type) -> Type {
+    match t {
+        &Type::Foo | &Type::Bar | &Type::Baz | &Type::Unit | &Type::Dyn | Type::Named(_, _) => {
+            t.clone()
+        }
+        Type::Sum(a, b) => Type::Sum(Box::new(subst_type(a, x, s)), Box::new(subst_type(b, x, s))),
+        Type::Fn(a, b, e) => Type::Fn(
+            Box::new(subst_type(a, x, s)),
+            Box::new(subst_type(b, x, s)),
+            e.clone(),
+        ),
+        Type::And(a, b) => Type::And(Box::new(subst_type(a, x, s)), Box::new(subst_type(b, x, s))),
+        Type::Or(a, b) => Type::Or(Box::new(subst_type(a, x, s)), Box::new(subst_type(b, x, s))),
+        Type::Ref(a) => Type::Ref(Box::new(subst_type(a, x, s))),
+        Type::Record(row) => Type::Record(Box::new(subst_row(row, x, s))),
+        Type::Variant(row) => Type::Variant(Box::new(subst_row(row, x, s))),
+        Type::App(f, a) => Type::App(Box::new(subst_type(f, x, s)), Box::new(subst_type(a, x, s))),
+        Type::Var(y) if x == y => s.clone(),
+        Type::Var(_) => t.clone(),
+        Type::Exists(y, a) => {
+            let (y, a) = subst_binder(y, a, x, s);
+            Type::Exists(y, Box::new(a))
+        }
+        Type::Forall(y, a) => {
+            let (y, a) = subst_binder(y, a, x, s);
+            Type::Forall(y, Box::new(a))
+        }
+        Type::Lam(y, k, a) => {
+            let (y, a) = subst_binder(y, a, x, s);
+            Type::Lam(y, k.clone(), Box::new(a))
+        }
+    }
+}
+
+/// Substitute within the fields of a row. A row variable is replaced
+/// by the fields of a record or variant type and renamed by a variable.
+fn subst_row(row: &Row, x: &str, s: &Type) -> Row {
+    let mut fields: Vec<(String, Type)> = row
+        .fields
+        .iter()
+        .map(|(l, t)| (l.clone(), subst_type(t, x, s)))
+        .collect();
+    let rest = match (&row.rest, s) {
+        (Some(r), Type::Record(more)) | (Some(r), Type::Variant(more)) if r == x => {
+            fields.extend(more.fields.iter().cloned());
+            more.rest.clone()
+        }
+        (Some(r), Type::Var(y)) if r == x => Some(y.clone()),
+        _ => row.rest.clone(),
+    };
+    Row { fields, rest }
+}
+
+/// Substitute below the binder `y` of the type `a`, renaming `y`
+/// if it would capture a variable of `s`.
+fn subst_binder(y: &str, a: &Type, x: &str, s: &Type) -> (String, Type) {
+    if x == y {
+        return (y.into(), a.clone());
+    }
+    if !free_in_type(y, s) {
+        return (y.into(), subst_type(a, x, s));
+    }
+    let mut fresh = format!("{}'", y);
+    while free_in_type(&fresh, s) || free_in_type(&fresh, a) {
+        fresh.push('\'');
+    }
+    let a = subst_type(a, y, &Type::Var(fresh.clone()));
+    let a = subst_type(&a, x, s);
+    (fresh, a)
+}
+
+/// Context struct holds process information
+/// about the type checking process.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "Vec<json::Binding>", into = "Vec<json::Binding>")]
+pub enum Context {
+    Empty(),
+    Snoc(Box<Context>, String, Type),
+}
+
+/// Test if a name is present within the context.
+fn not_in(name: &str, g: &Context) -> bool {
+    match g {
+        &Context::Empty() => true,
+        Context::Snoc(_, n, _) if n == name => false,
+        Context::Snoc(rest, _, _) => not_in(name, rest),
+    }
+}
+
+/// Test if the context is valid.
+pub fn judgment_ctx(g: &Context) -> bool {
+    match g {
+        &Context::Empty() => true,
+        Context::Snoc(rest, name, t) => judgment_ctx(rest) && judgment(t) && not_in(name, rest),
+    }
+}
+
+/// Represent different terms which can be recursive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "json::TermRepr", into = "json::TermRepr")]
+pub enum Term {
+    Pair(Box<Term>, Box<Term>),
+    Split(Box<Term>, String, Type, String, Type, Box<Term>),
+    Lam(String, Box<Term>),
+    App(Box<Term>, Box<Term>, Type),
+    Var(String),
+    Unit,
+    Ref(Box<Term>, Type),               // ref [T] t allocates a new cell
+    Deref(Box<Term>),                   // !t reads a cell
+    Assign(Box<Term>, Box<Term>, Type), // t := t writes a cell of type T
+    Loc(usize),                         // a store location, only created by evaluation
+    Pack(Type, Box<Term>, Type),        // pack [T, t] as exists X. S
+    Unpack(Box<Term>, Type, String, String, Box<Term>), // unpack t : exists X. S as [Y, x] in t
+    Cast(Box<Term>, Type, Type, String), // <A => B> t, labelled with the location of the cast
+    Blame(String, Type, Type),          // a failed cast expecting a type and finding another
+    Raise(String),                      // raise E
+    Try(Box<Term>, String, Box<Term>),  // try t with E => h
+}
+
+/// Shorthands which box the parts of a type.
+impl Type {
+    /// The pure function type `a -> b`.
+    pub fn arrow(a: Type, b: Type) -> Type {
+        Type::Fn(Box::new(a), Box::new(b), Effects::pure())
+    }
+
+    /// The function type `a -[effects]-> b`.
+    pub fn arrow_raising(a: Type, b: Type, effects: Effects) -> Type {
+        Type::Fn(Box::new(a), Box::new(b), effects)
+    }
+
+    /// The product `a * b`.
+    pub fn product(a: Type, b: Type) -> Type {
+        Type::Sum(Box::new(a), Box::new(b))
+    }
+
+    /// The type `Ref a` of cells holding an `a`.
+    pub fn reference(a: Type) -> Type {
+        Type::Ref(Box::new(a))
+    }
+
+    /// The type variable `x`.
+    pub fn var(x: &str) -> Type {
+        Type::Var(x.into())
+    }
+
+    /// The existential `exists x. body`.
+    pub fn exists(x: &str, body: Type) -> Type {
+        Type::Exists(x.into(), Box::new(body))
+    }
+
+    /// The polymorphic type `forall x. body`.
+    pub fn forall(x: &str, body: Type) -> Type {
+        Type::Forall(x.into(), Box::new(body))
+    }
+
+    /// The intersection `a /\ b`.
+    pub fn and(a: Type, b: Type) -> Type {
+        Type::And(Box::new(a), Box::new(b))
+    }
+
+    /// The union `a \/ b`.
+    pub fn or(a: Type, b: Type) -> Type {
+        Type::Or(Box::new(a), Box::new(b))
+    }
+}
+
+/// Shorthands which box the parts of a term.
+impl Term {
+    /// The variable `x`.
+    pub fn var(x: &str) -> Term {
+        Term::Var(x.into())
+    }
+
+    /// The function `\x. body`.
+    pub fn lam(x: &str, body: Term) -> Term {
+        Term::Lam(x.into(), Box::new(body))
+    }
+
+    /// The application `f a` where the argument has type `a_type`.
+    pub fn app(f: Term, a: Term, a_type: Type) -> Term {
+        Term::App(Box::new(f), Box::new(a), a_type)
+    }
+
+    /// The pair `(a, b)`.
+    pub fn pair(a: Term, b: Term) -> Term {
+        Term::Pair(Box::new(a), Box::new(b))
+    }
+
+    /// `split pair as (x, y) in body` with the component types `a` and `b`.
+    pub fn split(pair: Term, x: &str, a: Type, y: &str, b: Type, body: Term) -> Term {
+        Term::Split(Box::new(pair), x.into(), a, y.into(), b, Box::new(body))
+    }
+
+    /// `ref [t] init` allocates a cell.
+    pub fn reference(init: Term, t: Type) -> Term {
+        Term::Ref(Box::new(init), t)
+    }
+
+    /// `!cell` reads a cell.
+    pub fn deref(cell: Term) -> Term {
+        Term::Deref(Box::new(cell))
+    }
+
+    /// `cell := value` writes a value of type `t`.
+    pub fn assign(cell: Term, value: Term, t: Type) -> Term {
+        Term::Assign(Box::new(cell), Box::new(value), t)
+    }
+
+    /// `raise exception`.
+    pub fn raise(exception: &str) -> Term {
+        Term::Raise(exception.into())
+    }
+
+    /// `try body with exception => handler`.
+    pub fn try_with(body: Term, exception: &str, handler: Term) -> Term {
+        Term::Try(Box::new(body), exception.into(), Box::new(handler))
+    }
+}
+
+impl Context {
+    /// The empty context.
+    pub fn new() -> Context {
+        Context::Empty()
+    }
+
+    /// The context extended with the binding `name : t`.
+    pub fn with(self, name: &str, t: Type) -> Context {
+        Context::Snoc(Box::new(self), name.into(), t)
+    }
+}
+
+impl Default for Context {
+    fn default() -> Context {
+        Context::new()
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        /// Wrap functions and products into parens.
+        fn atom(t: &Type) -> String {
+            match t {
+                Type::Sum(_, _)
+                | Type::Fn(_, _, _)
+                | Type::And(_, _)
+                | Type::Or(_, _)
+                | Type::Exists(_, _)
+                | Type::Forall(_, _)
+                | Type::Lam(_, _, _)
+                | Type::App(_, _) => format!("({})", t),
+                _ => format!("{}", t),
+            }
+        }
+        match self {
+            Type::Foo => write!(f, "Foo"),
+            Type::Bar => write!(f, "Bar"),
+            Type::Baz => write!(f, "Baz"),
+            Type::Unit => write!(f, "Unit"),
+            Type::Sum(l, r) => write!(f, "{} * {}", atom(l), atom(r)),
+            Type::Fn(i, o, e) => {
+                let arrow = match e.is_pure() {
+                    true => "->".into(),
+                    false => format!("-[{}]->", e),
+                };
+                match **i {
+                    Type::Fn(_, _, _)
+                    | Type::Exists(_, _)
+                    | Type::Forall(_, _)
+                    | Type::Lam(_, _, _) => {
+                        write!(f, "({}) {} {}", i, arrow, o)
+                    }
+                    _ => write!(f, "{} {} {}", i, arrow, o),
+                }
+            }
+            Type::Ref(t) => write!(f, "Ref {}", atom(t)),
+            Type::Var(x) | Type::Named(x, _) => write!(f, "{}", x),
+            Type::Dyn => write!(f, "Dyn"),
+            Type::And(l, r) => write!(f, "{} /\\ {}", atom(l), atom(r)),
+            Type::Or(l, r) => write!(f, "{} \\/ {}", atom(l), atom(r)),
+            Type::Record(row) => write!(f, "{{{}}}", row),
+            Type::Variant(row) => write!(f, "<{}>", row),
+            Type::Exists(x, t) => write!(f, "exists {}. {}", x, t),
+            Type::Forall(x, t) => write!(f, "forall {}. {}", x, t),
+            Type::Lam(x, Kind::Star, t) => write!(f, "\\{}. {}", x, t),
+            Type::Lam(x, k, t) => write!(f, "\\{} :: {}. {}", x, k, t),
+            Type::App(g, a) => match **g {
+                Type::App(_, _) => write!(f, "{} {}", g, atom(a)),
+                _ => write!(f, "{} {}", atom(g), atom(a)),
+            },
+        }
+    }
+}
+
+/// The fields of a record or variant type, `l: A, ... | r`.
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(l, t)| format!("{}: {}", l, t))
+            .collect();
+        write!(f, "{}", fields.join(", "))?;
+        match &self.rest {
+            Some(r) if fields.is_empty() => write!(f, "| {}", r),
+            Some(r) => write!(f, " | {}", r),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::Star => write!(f, "*"),
+            Kind::Arrow(k, l) if matches!(**k, Kind::Arrow(_, _)) => write!(f, "({}) -> {}", k, l),
+            Kind::Arrow(k, l) => write!(f, "{} -> {}", k, l),
+        }
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        /// Wrap everything except atoms into parens.
+        fn atom(t: &Term) -> String {
+            match t {
+                Term::Var(_) | Term::Unit | Term::Pair(_, _) => format!("{}", t),
+                _ => format!("({})", t),
+            }
+        }
+        match self {
+            Term::Pair(a, b) => write!(f, "({}, {})", a, b),
+            Term::Split(p, x, _, y, _, body) => {
+                write!(f, "split {} as ({}, {}) in {}", p, x, y, body)
+            }
+            Term::Lam(x, body) => write!(f, "\\{}. {}", x, body),
+            Term::App(a, b, _) => match **a {
+                Term::App(_, _, _) => write!(f, "{} {}", a, atom(b)),
+                _ => write!(f, "{} {}", atom(a), atom(b)),
+            },
+            Term::Var(x) => write!(f, "{}", x),
+            Term::Unit => write!(f, "()"),
+            Term::Ref(t, _) => write!(f, "ref {}", atom(t)),
+            Term::Deref(t) => write!(f, "!{}", atom(t)),
+            Term::Assign(c, v, _) => write!(f, "{} := {}", atom(c), v),
+            Term::Loc(l) => write!(f, "loc {}", l),
+            Term::Pack(w, t, a) => write!(f, "pack [{}, {}] as {}", w, t, a),
+            Term::Unpack(p, _, x_type, x, body) => {
+                write!(f, "unpack {} as [{}, {}] in {}", p, x_type, x, body)
+            }
+            Term::Cast(e, from, to, _) => write!(f, "<{} => {}> {}", from, to, atom(e)),
+            Term::Blame(l, _, _) => write!(f, "blame {}", l),
+            Term::Raise(x) => write!(f, "raise {}", x),
+            Term::Try(body, x, handler) => write!(f, "try {} with {} => {}", body, x, handler),
+        }
+    }
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Context::Empty() => Ok(()),
+            Context::Snoc(rest, name, t) => match **rest {
+                Context::Empty() => write!(f, "{} : {}", name, t),
+                _ => write!(f, "{}, {} : {}", rest, name, t),
+            },
+        }
+    }
+}
+
+/// Check if a name has a type within the context.
+pub fn var_has_type(v: &str, a: &Type, g: &Context) -> bool {
+    match g {
+        &Context::Empty() => false,
+        Context::Snoc(rest, name, t) => match name == v {
+            true => subtype(t, a),
+            false => var_has_type(v, a, rest),
+        },
+    }
+}
+
+/// The real type checking process.
+pub fn judgment_check(g: &Context, term: &Term, t: &Type) -> bool {
+    judgment_check_store(&[], g, term, t)
+}
+
+/// Type check a term under a store typing which assigns
+/// a type to every location the term may mention.
+pub fn judgment_check_store(s: &[Type], g: &Context, term: &Term, t: &Type) -> bool {
+    judgment_check_effects(s, g, term, t, &Effects::pure())
+}
+
+/// Type check a term which may raise the exceptions `e`.
+pub fn judgment_check_effects(s: &[Type], g: &Context, term: &Term, t: &Type, e: &Effects) -> bool {
+    // aliases and type operators are expanded before the type is
+    // taken apart, ill-kinded types may not have a normal form
+    if !judgment(t) {
+        return false;
+    }
+    let t = &unfold(t);
+    // a value has an intersection if it has both of its parts, a term
+    // has a union if it has one of its parts or by one of the rules below.
+    // A value is polymorphic if it has its type for an unknown `X`, other
+    // terms only by the instances of the polymorphic types they use
+    match t {
+        Type::And(l, r) if eval::is_value(term) => {
+            return judgment_check_effects(s, g, term, l, e)
+                && judgment_check_effects(s, g, term, r, e);
+        }
+        Type::Forall(x, body) if eval::is_value(term) && !free_in_context(x, g) => {
+            return judgment_check_effects(s, g, term, body, e);
+        }
+        Type::Forall(x, body) if eval::is_value(term) => {
+            // another `x` is in scope, the bound one is renamed in the
+            // type and the annotations of the term
+            let unused = |y: &str| {
+                !free_in_context(y, g)
+                    && !free_in_type(y, body)
+                    && subst_type_in_term(term, y, &Type::Unit) == *term
+            };
+            let mut y = format!("{}'", x);
+            while !unused(&y) {
+                y.push('\'');
+            }
+            let term = subst_type_in_term(term, x, &Type::Var(y.clone()));
+            let body = subst_type(body, x, &Type::Var(y));
+            return judgment_check_effects(s, g, &term, &body, e);
+        }
+        Type::Or(l, r)
+            if judgment_check_effects(s, g, term, l, e)
+                || judgment_check_effects(s, g, term, r, e) =>
+        {
+            return true;
+        }
+        _ => (),
+    }
+    match (term, t) {
+        (Term::Pair(fst, snd), Type::Sum(l, r)) => {
+            judgment_check_effects(s, g, fst, l, e) && judgment_check_effects(s, g, snd, r, e)
+        }
+        (Term::Split(pair, name_a, type_a, name_b, type_b, body), _) => {
+            let ctx = Context::Snoc(
+                Box::new(Context::Snoc(
+                    Box::new((*g).clone()),
+                    name_a.clone(),
+                    type_a.clone(),
+                )),
+                name_b.clone(),
+                type_b.clone(),
+            );
+            judgment_check_effects(
+                s,
+                g,
+                pair,
+                &Type::Sum(Box::new(type_a.clone()), Box::new(type_b.clone())),
+                e,
+            ) && judgment_check_effects(s, &ctx, body, t, e)
+        }
+        // the body raises what the function type allows, an argument
+        // of a union may also be taken apart into each of its cases
+        (Term::Lam(name, body), Type::Fn(i, o, f)) => {
+            judgment_check_effects(
+                s,
+                &Context::Snoc(Box::new((*g).clone()), name.clone(), *i.clone()),
+                body,
+                o,
+                f,
+            ) || match unfold(i) {
+                Type::Or(l, r) => [l, r].iter().all(|i| {
+                    let case = Type::Fn(i.clone(), o.clone(), f.clone());
+                    judgment_check_effects(s, g, term, &case, e)
+                }),
+                _ => false,
+            }
+        }
+        (Term::App(fun, arg, type_arg), _) => {
+            judgment_check_effects(
+                s,
+                g,
+                fun,
+                &Type::Fn(Box::new(type_arg.clone()), Box::new(t.clone()), e.clone()),
+                e,
+            ) && judgment_check_effects(s, g, arg, type_arg, e)
+        }
+        (Term::Var(name), _) => var_has_type(name, t, g),
+        (Term::Unit, Type::Unit) => true,
+        (Term::Ref(init, type_init), Type::Ref(inner)) => {
+            type_equality(type_init, inner) && judgment_check_effects(s, g, init, inner, e)
+        }
+        (Term::Deref(cell), _) => {
+            judgment_check_effects(s, g, cell, &Type::Ref(Box::new(t.clone())), e)
+        }
+        (Term::Assign(cell, value, type_value), Type::Unit) => {
+            judgment_check_effects(s, g, cell, &Type::Ref(Box::new(type_value.clone())), e)
+                && judgment_check_effects(s, g, value, type_value, e)
+        }
+        (Term::Loc(l), Type::Ref(inner)) => match s.get(*l) {
+            Some(stored) => type_equality(stored, inner),
+            None => false,
+        },
+        (Term::Pack(witness, body, package), _) if judgment(package) => match unfold(package) {
+            Type::Exists(x, inner) => {
+                type_equality(package, t)
+                    && judgment_check_effects(s, g, body, &subst_type(&inner, &x, witness), e)
+            }
+            _ => false,
+        },
+        (Term::Unpack(package, package_type, x_type, x, body), _) if judgment(package_type) => {
+            let (y, inner) = match unfold(package_type) {
+                Type::Exists(y, inner) => (y, inner),
+                _ => return false,
+            };
+            // the hidden type is only known by its name within the body
+            // and must neither clash with nor escape into its surroundings
+            let ctx = Context::Snoc(
+                Box::new((*g).clone()),
+                x.clone(),
+                subst_type(&inner, &y, &Type::Var(x_type.clone())),
+            );
+            !free_in_type(x_type, t)
+                && !free_in_context(x_type, g)
+                && judgment_check_effects(s, g, package, &Type::Exists(y, inner), e)
+                && judgment_check_effects(s, &ctx, body, t, e)
+        }
+        (Term::Cast(inner, from, to, _), _) => {
+            type_equality(to, t)
+                && consistent(from, to)
+                && judgment_check_effects(s, g, inner, from, e)
+        }
+        // like locations, blame only appears during evaluation
+        (Term::Blame(_, _, _), _) => true,
+        (Term::Raise(x), _) => e.contains(x),
+        (Term::Try(body, x, handler), _) => {
+            judgment_check_effects(s, g, body, t, &e.with(x))
+                && judgment_check_effects(s, g, handler, t, e)
+        }
+        _ => false,
+    }
+}
+
+/// Test if every value within the store has the type
+/// the store typing assigns to its location.
+pub fn judgment_store(s: &[Type], store: &Store) -> bool {
+    s.len() == store.len()
+        && s.iter().enumerate().all(|(l, t)| match store.get(l) {
+            Some(v) => judgment_check_store(s, &Context::Empty(), v, t),
+            None => false,
+        })
+}
+
+/// The aliases for the signatures of curry and uncurry.
+///  type Curried = Foo -> Bar -> Baz
+///  type Uncurried = Foo*Bar -> Baz
+pub fn aliases() -> alias::Aliases {
+    let curried = Type::Fn(
+        Box::new(Type::Foo),
+        Box::new(Type::Fn(
+            Box::new(Type::Bar),
+            Box::new(Type::Baz),
+            Effects::pure(),
+        )),
+        Effects::pure(),
+    );
+    let uncurried = Type::Fn(
+        Box::new(Type::Sum(Box::new(Type::Foo), Box::new(Type::Bar))),
+        Box::new(Type::Baz),
+        Effects::pure(),
+    );
+    alias::Aliases::new(&[("Curried".into(), curried), ("Uncurried".into(), uncurried)])
+        .expect("the aliases don't refer to each other")
+}
+
+/// The named example terms together with the type they are checked against.
+pub fn examples() -> Vec<(&'static str, Term, Type)> {
+    let mut examples = Vec::new();
+
+    // the identity function for foo
+    //  !- \x. x : Foo -> Foo
+    let term = Term::Lam("x".into(), Box::new(Term::Var("x".into())));
+    let t = Type::Fn(Box::new(Type::Foo), Box::new(Type::Foo), Effects::pure());
+    examples.push(("identity", term, t));
+
+    // the fst function
+    // !- \p. split p as (x :: Foo, y :: Bar) in x : Foo*Bar -> Foo
+    let term = Term::Lam(
+        "p".into(),
+        Box::new(Term::Split(
+            Box::new(Term::Var("p".into())),
+            "x".into(),
+            Type::Foo,
+            "y".into(),
+            Type::Bar,
+            Box::new(Term::Var("x".into())),
+        )),
+    );
+    let t = Type::Fn(
+        Box::new(Type::Sum(Box::new(Type::Foo), Box::new(Type::Bar))),
+        Box::new(Type::Foo),
+        Effects::pure(),
+    );
+    examples.push(("fst", term, t));
+
+    // the const function
+    // !- \x. \y. x : Foo -> Bar -> Foo
+    let term = Term::Lam(
+        "x".into(),
+        Box::new(Term::Lam("y".into(), Box::new(Term::Var("x".into())))),
+    );
+    let t = Type::Fn(
+        Box::new(Type::Foo),
+        Box::new(Type::Fn(
+            Box::new(Type::Bar),
+            Box::new(Type::Foo),
+            Effects::pure(),
+        )),
+        Effects::pure(),
+    );
+    examples.push(("const", term, t));
+
+    // the apply function
+    // !- \f. \x. f x : (Foo -> Bar) -> Foo -> Bar
+    let term = Term::Lam(
+        "f".into(),
+        Box::new(Term::Lam(
+            "x".into(),
+            Box::new(Term::App(
+                Box::new(Term::Var("f".into())),
+                Box::new(Term::Var("x".into())),
+                Type::Foo,
+            )),
+        )),
+    );
+    let t = Type::Fn(
+        Box::new(Type::Fn(
+            Box::new(Type::Foo),
+            Box::new(Type::Bar),
+            Effects::pure(),
+        )),
+        Box::new(Type::Fn(
+            Box::new(Type::Foo),
+            Box::new(Type::Bar),
+            Effects::pure(),
+        )),
+        Effects::pure(),
+    );
+    examples.push(("apply", term, t));
+
+    // the continuize function or reverse apply function
+    // !- \x. \f. f x : Foo -> (Foo -> Bar) -> Bar
+    let term = Term::Lam(
+        "x".into(),
+        Box::new(Term::Lam(
+            "f".into(),
+            Box::new(Term::App(
+                Box::new(Term::Var("f".into())),
+                Box::new(Term::Var("x".into())),
+                Type::Foo,
+            )),
+        )),
+    );
+    let t = Type::Fn(
+        Box::new(Type::Foo),
+        Box::new(Type::Fn(
+            Box::new(Type::Fn(
+                Box::new(Type::Foo),
+                Box::new(Type::Bar),
+                Effects::pure(),
+            )),
+            Box::new(Type::Bar),
+            Effects::pure(),
+        )),
+        Effects::pure(),
+    );
+    examples.push(("continuize", term, t));
+
+    // currying
+    // !- \f. \x. \y. f (x,y) : Uncurried -> Curried
+    let term = Term::Lam(
+        "f".into(),
+        Box::new(Term::Lam(
+            "x".into(),
+            Box::new(Term::Lam(
+                "y".into(),
+                Box::new(Term::App(
+                    Box::new(Term::Var("f".into())),
+                    Box::new(Term::Pair(
+                        Box::new(Term::Var("x".into())),
+                        Box::new(Term::Var("y".into())),
+                    )),
+                    Type::Sum(Box::new(Type::Foo), Box::new(Type::Bar)),
+                )),
+            )),
+        )),
+    );
+    let alias = |name: &str| Box::new(aliases().get(name).unwrap());
+    let t = Type::Fn(alias("Uncurried"), alias("Curried"), Effects::pure());
+    examples.push(("curry", term, t));
+
+    // uncurrying
+    // !- \f. \p. split p as (x :: Foo, y :: Bar) in f x y
+    //  : Curried -> Uncurried
+    let term = Term::Lam(
+        "f".into(),
+        Box::new(Term::Lam(
+            "p".into(),
+            Box::new(Term::Split(
+                Box::new(Term::Var("p".into())),
+                "x".into(),
+                Type::Foo,
+                "y".into(),
+                Type::Bar,
+                Box::new(Term::App(
+                    Box::new(Term::App(
+                        Box::new(Term::Var("f".into())),
+                        Box::new(Term::Var("x".into())),
+                        Type::Foo,
+                    )),
+                    Box::new(Term::Var("y".into())),
+                    Type::Bar,
+                )),
+            )),
+        )),
+    );
+    let t = Type::Fn(alias("Curried"), alias("Uncurried"), Effects::pure());
+    examples.push(("uncurry", term, t));
+
+    examples
+}
+
+/// The abstract counter `exists X. X * ((X -> X) * (X -> Foo))`,
+/// an initial value with increment and read operations.
+pub fn counter_type() -> Type {
+    let x = || Box::new(Type::Var("X".into()));
+    Type::Exists(
+        "X".into(),
+        Box::new(Type::Sum(
+            x(),
+            Box::new(Type::Sum(
+                Box::new(Type::Fn(x(), x(), Effects::pure())),
+                Box::new(Type::Fn(x(), Box::new(Type::Foo), Effects::pure())),
+            )),
+        )),
+    )
+}
+
+/// The context the counters are checked in: `zero : Foo, succ : Foo -> Foo`.
+pub fn counter_context() -> Context {
+    let g = Context::Snoc(Box::new(Context::Empty()), "zero".into(), Type::Foo);
+    let succ = Type::Fn(Box::new(Type::Foo), Box::new(Type::Foo), Effects::pure());
+    Context::Snoc(Box::new(g), "succ".into(), succ)
+}
+
+/// Two implementations of the counter with different hidden types.
+pub fn counters() -> Vec<Term> {
+    let var = |x: &str| Box::new(Term::Var(x.into()));
+    let lam = |x: &str, body: Term| Box::new(Term::Lam(x.into(), Box::new(body)));
+    let ops = |new: Box<Term>, inc: Box<Term>, get: Box<Term>| {
+        Box::new(Term::Pair(new, Box::new(Term::Pair(inc, get))))
+    };
+
+    // pack [Foo, (zero, (succ, \x. x))] as Counter
+    let direct = Term::Pack(
+        Type::Foo,
+        ops(var("zero"), var("succ"), lam("x", Term::Var("x".into()))),
+        counter_type(),
+    );
+
+    // a delayed counter
+    // pack [Unit -> Foo, (\u. zero, (\f. \u. succ (f u), \f. f ()))] as Counter
+    let delayed = Type::Fn(Box::new(Type::Unit), Box::new(Type::Foo), Effects::pure());
+    let inc = Term::App(
+        var("succ"),
+        Box::new(Term::App(var("f"), var("u"), Type::Unit)),
+        Type::Foo,
+    );
+    let get = Term::App(var("f"), Box::new(Term::Unit), Type::Unit);
+    let delayed = Term::Pack(
+        delayed,
+        ops(
+            lam("u", Term::Var("zero".into())),
+            lam("f", *lam("u", inc)),
+            lam("f", get),
+        ),
+        counter_type(),
+    );
+
+    vec![direct, delayed]
+}
+
+/// A client of a counter which only uses its operations.
+///  unpack c as [X, ops] in
+///  split ops as (new, rest) in split rest as (inc, get) in
+///  get (inc (inc new)) : Foo
+pub fn counter_client(counter: Term) -> Term {
+    let x = || Type::Var("X".into());
+    let var = |x: &str| Box::new(Term::Var(x.into()));
+    let x_to_x = Type::Fn(Box::new(x()), Box::new(x()), Effects::pure());
+    let x_to_foo = Type::Fn(Box::new(x()), Box::new(Type::Foo), Effects::pure());
+    let twice = Term::App(
+        var("inc"),
+        Box::new(Term::App(var("inc"), var("new"), x())),
+        x(),
+    );
+    let body = Term::Split(
+        var("ops"),
+        "new".into(),
+        x(),
+        "rest".into(),
+        Type::Sum(Box::new(x_to_x.clone()), Box::new(x_to_foo.clone())),
+        Box::new(Term::Split(
+            var("rest"),
+            "inc".into(),
+            x_to_x,
+            "get".into(),
+            x_to_foo,
+            Box::new(Term::App(var("get"), Box::new(twice), x())),
+        )),
+    );
+    Term::Unpack(
+        Box::new(counter),
+        counter_type(),
+        "X".into(),
+        "ops".into(),
+        Box::new(body),
+    )
+}
+
+/// The type operator `Pair = \A. \B. A * B` of kind `* -> * -> *`.
+pub fn pair_operator() -> Type {
+    let var = |x: &str| Box::new(Type::Var(x.into()));
+    Type::Lam(
+        "A".into(),
+        Kind::Star,
+        Box::new(Type::Lam(
+            "B".into(),
+            Kind::Star,
+            Box::new(Type::Sum(var("A"), var("B"))),
+        )),
+    )
+}
+
+/// Apply a type operator to arguments.
+pub fn type_app(f: Type, args: Vec<Type>) -> Type {
+    args.into_iter()
+        .fold(f, |f, a| Type::App(Box::new(f), Box::new(a)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn valid_judgement() {
+        let foo = Type::Foo;
+        assert!(judgment(&foo));
+
+        let bar = Type::Bar;
+        assert!(judgment(&bar));
+
+        let baz = Type::Baz;
+        assert!(judgment(&baz));
+
+        let sum = Type::Sum(Box::new(Type::Foo), Box::new(Type::Bar));
+        assert!(judgment(&sum));
+
+        let fun = Type::Fn(Box::new(baz), Box::new(foo), Effects::pure());
+        assert!(judgment(&fun));
+
+        assert!(judgment(&counter_type()));
+    }
+
+    #[test]
+    fn kinds_of_type_operators() {
+        let star = || Box::new(Kind::Star);
+        let pair = pair_operator();
+        let pair_kind = Kind::Arrow(star(), Box::new(Kind::Arrow(star(), star())));
+        assert_eq!(kinding(&mut Vec::new(), &pair), Some(pair_kind));
+        assert!(!judgment(&pair));
+        assert!(judgment(&type_app(
+            pair.clone(),
+            vec![Type::Foo, Type::Bar]
+        )));
+        // partially applied operators are no types of terms
+        assert!(!judgment(&type_app(pair.clone(), vec![Type::Foo])));
+        // neither base types nor types of kind * -> * are arguments of Pair
+        assert!(!judgment(&type_app(Type::Foo, vec![Type::Bar])));
+        assert!(!judgment(&type_app(
+            pair.clone(),
+            vec![type_app(pair, vec![Type::Foo]), Type::Bar]
+        )));
+        assert_eq!(format!("{}", pair_operator()), "\\A. \\B. A * B");
+    }
+
+    #[test]
+    fn rows() {
+        let fields = |fs: &[(&str, Type)]| -> Vec<(String, Type)> {
+            fs.iter().map(|(l, t)| (l.to_string(), t.clone())).collect()
+        };
+        let xy = Type::record(fields(&[("x", Type::Foo), ("y", Type::Bar)]), None);
+        let yx = Type::record(fields(&[("y", Type::Bar), ("x", Type::Foo)]), None);
+        assert_eq!(format!("{}", yx), "{y: Bar, x: Foo}");
+        // the order of the fields doesn't matter, the labels do
+        assert!(type_equality(&xy, &yx));
+        assert!(!type_equality(
+            &xy,
+            &Type::variant(fields(&[("x", Type::Foo), ("y", Type::Bar)]), None)
+        ));
+        assert!(!type_equality(
+            &xy,
+            &Type::record(fields(&[("x", Type::Foo)]), None)
+        ));
+        let duplicate = Type::record(fields(&[("x", Type::Foo), ("x", Type::Foo)]), None);
+        assert!(!judgment(&duplicate));
+
+        // a row variable stands for more fields
+        let open = Type::record(fields(&[("x", Type::Foo)]), Some("r".into()));
+        assert_eq!(format!("{}", open), "{x: Foo | r}");
+        assert!(free_in_type("r", &open));
+        let more = Type::record(fields(&[("y", Type::Bar)]), None);
+        assert!(type_equality(&subst_type(&open, "r", &more), &yx));
+        let renamed = subst_type(&open, "r", &Type::var("s"));
+        assert_eq!(format!("{}", renamed), "{x: Foo | s}");
+        let variant = Type::variant(vec![], Some("r".into()));
+        assert_eq!(format!("{}", variant), "<| r>");
+    }
+
+    #[test]
+    fn polymorphic_values() {
+        let a = || Type::var("a");
+        let id_type = Type::forall("a", Type::arrow(a(), a()));
+        assert_eq!(format!("{}", id_type), "forall a. a -> a");
+        let id = Term::lam("x", Term::var("x"));
+        assert!(judgment_check(&Context::new(), &id, &id_type));
+        let g = Context::new().with("id", id_type.clone());
+        assert!(judgment_check(
+            &g,
+            &Term::var("id"),
+            &Type::arrow(Type::Foo, Type::Foo)
+        ));
+        assert!(!judgment_check(
+            &g,
+            &Term::var("id"),
+            &Type::arrow(Type::Foo, Type::Bar)
+        ));
+
+        // a polymorphic argument, used at two types
+        let both = Term::lam(
+            "f",
+            Term::pair(
+                Term::app(Term::var("f"), Term::var("foo"), Type::Foo),
+                Term::app(Term::var("f"), Term::var("bar"), Type::Bar),
+            ),
+        );
+        let rank2 = Type::arrow(id_type.clone(), Type::product(Type::Foo, Type::Bar));
+        let g = Context::new().with("foo", Type::Foo).with("bar", Type::Bar);
+        assert!(judgment_check(&g, &both, &rank2));
+        let mono = Type::arrow(
+            Type::arrow(Type::Foo, Type::Foo),
+            Type::product(Type::Foo, Type::Bar),
+        );
+        assert!(!judgment_check(&g, &both, &mono));
+        assert!(subtype(&mono, &rank2) && !subtype(&rank2, &mono));
+
+        // only values are generalized, a cell can't hold every type
+        let cell = Term::reference(id, Type::arrow(a(), a()));
+        let cells = Type::forall("a", Type::reference(Type::arrow(a(), a())));
+        assert!(!judgment_check(&Context::new(), &cell, &cells));
+        assert!(!judgment_check(
+            &Context::new().with("x", a()),
+            &Term::lam("y", Term::var("x")),
+            &id_type
+        ));
+
+        // the bound variable may have the name of one in the context
+        let g = Context::new().with("y", a());
+        assert!(judgment_check(
+            &g,
+            &Term::lam("z", Term::var("z")),
+            &id_type
+        ));
+        let b = Type::var("b");
+        let renamed = Type::forall("b", Type::arrow(b.clone(), b));
+        assert!(judgment_check(
+            &g,
+            &Term::lam("z", Term::var("z")),
+            &renamed
+        ));
+    }
+
+    #[test]
+    fn type_equality_reduces_operators() {
+        let var = |x: &str| Type::Var(x.into());
+        let applied = type_app(pair_operator(), vec![Type::Foo, Type::Bar]);
+        let product = Type::Sum(Box::new(Type::Foo), Box::new(Type::Bar));
+        assert!(type_equality(&applied, &product));
+        assert!(!type_equality(
+            &applied,
+            &type_app(pair_operator(), vec![Type::Bar, Type::Foo])
+        ));
+        // ill-kinded types are equal to nothing
+        let wrong = type_app(Type::Foo, vec![Type::Bar]);
+        assert!(!type_equality(&wrong, &wrong));
+
+        // Twice = \F :: * -> *. \A. F (F A)
+        // Twice (Pair Foo) Bar = Foo * (Foo * Bar)
+        let f_kind = Kind::Arrow(Box::new(Kind::Star), Box::new(Kind::Star));
+        let twice = Type::Lam(
+            "F".into(),
+            f_kind,
+            Box::new(Type::Lam(
+                "A".into(),
+                Kind::Star,
+                Box::new(type_app(var("F"), vec![type_app(var("F"), vec![var("A")])])),
+            )),
+        );
+        let pair_foo = type_app(pair_operator(), vec![Type::Foo]);
+        let t = type_app(twice, vec![pair_foo, Type::Bar]);
+        let nested = Type::Sum(Box::new(Type::Foo), Box::new(product));
+        assert!(type_equality(&t, &nested));
+        assert_eq!(
+            format!("{}", t),
+            "(\\F :: * -> *. \\A. F (F A)) ((\\A. \\B. A * B) Foo) Bar"
+        );
+
+        // the argument B is not captured by the bound B
+        // (\A. \B. A -> B) B = \C. B -> C
+        let arrow = Type::Lam(
+            "A".into(),
+            Kind::Star,
+            Box::new(Type::Lam(
+                "B".into(),
+                Kind::Star,
+                Box::new(Type::Fn(
+                    Box::new(var("A")),
+                    Box::new(var("B")),
+                    Effects::pure(),
+                )),
+            )),
+        );
+        let applied = type_app(arrow, vec![var("B")]);
+        let expected = Type::Lam(
+            "C".into(),
+            Kind::Star,
+            Box::new(Type::Fn(
+                Box::new(var("B")),
+                Box::new(var("C")),
+                Effects::pure(),
+            )),
+        );
+        assert!(type_equality(&applied, &expected));
+
+        // terms are checked against the reduced type
+        let g = Context::Snoc(Box::new(Context::Empty()), "x".into(), Type::Foo);
+        let g = Context::Snoc(Box::new(g), "y".into(), Type::Bar);
+        let term = Term::Pair(
+            Box::new(Term::Var("x".into())),
+            Box::new(Term::Pair(
+                Box::new(Term::Var("x".into())),
+                Box::new(Term::Var("y".into())),
+            )),
+        );
+        assert!(judgment_check(&g, &term, &t));
+        assert!(!judgment_check(&g, &term, &wrong));
+    }
+
+    #[test]
+    fn functions_contain_their_exceptions() {
+        let fail = Effects::of(&["Fail"]);
+        let arrow = |e: &Effects| Type::Fn(Box::new(Type::Foo), Box::new(Type::Foo), e.clone());
+        assert_eq!(format!("{}", arrow(&fail)), "Foo -[Fail]-> Foo");
+        assert!(!type_equality(&arrow(&fail), &arrow(&Effects::pure())));
+
+        // \x. raise Fail
+        let raising = Term::Lam("x".into(), Box::new(Term::Raise("Fail".into())));
+        assert!(judgment_check(&Context::Empty(), &raising, &arrow(&fail)));
+        // a pure function type can't hide the exception
+        assert!(!judgment_check(
+            &Context::Empty(),
+            &raising,
+            &arrow(&Effects::pure())
+        ));
+
+        // f : Foo -[Fail]-> Foo |- \x. try f x with Fail => x : Foo -> Foo
+        let g = Context::Snoc(Box::new(Context::Empty()), "f".into(), arrow(&fail));
+        let call = Term::App(
+            Box::new(Term::Var("f".into())),
+            Box::new(Term::Var("x".into())),
+            Type::Foo,
+        );
+        let handled = Term::Try(
+            Box::new(call.clone()),
+            "Fail".into(),
+            Box::new(Term::Var("x".into())),
+        );
+        let lam = |body: Term| Term::Lam("x".into(), Box::new(body));
+        assert!(judgment_check(&g, &lam(handled), &arrow(&Effects::pure())));
+        assert!(!judgment_check(
+            &g,
+            &lam(call.clone()),
+            &arrow(&Effects::pure())
+        ));
+        assert!(judgment_check(&g, &lam(call), &arrow(&fail)));
+
+        // a pure function may be used where one raising Fail is expected
+        let g = Context::Snoc(
+            Box::new(Context::Empty()),
+            "f".into(),
+            arrow(&Effects::pure()),
+        );
+        assert!(judgment_check(&g, &Term::Var("f".into()), &arrow(&fail)));
+        let g = Context::Snoc(Box::new(Context::Empty()), "f".into(), arrow(&fail));
+        assert!(!judgment_check(
+            &g,
+            &Term::Var("f".into()),
+            &arrow(&Effects::pure())
+        ));
+    }
+
+    #[test]
+    fn builders_and_syntactic_equality() {
+        let fst = Term::lam(
+            "p",
+            Term::split(
+                Term::var("p"),
+                "x",
+                Type::Foo,
+                "y",
+                Type::Bar,
+                Term::var("x"),
+            ),
+        );
+        let t = Type::arrow(Type::product(Type::Foo, Type::Bar), Type::Foo);
+        let example = examples().into_iter().find(|e| e.0 == "fst").unwrap();
+        assert_eq!((fst.clone(), t.clone()), (example.1, example.2));
+        assert!(judgment_check(&Context::new(), &fst, &t));
+        assert!(!judgment_check(
+            &Context::new(),
+            &fst,
+            &Type::arrow(Type::Foo, Type::Foo)
+        ));
+
+        // equal types with different syntax are different values
+        let applied = type_app(pair_operator(), vec![Type::Foo, Type::Bar]);
+        let product = Type::product(Type::Foo, Type::Bar);
+        assert!(type_equality(&applied, &product));
+        assert_ne!(applied, product);
+        let types: HashSet<Type> = vec![applied, product.clone(), product]
+            .into_iter()
+            .collect();
+        assert_eq!(types.len(), 2);
+
+        let g = Context::new().with("x", Type::Foo);
+        assert_eq!(
+            g,
+            Context::Snoc(Box::new(Context::Empty()), "x".into(), Type::Foo)
+        );
+    }
+
+    #[test]
+    fn intersections_and_unions() {
+        let arrow = |a: Type, b: Type| Type::Fn(Box::new(a), Box::new(b), Effects::pure());
+        let and = |a: Type, b: Type| Type::And(Box::new(a), Box::new(b));
+        let or = |a: Type, b: Type| Type::Or(Box::new(a), Box::new(b));
+        let both = and(arrow(Type::Foo, Type::Foo), arrow(Type::Bar, Type::Bar));
+        assert_eq!(format!("{}", both), "(Foo -> Foo) /\\ (Bar -> Bar)");
+
+        // the identity has both types, a constant function only one
+        let id = Term::Lam("x".into(), Box::new(Term::Var("x".into())));
+        assert!(judgment_check(&Context::Empty(), &id, &both));
+        let g = Context::Snoc(Box::new(Context::Empty()), "foo".into(), Type::Foo);
+        let constant = Term::Lam("x".into(), Box::new(Term::Var("foo".into())));
+        assert!(!judgment_check(&g, &constant, &both));
+
+        // each part of an intersection can be applied
+        let g = Context::Snoc(Box::new(g), "id".into(), both.clone());
+        let app = |a: Type| {
+            Term::App(
+                Box::new(Term::Var("id".into())),
+                Box::new(Term::Var("foo".into())),
+                a,
+            )
+        };
+        assert!(judgment_check(&g, &app(Type::Foo), &Type::Foo));
+        assert!(!judgment_check(&g, &app(Type::Foo), &Type::Bar));
+
+        // the subtyping of intersections and unions
+        assert!(subtype(&both, &arrow(Type::Bar, Type::Bar)));
+        assert!(!subtype(&arrow(Type::Bar, Type::Bar), &both));
+        assert!(subtype(
+            &and(Type::Foo, Type::Bar),
+            &or(Type::Bar, Type::Baz)
+        ));
+        assert!(subtype(
+            &or(Type::Foo, Type::Bar),
+            &or(Type::Bar, Type::Foo)
+        ));
+        assert!(!subtype(&or(Type::Foo, Type::Bar), &Type::Foo));
+        // a function on a union works on each case
+        assert!(subtype(
+            &arrow(or(Type::Foo, Type::Bar), Type::Baz),
+            &arrow(Type::Foo, Type::Baz)
+        ));
+
+        // a union is taken apart into its cases
+        // \x. (x, x) : Foo \/ Bar -> Foo * Foo \/ Bar * Bar
+        let dup = Term::Lam(
+            "x".into(),
+            Box::new(Term::Pair(
+                Box::new(Term::Var("x".into())),
+                Box::new(Term::Var("x".into())),
+            )),
+        );
+        let square = |a: Type| Type::Sum(Box::new(a.clone()), Box::new(a));
+        let t = arrow(
+            or(Type::Foo, Type::Bar),
+            or(square(Type::Foo), square(Type::Bar)),
+        );
+        assert!(judgment_check(&Context::Empty(), &dup, &t));
+        let t = arrow(or(Type::Foo, Type::Bar), square(Type::Foo));
+        assert!(!judgment_check(&Context::Empty(), &dup, &t));
+    }
+
+    #[test]
+    fn existentials_are_alpha_equivalent() {
+        let y = Type::Exists("Y".into(), Box::new(Type::Var("Y".into())));
+        let x = Type::Exists("X".into(), Box::new(Type::Var("X".into())));
+        assert!(type_equality(&x, &y));
+        assert!(!type_equality(&x, &Type::Var("X".into())));
+        // a bound variable is not equal to a free one
+        let z = Type::Exists("Z".into(), Box::new(Type::Var("X".into())));
+        assert!(!type_equality(&x, &z));
+    }
+
+    #[test]
+    fn counters_hide_their_representation() {
+        let ctx = counter_context();
+        let (direct, delayed) = match &counters()[..] {
+            [a, b] => (a.clone(), b.clone()),
+            _ => unreachable!(),
+        };
+        // the delayed counter is not a counter of Foo
+        let wrong = match delayed {
+            Term::Pack(_, body, t) => Term::Pack(Type::Foo, body, t),
+            _ => unreachable!(),
+        };
+        assert!(!judgment_check(&ctx, &wrong, &counter_type()));
+
+        // unpack c as [X, ops] in split ops as (new, rest) in new : X
+        // the hidden type may not escape the body
+        let escape = |t: Type| {
+            let mut client = counter_client(direct.clone());
+            if let Term::Unpack(_, _, _, _, ref mut body) = client {
+                if let Term::Split(_, _, _, _, _, ref mut rest) = **body {
+                    **rest = Term::Var("new".into());
+                }
+            }
+            judgment_check(&ctx, &client, &t)
+        };
+        assert!(!escape(Type::Var("X".into())));
+        assert!(!escape(counter_type()));
+
+        // nor can the client assume that X is Foo
+        // ... in succ new : Foo
+        let mut client = counter_client(direct);
+        if let Term::Unpack(_, _, _, _, ref mut body) = client {
+            if let Term::Split(_, _, _, _, _, ref mut rest) = **body {
+                **rest = Term::App(
+                    Box::new(Term::Var("succ".into())),
+                    Box::new(Term::Var("new".into())),
+                    Type::Foo,
+                );
+            }
+        }
+        assert!(!judgment_check(&ctx, &client, &Type::Foo));
+    }
+}
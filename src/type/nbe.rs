@@ -0,0 +1,377 @@
+//! Normalization by evaluation for well-typed terms.
+//!
+//! A term is evaluated into a semantic domain where functions are
+//! rust closures, and read back guided by its type. Reading back
+//! eta-expands every variable, so the result is beta-normal and
+//! eta-long. Two terms are beta-eta equal exactly if their normal
+//! forms are equal up to renaming of bound variables.
+//!
+//! Only the pure fragment is supported, terms using references
+//! or existentials have no normal form.
+use std::cell::Cell;
+use std::rc::Rc;
+
+use super::{normalize_type, type_equality, Term, Type};
+
+/// The semantic domain. Neutral values are stuck on a variable
+/// and only appear at base types after reflection.
+#[derive(Clone)]
+enum Value {
+    Lam(Rc<dyn Fn(Value) -> Option<Value>>),
+    Pair(Box<Value>, Box<Value>),
+    Unit,
+    Neutral(Term),
+}
+
+type Env = Vec<(String, Value)>;
+
+/// Generates variable names for the read back.
+#[derive(Clone, Default)]
+struct Fresh(Rc<Cell<usize>>);
+
+impl Fresh {
+    fn next(&self) -> String {
+        let n = self.0.get();
+        self.0.set(n + 1);
+        format!("x{}", n)
+    }
+}
+
+fn eval(env: &Env, t: &Term) -> Option<Value> {
+    match t {
+        Term::Var(x) => env.iter().rev().find(|b| &b.0 == x).map(|b| b.1.clone()),
+        Term::Lam(x, body) => {
+            let (env, x, body) = (env.clone(), x.clone(), (**body).clone());
+            Some(Value::Lam(Rc::new(move |v| {
+                let mut env = env.clone();
+                env.push((x.clone(), v));
+                eval(&env, &body)
+            })))
+        }
+        Term::App(f, a, _) => match eval(env, f)? {
+            Value::Lam(f) => f(eval(env, a)?),
+            _ => None,
+        },
+        Term::Pair(a, b) => Some(Value::Pair(
+            Box::new(eval(env, a)?),
+            Box::new(eval(env, b)?),
+        )),
+        Term::Split(p, na, _, nb, _, body) => match eval(env, p)? {
+            Value::Pair(a, b) => {
+                let mut env = env.clone();
+                env.push((na.clone(), *a));
+                env.push((nb.clone(), *b));
+                eval(&env, body)
+            }
+            _ => None,
+        },
+        Term::Unit => Some(Value::Unit),
+        Term::Ref(_, _)
+        | Term::Deref(_)
+        | Term::Assign(_, _, _)
+        | Term::Loc(_)
+        | Term::Pack(_, _, _)
+        | Term::Unpack(_, _, _, _, _)
+        | Term::Cast(_, _, _, _)
+        | Term::Blame(_, _, _)
+        | Term::Raise(_)
+        | Term::Try(_, _, _) => None,
+    }
+}
+
+/// The projections of a neutral pair expressed with split.
+fn project(n: &Term, a: &Type, b: &Type, first: bool) -> Term {
+    let name = if first { "l" } else { "r" };
+    Term::Split(
+        Box::new(n.clone()),
+        "l".into(),
+        a.clone(),
+        "r".into(),
+        b.clone(),
+        Box::new(Term::Var(name.into())),
+    )
+}
+
+/// Turn a neutral term into a value by eta-expanding it along its type.
+fn reflect(t: &Type, n: Term, fresh: &Fresh) -> Value {
+    match t {
+        Type::Fn(a, b, _) => {
+            let (a, b, fresh) = ((**a).clone(), (**b).clone(), fresh.clone());
+            Value::Lam(Rc::new(move |v| {
+                let arg = reify(&a, v, &fresh)?;
+                let app = Term::App(Box::new(n.clone()), Box::new(arg), a.clone());
+                Some(reflect(&b, app, &fresh))
+            }))
+        }
+        Type::Sum(a, b) => Value::Pair(
+            Box::new(reflect(a, project(&n, a, b, true), fresh)),
+            Box::new(reflect(b, project(&n, a, b, false), fresh)),
+        ),
+        Type::Unit => Value::Unit,
+        Type::Named(_, t) => reflect(t, n, fresh),
+        Type::Foo
+        | Type::Bar
+        | Type::Baz
+        | Type::Ref(_)
+        | Type::Var(_)
+        | Type::Exists(_, _)
+        | Type::Forall(_, _)
+        | Type::Lam(_, _, _)
+        | Type::App(_, _)
+        | Type::Dyn
+        | Type::And(_, _)
+        | Type::Or(_, _)
+        | Type::Record(_)
+        | Type::Variant(_) => Value::Neutral(n),
+    }
+}
+
+/// Read a value back into a normal term of the given type.
+fn reify(t: &Type, v: Value, fresh: &Fresh) -> Option<Term> {
+    match (t, v) {
+        (Type::Fn(a, b, _), Value::Lam(f)) => {
+            let x = fresh.next();
+            let body = f(reflect(a, Term::Var(x.clone()), fresh))?;
+            Some(Term::Lam(x, Box::new(reify(b, body, fresh)?)))
+        }
+        (Type::Sum(a, b), Value::Pair(l, r)) => Some(Term::Pair(
+            Box::new(reify(a, *l, fresh)?),
+            Box::new(reify(b, *r, fresh)?),
+        )),
+        (&Type::Unit, _) => Some(Term::Unit),
+        (_, Value::Neutral(n)) => Some(n),
+        _ => None,
+    }
+}
+
+/// Compute the beta-normal eta-long form of a closed well-typed term.
+pub fn normalize(term: &Term, t: &Type) -> Option<Term> {
+    let fresh = Fresh::default();
+    reify(&normalize_type(t), eval(&Vec::new(), term)?, &fresh)
+}
+
+/// Test if two terms are equal up to renaming of bound variables.
+pub fn alpha_equal(a: &Term, b: &Term) -> bool {
+    fn bound(env: &[(String, String)], x: &str, left: bool) -> Option<usize> {
+        env.iter()
+            .rposition(|p| if left { p.0 == x } else { p.1 == x })
+    }
+    fn go(a: &Term, b: &Term, env: &mut Vec<(String, String)>) -> bool {
+        match (a, b) {
+            (Term::Var(x), Term::Var(y)) => match (bound(env, x, true), bound(env, y, false)) {
+                (Some(i), Some(j)) => i == j,
+                (None, None) => x == y,
+                _ => false,
+            },
+            (Term::Lam(x, a), Term::Lam(y, b)) => {
+                env.push((x.clone(), y.clone()));
+                let r = go(a, b, env);
+                env.pop();
+                r
+            }
+            (Term::App(f1, a1, t1), Term::App(f2, a2, t2)) => {
+                type_equality(t1, t2) && go(f1, f2, env) && go(a1, a2, env)
+            }
+            (Term::Pair(a1, b1), Term::Pair(a2, b2)) => go(a1, a2, env) && go(b1, b2, env),
+            (Term::Split(p1, x1, s1, y1, t1, a), Term::Split(p2, x2, s2, y2, t2, b)) => {
+                if !(type_equality(s1, s2) && type_equality(t1, t2) && go(p1, p2, env)) {
+                    return false;
+                }
+                env.push((x1.clone(), x2.clone()));
+                env.push((y1.clone(), y2.clone()));
+                let r = go(a, b, env);
+                env.truncate(env.len() - 2);
+                r
+            }
+            (&Term::Unit, &Term::Unit) => true,
+            (Term::Ref(a, s), Term::Ref(b, t)) => type_equality(s, t) && go(a, b, env),
+            (Term::Deref(a), Term::Deref(b)) => go(a, b, env),
+            (Term::Assign(c1, v1, s), Term::Assign(c2, v2, t)) => {
+                type_equality(s, t) && go(c1, c2, env) && go(v1, v2, env)
+            }
+            (Term::Loc(a), Term::Loc(b)) => a == b,
+            (Term::Pack(w1, a, t1), Term::Pack(w2, b, t2)) => {
+                type_equality(w1, w2) && type_equality(t1, t2) && go(a, b, env)
+            }
+            (Term::Unpack(p1, t1, x1, y1, a), Term::Unpack(p2, t2, x2, y2, b)) => {
+                // the type variables are compared by name
+                if !(x1 == x2 && type_equality(t1, t2) && go(p1, p2, env)) {
+                    return false;
+                }
+                env.push((y1.clone(), y2.clone()));
+                let r = go(a, b, env);
+                env.pop();
+                r
+            }
+            (Term::Cast(a, s1, t1, l1), Term::Cast(b, s2, t2, l2)) => {
+                l1 == l2 && type_equality(s1, s2) && type_equality(t1, t2) && go(a, b, env)
+            }
+            (Term::Blame(l1, _, _), Term::Blame(l2, _, _)) => l1 == l2,
+            _ => false,
+        }
+    }
+    go(a, b, &mut Vec::new())
+}
+
+/// Decide if two closed terms of type `t` are beta-eta equivalent.
+pub fn terms_equal(a: &Term, b: &Term, t: &Type) -> bool {
+    match (normalize(a, t), normalize(b, t)) {
+        (Some(a), Some(b)) => alpha_equal(&a, &b),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{judgment_check, Context, Effects};
+    use super::*;
+
+    fn var(x: &str) -> Box<Term> {
+        Box::new(Term::Var(x.into()))
+    }
+
+    fn lam(x: &str, body: Term) -> Term {
+        Term::Lam(x.into(), Box::new(body))
+    }
+
+    fn arrow(a: Type, b: Type) -> Type {
+        Type::Fn(Box::new(a), Box::new(b), Effects::pure())
+    }
+
+    fn product(a: Type, b: Type) -> Type {
+        Type::Sum(Box::new(a), Box::new(b))
+    }
+
+    /// \f. \x. \y. f (x, y)
+    fn curry() -> Term {
+        lam(
+            "f",
+            lam(
+                "x",
+                lam(
+                    "y",
+                    Term::App(
+                        var("f"),
+                        Box::new(Term::Pair(var("x"), var("y"))),
+                        product(Type::Foo, Type::Bar),
+                    ),
+                ),
+            ),
+        )
+    }
+
+    /// \f. \p. split p as (x :: Foo, y :: Bar) in f x y
+    fn uncurry() -> Term {
+        lam(
+            "f",
+            lam(
+                "p",
+                Term::Split(
+                    var("p"),
+                    "x".into(),
+                    Type::Foo,
+                    "y".into(),
+                    Type::Bar,
+                    Box::new(Term::App(
+                        Box::new(Term::App(var("f"), var("x"), Type::Foo)),
+                        var("y"),
+                        Type::Bar,
+                    )),
+                ),
+            ),
+        )
+    }
+
+    fn curried() -> Type {
+        arrow(Type::Foo, arrow(Type::Bar, Type::Baz))
+    }
+
+    fn uncurried() -> Type {
+        arrow(product(Type::Foo, Type::Bar), Type::Baz)
+    }
+
+    #[test]
+    fn uncurry_after_curry_is_identity() {
+        // \g. uncurry (curry g) : (Foo*Bar -> Baz) -> Foo*Bar -> Baz
+        let t = arrow(uncurried(), uncurried());
+        let term = lam(
+            "g",
+            Term::App(
+                Box::new(uncurry()),
+                Box::new(Term::App(Box::new(curry()), var("g"), uncurried())),
+                curried(),
+            ),
+        );
+        assert!(judgment_check(&Context::Empty(), &term, &t));
+        assert!(terms_equal(&term, &lam("g", Term::Var("g".into())), &t));
+    }
+
+    #[test]
+    fn curry_after_uncurry_is_identity() {
+        let t = arrow(curried(), curried());
+        let term = lam(
+            "g",
+            Term::App(
+                Box::new(curry()),
+                Box::new(Term::App(Box::new(uncurry()), var("g"), curried())),
+                uncurried(),
+            ),
+        );
+        assert!(terms_equal(&term, &lam("g", Term::Var("g".into())), &t));
+    }
+
+    #[test]
+    fn normal_forms_are_eta_long() {
+        // \f. f : (Foo -> Bar) -> Foo -> Bar normalizes to \f. \x. f x
+        let t = arrow(arrow(Type::Foo, Type::Bar), arrow(Type::Foo, Type::Bar));
+        let nf = normalize(&lam("f", Term::Var("f".into())), &t).unwrap();
+        let expected = lam("a", lam("b", Term::App(var("a"), var("b"), Type::Foo)));
+        assert!(alpha_equal(&nf, &expected));
+        assert!(judgment_check(&Context::Empty(), &nf, &t));
+
+        // every term of type Unit is ()
+        let t = arrow(Type::Unit, Type::Unit);
+        assert!(terms_equal(
+            &lam("u", Term::Var("u".into())),
+            &lam("u", Term::Unit),
+            &t
+        ));
+
+        // pairs are expanded into their projections
+        let t = arrow(product(Type::Foo, Type::Bar), product(Type::Foo, Type::Bar));
+        let nf = normalize(&lam("p", Term::Var("p".into())), &t).unwrap();
+        assert!(judgment_check(&Context::Empty(), &nf, &t));
+        assert!(matches!(nf, Term::Lam(_, ref body) if matches!(**body, Term::Pair(_, _))));
+    }
+
+    #[test]
+    fn beta_reduction_under_binders() {
+        // \x. (\y. y) x = \x. x : Foo -> Foo
+        let t = arrow(Type::Foo, Type::Foo);
+        let term = lam(
+            "x",
+            Term::App(
+                Box::new(lam("y", Term::Var("y".into()))),
+                var("x"),
+                Type::Foo,
+            ),
+        );
+        assert!(terms_equal(&term, &lam("z", Term::Var("z".into())), &t));
+    }
+
+    #[test]
+    fn different_terms_are_not_equal() {
+        // \x. \y. x /= \x. \y. y : Foo -> Foo -> Foo
+        let t = arrow(Type::Foo, arrow(Type::Foo, Type::Foo));
+        let fst = lam("x", lam("y", Term::Var("x".into())));
+        let snd = lam("x", lam("y", Term::Var("y".into())));
+        assert!(!terms_equal(&fst, &snd, &t));
+        assert!(terms_equal(&fst, &fst, &t));
+    }
+
+    #[test]
+    fn references_have_no_normal_form() {
+        let t = Type::Ref(Box::new(Type::Unit));
+        assert!(normalize(&Term::Ref(Box::new(Term::Unit), Type::Unit), &t).is_none());
+    }
+}
@@ -0,0 +1,519 @@
+//! A decision procedure for linear arithmetic over the integers.
+//!
+//! The Omega test of Pugh decides if a conjunction of linear equalities
+//! and inequalities has an integer solution, and finds one if it does.
+//! Equalities are solved for a variable with a unit coefficient, others
+//! first shrink their coefficients by a substitution which introduces a
+//! new variable. Inequalities are eliminated one variable at a time like
+//! in Fourier–Motzkin: the real shadow combines every lower bound of the
+//! variable with every upper bound. The real shadow may have solutions
+//! where the original problem has no integer one, so unless a bound has
+//! a unit coefficient the dark shadow, which only has solutions with an
+//! integer in between the bounds, is tried first. If only the real shadow
+//! has solutions the remaining candidates are the splinters, which fix the
+//! variable close to one of its lower bounds.
+//!
+//! The solution is built on the way back: an eliminated variable takes
+//! the least integer its lower bounds allow.
+use std::collections::BTreeMap;
+
+/// A linear expression `a1 * x1 + ... + an * xn + c`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Linear {
+    pub coeffs: BTreeMap<String, i64>,
+    pub constant: i64,
+}
+
+impl Linear {
+    pub fn constant(c: i64) -> Linear {
+        Linear {
+            coeffs: BTreeMap::new(),
+            constant: c,
+        }
+    }
+
+    pub fn var(x: &str) -> Linear {
+        let mut coeffs = BTreeMap::new();
+        coeffs.insert(x.to_string(), 1);
+        Linear {
+            coeffs,
+            constant: 0,
+        }
+    }
+
+    pub fn plus(&self, other: &Linear) -> Linear {
+        let mut sum = self.clone();
+        for (x, a) in &other.coeffs {
+            *sum.coeffs.entry(x.clone()).or_insert(0) += a;
+        }
+        sum.coeffs.retain(|_, a| *a != 0);
+        sum.constant += other.constant;
+        sum
+    }
+
+    pub fn minus(&self, other: &Linear) -> Linear {
+        self.plus(&other.times(-1))
+    }
+
+    pub fn times(&self, k: i64) -> Linear {
+        let mut coeffs = BTreeMap::new();
+        if k != 0 {
+            for (x, a) in &self.coeffs {
+                coeffs.insert(x.clone(), a * k);
+            }
+        }
+        Linear {
+            coeffs,
+            constant: self.constant * k,
+        }
+    }
+
+    /// The value at an assignment, missing variables are zero.
+    pub fn eval(&self, model: &BTreeMap<String, i64>) -> i64 {
+        self.coeffs
+            .iter()
+            .map(|(x, a)| a * model.get(x).cloned().unwrap_or(0))
+            .sum::<i64>()
+            + self.constant
+    }
+}
+
+/// A constraint `e = 0` or `e >= 0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constraint {
+    Eq(Linear),
+    Geq(Linear),
+}
+
+impl Constraint {
+    pub fn holds(&self, model: &BTreeMap<String, i64>) -> bool {
+        match self {
+            Constraint::Eq(e) => e.eval(model) == 0,
+            Constraint::Geq(e) => e.eval(model) >= 0,
+        }
+    }
+}
+
+/// Find integers which satisfy all the constraints, `None` if there are none.
+pub fn satisfiable(constraints: &[Constraint]) -> Option<BTreeMap<String, i64>> {
+    let mut names: Vec<&String> = Vec::new();
+    for c in constraints {
+        let (Constraint::Eq(e) | Constraint::Geq(e)) = c;
+        for x in e.coeffs.keys() {
+            if !names.contains(&x) {
+                names.push(x);
+            }
+        }
+    }
+    let row = |e: &Linear| Row {
+        a: names
+            .iter()
+            .map(|x| e.coeffs.get(*x).cloned().unwrap_or(0))
+            .collect(),
+        c: e.constant,
+    };
+    let (mut eqs, mut geqs) = (Vec::new(), Vec::new());
+    for c in constraints {
+        match c {
+            Constraint::Eq(e) => eqs.push(row(e)),
+            Constraint::Geq(e) => geqs.push(row(e)),
+        }
+    }
+    let model = solve(eqs, geqs, names.len())?;
+    Some(names.into_iter().cloned().zip(model).collect())
+}
+
+/// Eliminate `x` from the constraints, the result holds wherever the
+/// constraints hold for some integer `x`. An equality with a unit
+/// coefficient of `x` is exact, otherwise this is the real shadow, which
+/// may also hold where only a rational `x` would do.
+pub fn shadow(constraints: &[Constraint], x: &str) -> Vec<Constraint> {
+    let coeff = |e: &Linear| e.coeffs.get(x).cloned().unwrap_or(0);
+    let unit = constraints.iter().find_map(|c| match c {
+        Constraint::Eq(e) if coeff(e).abs() == 1 => Some(e),
+        _ => None,
+    });
+    if let Some(e) = unit {
+        // x = -(e - a * x) / a
+        let a = coeff(e);
+        let value = e.minus(&Linear::var(x).times(a)).times(-a);
+        let subst = |f: &Linear| {
+            let k = coeff(f);
+            f.minus(&Linear::var(x).times(k)).plus(&value.times(k))
+        };
+        return constraints
+            .iter()
+            .filter(|c| !matches!(c, Constraint::Eq(f) if f == e))
+            .map(|c| match c {
+                Constraint::Eq(f) => Constraint::Eq(subst(f)),
+                Constraint::Geq(f) => Constraint::Geq(subst(f)),
+            })
+            .collect();
+    }
+    let (mut lower, mut upper, mut rest) = (Vec::new(), Vec::new(), Vec::new());
+    for c in constraints {
+        let geqs = match c {
+            Constraint::Eq(e) if coeff(e) != 0 => vec![e.clone(), e.times(-1)],
+            Constraint::Geq(e) => vec![e.clone()],
+            Constraint::Eq(_) => {
+                rest.push(c.clone());
+                continue;
+            }
+        };
+        for e in geqs {
+            match coeff(&e) {
+                a if a > 0 => lower.push(e),
+                a if a < 0 => upper.push(e),
+                _ => rest.push(Constraint::Geq(e)),
+            }
+        }
+    }
+    for l in &lower {
+        for u in &upper {
+            let e = l.times(-coeff(u)).plus(&u.times(coeff(l)));
+            // the integers on the left of >= 0 are a multiple of the gcd
+            let g = e.coeffs.values().fold(0, |g, a| gcd(g, a.abs()));
+            let e = match g {
+                0 | 1 => e,
+                g => Linear {
+                    coeffs: e.coeffs.iter().map(|(y, a)| (y.clone(), a / g)).collect(),
+                    constant: floor_div(e.constant, g),
+                },
+            };
+            if !e.coeffs.is_empty() || e.constant < 0 {
+                rest.push(Constraint::Geq(e));
+            }
+        }
+    }
+    rest
+}
+
+/// `a[0] * x0 + ... + c` over the variables numbered by their position.
+#[derive(Debug, Clone, PartialEq)]
+struct Row {
+    a: Vec<i64>,
+    c: i64,
+}
+
+impl Row {
+    fn eval(&self, model: &[i64]) -> i64 {
+        self.a.iter().zip(model).map(|(a, x)| a * x).sum::<i64>() + self.c
+    }
+
+    fn gcd(&self) -> i64 {
+        self.a.iter().fold(0, |g, a| gcd(g, a.abs()))
+    }
+
+    /// `k * self + l * other`
+    fn combine(&self, k: i64, other: &Row, l: i64) -> Row {
+        Row {
+            a: self
+                .a
+                .iter()
+                .zip(&other.a)
+                .map(|(a, b)| k * a + l * b)
+                .collect(),
+            c: k * self.c + l * other.c,
+        }
+    }
+
+    /// Replace the variable `x` by an expression.
+    fn subst(&self, x: usize, e: &Row) -> Row {
+        let mut r = self.clone();
+        r.a[x] = 0;
+        r.combine(1, e, self.a[x])
+    }
+
+    /// Add a variable which the row doesn't mention.
+    fn extend(&self) -> Row {
+        let mut r = self.clone();
+        r.a.push(0);
+        r
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+fn floor_div(a: i64, b: i64) -> i64 {
+    a.div_euclid(b)
+}
+
+fn ceil_div(a: i64, b: i64) -> i64 {
+    -(-a).div_euclid(b)
+}
+
+/// The symmetric remainder `a - m * floor(a / m + 1/2)`.
+fn mod_hat(a: i64, m: i64) -> i64 {
+    a - m * floor_div(2 * a + m, 2 * m)
+}
+
+/// Normalize the constraints by the gcd of their coefficients, which
+/// tightens the constant of an inequality, then solve them.
+fn solve(eqs: Vec<Row>, geqs: Vec<Row>, n: usize) -> Option<Vec<i64>> {
+    let mut equalities = Vec::new();
+    for r in eqs {
+        match r.gcd() {
+            0 if r.c == 0 => {}
+            0 => return None,
+            g if r.c % g != 0 => return None,
+            g => equalities.push(Row {
+                a: r.a.iter().map(|a| a / g).collect(),
+                c: r.c / g,
+            }),
+        }
+    }
+    let mut inequalities: Vec<Row> = Vec::new();
+    for r in geqs {
+        let r = match r.gcd() {
+            0 if r.c >= 0 => continue,
+            0 => return None,
+            g => Row {
+                a: r.a.iter().map(|a| a / g).collect(),
+                c: floor_div(r.c, g),
+            },
+        };
+        if !inequalities.contains(&r) {
+            inequalities.push(r);
+        }
+    }
+    match equalities.pop() {
+        Some(e) => equality(e, equalities, inequalities, n),
+        None => eliminate(inequalities, n),
+    }
+}
+
+/// Solve an equality for one of its variables and substitute it.
+fn equality(e: Row, eqs: Vec<Row>, geqs: Vec<Row>, n: usize) -> Option<Vec<i64>> {
+    let substituted = |rows: &[Row], x: usize, def: &Row| -> Vec<Row> {
+        rows.iter().map(|r| r.subst(x, def)).collect()
+    };
+    if let Some(x) = (0..n).find(|&x| e.a[x].abs() == 1) {
+        // a * x + rest = 0 with a = 1 or -1, so x = -a * rest
+        let mut def = e.clone();
+        def.a[x] = 0;
+        let def = def.combine(-e.a[x], &def, 0);
+        let mut model = solve(substituted(&eqs, x, &def), substituted(&geqs, x, &def), n)?;
+        model[x] = 0;
+        model[x] = def.eval(&model);
+        return Some(model);
+    }
+    // with the least coefficient a of x and m = |a| + 1, the equality
+    // implies sum(a_i mod^ m * x_i) + c mod^ m = m * s for a new variable
+    // s, where a mod^ m = -sign(a). Solving it for x shrinks the others.
+    let x = (0..n)
+        .filter(|&x| e.a[x] != 0)
+        .min_by_key(|&x| e.a[x].abs())
+        .expect("an equality with a variable");
+    let (sign, m) = (e.a[x].signum(), e.a[x].abs() + 1);
+    let mut def = Row {
+        a: vec![0; n + 1],
+        c: sign * mod_hat(e.c, m),
+    };
+    for i in (0..n).filter(|&i| i != x) {
+        def.a[i] = sign * mod_hat(e.a[i], m);
+    }
+    def.a[n] = -sign * m;
+    let mut eqs: Vec<Row> = eqs.iter().map(Row::extend).collect();
+    eqs.push(e.extend());
+    let geqs: Vec<Row> = geqs.iter().map(Row::extend).collect();
+    let mut model = solve(
+        substituted(&eqs, x, &def),
+        substituted(&geqs, x, &def),
+        n + 1,
+    )?;
+    model[x] = 0;
+    model[x] = def.eval(&model);
+    model.truncate(n);
+    Some(model)
+}
+
+/// Eliminate a variable from normalized inequalities.
+fn eliminate(geqs: Vec<Row>, n: usize) -> Option<Vec<i64>> {
+    let bounds = |x: usize| -> (Vec<&Row>, Vec<&Row>) {
+        (
+            geqs.iter().filter(|r| r.a[x] > 0).collect(),
+            geqs.iter().filter(|r| r.a[x] < 0).collect(),
+        )
+    };
+    let present: Vec<usize> = (0..n)
+        .filter(|&x| geqs.iter().any(|r| r.a[x] != 0))
+        .collect();
+    if present.is_empty() {
+        return Some(vec![0; n]);
+    }
+    // a variable without bounds on one side first, then one which is
+    // eliminated exactly, each with the fewest combinations
+    let x = *present
+        .iter()
+        .min_by_key(|&&x| {
+            let (lower, upper) = bounds(x);
+            let exact = lower.iter().all(|r| r.a[x] == 1) || upper.iter().all(|r| r.a[x] == -1);
+            match (lower.is_empty() || upper.is_empty(), exact) {
+                (true, _) => (0, 0),
+                (false, true) => (1, lower.len() * upper.len()),
+                (false, false) => (2, lower.len() * upper.len()),
+            }
+        })
+        .expect("a variable");
+    let (lower, upper) = bounds(x);
+    let rest: Vec<Row> = geqs.iter().filter(|r| r.a[x] == 0).cloned().collect();
+    // the least value of x the lower bounds allow, or the greatest below
+    // the upper bounds if it has none
+    let value = |model: &mut Vec<i64>| {
+        model[x] = 0;
+        model[x] = match lower.is_empty() {
+            false => lower.iter().map(|r| ceil_div(-r.eval(model), r.a[x])).max(),
+            true => upper
+                .iter()
+                .map(|r| floor_div(r.eval(model), -r.a[x]))
+                .min(),
+        }
+        .expect("a bound");
+    };
+    if lower.is_empty() || upper.is_empty() {
+        let mut model = solve(Vec::new(), rest, n)?;
+        value(&mut model);
+        return Some(model);
+    }
+    let shadow = |dark: bool| -> Vec<Row> {
+        let mut rows = rest.clone();
+        for l in &lower {
+            for u in &upper {
+                let (a, b) = (l.a[x], -u.a[x]);
+                let mut r = l.combine(b, u, a);
+                if dark {
+                    r.c -= (a - 1) * (b - 1);
+                }
+                rows.push(r);
+            }
+        }
+        rows
+    };
+    if let Some(mut model) = solve(Vec::new(), shadow(true), n) {
+        value(&mut model);
+        return Some(model);
+    }
+    let exact = lower.iter().all(|r| r.a[x] == 1) || upper.iter().all(|r| r.a[x] == -1);
+    if exact {
+        return None;
+    }
+    solve(Vec::new(), shadow(false), n)?;
+    // an integer solution in the real shadow but not the dark one is close
+    // to a lower bound: a * x + e = i for some small i
+    let b = upper.iter().map(|u| -u.a[x]).max().expect("an upper bound");
+    for l in &lower {
+        let a = l.a[x];
+        for i in 0..=floor_div(a * b - a - b, b) {
+            let mut e = (*l).clone();
+            e.c -= i;
+            if let Some(model) = solve(vec![e], geqs.clone(), n) {
+                return Some(model);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn x() -> Linear {
+        Linear::var("x")
+    }
+
+    fn y() -> Linear {
+        Linear::var("y")
+    }
+
+    fn c(k: i64) -> Linear {
+        Linear::constant(k)
+    }
+
+    /// The constraint `a <= b`.
+    fn le(a: Linear, b: Linear) -> Constraint {
+        Constraint::Geq(b.minus(&a))
+    }
+
+    fn eq(a: Linear, b: Linear) -> Constraint {
+        Constraint::Eq(b.minus(&a))
+    }
+
+    /// Solve the constraints and check the solution.
+    fn solution(constraints: &[Constraint]) -> Option<BTreeMap<String, i64>> {
+        let model = satisfiable(constraints)?;
+        assert!(constraints.iter().all(|c| c.holds(&model)), "{:?}", model);
+        Some(model)
+    }
+
+    #[test]
+    fn inequalities() {
+        let sum = x().plus(&y());
+        let model = solution(&[
+            le(c(0), x()),
+            le(c(0), y()),
+            le(sum.clone(), c(1)),
+            le(c(1), sum),
+        ]);
+        assert!(model.is_some());
+        assert!(solution(&[le(c(3), x()), le(x(), c(2))]).is_none());
+        // 3 <= 2x <= 3 has a rational solution only
+        assert!(solution(&[le(c(3), x().times(2)), le(x().times(2), c(3))]).is_none());
+        // a variable bounded on one side only
+        assert_eq!(solution(&[le(c(5), x().times(3))]).unwrap()["x"], 2);
+    }
+
+    #[test]
+    fn equalities() {
+        assert!(solution(&[eq(x().times(2), c(1))]).is_none());
+        // 3x + 5y = 7 needs a negative variable
+        let mut e = vec![eq(x().times(3).plus(&y().times(5)), c(7))];
+        assert!(solution(&e).is_some());
+        e.extend(vec![le(c(0), x()), le(c(0), y())]);
+        assert!(solution(&e).is_none());
+        let twice = eq(x().times(4).plus(&y().times(6)), c(14));
+        assert!(solution(&[twice, le(c(0), x()), le(c(0), y())]).is_some());
+    }
+
+    #[test]
+    fn dark_shadows_and_splinters() {
+        // Pugh's example: the real shadow has solutions, the integers don't
+        let a = x().times(11).plus(&y().times(13));
+        let b = x().times(7).minus(&y().times(9));
+        let problem = [
+            le(c(27), a.clone()),
+            le(a, c(45)),
+            le(c(-10), b.clone()),
+            le(b, c(4)),
+        ];
+        assert!(solution(&problem).is_none());
+        // an integer solution only found by a splinter
+        let a = x().times(3).minus(&y().times(2));
+        let splinter = [
+            le(c(1), a.clone()),
+            le(a, c(1)),
+            le(c(0), y()),
+            le(y(), c(4)),
+        ];
+        let model = solution(&splinter).unwrap();
+        assert_eq!(3 * model["x"] - 2 * model["y"], 1);
+    }
+
+    #[test]
+    fn shadows() {
+        // 1 <= y and y + 1 <= x leave 2 <= x
+        let bounds = [le(c(1), y()), le(y().plus(&c(1)), x())];
+        assert_eq!(shadow(&bounds, "y"), vec![le(c(2), x())]);
+        // an equality is substituted
+        let exact = [eq(y(), x().times(2)), le(c(3), y())];
+        assert_eq!(shadow(&exact, "y"), vec![le(c(3), x().times(2))]);
+        // 2y = x + 1 only holds for odd x, the real shadow has them all
+        let odd = [eq(y().times(2), x().plus(&c(1))), le(c(0), y())];
+        assert_eq!(shadow(&odd, "y"), vec![le(c(-1), x())]);
+    }
+}
@@ -0,0 +1,517 @@
+//! A prover for intuitionistic propositional logic.
+//!
+//! By the Curry–Howard correspondence a formula is a type and a proof
+//! is a term of that type. Conjunction is the product, implication the
+//! function type and `True` is `Unit`. The atoms are mapped to the base
+//! types and, once those are used up, to type variables. The calculus
+//! has neither sums nor an empty type, so disjunction and negation
+//! can't be expressed.
+//!
+//! Proof search introduces functions and pairs for the goal, splits
+//! every product assumption and applies assumed functions until the
+//! atomic goal is assumed. A sequent which reappears on the current
+//! path is abandoned, which makes the search terminate and complete.
+use std::fmt;
+
+use super::{judgment_check, type_equality, var_has_type, Context, Effects, Term, Type};
+
+/// A formula of propositional logic.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Formula {
+    Atom(String),
+    True,
+    And(Box<Formula>, Box<Formula>),
+    Implies(Box<Formula>, Box<Formula>),
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn atom(p: &Formula) -> String {
+            match p {
+                Formula::Atom(_) | Formula::True => format!("{}", p),
+                _ => format!("({})", p),
+            }
+        }
+        match self {
+            Formula::Atom(a) => write!(f, "{}", a),
+            Formula::True => write!(f, "True"),
+            Formula::And(a, b) => write!(f, "{} /\\ {}", atom(a), atom(b)),
+            Formula::Implies(a, b) if matches!(**a, Formula::Implies(_, _)) => {
+                write!(f, "({}) -> {}", a, b)
+            }
+            Formula::Implies(a, b) => write!(f, "{} -> {}", a, b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    And,
+    Implies,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '/' if chars.next_if_eq(&'\\').is_some() => tokens.push(Token::And),
+            '-' if chars.next_if_eq(&'>').is_some() => tokens.push(Token::Implies),
+            '\\' if chars.peek() == Some(&'/') => {
+                return Err("disjunction has no counterpart in the calculus".into())
+            }
+            '~' => return Err("negation has no counterpart in the calculus".into()),
+            c if c.is_alphabetic() => {
+                let mut name = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_alphanumeric() {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Name(name));
+            }
+            c => return Err(format!("unexpected character '{}'", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+/// formula := conj ('->' formula)?
+/// conj := atom ('/\' atom)*
+/// atom := name | 'True' | '(' formula ')'
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn formula(&mut self) -> Result<Formula, String> {
+        let a = self.conj()?;
+        if self.peek() == Some(&Token::Implies) {
+            self.pos += 1;
+            let b = self.formula()?;
+            return Ok(Formula::Implies(Box::new(a), Box::new(b)));
+        }
+        Ok(a)
+    }
+
+    fn conj(&mut self) -> Result<Formula, String> {
+        let mut a = self.atom()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            a = Formula::And(Box::new(a), Box::new(self.atom()?));
+        }
+        Ok(a)
+    }
+
+    fn atom(&mut self) -> Result<Formula, String> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Name(ref n)) if n == "True" => Ok(Formula::True),
+            Some(Token::Name(ref n)) if n == "False" => {
+                Err("falsity has no counterpart in the calculus".into())
+            }
+            Some(Token::Name(n)) => Ok(Formula::Atom(n)),
+            Some(Token::Open) => {
+                let a = self.formula()?;
+                match self.peek() {
+                    Some(&Token::Close) => {
+                        self.pos += 1;
+                        Ok(a)
+                    }
+                    _ => Err("expected ')'".into()),
+                }
+            }
+            Some(t) => Err(format!("unexpected {:?}", t)),
+            None => Err("unexpected end of formula".into()),
+        }
+    }
+}
+
+/// Parse a formula like `A /\ B -> B /\ A`.
+pub fn parse(s: &str) -> Result<Formula, String> {
+    let mut parser = Parser {
+        tokens: tokenize(s)?,
+        pos: 0,
+    };
+    let formula = parser.formula()?;
+    match parser.peek() {
+        None => Ok(formula),
+        Some(t) => Err(format!("unexpected {:?}", t)),
+    }
+}
+
+/// Assigns a type to every atom. Atoms named after a base type keep it,
+/// the others get the remaining ones in order of appearance and then
+/// the type variable of their name.
+pub fn atoms(p: &Formula) -> Vec<(String, Type)> {
+    fn collect(p: &Formula, acc: &mut Vec<String>) {
+        match p {
+            Formula::Atom(a) if !acc.contains(a) => acc.push(a.clone()),
+            Formula::And(a, b) | Formula::Implies(a, b) => {
+                collect(a, acc);
+                collect(b, acc);
+            }
+            _ => {}
+        }
+    }
+    let mut names = Vec::new();
+    collect(p, &mut names);
+    let base = [("Foo", Type::Foo), ("Bar", Type::Bar), ("Baz", Type::Baz)];
+    let mut free: Vec<&(&str, Type)> = base
+        .iter()
+        .filter(|b| !names.iter().any(|n| n == b.0))
+        .collect();
+    free.reverse();
+    let mut assignment = Vec::new();
+    for n in names {
+        let t = match base.iter().find(|b| b.0 == n) {
+            Some(b) => b.1.clone(),
+            None => match free.pop() {
+                Some(b) => b.1.clone(),
+                None => Type::Var(n.clone()),
+            },
+        };
+        assignment.push((n, t));
+    }
+    assignment
+}
+
+/// The type corresponding to a formula.
+pub fn to_type(p: &Formula, atoms: &[(String, Type)]) -> Type {
+    match p {
+        Formula::Atom(a) => atoms
+            .iter()
+            .find(|b| &b.0 == a)
+            .map(|b| b.1.clone())
+            .expect("every atom is assigned"),
+        Formula::True => Type::Unit,
+        Formula::And(a, b) => Type::Sum(Box::new(to_type(a, atoms)), Box::new(to_type(b, atoms))),
+        Formula::Implies(a, b) => Type::Fn(
+            Box::new(to_type(a, atoms)),
+            Box::new(to_type(b, atoms)),
+            Effects::pure(),
+        ),
+    }
+}
+
+/// A split of an assumed pair, which has to wrap the proof using it.
+type Split = (String, String, Type, String, Type);
+
+struct Search {
+    counter: usize,
+    /// The sequents on the current path.
+    history: Vec<(Vec<Type>, Type)>,
+}
+
+fn contains(ts: &[Type], t: &Type) -> bool {
+    ts.iter().any(|s| type_equality(s, t))
+}
+
+fn same_types(a: &[Type], b: &[Type]) -> bool {
+    a.iter().all(|t| contains(b, t)) && b.iter().all(|t| contains(a, t))
+}
+
+impl Search {
+    fn fresh(&mut self) -> String {
+        self.counter += 1;
+        format!("x{}", self.counter)
+    }
+
+    /// Assume `x : t`, splitting products into their components.
+    /// Returns the number of new types among the assumptions.
+    fn assume(
+        &mut self,
+        hyps: &mut Vec<(String, Type)>,
+        x: String,
+        t: &Type,
+        splits: &mut Vec<Split>,
+    ) -> usize {
+        match t {
+            Type::Sum(a, b) => {
+                let (l, r) = (self.fresh(), self.fresh());
+                splits.push((x, l.clone(), (**a).clone(), r.clone(), (**b).clone()));
+                self.assume(hyps, l, a, splits) + self.assume(hyps, r, b, splits)
+            }
+            _ => {
+                let new = !hyps.iter().any(|h| type_equality(&h.1, t));
+                hyps.push((x, t.clone()));
+                new as usize
+            }
+        }
+    }
+
+    /// Wrap a proof into the splits it depends on.
+    fn wrap(body: Term, splits: Vec<Split>) -> Term {
+        splits
+            .into_iter()
+            .rev()
+            .fold(body, |body, (p, l, a, r, b)| {
+                Term::Split(Box::new(Term::Var(p)), l, a, r, b, Box::new(body))
+            })
+    }
+
+    fn prove(&mut self, hyps: &[(String, Type)], goal: &Type) -> Option<Term> {
+        match goal {
+            Type::Unit => Some(Term::Unit),
+            Type::Fn(a, b, _) => {
+                let x = self.fresh();
+                let (mut hyps, mut splits) = (hyps.to_vec(), Vec::new());
+                self.assume(&mut hyps, x.clone(), a, &mut splits);
+                let body = self.prove(&hyps, b)?;
+                Some(Term::Lam(x, Box::new(Self::wrap(body, splits))))
+            }
+            Type::Sum(a, b) => Some(Term::Pair(
+                Box::new(self.prove(hyps, a)?),
+                Box::new(self.prove(hyps, b)?),
+            )),
+            _ => self.prove_atom(hyps, goal),
+        }
+    }
+
+    fn prove_atom(&mut self, hyps: &[(String, Type)], goal: &Type) -> Option<Term> {
+        if let Some(h) = hyps.iter().find(|h| type_equality(&h.1, goal)) {
+            return Some(Term::Var(h.0.clone()));
+        }
+        let types: Vec<Type> = hyps.iter().map(|h| h.1.clone()).collect();
+        if self
+            .history
+            .iter()
+            .any(|s| type_equality(&s.1, goal) && same_types(&s.0, &types))
+        {
+            return None;
+        }
+        self.history.push((types, goal.clone()));
+        let mut proof = None;
+        for (f, t) in hyps {
+            if let Type::Fn(a, b, _) = t {
+                // let y = f arg in ...
+                let y = self.fresh();
+                let (mut more, mut splits) = (hyps.to_vec(), Vec::new());
+                if self.assume(&mut more, y.clone(), b, &mut splits) == 0 {
+                    continue;
+                }
+                let arg = match self.prove(hyps, a) {
+                    Some(arg) => arg,
+                    None => continue,
+                };
+                let call = Term::App(Box::new(Term::Var(f.clone())), Box::new(arg), (**a).clone());
+                if type_equality(b, goal) {
+                    proof = Some(call);
+                    break;
+                }
+                if let Some(body) = self.prove(&more, goal) {
+                    let body = Term::Lam(y, Box::new(Self::wrap(body, splits)));
+                    proof = Some(Term::App(Box::new(body), Box::new(call), (**b).clone()));
+                    break;
+                }
+            }
+        }
+        self.history.pop();
+        proof
+    }
+}
+
+/// Search for a closed term of type `t`.
+/// Returns `None` if the type is not inhabited.
+pub fn prove(t: &Type) -> Option<Term> {
+    let mut search = Search {
+        counter: 0,
+        history: Vec::new(),
+    };
+    let term = search.prove(&[], t)?;
+    debug_assert!(judgment_check(&Context::Empty(), &term, t));
+    Some(term)
+}
+
+/// The typing derivation of a checked term, one judgment per line
+/// with its premises indented below.
+pub fn derivation(g: &Context, term: &Term, t: &Type) -> Option<Vec<String>> {
+    fn go(g: &Context, term: &Term, t: &Type, depth: usize, out: &mut Vec<String>) -> Option<()> {
+        let rule = match (term, t) {
+            (Term::Var(x), _) if var_has_type(x, t, g) => "Var",
+            (Term::Lam(_, _), Type::Fn(_, _, _)) => "Lam",
+            (Term::App(_, _, _), _) => "App",
+            (Term::Pair(_, _), Type::Sum(_, _)) => "Pair",
+            (Term::Split(_, _, _, _, _, _), _) => "Split",
+            (&Term::Unit, &Type::Unit) => "Unit",
+            _ => return None,
+        };
+        let turnstile = match g {
+            &Context::Empty() => "|-".to_string(),
+            _ => format!("{} |-", g),
+        };
+        out.push(format!(
+            "{}{} {} : {}   ({})",
+            "  ".repeat(depth),
+            turnstile,
+            term,
+            t,
+            rule
+        ));
+        let snoc = |g: &Context, x: &str, t: &Type| {
+            Context::Snoc(Box::new(g.clone()), x.into(), t.clone())
+        };
+        match (term, t) {
+            (Term::Lam(x, body), Type::Fn(a, b, _)) => go(&snoc(g, x, a), body, b, depth + 1, out),
+            (Term::App(f, a, type_arg), _) => {
+                let fun = Type::Fn(
+                    Box::new(type_arg.clone()),
+                    Box::new(t.clone()),
+                    Effects::pure(),
+                );
+                go(g, f, &fun, depth + 1, out)?;
+                go(g, a, type_arg, depth + 1, out)
+            }
+            (Term::Pair(a, b), Type::Sum(l, r)) => {
+                go(g, a, l, depth + 1, out)?;
+                go(g, b, r, depth + 1, out)
+            }
+            (Term::Split(p, x, a, y, b, body), _) => {
+                let pair = Type::Sum(Box::new(a.clone()), Box::new(b.clone()));
+                go(g, p, &pair, depth + 1, out)?;
+                go(&snoc(&snoc(g, x, a), y, b), body, t, depth + 1, out)
+            }
+            _ => Some(()),
+        }
+    }
+    let mut out = Vec::new();
+    go(g, term, t, 0, &mut out)?;
+    Some(out)
+}
+
+/// Parse a formula, search for a proof and describe the result.
+pub fn report(s: &str) -> String {
+    let formula = match parse(s) {
+        Ok(formula) => formula,
+        Err(e) => return format!("{}\n  error: {}\n", s, e),
+    };
+    let atoms = atoms(&formula);
+    let t = to_type(&formula, &atoms);
+    let assignment: Vec<String> = atoms.iter().map(|a| format!("{} = {}", a.0, a.1)).collect();
+    let mut out = format!("{}\n  type: {}  ({})\n", formula, t, assignment.join(", "));
+    match prove(&t) {
+        Some(term) => {
+            out.push_str(&format!("  proof: {}\n  derivation:\n", term));
+            for line in derivation(&Context::Empty(), &term, &t).expect("proofs are well-typed") {
+                out.push_str(&format!("    {}\n", line));
+            }
+        }
+        None => out.push_str("  not provable intuitionistically\n"),
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn provable(s: &str) -> bool {
+        let formula = parse(s).unwrap();
+        let t = to_type(&formula, &atoms(&formula));
+        match prove(&t) {
+            Some(term) => {
+                assert!(judgment_check(&Context::Empty(), &term, &t));
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[test]
+    fn parse_formulas() {
+        let p = parse("A /\\ B -> B /\\ A").unwrap();
+        assert_eq!(p.to_string(), "A /\\ B -> B /\\ A");
+        let p = parse("((A -> B) -> A) -> A").unwrap();
+        assert_eq!(p.to_string(), "((A -> B) -> A) -> A");
+        // implication associates to the right, conjunction binds tighter
+        assert_eq!(
+            parse("A -> B -> A").unwrap(),
+            parse("A -> (B -> A)").unwrap()
+        );
+        assert_eq!(
+            parse("A /\\ B -> C").unwrap(),
+            parse("(A /\\ B) -> C").unwrap()
+        );
+        assert!(parse("A \\/ B").is_err());
+        assert!(parse("~A").is_err());
+        assert!(parse("A -> ").is_err());
+        assert!(parse("(A").is_err());
+    }
+
+    #[test]
+    fn atoms_are_base_types() {
+        let p = parse("X -> Foo -> Y").unwrap();
+        let t = to_type(&p, &atoms(&p));
+        assert!(type_equality(
+            &t,
+            &Type::Fn(
+                Box::new(Type::Bar),
+                Box::new(Type::Fn(
+                    Box::new(Type::Foo),
+                    Box::new(Type::Baz),
+                    Effects::pure()
+                )),
+                Effects::pure()
+            )
+        ));
+        let p = parse("A -> B -> C -> D").unwrap();
+        assert_eq!(atoms(&p)[3], ("D".to_string(), Type::Var("D".into())));
+    }
+
+    #[test]
+    fn intuitionistic_tautologies() {
+        assert!(provable("A /\\ B -> B /\\ A"));
+        assert!(provable("(A -> B) -> (B -> C) -> (C -> D) -> A -> D"));
+        assert!(!provable("(A -> B) -> (B -> C) -> (C -> D) -> D"));
+        assert!(provable("A -> B -> A"));
+        assert!(provable("(A -> B -> C) -> (A -> B) -> A -> C"));
+        assert!(provable("(A /\\ B -> C) -> A -> B -> C"));
+        assert!(provable("(A -> B -> C) -> A /\\ B -> C"));
+        assert!(provable("(A -> B) -> (B -> C) -> A -> C"));
+        assert!(provable("(A -> B /\\ C) -> (A -> B) /\\ (A -> C)"));
+        assert!(provable("((A -> A) -> B) -> B"));
+        assert!(provable("True"));
+        assert!(provable("A -> True /\\ A"));
+    }
+
+    #[test]
+    fn not_provable() {
+        assert!(!provable("A"));
+        assert!(!provable("A -> B"));
+        assert!(!provable("(A -> B) -> B -> A"));
+        // Peirce's law only holds classically
+        assert!(!provable("((A -> B) -> A) -> A"));
+    }
+
+    #[test]
+    fn derivations() {
+        let t = Type::Fn(Box::new(Type::Foo), Box::new(Type::Foo), Effects::pure());
+        let term = prove(&t).unwrap();
+        let lines = derivation(&Context::Empty(), &term, &t).unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                "|- \\x1. x1 : Foo -> Foo   (Lam)",
+                "  x1 : Foo |- x1 : Foo   (Var)"
+            ]
+        );
+        let report = report("((A -> B) -> A) -> A");
+        assert!(report.ends_with("not provable intuitionistically\n"));
+        assert!(report.contains("type: ((Foo -> Bar) -> Foo) -> Foo"));
+        assert!(derivation(&Context::Empty(), &term, &Type::Foo).is_none());
+    }
+}
@@ -0,0 +1,772 @@
+//! Higher-rank polymorphism by bidirectional type checking.
+//!
+//! A polymorphic type `forall X. T` may appear anywhere in a type, also
+//! in the argument of a function like `(forall a. a -> a) -> Foo * Bar`.
+//! Such types can't be inferred, so the checker follows Dunfield and
+//! Krishnaswami: expressions are either checked against a known type or
+//! synthesize one, and annotations `(e : A)` switch from checking to
+//! synthesis. A lambda checked against a function type learns the type
+//! of its argument, an application synthesizes the type of the function
+//! and checks the argument against it.
+//!
+//! Where a type is missing the checker invents an existential variable
+//! `^a` and solves it on the way, the ordered context records which type
+//! variables are in scope at each existential. A polymorphic type is
+//! used at an instance by subsumption: `A` fits where `B` is expected
+//! if `A <: B`, where `forall a. a -> a <: Foo -> Foo` instantiates the
+//! quantifier and the argument of a function is compared the other way.
+//! An unannotated expression still synthesizes a type, `\x. x` gets
+//! `forall a. a -> a` from the existentials it leaves open.
+//!
+//! The core leaves type abstraction and application implicit, so an
+//! expression translates into the core term with the argument types of
+//! its applications, which `judgment_check` accepts at the same type. As
+//! in the core, only values are generalized.
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{
+    free_in_context, free_in_type, judgment, subst_type, subtype, unfold, Context, Term, Type,
+};
+
+/// Expressions whose lambdas have no annotations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Var(String),
+    Unit,
+    Lam(String, Box<Expr>),
+    App(Box<Expr>, Box<Expr>),
+    Pair(Box<Expr>, Box<Expr>),
+    Ann(Box<Expr>, Type), // (e : A)
+}
+
+pub fn var(x: &str) -> Expr {
+    Expr::Var(x.into())
+}
+
+pub fn lam(x: &str, body: Expr) -> Expr {
+    Expr::Lam(x.into(), Box::new(body))
+}
+
+/// Apply a function to a list of arguments.
+pub fn app(f: Expr, args: Vec<Expr>) -> Expr {
+    args.into_iter()
+        .fold(f, |f, a| Expr::App(Box::new(f), Box::new(a)))
+}
+
+pub fn pair(a: Expr, b: Expr) -> Expr {
+    Expr::Pair(Box::new(a), Box::new(b))
+}
+
+pub fn ann(e: Expr, t: Type) -> Expr {
+    Expr::Ann(Box::new(e), t)
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        /// Wrap everything except atoms into parens.
+        fn atom(e: &Expr) -> String {
+            match e {
+                Expr::Var(_) | Expr::Unit | Expr::Pair(_, _) | Expr::Ann(_, _) => format!("{}", e),
+                _ => format!("({})", e),
+            }
+        }
+        match self {
+            Expr::Var(x) => write!(f, "{}", x),
+            Expr::Unit => write!(f, "()"),
+            Expr::Lam(x, body) => write!(f, "\\{}. {}", x, body),
+            Expr::App(a, b) => match **a {
+                Expr::App(_, _) => write!(f, "{} {}", a, atom(b)),
+                _ => write!(f, "{} {}", atom(a), atom(b)),
+            },
+            Expr::Pair(a, b) => write!(f, "({}, {})", a, b),
+            Expr::Ann(e, t) => write!(f, "({} : {})", e, t),
+        }
+    }
+}
+
+/// Why an expression doesn't have a type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Unbound(String),
+    /// A type which doesn't fit where the other one is expected.
+    Subtype(Type, Type),
+    NotFunction(Type),
+    /// An annotation which is not the type of a term.
+    Kind(Type),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // existentials are named in order of appearance
+        let mut vars = Vec::new();
+        match self {
+            Error::Subtype(a, b) => {
+                existentials(a, &mut vars);
+                existentials(b, &mut vars);
+            }
+            Error::NotFunction(t) | Error::Kind(t) => existentials(t, &mut vars),
+            _ => {}
+        }
+        let t = |t: &Type| {
+            let letters = "abcdefghijklmnopqrstuvwxyz".chars();
+            vars.iter().zip(letters).fold(t.clone(), |t, (x, c)| {
+                subst_type(&t, x, &Type::Var(format!("^{}", c)))
+            })
+        };
+        match self {
+            Error::Unbound(x) => write!(f, "'{}' is not defined", x),
+            Error::Subtype(a, b) => write!(f, "{} is not a subtype of {}", t(a), t(b)),
+            Error::NotFunction(a) => write!(f, "{} is not a function type", t(a)),
+            Error::Kind(a) => write!(f, "{} is not the type of a term", t(a)),
+        }
+    }
+}
+
+/// Existentials are the type variables the checker invents.
+fn is_existential(x: &str) -> bool {
+    x.starts_with('^')
+}
+
+/// Collect the existentials of a type in order.
+fn existentials(t: &Type, acc: &mut Vec<String>) {
+    let mut vars = Vec::new();
+    free_vars(t, &mut Vec::new(), &mut vars);
+    for x in vars {
+        if is_existential(&x) && !acc.contains(&x) {
+            acc.push(x);
+        }
+    }
+}
+
+/// Collect the free type variables of a type in order.
+fn free_vars(t: &Type, bound: &mut Vec<String>, acc: &mut Vec<String>) {
+    match t {
+        Type::Foo | Type::Bar | Type::Baz | Type::Unit | Type::Dyn | Type::Named(_, _) => {}
+        Type::Var(x) if !bound.contains(x) && !acc.contains(x) => acc.push(x.clone()),
+        Type::Var(_) => {}
+        Type::Sum(a, b)
+        | Type::Fn(a, b, _)
+        | Type::App(a, b)
+        | Type::And(a, b)
+        | Type::Or(a, b) => {
+            free_vars(a, bound, acc);
+            free_vars(b, bound, acc);
+        }
+        Type::Ref(a) => free_vars(a, bound, acc),
+        Type::Record(row) | Type::Variant(row) => {
+            for (_, t) in &row.fields {
+                free_vars(t, bound, acc);
+            }
+        }
+        Type::Exists(x, a) | Type::Forall(x, a) | Type::Lam(x, _, a) => {
+            bound.push(x.clone());
+            free_vars(a, bound, acc);
+            bound.pop();
+        }
+    }
+}
+
+/// Test if a type has a quantifier somewhere, the other types are the
+/// monotypes an existential may be solved with.
+fn polymorphic(t: &Type) -> bool {
+    match t {
+        Type::Forall(_, _) => true,
+        Type::Sum(a, b)
+        | Type::Fn(a, b, _)
+        | Type::App(a, b)
+        | Type::And(a, b)
+        | Type::Or(a, b) => polymorphic(a) || polymorphic(b),
+        Type::Ref(a) | Type::Exists(_, a) | Type::Lam(_, _, a) | Type::Named(_, a) => {
+            polymorphic(a)
+        }
+        Type::Record(row) | Type::Variant(row) => row.fields.iter().any(|f| polymorphic(&f.1)),
+        Type::Foo | Type::Bar | Type::Baz | Type::Unit | Type::Dyn | Type::Var(_) => false,
+    }
+}
+
+/// Test if an expression translates into a value of the core.
+fn is_value(e: &Expr) -> bool {
+    match e {
+        Expr::Lam(_, _) | Expr::Unit => true,
+        Expr::Pair(a, b) => is_value(a) && is_value(b),
+        Expr::Ann(e, _) => is_value(e),
+        Expr::Var(_) | Expr::App(_, _) => false,
+    }
+}
+
+/// The ordered context of the algorithm, later entries may only
+/// mention the type variables of earlier ones.
+#[derive(Debug, Clone, PartialEq)]
+enum Entry {
+    Var(String),          // a type variable in scope
+    Exists(String),       // an unsolved existential
+    Solved(String, Type), // an existential and its solution
+    Marker(String),       // the start of the scope of an existential
+    Term(String, Type),   // x : A
+}
+
+struct Checker {
+    entries: Vec<Entry>,
+    // the solutions outlive their entries, the argument types of the
+    // translated applications are solved at the end
+    solutions: HashMap<String, Type>,
+    next: usize,
+}
+
+impl Checker {
+    fn new(g: &Context) -> Checker {
+        fn bindings(g: &Context, acc: &mut Vec<Entry>) {
+            if let Context::Snoc(rest, x, t) = g {
+                bindings(rest, acc);
+                acc.push(Entry::Term(x.clone(), t.clone()));
+            }
+        }
+        let mut entries = Vec::new();
+        bindings(g, &mut entries);
+        Checker {
+            entries,
+            solutions: HashMap::new(),
+            next: 0,
+        }
+    }
+
+    fn fresh(&mut self) -> String {
+        self.next += 1;
+        format!("^{}", self.next)
+    }
+
+    /// Drop an entry and everything after it, when leaving its scope.
+    fn leave(&mut self, entry: &Entry) {
+        let i = self
+            .entries
+            .iter()
+            .rposition(|e| e == entry)
+            .expect("the entry is in scope");
+        self.entries.truncate(i);
+    }
+
+    fn position(&self, x: &str) -> usize {
+        self.entries
+            .iter()
+            .position(|e| matches!(e, Entry::Exists(y) if y == x))
+            .expect("an unsolved existential")
+    }
+
+    /// Apply the solutions to a type.
+    fn apply(&self, t: &Type) -> Type {
+        let mut vars = Vec::new();
+        existentials(t, &mut vars);
+        vars.iter()
+            .filter_map(|x| self.solutions.get(x).map(|s| (x, s)))
+            .fold(t.clone(), |t, (x, s)| subst_type(&t, x, &self.apply(s)))
+    }
+
+    fn solve(&mut self, x: &str, t: Type) {
+        let i = self.position(x);
+        self.entries[i] = Entry::Solved(x.into(), t.clone());
+        self.solutions.insert(x.into(), t);
+    }
+
+    /// Solve an existential with a type built from two new ones, which
+    /// are placed before it so the solution is in scope.
+    fn articulate(&mut self, x: &str, make: impl Fn(Type, Type) -> Type) -> (String, String) {
+        let (a, b) = (self.fresh(), self.fresh());
+        let i = self.position(x);
+        self.entries.insert(i, Entry::Exists(a.clone()));
+        self.entries.insert(i, Entry::Exists(b.clone()));
+        self.solve(x, make(Type::Var(a.clone()), Type::Var(b.clone())));
+        (a, b)
+    }
+
+    /// Test if a monotype only mentions the type variables in scope at an
+    /// existential. Type variables the context doesn't know are global.
+    fn well_formed_at(&self, x: &str, t: &Type) -> bool {
+        let i = self.position(x);
+        !self.entries[i..].iter().any(|e| match e {
+            Entry::Var(y) | Entry::Exists(y) | Entry::Solved(y, _) => free_in_type(y, t),
+            _ => false,
+        })
+    }
+
+    /// A name for a type variable which no type in scope mentions.
+    fn skolem(&self, x: &str, avoid: &[&Type]) -> String {
+        let taken = |y: &str| {
+            avoid.iter().any(|t| free_in_type(y, t))
+                || self.entries.iter().any(|e| match e {
+                    Entry::Var(z) => z == y,
+                    Entry::Term(_, t) => free_in_type(y, &self.apply(t)),
+                    _ => false,
+                })
+        };
+        let mut name = x.to_string();
+        let mut i = 0;
+        while taken(&name) {
+            i += 1;
+            name = format!("{}{}", x, i);
+        }
+        name
+    }
+
+    /// The subtyping `a <: b`, a value of type `a` fits where `b` is expected.
+    fn sub(&mut self, a: &Type, b: &Type) -> Result<(), Error> {
+        let (a, b) = (unfold(&self.apply(a)), unfold(&self.apply(b)));
+        match (&a, &b) {
+            (Type::Var(x), Type::Var(y)) if x == y => Ok(()),
+            // the quantifier on the right first, it always applies
+            (_, Type::Forall(y, body)) => {
+                let y2 = self.skolem(y, &[&a]);
+                self.entries.push(Entry::Var(y2.clone()));
+                self.sub(&a, &subst_type(body, y, &Type::Var(y2.clone())))?;
+                self.leave(&Entry::Var(y2));
+                Ok(())
+            }
+            (Type::Forall(x, body), _) => {
+                let e = self.fresh();
+                self.entries.push(Entry::Marker(e.clone()));
+                self.entries.push(Entry::Exists(e.clone()));
+                self.sub(&subst_type(body, x, &Type::Var(e.clone())), &b)?;
+                self.leave(&Entry::Marker(e));
+                Ok(())
+            }
+            (Type::Fn(ai, ao, ae), Type::Fn(bi, bo, be)) if ae.within(be) => {
+                self.sub(bi, ai)?;
+                self.sub(ao, bo)
+            }
+            (Type::Sum(al, ar), Type::Sum(bl, br)) => {
+                self.sub(al, bl)?;
+                self.sub(ar, br)
+            }
+            (Type::Ref(a), Type::Ref(b)) => {
+                self.sub(a, b)?;
+                self.sub(b, a)
+            }
+            (Type::Var(x), _) if is_existential(x) && !free_in_type(x, &b) => {
+                self.instantiate_left(x, &b)
+            }
+            (_, Type::Var(y)) if is_existential(y) && !free_in_type(y, &a) => {
+                self.instantiate_right(&a, y)
+            }
+            _ => {
+                let mut vars = Vec::new();
+                existentials(&a, &mut vars);
+                existentials(&b, &mut vars);
+                match vars.is_empty() && subtype(&a, &b) {
+                    true => Ok(()),
+                    false => Err(Error::Subtype(a, b)),
+                }
+            }
+        }
+    }
+
+    /// Solve the existential `x` so that `x <: t`.
+    fn instantiate_left(&mut self, x: &str, t: &Type) -> Result<(), Error> {
+        if !polymorphic(t) && self.well_formed_at(x, t) {
+            self.solve(x, t.clone());
+            return Ok(());
+        }
+        match t {
+            // a later existential is solved with the earlier one
+            Type::Var(y) if is_existential(y) => {
+                self.solve(y, Type::Var(x.into()));
+                Ok(())
+            }
+            Type::Fn(i, o, e) => {
+                let (a, b) =
+                    self.articulate(x, |a, b| Type::Fn(Box::new(a), Box::new(b), e.clone()));
+                self.instantiate_right(i, &a)?;
+                let o = self.apply(o);
+                self.instantiate_left(&b, &o)
+            }
+            Type::Sum(l, r) => {
+                let (a, b) = self.articulate(x, Type::product);
+                self.instantiate_left(&a, l)?;
+                let r = self.apply(r);
+                self.instantiate_left(&b, &r)
+            }
+            Type::Forall(y, body) => {
+                let y2 = self.skolem(y, &[]);
+                self.entries.push(Entry::Var(y2.clone()));
+                self.instantiate_left(x, &subst_type(body, y, &Type::Var(y2.clone())))?;
+                self.leave(&Entry::Var(y2));
+                Ok(())
+            }
+            _ => Err(Error::Subtype(Type::Var(x.into()), t.clone())),
+        }
+    }
+
+    /// Solve the existential `x` so that `t <: x`.
+    fn instantiate_right(&mut self, t: &Type, x: &str) -> Result<(), Error> {
+        if !polymorphic(t) && self.well_formed_at(x, t) {
+            self.solve(x, t.clone());
+            return Ok(());
+        }
+        match t {
+            Type::Var(y) if is_existential(y) => {
+                self.solve(y, Type::Var(x.into()));
+                Ok(())
+            }
+            Type::Fn(i, o, e) => {
+                let (a, b) =
+                    self.articulate(x, |a, b| Type::Fn(Box::new(a), Box::new(b), e.clone()));
+                self.instantiate_left(&a, i)?;
+                let o = self.apply(o);
+                self.instantiate_right(&o, &b)
+            }
+            Type::Sum(l, r) => {
+                let (a, b) = self.articulate(x, Type::product);
+                self.instantiate_right(l, &a)?;
+                let r = self.apply(r);
+                self.instantiate_right(&r, &b)
+            }
+            // the quantifier is instantiated, as on the left of `<:`
+            Type::Forall(y, body) => {
+                let e = self.fresh();
+                self.entries.push(Entry::Marker(e.clone()));
+                self.entries.push(Entry::Exists(e.clone()));
+                self.instantiate_right(&subst_type(body, y, &Type::Var(e.clone())), x)?;
+                self.leave(&Entry::Marker(e));
+                Ok(())
+            }
+            _ => Err(Error::Subtype(t.clone(), Type::Var(x.into()))),
+        }
+    }
+
+    /// Check an expression against a type and translate it.
+    fn check(&mut self, e: &Expr, t: &Type) -> Result<Term, Error> {
+        let t = unfold(&self.apply(t));
+        match (e, &t) {
+            (_, Type::Forall(x, body)) if is_value(e) => {
+                let y = self.skolem(x, &[]);
+                let body = subst_type(body, x, &Type::Var(y.clone()));
+                self.entries.push(Entry::Var(y.clone()));
+                let term = self.check(e, &body)?;
+                self.leave(&Entry::Var(y.clone()));
+                Ok(self.rename(&term, &y, x))
+            }
+            (Expr::Lam(x, body), Type::Fn(i, o, _)) => {
+                let entry = Entry::Term(x.clone(), (**i).clone());
+                self.entries.push(entry.clone());
+                let body = self.check(body, o)?;
+                self.leave(&entry);
+                Ok(Term::Lam(x.clone(), Box::new(body)))
+            }
+            (Expr::Pair(a, b), Type::Sum(l, r)) => {
+                let a = self.check(a, l)?;
+                let b = self.check(b, r)?;
+                Ok(Term::Pair(Box::new(a), Box::new(b)))
+            }
+            (Expr::Unit, Type::Unit) => Ok(Term::Unit),
+            _ => {
+                let (term, found) = self.synth(e)?;
+                self.sub(&found, &t)?;
+                Ok(term)
+            }
+        }
+    }
+
+    /// Synthesize the type of an expression and translate it.
+    fn synth(&mut self, e: &Expr) -> Result<(Term, Type), Error> {
+        match e {
+            Expr::Var(x) => {
+                let found = self.entries.iter().rev().find_map(|e| match e {
+                    Entry::Term(y, t) if y == x => Some(t.clone()),
+                    _ => None,
+                });
+                match found {
+                    Some(t) => Ok((Term::Var(x.clone()), t)),
+                    None => Err(Error::Unbound(x.clone())),
+                }
+            }
+            Expr::Unit => Ok((Term::Unit, Type::Unit)),
+            Expr::Ann(e, t) => {
+                if !judgment(t) {
+                    return Err(Error::Kind(t.clone()));
+                }
+                Ok((self.check(e, t)?, t.clone()))
+            }
+            Expr::Lam(x, body) => {
+                let (a, b) = (self.fresh(), self.fresh());
+                self.entries.push(Entry::Exists(a.clone()));
+                self.entries.push(Entry::Exists(b.clone()));
+                let (a, b) = (Type::Var(a), Type::Var(b));
+                let entry = Entry::Term(x.clone(), a.clone());
+                self.entries.push(entry.clone());
+                let body = self.check(body, &b)?;
+                self.leave(&entry);
+                Ok((Term::Lam(x.clone(), Box::new(body)), Type::arrow(a, b)))
+            }
+            Expr::App(f, arg) => {
+                let (f, t) = self.synth(f)?;
+                let t = self.apply(&t);
+                let (arg, arg_type, result) = self.synth_app(&t, arg)?;
+                Ok((Term::App(Box::new(f), Box::new(arg), arg_type), result))
+            }
+            Expr::Pair(a, b) => {
+                let (a, l) = self.synth(a)?;
+                let (b, r) = self.synth(b)?;
+                Ok((Term::Pair(Box::new(a), Box::new(b)), Type::product(l, r)))
+            }
+        }
+    }
+
+    /// Apply a function of type `t` to an argument, giving the translated
+    /// argument, the type it is passed at and the type of the result.
+    fn synth_app(&mut self, t: &Type, arg: &Expr) -> Result<(Term, Type, Type), Error> {
+        match unfold(t) {
+            Type::Forall(x, body) => {
+                let e = self.fresh();
+                self.entries.push(Entry::Exists(e.clone()));
+                self.synth_app(&subst_type(&body, &x, &Type::Var(e)), arg)
+            }
+            Type::Var(x) if is_existential(&x) => {
+                let (a, b) = self.articulate(&x, Type::arrow);
+                let (a, b) = (Type::Var(a), Type::Var(b));
+                Ok((self.check(arg, &a)?, a, b))
+            }
+            // the language of this module raises nothing
+            Type::Fn(i, o, e) if e.is_pure() => Ok((self.check(arg, &i)?, *i, *o)),
+            t => Err(Error::NotFunction(t)),
+        }
+    }
+
+    /// Apply the solutions within the argument types of a translated term,
+    /// the existentials without one are given by `open`.
+    /// Rename the type variable `y` to `x` in the annotations of a term.
+    /// The core has no type abstraction, there the annotations below a
+    /// quantifier name its variable.
+    fn rename(&self, term: &Term, y: &str, x: &str) -> Term {
+        let r = |t: &Term| Box::new(self.rename(t, y, x));
+        match term {
+            Term::Lam(z, body) => Term::Lam(z.clone(), r(body)),
+            Term::App(f, a, t) => {
+                let t = subst_type(&self.apply(t), y, &Type::Var(x.into()));
+                Term::App(r(f), r(a), t)
+            }
+            Term::Pair(a, b) => Term::Pair(r(a), r(b)),
+            _ => term.clone(),
+        }
+    }
+
+    fn resolve(&self, term: &Term, open: &HashMap<String, Type>) -> Term {
+        let r = |t: &Term| Box::new(self.resolve(t, open));
+        match term {
+            Term::Lam(x, body) => Term::Lam(x.clone(), r(body)),
+            Term::App(f, a, t) => {
+                let t = self.apply(t);
+                let mut vars = Vec::new();
+                existentials(&t, &mut vars);
+                let t = vars.iter().fold(t, |t, x| {
+                    let s = open.get(x).cloned().unwrap_or(Type::Unit);
+                    subst_type(&t, x, &s)
+                });
+                Term::App(r(f), r(a), t)
+            }
+            Term::Pair(a, b) => Term::Pair(r(a), r(b)),
+            _ => term.clone(),
+        }
+    }
+}
+
+/// Check an expression against a type, giving the core term which
+/// `judgment_check` accepts at that type.
+pub fn check(g: &Context, e: &Expr, t: &Type) -> Result<Term, Error> {
+    if !judgment(t) {
+        return Err(Error::Kind(t.clone()));
+    }
+    let mut checker = Checker::new(g);
+    let term = checker.check(e, t)?;
+    Ok(checker.resolve(&term, &HashMap::new()))
+}
+
+/// Synthesize the type of an expression together with its core term.
+/// The existentials a value leaves open are generalized, those of other
+/// expressions stand for `Unit`.
+pub fn synth(g: &Context, e: &Expr) -> Result<(Term, Type), Error> {
+    let mut checker = Checker::new(g);
+    let (term, t) = checker.synth(e)?;
+    let t = checker.apply(&t);
+    let mut open = HashMap::new();
+    if !is_value(e) {
+        return Ok((checker.resolve(&term, &open), t));
+    }
+    let mut vars = Vec::new();
+    existentials(&t, &mut vars);
+    let names = ('a'..='z')
+        .map(|c| c.to_string())
+        .chain((1..).map(|n| format!("t{}", n)))
+        .filter(|x| !free_in_type(x, &t) && !free_in_context(x, g));
+    for (x, name) in vars.iter().zip(names) {
+        open.insert(x.clone(), Type::Var(name));
+    }
+    let body = vars.iter().fold(t, |t, x| subst_type(&t, x, &open[x]));
+    let t = vars.iter().rev().fold(body, |t, x| match &open[x] {
+        Type::Var(name) => Type::forall(name, t),
+        _ => unreachable!("existentials are generalized to variables"),
+    });
+    Ok((checker.resolve(&term, &open), t))
+}
+
+/// Test if a value of type `a` can be used at type `b`, where the
+/// quantifiers of `a` may be instantiated. The other types are compared
+/// by `subtype`.
+pub fn subsumes(a: &Type, b: &Type) -> bool {
+    Checker::new(&Context::new()).sub(a, b).is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::judgment_check;
+    use super::*;
+
+    fn a() -> Type {
+        Type::var("a")
+    }
+
+    fn id_type() -> Type {
+        Type::forall("a", Type::arrow(a(), a()))
+    }
+
+    fn context() -> Context {
+        Context::new()
+            .with("foo", Type::Foo)
+            .with("bar", Type::Bar)
+            .with("id", id_type())
+    }
+
+    /// Synthesize a type and check the translation against it.
+    fn synthesized(e: &Expr) -> String {
+        let g = context();
+        let (term, t) = synth(&g, e).unwrap();
+        assert!(judgment_check(&g, &term, &t), "{} : {}", term, t);
+        t.to_string()
+    }
+
+    #[test]
+    fn polymorphic_arguments() {
+        let g = context();
+        let both = lam(
+            "f",
+            pair(
+                app(var("f"), vec![var("foo")]),
+                app(var("f"), vec![var("bar")]),
+            ),
+        );
+        let rank2 = Type::arrow(id_type(), Type::product(Type::Foo, Type::Bar));
+        let term = check(&g, &both, &rank2).unwrap();
+        assert_eq!(term.to_string(), "\\f. (f foo, f bar)");
+        assert!(judgment_check(&g, &term, &rank2));
+
+        // a polymorphic function is passed where one is expected
+        let applied = app(ann(both.clone(), rank2.clone()), vec![lam("x", var("x"))]);
+        assert_eq!(synthesized(&applied), "Foo * Bar");
+        assert_eq!(
+            synthesized(&app(ann(both.clone(), rank2), vec![var("id")])),
+            "Foo * Bar"
+        );
+
+        // without the annotation the argument is monomorphic
+        let error = synth(&g, &both).unwrap_err();
+        assert_eq!(error.to_string(), "Bar is not a subtype of Foo");
+        let twice = Type::arrow(
+            Type::arrow(Type::Foo, Type::Foo),
+            Type::product(Type::Foo, Type::Bar),
+        );
+        assert!(check(&g, &both, &twice).is_err());
+    }
+
+    #[test]
+    fn unannotated_inference() {
+        assert_eq!(synthesized(&lam("x", var("x"))), "forall a. a -> a");
+        assert_eq!(
+            synthesized(&lam("f", lam("x", app(var("f"), vec![var("x")])))),
+            "forall a. forall b. (a -> b) -> a -> b"
+        );
+        assert_eq!(synthesized(&app(var("id"), vec![var("foo")])), "Foo");
+        assert_eq!(
+            synthesized(&app(lam("x", var("x")), vec![var("bar")])),
+            "Bar"
+        );
+        // an application is not generalized
+        let (_, t) = synth(&context(), &app(var("id"), vec![lam("x", var("x"))])).unwrap();
+        assert!(matches!(t, Type::Fn(_, _, _)));
+
+        // the names continue after z
+        let params: Vec<String> = (0..27).map(|i| format!("x{}", i)).collect();
+        let body = params.iter().rev().fold(var("x26"), |e, x| lam(x, e));
+        let mut names: Vec<String> = ('a'..='z').map(|c| c.to_string()).collect();
+        names.push("t1".into());
+        let quantifiers: String = names.iter().map(|x| format!("forall {}. ", x)).collect();
+        let expected = format!("{}{} -> t1", quantifiers, names.join(" -> "));
+        assert_eq!(synthesized(&body), expected);
+    }
+
+    #[test]
+    fn subsumption() {
+        let foo_foo = Type::arrow(Type::Foo, Type::Foo);
+        assert!(subsumes(&id_type(), &foo_foo));
+        assert!(!subsumes(&foo_foo, &id_type()));
+        let b = Type::var("b");
+        assert!(subsumes(
+            &id_type(),
+            &Type::forall("b", Type::arrow(b.clone(), b.clone()))
+        ));
+        // arguments are compared the other way
+        let takes = |t: Type| Type::arrow(t, Type::Bar);
+        assert!(subsumes(&takes(foo_foo.clone()), &takes(id_type())));
+        assert!(!subsumes(&takes(id_type()), &takes(foo_foo)));
+        // a type variable of the other side is not captured
+        assert!(!subsumes(&Type::arrow(a(), a()), &id_type()));
+        let k = Type::forall(
+            "a",
+            Type::forall("b", Type::arrow(a(), Type::arrow(b, a()))),
+        );
+        assert!(subsumes(
+            &k,
+            &Type::arrow(Type::Foo, Type::arrow(Type::Bar, Type::Foo))
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        let g = context();
+        let wrong = ann(
+            lam("x", var("x")),
+            Type::forall("a", Type::arrow(a(), Type::Foo)),
+        );
+        assert_eq!(
+            synth(&g, &wrong).unwrap_err().to_string(),
+            "a is not a subtype of Foo"
+        );
+        let mismatch = ann(var("id"), Type::arrow(Type::Foo, Type::Bar));
+        assert_eq!(
+            synth(&g, &mismatch).unwrap_err().to_string(),
+            "Foo is not a subtype of Bar"
+        );
+        assert_eq!(
+            synth(&g, &app(var("foo"), vec![var("bar")]))
+                .unwrap_err()
+                .to_string(),
+            "Foo is not a function type"
+        );
+        assert_eq!(
+            synth(&g, &var("baz")).unwrap_err().to_string(),
+            "'baz' is not defined"
+        );
+        // an inner quantifier may reuse the variable of an outer one
+        let body = app(lam("w", var("w")), vec![var("z")]);
+        let inner = ann(lam("z", body), id_type());
+        let outer = Type::forall("a", Type::arrow(a(), id_type()));
+        let term = check(&g, &lam("y", inner), &outer).unwrap();
+        assert_eq!(term.to_string(), "\\y. \\z. (\\w. w) z");
+        assert!(judgment_check(&g, &term, &outer));
+        // and within subtyping it is renamed
+        let y = ann(var("y"), id_type());
+        assert_eq!(
+            check(&g, &lam("y", y), &Type::arrow(a(), id_type()))
+                .unwrap_err()
+                .to_string(),
+            "a is not a subtype of a1 -> a1"
+        );
+    }
+}
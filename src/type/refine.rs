@@ -0,0 +1,905 @@
+//! Refinement types over the natural numbers.
+//!
+//! A refinement `{v: Nat | p}` is the type of the numbers `v` for which
+//! the predicate `p` holds. Predicates compare linear expressions of the
+//! numbers in scope by `<=`, `<` and `=` and combine them by `&&`, `||`
+//! and `!`. A function `(x: A) -> B` names its argument, so the result
+//! may depend on it like in `(x: Nat) -> {v: Nat | x < v}`.
+//!
+//! Checking is bidirectional like for higher-rank types, annotations
+//! `(e : A)` switch from checking to synthesis. A number synthesizes the
+//! most precise refinement `{v: Nat | v = e}` and each variable brings the
+//! predicate of its type into scope as an assumption, just like the
+//! condition of an `if` in its branches. Subtyping compares refinements
+//! by implication: `{v: Nat | p} <: {v: Nat | q}` if `p` implies `q`
+//! under the assumptions. Every such obligation is decided by the Omega
+//! test in `omega`, which finds integer values of the variables where the
+//! assumptions hold and `q` doesn't if the obligation is false. Such a
+//! counterexample is part of the error.
+//!
+//! The result of an application which is used as a number is named by a
+//! new variable with the refinement of the result as an assumption. A
+//! synthesized type doesn't mention these names, they are eliminated
+//! from its refinements together with their assumptions like variables
+//! in the Omega test, so `succ (succ 0)` gets `{v1: Nat | 2 <= v1}`.
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::omega::{satisfiable, shadow, Constraint, Linear};
+
+/// Linear integer expressions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Index {
+    Var(String),
+    Num(i64),
+    Add(Box<Index>, Box<Index>),
+    Sub(Box<Index>, Box<Index>),
+    Mul(i64, Box<Index>),
+}
+
+impl From<&str> for Index {
+    fn from(x: &str) -> Index {
+        Index::Var(x.into())
+    }
+}
+
+impl From<i64> for Index {
+    fn from(n: i64) -> Index {
+        Index::Num(n)
+    }
+}
+
+pub fn plus<A: Into<Index>, B: Into<Index>>(a: A, b: B) -> Index {
+    Index::Add(Box::new(a.into()), Box::new(b.into()))
+}
+
+pub fn minus<A: Into<Index>, B: Into<Index>>(a: A, b: B) -> Index {
+    Index::Sub(Box::new(a.into()), Box::new(b.into()))
+}
+
+pub fn scale<A: Into<Index>>(k: i64, a: A) -> Index {
+    Index::Mul(k, Box::new(a.into()))
+}
+
+/// Linear integer predicates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pred {
+    True,
+    False,
+    Le(Index, Index),
+    Lt(Index, Index),
+    Eq(Index, Index),
+    And(Box<Pred>, Box<Pred>),
+    Or(Box<Pred>, Box<Pred>),
+    Not(Box<Pred>),
+}
+
+pub fn le<A: Into<Index>, B: Into<Index>>(a: A, b: B) -> Pred {
+    Pred::Le(a.into(), b.into())
+}
+
+pub fn lt<A: Into<Index>, B: Into<Index>>(a: A, b: B) -> Pred {
+    Pred::Lt(a.into(), b.into())
+}
+
+pub fn equal<A: Into<Index>, B: Into<Index>>(a: A, b: B) -> Pred {
+    Pred::Eq(a.into(), b.into())
+}
+
+pub fn and(p: Pred, q: Pred) -> Pred {
+    Pred::And(Box::new(p), Box::new(q))
+}
+
+pub fn or(p: Pred, q: Pred) -> Pred {
+    Pred::Or(Box::new(p), Box::new(q))
+}
+
+pub fn not(p: Pred) -> Pred {
+    Pred::Not(Box::new(p))
+}
+
+/// Refinements of the natural numbers and dependent functions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RType {
+    Nat(String, Pred),                  // {v: Nat | p}
+    Fn(String, Box<RType>, Box<RType>), // (x: A) -> B
+}
+
+pub fn nat(v: &str, p: Pred) -> RType {
+    RType::Nat(v.into(), p)
+}
+
+pub fn arrow(x: &str, a: RType, b: RType) -> RType {
+    RType::Fn(x.into(), Box::new(a), Box::new(b))
+}
+
+/// Expressions over the natural numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Var(String),
+    Num(u32),
+    Add(Box<Expr>, Box<Expr>),
+    Lam(String, Box<Expr>),
+    App(Box<Expr>, Box<Expr>),
+    Ann(Box<Expr>, RType),             // (e : A)
+    Let(String, Box<Expr>, Box<Expr>), // let x = e in e
+    If(Pred, Box<Expr>, Box<Expr>),    // if p then e else e
+}
+
+pub fn var(x: &str) -> Expr {
+    Expr::Var(x.into())
+}
+
+pub fn num(n: u32) -> Expr {
+    Expr::Num(n)
+}
+
+pub fn add(a: Expr, b: Expr) -> Expr {
+    Expr::Add(Box::new(a), Box::new(b))
+}
+
+pub fn lam(x: &str, body: Expr) -> Expr {
+    Expr::Lam(x.into(), Box::new(body))
+}
+
+pub fn app(f: Expr, a: Expr) -> Expr {
+    Expr::App(Box::new(f), Box::new(a))
+}
+
+pub fn ann(e: Expr, t: RType) -> Expr {
+    Expr::Ann(Box::new(e), t)
+}
+
+pub fn bind(x: &str, e: Expr, body: Expr) -> Expr {
+    Expr::Let(x.into(), Box::new(e), Box::new(body))
+}
+
+pub fn cond(p: Pred, a: Expr, b: Expr) -> Expr {
+    Expr::If(p, Box::new(a), Box::new(b))
+}
+
+impl fmt::Display for Index {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sum = |i: &Index| match i {
+            Index::Add(_, _) | Index::Sub(_, _) => format!("({})", i),
+            _ => i.to_string(),
+        };
+        match self {
+            Index::Var(x) => write!(f, "{}", x),
+            Index::Num(n) => write!(f, "{}", n),
+            Index::Add(a, b) => write!(f, "{} + {}", a, sum(b)),
+            Index::Sub(a, b) => write!(f, "{} - {}", a, sum(b)),
+            Index::Mul(k, a) => write!(f, "{} * {}", k, sum(a)),
+        }
+    }
+}
+
+impl fmt::Display for Pred {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // disjunctions bind weaker than conjunctions, negation binds tightest
+        let operand = |p: &Pred, level: u8| match (p, level) {
+            (Pred::Or(_, _), 1) | (Pred::Or(_, _), 2) | (Pred::And(_, _), 2) => format!("({})", p),
+            (Pred::Le(_, _), 2) | (Pred::Lt(_, _), 2) | (Pred::Eq(_, _), 2) => format!("({})", p),
+            _ => p.to_string(),
+        };
+        match self {
+            Pred::True => write!(f, "true"),
+            Pred::False => write!(f, "false"),
+            Pred::Le(a, b) => write!(f, "{} <= {}", a, b),
+            Pred::Lt(a, b) => write!(f, "{} < {}", a, b),
+            Pred::Eq(a, b) => write!(f, "{} = {}", a, b),
+            Pred::And(p, q) => write!(f, "{} && {}", operand(p, 1), operand(q, 1)),
+            Pred::Or(p, q) => write!(f, "{} || {}", operand(p, 0), operand(q, 0)),
+            Pred::Not(p) => write!(f, "!{}", operand(p, 2)),
+        }
+    }
+}
+
+impl fmt::Display for RType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RType::Nat(_, Pred::True) => write!(f, "Nat"),
+            RType::Nat(v, p) => write!(f, "{{{}: Nat | {}}}", v, p),
+            RType::Fn(x, a, b) => write!(f, "({}: {}) -> {}", x, a, b),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let atom = |e: &Expr| match e {
+            Expr::Var(_) | Expr::Num(_) | Expr::Ann(_, _) => e.to_string(),
+            _ => format!("({})", e),
+        };
+        match self {
+            Expr::Var(x) => write!(f, "{}", x),
+            Expr::Num(n) => write!(f, "{}", n),
+            Expr::Add(a, b) => write!(f, "{} + {}", a, atom(b)),
+            Expr::Lam(x, body) => write!(f, "\\{}. {}", x, body),
+            Expr::App(g, a) => match **g {
+                Expr::App(_, _) => write!(f, "{} {}", g, atom(a)),
+                _ => write!(f, "{} {}", atom(g), atom(a)),
+            },
+            Expr::Ann(e, t) => write!(f, "({} : {})", e, t),
+            Expr::Let(x, e, body) => write!(f, "let {} = {} in {}", x, e, body),
+            Expr::If(p, a, b) => write!(f, "if {} then {} else {}", p, a, b),
+        }
+    }
+}
+
+/// Why an expression doesn't have a type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Unbound(String),
+    /// A predicate which mentions a variable that is not a number in scope.
+    Scope(String),
+    /// A binder which would hide a variable in scope.
+    Shadowed(String),
+    /// An expression whose type doesn't have the expected shape.
+    Mismatch(Box<RType>, Box<RType>),
+    NotFunction(RType),
+    Unannotated(Box<Expr>),
+    /// A predicate which doesn't follow from the assumptions, with values
+    /// of the variables where it fails.
+    Unprovable(Pred, Vec<(String, i64)>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Unbound(x) => write!(f, "unbound variable '{}'", x),
+            Error::Scope(x) => write!(f, "'{}' is not a number in scope", x),
+            Error::Shadowed(x) => write!(f, "'{}' is already bound", x),
+            Error::Mismatch(a, b) => write!(f, "{} doesn't fit {}", a, b),
+            Error::NotFunction(t) => write!(f, "{} is not a function type", t),
+            Error::Unannotated(e) => write!(f, "the type of {} needs an annotation", e),
+            Error::Unprovable(p, model) if model.is_empty() => write!(f, "{} doesn't hold", p),
+            Error::Unprovable(p, model) => {
+                let values: Vec<String> = model
+                    .iter()
+                    .map(|(x, n)| format!("{} = {}", x, n))
+                    .collect();
+                write!(f, "{} doesn't hold for {}", p, values.join(", "))
+            }
+        }
+    }
+}
+
+fn index_vars(i: &Index, vars: &mut BTreeSet<String>) {
+    match i {
+        Index::Var(x) => {
+            vars.insert(x.clone());
+        }
+        Index::Num(_) => {}
+        Index::Add(a, b) | Index::Sub(a, b) => {
+            index_vars(a, vars);
+            index_vars(b, vars);
+        }
+        Index::Mul(_, a) => index_vars(a, vars),
+    }
+}
+
+fn pred_vars(p: &Pred, vars: &mut BTreeSet<String>) {
+    match p {
+        Pred::True | Pred::False => {}
+        Pred::Le(a, b) | Pred::Lt(a, b) | Pred::Eq(a, b) => {
+            index_vars(a, vars);
+            index_vars(b, vars);
+        }
+        Pred::And(p, q) | Pred::Or(p, q) => {
+            pred_vars(p, vars);
+            pred_vars(q, vars);
+        }
+        Pred::Not(p) => pred_vars(p, vars),
+    }
+}
+
+fn type_vars(t: &RType, vars: &mut BTreeSet<String>) {
+    match t {
+        RType::Nat(v, p) => {
+            vars.insert(v.clone());
+            pred_vars(p, vars);
+        }
+        RType::Fn(x, a, b) => {
+            vars.insert(x.clone());
+            type_vars(a, vars);
+            type_vars(b, vars);
+        }
+    }
+}
+
+fn subst_index(i: &Index, x: &str, j: &Index) -> Index {
+    match i {
+        Index::Var(y) if y == x => j.clone(),
+        Index::Var(_) | Index::Num(_) => i.clone(),
+        Index::Add(a, b) => Index::Add(
+            Box::new(subst_index(a, x, j)),
+            Box::new(subst_index(b, x, j)),
+        ),
+        Index::Sub(a, b) => Index::Sub(
+            Box::new(subst_index(a, x, j)),
+            Box::new(subst_index(b, x, j)),
+        ),
+        Index::Mul(k, a) => Index::Mul(*k, Box::new(subst_index(a, x, j))),
+    }
+}
+
+pub fn subst_pred(p: &Pred, x: &str, j: &Index) -> Pred {
+    let go = |p: &Pred| Box::new(subst_pred(p, x, j));
+    match p {
+        Pred::True | Pred::False => p.clone(),
+        Pred::Le(a, b) => Pred::Le(subst_index(a, x, j), subst_index(b, x, j)),
+        Pred::Lt(a, b) => Pred::Lt(subst_index(a, x, j), subst_index(b, x, j)),
+        Pred::Eq(a, b) => Pred::Eq(subst_index(a, x, j), subst_index(b, x, j)),
+        Pred::And(p, q) => Pred::And(go(p), go(q)),
+        Pred::Or(p, q) => Pred::Or(go(p), go(q)),
+        Pred::Not(p) => Pred::Not(go(p)),
+    }
+}
+
+/// A name based on `x` which is not in `used`.
+fn fresh_name(x: &str, used: &BTreeSet<String>) -> String {
+    (0..)
+        .map(|n| match n {
+            0 => x.to_string(),
+            n => format!("{}{}", x, n),
+        })
+        .find(|y| !used.contains(y))
+        .expect("a fresh name")
+}
+
+/// Substitute `j` for `x` in a type, binders which would capture a
+/// variable of `j` are renamed.
+pub fn subst_rtype(t: &RType, x: &str, j: &Index) -> RType {
+    let binder = |y: &str, body: &RType| -> String {
+        let mut used = BTreeSet::new();
+        index_vars(j, &mut used);
+        match used.contains(y) {
+            true => {
+                type_vars(body, &mut used);
+                fresh_name(y, &used)
+            }
+            false => y.to_string(),
+        }
+    };
+    match t {
+        RType::Nat(v, _) if v == x => t.clone(),
+        RType::Nat(v, p) => {
+            let w = binder(v, t);
+            let p = subst_pred(p, v, &Index::Var(w.clone()));
+            RType::Nat(w, subst_pred(&p, x, j))
+        }
+        RType::Fn(y, a, b) => {
+            let a = Box::new(subst_rtype(a, x, j));
+            if y == x {
+                return RType::Fn(y.clone(), a, b.clone());
+            }
+            let z = binder(y, t);
+            let b = subst_rtype(b, y, &Index::Var(z.clone()));
+            RType::Fn(z, a, Box::new(subst_rtype(&b, x, j)))
+        }
+    }
+}
+
+fn linear(i: &Index) -> Linear {
+    match i {
+        Index::Var(x) => Linear::var(x),
+        Index::Num(n) => Linear::constant(*n),
+        Index::Add(a, b) => linear(a).plus(&linear(b)),
+        Index::Sub(a, b) => linear(a).minus(&linear(b)),
+        Index::Mul(k, a) => linear(a).times(*k),
+    }
+}
+
+/// The disjunctive normal form of a predicate, or of its negation if it
+/// is not `positive`, as alternatives of conjunctions of constraints.
+fn dnf(p: &Pred, positive: bool) -> Vec<Vec<Constraint>> {
+    let geq = |a: &Index, b: &Index, k: i64| {
+        // a >= b + k
+        Constraint::Geq(linear(a).minus(&linear(b)).plus(&Linear::constant(-k)))
+    };
+    match (p, positive) {
+        (Pred::True, true) | (Pred::False, false) => vec![Vec::new()],
+        (Pred::True, false) | (Pred::False, true) => Vec::new(),
+        (Pred::Le(a, b), true) => vec![vec![geq(b, a, 0)]],
+        (Pred::Le(a, b), false) => vec![vec![geq(a, b, 1)]],
+        (Pred::Lt(a, b), true) => vec![vec![geq(b, a, 1)]],
+        (Pred::Lt(a, b), false) => vec![vec![geq(a, b, 0)]],
+        (Pred::Eq(a, b), true) => vec![vec![Constraint::Eq(linear(a).minus(&linear(b)))]],
+        (Pred::Eq(a, b), false) => vec![vec![geq(a, b, 1)], vec![geq(b, a, 1)]],
+        (Pred::And(p, q), true) | (Pred::Or(p, q), false) => {
+            let (ps, qs) = (dnf(p, positive), dnf(q, positive));
+            let mut conjunctions = Vec::new();
+            for p in &ps {
+                for q in &qs {
+                    conjunctions.push(p.iter().chain(q).cloned().collect());
+                }
+            }
+            conjunctions
+        }
+        (Pred::Or(p, q), true) | (Pred::And(p, q), false) => {
+            let mut conjunctions = dnf(p, positive);
+            conjunctions.extend(dnf(q, positive));
+            conjunctions
+        }
+        (Pred::Not(p), _) => dnf(p, !positive),
+    }
+}
+
+/// The constraint as a predicate, the terms with a negative coefficient
+/// move to the left: `x - 2 >= 0` is `2 <= x`.
+fn constraint_pred(c: &Constraint) -> Pred {
+    let (Constraint::Eq(e) | Constraint::Geq(e)) = c;
+    if e.coeffs.is_empty() {
+        return match c.holds(&Default::default()) {
+            true => Pred::True,
+            false => Pred::False,
+        };
+    }
+    let (mut left, mut right) = (Vec::new(), Vec::new());
+    for (x, a) in &e.coeffs {
+        let term = |k: i64| match k {
+            1 => Index::Var(x.clone()),
+            k => scale(k, x.as_str()),
+        };
+        match *a > 0 {
+            true => right.push(term(*a)),
+            false => left.push(term(-*a)),
+        }
+    }
+    match e.constant {
+        k if k > 0 => right.push(Index::Num(k)),
+        k if k < 0 => left.push(Index::Num(-k)),
+        _ => {}
+    }
+    let sum = |terms: Vec<Index>| terms.into_iter().reduce(plus).unwrap_or(Index::Num(0));
+    match c {
+        Constraint::Eq(_) => equal(sum(left), sum(right)),
+        Constraint::Geq(_) => le(sum(left), sum(right)),
+    }
+}
+
+/// The predicate `exists xs. p` over the naturals without the quantifier,
+/// the variables are eliminated from every alternative of `p`.
+fn project(p: &Pred, xs: &[String]) -> Pred {
+    dnf(p, true)
+        .into_iter()
+        .map(|mut conjunction| {
+            conjunction.extend(xs.iter().map(|x| Constraint::Geq(Linear::var(x))));
+            let mut conjunction = xs.iter().fold(conjunction, |c, x| shadow(&c, x));
+            // drop the inequalities which the others imply
+            let mut i = 0;
+            while i < conjunction.len() {
+                let mut others = conjunction.clone();
+                let implied = match others.remove(i) {
+                    Constraint::Geq(e) => {
+                        others.push(Constraint::Geq(e.times(-1).plus(&Linear::constant(-1))));
+                        satisfiable(&others).is_none()
+                    }
+                    Constraint::Eq(_) => false,
+                };
+                match implied {
+                    true => {
+                        conjunction.remove(i);
+                    }
+                    false => i += 1,
+                }
+            }
+            conjunction
+                .iter()
+                .map(constraint_pred)
+                .reduce(and)
+                .unwrap_or(Pred::True)
+        })
+        .reduce(or)
+        .unwrap_or(Pred::False)
+}
+
+/// Remove the names `xs` of intermediate results from a type, given the
+/// facts about them. The refinement of a result allows the numbers it
+/// holds for with some values of the names, the refinement of an
+/// argument only those it holds for with all of them.
+fn close(t: &RType, xs: &[String], facts: &Pred, positive: bool) -> RType {
+    let mut used = BTreeSet::new();
+    pred_vars(facts, &mut used);
+    // a binder of the type may not capture a variable of the facts
+    let binder = |y: &str, body: &RType| match used.contains(y) {
+        true => {
+            let mut used = used.clone();
+            type_vars(body, &mut used);
+            fresh_name(y, &used)
+        }
+        false => y.to_string(),
+    };
+    match t {
+        RType::Nat(v, p) => {
+            let mut vars = BTreeSet::new();
+            pred_vars(p, &mut vars);
+            if xs.iter().all(|x| !vars.contains(x)) {
+                return t.clone();
+            }
+            let w = binder(v, t);
+            let p = subst_pred(p, v, &Index::Var(w.clone()));
+            let p = match positive {
+                true => project(&and(facts.clone(), p), xs),
+                false => not(project(&and(facts.clone(), not(p)), xs)),
+            };
+            RType::Nat(w, p)
+        }
+        RType::Fn(x, a, b) => {
+            let z = binder(x, t);
+            let b = subst_rtype(b, x, &Index::Var(z.clone()));
+            RType::Fn(
+                z,
+                Box::new(close(a, xs, facts, !positive)),
+                Box::new(close(&b, xs, facts, positive)),
+            )
+        }
+    }
+}
+
+/// Decide if the assumptions imply the goal for all natural numbers,
+/// otherwise give values of the variables where they don't.
+pub fn valid(assumptions: &[Pred], goal: &Pred) -> Result<(), Vec<(String, i64)>> {
+    let refutation = assumptions
+        .iter()
+        .fold(not(goal.clone()), |p, q| and(q.clone(), p));
+    let mut vars = BTreeSet::new();
+    pred_vars(&refutation, &mut vars);
+    let naturals: Vec<Constraint> = vars
+        .iter()
+        .map(|x| Constraint::Geq(Linear::var(x)))
+        .collect();
+    for mut conjunction in dnf(&refutation, true) {
+        conjunction.extend(naturals.iter().cloned());
+        if let Some(model) = satisfiable(&conjunction) {
+            let values = vars
+                .iter()
+                .map(|x| (x.clone(), model.get(x).cloned().unwrap_or(0)))
+                .collect();
+            return Err(values);
+        }
+    }
+    Ok(())
+}
+
+/// The variables in scope with their types and the assumptions about
+/// them, both grow and shrink like a stack.
+struct Checker {
+    scope: Vec<(String, RType)>,
+    facts: Vec<Pred>,
+}
+
+impl Checker {
+    fn lookup(&self, x: &str) -> Option<&RType> {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(y, _)| y == x)
+            .map(|(_, t)| t)
+    }
+
+    fn fresh(&self, x: &str) -> String {
+        let used = self.scope.iter().map(|(y, _)| y.clone()).collect();
+        fresh_name(x, &used)
+    }
+
+    /// Bring a variable into scope, a number with its refinement.
+    fn bind(&mut self, x: &str, t: &RType) {
+        if let RType::Nat(v, p) = t {
+            self.facts.push(subst_pred(p, v, &Index::Var(x.into())));
+        }
+        self.scope.push((x.into(), t.clone()));
+    }
+
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Checker) -> T) -> T {
+        let (scope, facts) = (self.scope.len(), self.facts.len());
+        let result = f(self);
+        self.scope.truncate(scope);
+        self.facts.truncate(facts);
+        result
+    }
+
+    fn fresh_binder(&self, x: &str) -> Result<(), Error> {
+        match self.lookup(x) {
+            Some(_) => Err(Error::Shadowed(x.into())),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that a predicate only mentions numbers in scope.
+    fn well_scoped(&self, p: &Pred) -> Result<(), Error> {
+        let mut vars = BTreeSet::new();
+        pred_vars(p, &mut vars);
+        match vars
+            .into_iter()
+            .find(|x| !matches!(self.lookup(x), Some(RType::Nat(_, _))))
+        {
+            Some(x) => Err(Error::Scope(x)),
+            None => Ok(()),
+        }
+    }
+
+    fn well_formed(&mut self, t: &RType) -> Result<(), Error> {
+        self.scoped(|c| match t {
+            RType::Nat(v, p) => {
+                c.scope.push((v.clone(), nat(v, Pred::True)));
+                c.well_scoped(p)
+            }
+            RType::Fn(x, a, b) => {
+                c.well_formed(a)?;
+                c.scope.push((x.clone(), (**a).clone()));
+                c.well_formed(b)
+            }
+        })
+    }
+
+    fn prove(&self, goal: &Pred) -> Result<(), Error> {
+        valid(&self.facts, goal).map_err(|model| Error::Unprovable(goal.clone(), model))
+    }
+
+    /// The type of exactly the value of an index.
+    fn exactly(&self, i: Index) -> RType {
+        let mut used: BTreeSet<String> = self.scope.iter().map(|(y, _)| y.clone()).collect();
+        index_vars(&i, &mut used);
+        let v = fresh_name("v", &used);
+        RType::Nat(v.clone(), Pred::Eq(Index::Var(v), i))
+    }
+
+    /// The value of a number as an index, the result of an application is
+    /// named by a new variable with the assumptions of its type.
+    fn value(&mut self, e: &Expr) -> Result<Index, Error> {
+        match e {
+            Expr::Var(x) if matches!(self.lookup(x), Some(RType::Nat(_, _))) => {
+                Ok(Index::Var(x.clone()))
+            }
+            Expr::Num(n) => Ok(Index::Num(i64::from(*n))),
+            Expr::Add(a, b) => Ok(plus(self.value(a)?, self.value(b)?)),
+            _ => match self.synth(e)? {
+                RType::Nat(v, p) => {
+                    let x = self.fresh(&v);
+                    self.bind(&x, &RType::Nat(v, p));
+                    Ok(Index::Var(x))
+                }
+                t => Err(Error::Mismatch(Box::new(t), Box::new(nat("v", Pred::True)))),
+            },
+        }
+    }
+
+    fn sub(&mut self, a: &RType, b: &RType) -> Result<(), Error> {
+        match (a, b) {
+            (RType::Nat(v, p), RType::Nat(w, q)) => self.scoped(|c| {
+                let z = c.fresh(w);
+                c.bind(&z, &RType::Nat(v.clone(), p.clone()));
+                c.prove(&subst_pred(q, w, &Index::Var(z)))
+            }),
+            (RType::Fn(x, a1, b1), RType::Fn(y, a2, b2)) => {
+                self.sub(a2, a1)?;
+                self.scoped(|c| {
+                    let z = c.fresh(y);
+                    c.bind(&z, a2);
+                    let b1 = subst_rtype(b1, x, &Index::Var(z.clone()));
+                    c.sub(&b1, &subst_rtype(b2, y, &Index::Var(z)))
+                })
+            }
+            _ => Err(Error::Mismatch(Box::new(a.clone()), Box::new(b.clone()))),
+        }
+    }
+
+    fn check(&mut self, e: &Expr, t: &RType) -> Result<(), Error> {
+        match (e, t) {
+            (Expr::Lam(x, body), RType::Fn(y, a, b)) => {
+                self.fresh_binder(x)?;
+                let b = subst_rtype(b, y, &Index::Var(x.clone()));
+                self.scoped(|c| {
+                    c.bind(x, a);
+                    c.check(body, &b)
+                })
+            }
+            (Expr::Let(x, e, body), t) => {
+                self.fresh_binder(x)?;
+                self.scoped(|c| {
+                    let a = c.synth(e)?;
+                    c.bind(x, &a);
+                    c.check(body, t)
+                })
+            }
+            (Expr::If(p, a, b), t) => {
+                self.well_scoped(p)?;
+                self.scoped(|c| {
+                    c.facts.push(p.clone());
+                    c.check(a, t)
+                })?;
+                self.scoped(|c| {
+                    c.facts.push(not(p.clone()));
+                    c.check(b, t)
+                })
+            }
+            _ => {
+                let a = self.synth(e)?;
+                self.sub(&a, t)
+            }
+        }
+    }
+
+    fn synth(&mut self, e: &Expr) -> Result<RType, Error> {
+        match e {
+            Expr::Var(x) => match self.lookup(x) {
+                Some(RType::Nat(_, _)) => Ok(self.exactly(Index::Var(x.clone()))),
+                Some(t) => Ok(t.clone()),
+                None => Err(Error::Unbound(x.clone())),
+            },
+            Expr::Num(_) | Expr::Add(_, _) => {
+                let i = self.value(e)?;
+                Ok(self.exactly(i))
+            }
+            Expr::App(f, arg) => match self.synth(f)? {
+                RType::Fn(x, a, b) => match *a {
+                    RType::Nat(_, _) => {
+                        let i = self.value(arg)?;
+                        let exact = self.exactly(i.clone());
+                        self.sub(&exact, &a)?;
+                        Ok(subst_rtype(&b, &x, &i))
+                    }
+                    _ => {
+                        self.check(arg, &a)?;
+                        Ok(*b)
+                    }
+                },
+                t => Err(Error::NotFunction(t)),
+            },
+            Expr::Ann(e, t) => {
+                self.well_formed(t)?;
+                self.check(e, t)?;
+                Ok(t.clone())
+            }
+            Expr::Lam(_, _) | Expr::Let(_, _, _) | Expr::If(_, _, _) => {
+                Err(Error::Unannotated(Box::new(e.clone())))
+            }
+        }
+    }
+}
+
+fn checker(env: &[(String, RType)]) -> Result<Checker, Error> {
+    let mut c = Checker {
+        scope: Vec::new(),
+        facts: Vec::new(),
+    };
+    for (x, t) in env {
+        c.fresh_binder(x)?;
+        c.well_formed(t)?;
+        c.bind(x, t);
+    }
+    Ok(c)
+}
+
+/// Check an expression against a type in an environment of variables.
+pub fn check(env: &[(String, RType)], e: &Expr, t: &RType) -> Result<(), Error> {
+    let mut c = checker(env)?;
+    c.well_formed(t)?;
+    c.check(e, t)
+}
+
+/// Synthesize the type of an expression in an environment of variables.
+pub fn synth(env: &[(String, RType)], e: &Expr) -> Result<RType, Error> {
+    let mut c = checker(env)?;
+    let (scope, facts) = (c.scope.len(), c.facts.len());
+    let t = c.synth(e)?;
+    // the names of the results of applications are out of scope now
+    let names: Vec<String> = c.scope[scope..].iter().map(|(x, _)| x.clone()).collect();
+    let facts = c.facts[facts..].iter().cloned().reduce(and);
+    Ok(close(&t, &names, &facts.unwrap_or(Pred::True), true))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn any() -> RType {
+        nat("v", Pred::True)
+    }
+
+    /// (x: Nat) -> {v: Nat | x < v}
+    fn successor() -> RType {
+        arrow("x", any(), nat("v", lt("x", "v")))
+    }
+
+    /// f : (x: {x: Nat | 1 <= x}) -> Nat
+    fn positive() -> Vec<(String, RType)> {
+        vec![("f".into(), arrow("x", nat("x", le(1, "x")), any()))]
+    }
+
+    #[test]
+    fn decisions() {
+        let chain = [le("x", "y"), le("y", "z")];
+        assert_eq!(valid(&chain, &le("x", "z")), Ok(()));
+        // x <= y doesn't make x < y
+        let model = valid(&chain[..1], &lt("x", "y")).unwrap_err();
+        assert_eq!(model[0].1, model[1].1);
+        // 2x = 1 has a rational solution only
+        let even = [equal("v", scale(2, "x"))];
+        assert_eq!(valid(&even, &not(equal("v", 1))), Ok(()));
+        assert!(valid(&even, &not(equal("v", 2))).is_err());
+        // natural numbers
+        assert_eq!(valid(&[], &le(0, "x")), Ok(()));
+        assert_eq!(
+            valid(&[le(minus("x", 3), 0)], &or(le("x", 2), equal("x", 3))),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn dependent_functions() {
+        let succ = lam("x", add(var("x"), num(1)));
+        assert_eq!(check(&[], &succ, &successor()), Ok(()));
+        // max x y is at least x and y and one of them
+        let at_least = and(le("x", "v"), le("y", "v"));
+        let one_of = or(equal("v", "x"), equal("v", "y"));
+        let max = arrow(
+            "x",
+            any(),
+            arrow("y", any(), nat("v", and(at_least, one_of))),
+        );
+        let body = cond(le("x", "y"), var("y"), var("x"));
+        assert_eq!(check(&[], &lam("x", lam("y", body)), &max), Ok(()));
+        // the result depends on the argument
+        let two = app(ann(succ.clone(), successor()), num(2));
+        assert_eq!(synth(&[], &two), Ok(nat("v", lt(2, "v"))));
+        // the intermediate result is not in scope, 0 < v < v1
+        let succ = ann(succ.clone(), successor());
+        let nested = app(succ.clone(), app(succ, num(0)));
+        assert_eq!(synth(&[], &nested), Ok(nat("v1", le(2, "v1"))));
+        let three = bind("y", two.clone(), add(var("y"), var("y")));
+        assert_eq!(check(&[], &three, &nat("v", le(6, "v"))), Ok(()));
+        // x + x is even
+        let double = lam("x", add(var("x"), var("x")));
+        let odd = not(equal("v", plus(scale(2, "k"), 1)));
+        let env = vec![("k".into(), any())];
+        assert_eq!(
+            check(&env, &double, &arrow("x", any(), nat("v", odd))),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn counterexamples() {
+        let succ = ann(lam("x", add(var("x"), num(1))), successor());
+        let error = check(&[], &app(succ, num(2)), &nat("v", lt(3, "v"))).unwrap_err();
+        assert_eq!(error, Error::Unprovable(lt(3, "v"), vec![("v".into(), 3)]));
+        assert_eq!(error.to_string(), "3 < v doesn't hold for v = 3");
+        // the argument of f must be positive
+        let env = positive();
+        let shifted = lam("y", app(var("f"), add(var("y"), num(1))));
+        assert_eq!(check(&env, &shifted, &arrow("y", any(), any())), Ok(()));
+        let unshifted = lam("y", app(var("f"), var("y")));
+        let error = check(&env, &unshifted, &arrow("y", any(), any())).unwrap_err();
+        assert_eq!(error.to_string(), "1 <= x doesn't hold for x = 0, y = 0");
+        // the condition of an if is an assumption in its branches
+        let guarded = lam("y", cond(lt(0, "y"), app(var("f"), var("y")), num(0)));
+        assert_eq!(check(&env, &guarded, &arrow("y", any(), any())), Ok(()));
+    }
+
+    #[test]
+    fn errors() {
+        let t = arrow("x", any(), nat("v", lt("y", "v")));
+        let e = lam("x", var("x"));
+        assert_eq!(check(&[], &e, &t), Err(Error::Scope("y".into())));
+        assert_eq!(synth(&[], &e), Err(Error::Unannotated(Box::new(e.clone()))));
+        assert_eq!(synth(&[], &var("z")), Err(Error::Unbound("z".into())));
+        assert_eq!(
+            synth(&[], &app(num(1), num(2))).unwrap_err().to_string(),
+            "{v: Nat | v = 1} is not a function type"
+        );
+        let env = positive();
+        let shadowing = lam("f", var("f"));
+        assert_eq!(
+            check(&env, &shadowing, &arrow("x", any(), any())),
+            Err(Error::Shadowed("f".into()))
+        );
+        let error = check(&env, &add(var("f"), num(1)), &any()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "(x: {x: Nat | 1 <= x}) -> Nat doesn't fit Nat"
+        );
+    }
+}
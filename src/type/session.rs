@@ -0,0 +1,649 @@
+//! Session types for two processes talking over a channel.
+//!
+//! A session type describes a protocol from the view of one endpoint:
+//! `!A. S` sends a value of type `A` and continues with `S`, `?A. S`
+//! receives one, `+{l: S, ...}` chooses one of the labels, `&{l: S, ...}`
+//! offers all of them to the other side and `end` closes the channel.
+//! The other endpoint follows the dual protocol, where sending and
+//! receiving as well as choosing and offering are swapped.
+//!
+//! Processes extend the calculus by actions on channels whose messages
+//! are core terms. Channels are linear: every action consumes the channel
+//! at its session type and gives it back at the rest of the protocol, so
+//! a process must follow its protocol to the end, close the channel and
+//! can't use it afterwards. A channel is not a value and can't be used
+//! within a message.
+//!
+//! `run` executes the two endpoints of a program as threads connected by
+//! a pair of queues. Each thread checks its own actions and the messages
+//! it receives against its session, so a program which doesn't check
+//! fails with the first violation of its protocol instead of deadlocking.
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use super::eval::{eval, subst, Store};
+use super::{judgment, judgment_check, Context, Term, Type};
+
+/// A protocol as seen from one endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Session {
+    Send(Type, Box<Session>),       // !A. S
+    Recv(Type, Box<Session>),       // ?A. S
+    Choose(Vec<(String, Session)>), // +{l: S, ...}
+    Offer(Vec<(String, Session)>),  // &{l: S, ...}
+    End,
+}
+
+/// The protocol of the other endpoint.
+pub fn dual(s: &Session) -> Session {
+    let duals = |branches: &[(String, Session)]| {
+        branches.iter().map(|(l, s)| (l.clone(), dual(s))).collect()
+    };
+    match s {
+        Session::Send(a, s) => Session::Recv(a.clone(), Box::new(dual(s))),
+        Session::Recv(a, s) => Session::Send(a.clone(), Box::new(dual(s))),
+        Session::Choose(branches) => Session::Offer(duals(branches)),
+        Session::Offer(branches) => Session::Choose(duals(branches)),
+        Session::End => Session::End,
+    }
+}
+
+/// Test if the messages of a session are types of terms which can be
+/// passed between threads, references are local to their thread.
+pub fn well_formed(s: &Session) -> bool {
+    fn local(t: &Type) -> bool {
+        match t {
+            Type::Ref(_) => true,
+            Type::Sum(a, b) | Type::Fn(a, b, _) | Type::And(a, b) | Type::Or(a, b) => {
+                local(a) || local(b)
+            }
+            Type::Named(_, t) | Type::Exists(_, t) | Type::Forall(_, t) => local(t),
+            _ => false,
+        }
+    }
+    match s {
+        Session::Send(a, s) | Session::Recv(a, s) => judgment(a) && !local(a) && well_formed(s),
+        Session::Choose(branches) | Session::Offer(branches) => branches
+            .iter()
+            .enumerate()
+            .all(|(i, (l, s))| !branches[..i].iter().any(|b| &b.0 == l) && well_formed(s)),
+        Session::End => true,
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let branches = |branches: &[(String, Session)]| {
+            let branches: Vec<String> = branches
+                .iter()
+                .map(|(l, s)| format!("{}: {}", l, s))
+                .collect();
+            branches.join(", ")
+        };
+        match self {
+            Session::Send(a, s) => write!(f, "!{}. {}", atom(a), s),
+            Session::Recv(a, s) => write!(f, "?{}. {}", atom(a), s),
+            Session::Choose(bs) => write!(f, "+{{{}}}", branches(bs)),
+            Session::Offer(bs) => write!(f, "&{{{}}}", branches(bs)),
+            Session::End => write!(f, "end"),
+        }
+    }
+}
+
+/// Wrap the types of messages which aren't atoms into parens.
+fn atom(t: &Type) -> String {
+    match t {
+        Type::Foo | Type::Bar | Type::Baz | Type::Unit | Type::Dyn | Type::Var(_) => t.to_string(),
+        Type::Named(_, _) | Type::Record(_) | Type::Variant(_) => t.to_string(),
+        _ => format!("({})", t),
+    }
+}
+
+/// A process acting on channels, the messages are core terms.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Process {
+    Send(String, Term, Box<Process>),       // send c t; P
+    Recv(String, String, Box<Process>),     // let x = recv c; P
+    Select(String, String, Box<Process>),   // select c l; P
+    Branch(String, Vec<(String, Process)>), // branch c {l => P | ...}
+    Close(String, Box<Process>),            // close c; P
+    Stop,
+}
+
+pub fn send(c: &str, t: Term, next: Process) -> Process {
+    Process::Send(c.into(), t, Box::new(next))
+}
+
+pub fn recv(c: &str, x: &str, next: Process) -> Process {
+    Process::Recv(c.into(), x.into(), Box::new(next))
+}
+
+pub fn select(c: &str, l: &str, next: Process) -> Process {
+    Process::Select(c.into(), l.into(), Box::new(next))
+}
+
+pub fn branch(c: &str, arms: Vec<(&str, Process)>) -> Process {
+    Process::Branch(
+        c.into(),
+        arms.into_iter().map(|(l, p)| (l.into(), p)).collect(),
+    )
+}
+
+pub fn close(c: &str, next: Process) -> Process {
+    Process::Close(c.into(), Box::new(next))
+}
+
+impl fmt::Display for Process {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Process::Send(c, t, p) => write!(f, "send {} {}; {}", c, t, p),
+            Process::Recv(c, x, p) => write!(f, "let {} = recv {}; {}", x, c, p),
+            Process::Select(c, l, p) => write!(f, "select {} {}; {}", c, l, p),
+            Process::Branch(c, arms) => {
+                let arms: Vec<String> = arms
+                    .iter()
+                    .map(|(l, p)| format!("{} => {}", l, p))
+                    .collect();
+                write!(f, "branch {} {{{}}}", c, arms.join(" | "))
+            }
+            Process::Close(c, p) => write!(f, "close {}; {}", c, p),
+            Process::Stop => write!(f, "stop"),
+        }
+    }
+}
+
+/// Two processes at the endpoints of a channel, the left one follows
+/// the session and the right one its dual.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub channel: String,
+    pub session: Session,
+    pub left: Process,
+    pub right: Process,
+}
+
+/// Why a process doesn't follow its protocol.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A session with an ill-kinded or local message type or a repeated label.
+    Malformed(Session),
+    /// A channel which was never opened or is already closed.
+    Unknown(String),
+    /// An action which the session of the channel doesn't allow.
+    Unexpected(String, Session, &'static str),
+    Mismatch(Box<Term>, Type),
+    /// A channel mentioned by a message.
+    Captured(String),
+    /// A variable which would hide a channel.
+    Shadowed(String),
+    MissingBranch(String, String),
+    UnknownBranch(String, String),
+    /// A channel whose protocol is not finished when the process stops.
+    Unfinished(String, Session),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Malformed(s) => write!(f, "{} is not a session type", s),
+            Error::Unknown(c) => write!(f, "'{}' is not an open channel", c),
+            Error::Unexpected(c, s, action) => {
+                write!(f, "{} : {} doesn't allow {}", c, s, action)
+            }
+            Error::Mismatch(t, a) => write!(f, "the message {} doesn't have the type {}", t, a),
+            Error::Captured(c) => write!(f, "the channel {} is used within a message", c),
+            Error::Shadowed(x) => write!(f, "'{}' is already a channel", x),
+            Error::MissingBranch(c, l) => write!(f, "the branch on {} lacks '{}'", c, l),
+            Error::UnknownBranch(c, l) => write!(f, "{} offers no '{}'", c, l),
+            Error::Unfinished(c, s) => write!(f, "the channel {} is left at {}", c, s),
+        }
+    }
+}
+
+/// The linear context of the open channels and their sessions.
+type Channels = Vec<(String, Session)>;
+
+/// Take a channel out of the context.
+fn take(channels: &mut Channels, c: &str) -> Result<Session, Error> {
+    match channels.iter().position(|(d, _)| d == c) {
+        Some(i) => Ok(channels.remove(i).1),
+        None => Err(Error::Unknown(c.into())),
+    }
+}
+
+/// Check a message against its type, it may not mention a channel.
+fn message(g: &Context, channels: &Channels, t: &Term, a: &Type) -> Result<(), Error> {
+    for (c, _) in channels {
+        if subst(t, c, &Term::Unit) != *t {
+            return Err(Error::Captured(c.clone()));
+        }
+    }
+    match judgment_check(g, t, a) {
+        true => Ok(()),
+        false => Err(Error::Mismatch(Box::new(t.clone()), a.clone())),
+    }
+}
+
+/// Check that a process follows the sessions of its channels to the end,
+/// the messages are checked in the context `g`.
+pub fn check_process(g: &Context, mut channels: Channels, p: &Process) -> Result<(), Error> {
+    match p {
+        Process::Send(c, t, next) => match take(&mut channels, c)? {
+            Session::Send(a, s) => {
+                channels.push((c.clone(), *s));
+                message(g, &channels, t, &a)?;
+                check_process(g, channels, next)
+            }
+            s => Err(Error::Unexpected(c.clone(), s, "sending")),
+        },
+        Process::Recv(c, x, next) => match take(&mut channels, c)? {
+            Session::Recv(a, s) => {
+                if x == c || channels.iter().any(|(d, _)| d == x) {
+                    return Err(Error::Shadowed(x.clone()));
+                }
+                channels.push((c.clone(), *s));
+                check_process(&g.clone().with(x, a), channels, next)
+            }
+            s => Err(Error::Unexpected(c.clone(), s, "receiving")),
+        },
+        Process::Select(c, l, next) => match take(&mut channels, c)? {
+            Session::Choose(branches) => match branches.into_iter().find(|b| b.0 == *l) {
+                Some((_, s)) => {
+                    channels.push((c.clone(), s));
+                    check_process(g, channels, next)
+                }
+                None => Err(Error::UnknownBranch(c.clone(), l.clone())),
+            },
+            s => Err(Error::Unexpected(c.clone(), s, "selecting")),
+        },
+        Process::Branch(c, arms) => match take(&mut channels, c)? {
+            Session::Offer(branches) => {
+                if let Some((l, _)) = arms.iter().find(|a| !branches.iter().any(|b| b.0 == a.0)) {
+                    return Err(Error::UnknownBranch(c.clone(), l.clone()));
+                }
+                for (l, s) in branches {
+                    let arm = match arms.iter().find(|a| a.0 == l) {
+                        Some((_, arm)) => arm,
+                        None => return Err(Error::MissingBranch(c.clone(), l)),
+                    };
+                    let mut channels = channels.clone();
+                    channels.push((c.clone(), s));
+                    check_process(g, channels, arm)?;
+                }
+                Ok(())
+            }
+            s => Err(Error::Unexpected(c.clone(), s, "branching")),
+        },
+        Process::Close(c, next) => match take(&mut channels, c)? {
+            Session::End => check_process(g, channels, next),
+            s => Err(Error::Unexpected(c.clone(), s, "closing")),
+        },
+        Process::Stop => match channels.into_iter().next() {
+            Some((c, s)) => Err(Error::Unfinished(c, s)),
+            None => Ok(()),
+        },
+    }
+}
+
+/// Check both endpoints of a program against their sessions.
+pub fn check(g: &Context, program: &Program) -> Result<(), Error> {
+    if !well_formed(&program.session) {
+        return Err(Error::Malformed(program.session.clone()));
+    }
+    let c = &program.channel;
+    check_process(g, vec![(c.clone(), program.session.clone())], &program.left)?;
+    check_process(g, vec![(c.clone(), dual(&program.session))], &program.right)
+}
+
+/// What an endpoint did, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Sent(Term),
+    Received(Term),
+    Selected(String),
+    Branched(String),
+    Closed,
+}
+
+/// The first action of a run which breaks the protocol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub endpoint: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the {} endpoint: {}", self.endpoint, self.message)
+    }
+}
+
+enum Message {
+    Value(Term),
+    Label(String),
+    Close,
+}
+
+const HUNG_UP: &str = "the other endpoint hung up";
+
+/// An endpoint running a process against its session.
+struct Endpoint {
+    g: Context,
+    channel: String,
+    session: Option<Session>,
+    // the values received so far, the latest last
+    env: Vec<(String, Term)>,
+    store: Store,
+    actions: Vec<Action>,
+    tx: Sender<Message>,
+    rx: Receiver<Message>,
+}
+
+impl Endpoint {
+    fn session(&mut self, c: &str) -> Result<Session, String> {
+        match self.session.take() {
+            Some(s) if c == self.channel => Ok(s),
+            _ => Err(format!("'{}' is not an open channel", c)),
+        }
+    }
+
+    fn send(&mut self, m: Message) -> Result<(), String> {
+        self.tx.send(m).map_err(|_| HUNG_UP.to_string())
+    }
+
+    fn recv(&mut self) -> Result<Message, String> {
+        self.rx.recv().map_err(|_| HUNG_UP.to_string())
+    }
+
+    /// Evaluate a message with the received values in place, a value
+    /// with a location would be meaningless to the other thread.
+    fn value(&mut self, t: &Term, a: &Type) -> Result<Term, String> {
+        let t = self
+            .env
+            .iter()
+            .rev()
+            .fold(t.clone(), |t, (x, v)| subst(&t, x, v));
+        let v = eval(&t, &mut self.store);
+        match judgment_check(&self.g, &v, a) {
+            true => Ok(v),
+            false => Err(format!("the message {} doesn't have the type {}", v, a)),
+        }
+    }
+
+    fn run(&mut self, p: &Process) -> Result<(), String> {
+        let unexpected = |s: &Session, action: &str| format!("{} doesn't allow {}", s, action);
+        match p {
+            Process::Send(c, t, next) => match self.session(c)? {
+                Session::Send(a, s) => {
+                    let v = self.value(t, &a)?;
+                    self.send(Message::Value(v.clone()))?;
+                    self.actions.push(Action::Sent(v));
+                    self.session = Some(*s);
+                    self.run(next)
+                }
+                s => Err(unexpected(&s, "sending")),
+            },
+            Process::Recv(c, x, next) => match self.session(c)? {
+                Session::Recv(a, s) => match self.recv()? {
+                    Message::Value(v) if judgment_check(&self.g, &v, &a) => {
+                        self.actions.push(Action::Received(v.clone()));
+                        self.env.push((x.clone(), v));
+                        self.session = Some(*s);
+                        self.run(next)
+                    }
+                    Message::Value(v) => Err(format!("received {}, expected {}", v, a)),
+                    _ => Err(format!("expected a value of type {}", a)),
+                },
+                s => Err(unexpected(&s, "receiving")),
+            },
+            Process::Select(c, l, next) => match self.session(c)? {
+                Session::Choose(branches) => match branches.into_iter().find(|b| b.0 == *l) {
+                    Some((_, s)) => {
+                        self.send(Message::Label(l.clone()))?;
+                        self.actions.push(Action::Selected(l.clone()));
+                        self.session = Some(s);
+                        self.run(next)
+                    }
+                    None => Err(format!("{} offers no '{}'", c, l)),
+                },
+                s => Err(unexpected(&s, "selecting")),
+            },
+            Process::Branch(c, arms) => match self.session(c)? {
+                Session::Offer(branches) => match self.recv()? {
+                    Message::Label(l) => {
+                        let s = match branches.into_iter().find(|b| b.0 == l) {
+                            Some((_, s)) => s,
+                            None => return Err(format!("received the unknown label '{}'", l)),
+                        };
+                        let arm = match arms.iter().find(|a| a.0 == l) {
+                            Some((_, arm)) => arm,
+                            None => return Err(format!("the branch on {} lacks '{}'", c, l)),
+                        };
+                        self.actions.push(Action::Branched(l));
+                        self.session = Some(s);
+                        self.run(arm)
+                    }
+                    _ => Err("expected a label".to_string()),
+                },
+                s => Err(unexpected(&s, "branching")),
+            },
+            // both endpoints announce the end, the queues don't block on sending
+            Process::Close(c, next) => match self.session(c)? {
+                Session::End => {
+                    self.send(Message::Close)?;
+                    match self.recv()? {
+                        Message::Close => {
+                            self.actions.push(Action::Closed);
+                            self.run(next)
+                        }
+                        _ => Err("expected the channel to be closed".to_string()),
+                    }
+                }
+                s => Err(unexpected(&s, "closing")),
+            },
+            Process::Stop => match &self.session {
+                Some(s) => Err(format!("the channel {} is left at {}", self.channel, s)),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+/// Run the two endpoints of a program as threads and give the actions
+/// of each of them, or the first violation of the protocol. The terms of
+/// the messages are evaluated, the variables of `g` stand for themselves.
+pub fn run(g: &Context, program: &Program) -> Result<(Vec<Action>, Vec<Action>), Violation> {
+    let (left_tx, right_rx) = channel();
+    let (right_tx, left_rx) = channel();
+    let spawn = |session: Session, p: &Process, tx, rx| {
+        let mut endpoint = Endpoint {
+            g: g.clone(),
+            channel: program.channel.clone(),
+            session: Some(session),
+            env: Vec::new(),
+            store: Store::new(),
+            actions: Vec::new(),
+            tx,
+            rx,
+        };
+        let p = p.clone();
+        thread::spawn(move || endpoint.run(&p).map(|_| endpoint.actions))
+    };
+    let left = spawn(program.session.clone(), &program.left, left_tx, left_rx);
+    let right = spawn(dual(&program.session), &program.right, right_tx, right_rx);
+    let results = [
+        ("left", left.join().expect("the left endpoint panicked")),
+        ("right", right.join().expect("the right endpoint panicked")),
+    ];
+    // an endpoint which hangs up is the consequence of the other's violation
+    let mut violations: Vec<Violation> = results
+        .iter()
+        .filter_map(|(endpoint, result)| match result {
+            Err(message) => Some(Violation {
+                endpoint,
+                message: message.clone(),
+            }),
+            Ok(_) => None,
+        })
+        .collect();
+    violations.sort_by_key(|v| v.message == HUNG_UP);
+    if let Some(v) = violations.into_iter().next() {
+        return Err(v);
+    }
+    match results {
+        [(_, Ok(left)), (_, Ok(right))] => Ok((left, right)),
+        _ => unreachable!("the violations are reported above"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn unit_pair() -> Type {
+        Type::product(Type::Unit, Type::Unit)
+    }
+
+    /// +{apply: !(Unit -> Unit * Unit). ?(Unit * Unit). end, quit: end}
+    fn protocol() -> Session {
+        let apply = Session::Send(
+            Type::arrow(Type::Unit, unit_pair()),
+            Box::new(Session::Recv(unit_pair(), Box::new(Session::End))),
+        );
+        Session::Choose(vec![("apply".into(), apply), ("quit".into(), Session::End)])
+    }
+
+    fn client() -> Process {
+        let twice = Term::lam("x", Term::pair(Term::var("x"), Term::var("x")));
+        select(
+            "c",
+            "apply",
+            send("c", twice, recv("c", "r", close("c", Process::Stop))),
+        )
+    }
+
+    fn server() -> Process {
+        let applied = Term::app(Term::var("f"), Term::Unit, Type::Unit);
+        branch(
+            "c",
+            vec![
+                (
+                    "apply",
+                    recv("c", "f", send("c", applied, close("c", Process::Stop))),
+                ),
+                ("quit", close("c", Process::Stop)),
+            ],
+        )
+    }
+
+    fn program(left: Process, right: Process) -> Program {
+        Program {
+            channel: "c".into(),
+            session: protocol(),
+            left,
+            right,
+        }
+    }
+
+    #[test]
+    fn duality() {
+        let s = protocol();
+        assert_eq!(
+            s.to_string(),
+            "+{apply: !(Unit -> Unit * Unit). ?(Unit * Unit). end, quit: end}"
+        );
+        assert_eq!(
+            dual(&s).to_string(),
+            "&{apply: ?(Unit -> Unit * Unit). !(Unit * Unit). end, quit: end}"
+        );
+        assert_eq!(dual(&dual(&s)), s);
+        let cell = Session::Send(Type::reference(Type::Foo), Box::new(Session::End));
+        assert!(well_formed(&s) && !well_formed(&cell));
+        let twice = Session::Offer(vec![("a".into(), Session::End), ("a".into(), Session::End)]);
+        assert!(!well_formed(&twice));
+    }
+
+    #[test]
+    fn linear_checking() {
+        let g = Context::new();
+        assert_eq!(check(&g, &program(client(), server())), Ok(()));
+        let error = |left: Process, right: Process| {
+            check(&g, &program(left, right)).unwrap_err().to_string()
+        };
+        // the channel must be closed exactly once, and not used afterwards
+        let open = select("c", "quit", Process::Stop);
+        assert_eq!(error(open, server()), "the channel c is left at end");
+        let twice = select("c", "quit", close("c", close("c", Process::Stop)));
+        assert_eq!(error(twice, server()), "'c' is not an open channel");
+        // both endpoints may not send at once
+        let eager = send("c", Term::Unit, Process::Stop);
+        assert_eq!(
+            error(
+                client(),
+                branch(
+                    "c",
+                    vec![
+                        ("apply", eager.clone()),
+                        ("quit", close("c", Process::Stop))
+                    ]
+                )
+            ),
+            "c : ?(Unit -> Unit * Unit). !(Unit * Unit). end doesn't allow sending"
+        );
+        let partial = branch("c", vec![("quit", close("c", Process::Stop))]);
+        assert_eq!(error(client(), partial), "the branch on c lacks 'apply'");
+        let unknown = select("c", "stop", Process::Stop);
+        assert_eq!(error(unknown, server()), "c offers no 'stop'");
+        // messages have their types and can't hold the channel
+        let wrong = select("c", "apply", send("c", Term::Unit, Process::Stop));
+        assert_eq!(
+            error(wrong, server()),
+            "the message () doesn't have the type Unit -> Unit * Unit"
+        );
+        let captured = Term::lam("x", Term::pair(Term::var("c"), Term::var("x")));
+        let leaking = select("c", "apply", send("c", captured, Process::Stop));
+        assert_eq!(
+            error(leaking, server()),
+            "the channel c is used within a message"
+        );
+    }
+
+    #[test]
+    fn threads_follow_the_protocol() {
+        let g = Context::new();
+        let (left, right) = run(&g, &program(client(), server())).unwrap();
+        let result = Term::pair(Term::Unit, Term::Unit);
+        assert_eq!(left[0], Action::Selected("apply".into()));
+        assert_eq!(left[2], Action::Received(result.clone()));
+        assert_eq!(left.len(), 4);
+        assert_eq!(right[0], Action::Branched("apply".into()));
+        assert_eq!(right[2], Action::Sent(result));
+        assert_eq!(right[3], Action::Closed);
+
+        let quit = select("c", "quit", close("c", Process::Stop));
+        let (left, right) = run(&g, &program(quit, server())).unwrap();
+        assert_eq!(left, vec![Action::Selected("quit".into()), Action::Closed]);
+        assert_eq!(right, vec![Action::Branched("quit".into()), Action::Closed]);
+    }
+
+    #[test]
+    fn violations_stop_the_run() {
+        let g = Context::new();
+        // the server answers before it is asked, instead of deadlocking
+        // the client is told that its partner hung up
+        let eager = branch("c", vec![("apply", send("c", Term::Unit, Process::Stop))]);
+        let violation = run(&g, &program(client(), eager)).unwrap_err();
+        assert_eq!(violation.endpoint, "right");
+        assert!(violation.message.contains("doesn't allow sending"));
+
+        // a message which evaluates to a value of the wrong type
+        let unit = Term::app(Term::lam("x", Term::Unit), Term::Unit, Type::Unit);
+        let wrong = select("c", "apply", send("c", unit, Process::Stop));
+        let violation = run(&g, &program(wrong, server())).unwrap_err();
+        assert_eq!(
+            violation.to_string(),
+            "the left endpoint: the message () doesn't have the type Unit -> Unit * Unit"
+        );
+    }
+}
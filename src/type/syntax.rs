@@ -0,0 +1,1466 @@
+//! A surface syntax for source files of the calculus.
+//!
+//! A file is a list of definitions which may refer to earlier ones,
+//! and of type aliases which may refer to each other in any order:
+//!
+//! ```text
+//! type Pair A B = A * B
+//!
+//! -- swap the components of a pair
+//! def swap : Pair Foo Bar -> Pair Bar Foo =
+//!   \p. split p as (x, y) in (y, x)
+//! ```
+//!
+//! Terms are elaborated bidirectionally, which fills in the argument
+//! types of applications and the component types of splits, before
+//! the annotated term is handed to `judgment_check`. Every error is
+//! reported with the span of the offending subterm. The elaboration
+//! also records the type of every subterm, the binder of every variable
+//! and the goal of every hole `?name`, which is used by the language
+//! server.
+//!
+//! Types may leave parts unknown with `Dyn`. Where a subterm only has a
+//! type consistent with the expected one, like in the `gradual` module,
+//! the elaboration inserts a cast labelled with the line and column of
+//! the subterm, which a failing cast blames at runtime.
+use super::alias::{self, Aliases};
+use super::eval::subst;
+use super::{
+    consistent, free_in_type, judgment, judgment_check, normalize_type, subtype, type_equality,
+    unfold, Context, Effects, Kind, Term, Type,
+};
+
+/// A range of bytes within the source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset <= self.end
+    }
+}
+
+/// The line and column of an offset in the source, both counted from 1.
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+/// An error at some place in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+}
+
+fn error<T>(span: Span, message: String) -> Result<T, Diagnostic> {
+    Err(Diagnostic { span, message })
+}
+
+/// A name introduced by a lambda, split or definition.
+#[derive(Debug, Clone)]
+pub struct Binder {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Var(String),
+    Lam(Binder, Option<Type>, Box<Node>),
+    App(Box<Node>, Box<Node>),
+    Pair(Box<Node>, Box<Node>),
+    Split(
+        Box<Node>,
+        Binder,
+        Option<Type>,
+        Binder,
+        Option<Type>,
+        Box<Node>,
+    ),
+    Unit,
+    Ann(Box<Node>, Type),
+    Hole(String),
+    Ref(Box<Node>),
+    Deref(Box<Node>),
+    Assign(Box<Node>, Box<Node>),
+    Raise(String),
+    Try(Box<Node>, String, Box<Node>),
+}
+
+/// An expression together with its place in the source.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub expr: Expr,
+    pub span: Span,
+}
+
+/// def name : type = body
+#[derive(Debug, Clone)]
+pub struct Def {
+    pub name: Binder,
+    pub t: Type,
+    pub body: Node,
+}
+
+/// type Name params = body
+#[derive(Debug, Clone)]
+pub struct TypeDef {
+    pub name: Binder,
+    pub params: Vec<Binder>,
+    pub body: Type,
+}
+
+impl TypeDef {
+    /// The definition as a type operator over its parameters.
+    fn definition(&self) -> Type {
+        self.params.iter().rev().fold(self.body.clone(), |t, p| {
+            Type::Lam(p.name.clone(), Kind::Star, Box::new(t))
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Item {
+    Def(Def),
+    Type(TypeDef),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Hole(String),
+    Lambda,
+    Dot,
+    Colon,
+    Comma,
+    Open,
+    Close,
+    Arrow,
+    /// The start `-[` of an arrow with effects `-[E, F]->`.
+    EffectArrow,
+    CloseBracket,
+    FatArrow,
+    Star,
+    /// The intersection `/\` of two types.
+    And,
+    /// The union `\/` of two types.
+    Or,
+    Equals,
+    Bang,
+    Assign,
+}
+
+fn tokenize(s: &str) -> Result<Vec<(Token, Span)>, Diagnostic> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let single = |t: Token| {
+            (
+                t,
+                Span {
+                    start,
+                    end: start + 1,
+                },
+            )
+        };
+        match c {
+            c if c.is_whitespace() => {}
+            '-' if chars.next_if(|c| c.1 == '-').is_some() => {
+                while chars.next_if(|c| c.1 != '\n').is_some() {}
+            }
+            '-' if chars.next_if(|c| c.1 == '>').is_some() => tokens.push((
+                Token::Arrow,
+                Span {
+                    start,
+                    end: start + 2,
+                },
+            )),
+            '-' if chars.next_if(|c| c.1 == '[').is_some() => tokens.push((
+                Token::EffectArrow,
+                Span {
+                    start,
+                    end: start + 2,
+                },
+            )),
+            '=' if chars.next_if(|c| c.1 == '>').is_some() => tokens.push((
+                Token::FatArrow,
+                Span {
+                    start,
+                    end: start + 2,
+                },
+            )),
+            ':' if chars.next_if(|c| c.1 == '=').is_some() => tokens.push((
+                Token::Assign,
+                Span {
+                    start,
+                    end: start + 2,
+                },
+            )),
+            '/' if chars.next_if(|c| c.1 == '\\').is_some() => tokens.push((
+                Token::And,
+                Span {
+                    start,
+                    end: start + 2,
+                },
+            )),
+            '\\' if chars.next_if(|c| c.1 == '/').is_some() => tokens.push((
+                Token::Or,
+                Span {
+                    start,
+                    end: start + 2,
+                },
+            )),
+            '\\' => tokens.push(single(Token::Lambda)),
+            '.' => tokens.push(single(Token::Dot)),
+            ':' => tokens.push(single(Token::Colon)),
+            ',' => tokens.push(single(Token::Comma)),
+            '(' => tokens.push(single(Token::Open)),
+            ')' => tokens.push(single(Token::Close)),
+            ']' => tokens.push(single(Token::CloseBracket)),
+            '*' => tokens.push(single(Token::Star)),
+            '=' => tokens.push(single(Token::Equals)),
+            '!' => tokens.push(single(Token::Bang)),
+            c if c == '?' || c.is_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) =
+                    chars.next_if(|c| c.1.is_alphanumeric() || "_'".contains(c.1))
+                {
+                    end = i + c.len_utf8();
+                }
+                let text = &s[start..end];
+                let token = match text.strip_prefix('?') {
+                    Some(name) => Token::Hole(name.into()),
+                    None => Token::Ident(text.into()),
+                };
+                tokens.push((token, Span { start, end }));
+            }
+            c => {
+                let span = Span {
+                    start,
+                    end: start + c.len_utf8(),
+                };
+                return error(span, format!("unexpected character '{}'", c));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+const KEYWORDS: &[&str] = &[
+    "def", "type", "split", "as", "in", "ref", "raise", "try", "with",
+];
+
+struct Parser {
+    tokens: Vec<(Token, Span)>,
+    pos: usize,
+    /// The end of the source, where a missing token is reported.
+    end: usize,
+    /// The names of the aliases declared anywhere in the source
+    /// and the parameters of the alias being parsed.
+    type_names: Vec<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.0)
+    }
+
+    fn span(&self) -> Span {
+        match self.tokens.get(self.pos) {
+            Some(t) => t.1,
+            None => Span {
+                start: self.end,
+                end: self.end,
+            },
+        }
+    }
+
+    /// The span of the last consumed token.
+    fn last(&self) -> Span {
+        self.tokens[self.pos - 1].1
+    }
+
+    fn keyword(&self, k: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(i)) if i == k)
+    }
+
+    fn expect(&mut self, t: Token, what: &str) -> Result<Span, Diagnostic> {
+        if self.peek() == Some(&t) {
+            self.pos += 1;
+            return Ok(self.last());
+        }
+        error(self.span(), format!("expected {}", what))
+    }
+
+    fn expect_keyword(&mut self, k: &str) -> Result<(), Diagnostic> {
+        if self.keyword(k) {
+            self.pos += 1;
+            return Ok(());
+        }
+        error(self.span(), format!("expected '{}'", k))
+    }
+
+    fn name(&mut self) -> Result<Binder, Diagnostic> {
+        match self.peek() {
+            Some(Token::Ident(name)) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(Binder {
+                    name,
+                    span: self.last(),
+                })
+            }
+            _ => error(self.span(), "expected a name".into()),
+        }
+    }
+
+    /// type := union ('->' type)? | union '-[' name (',' name)* ']' '->' type
+    fn type_(&mut self) -> Result<Type, Diagnostic> {
+        let a = self.union()?;
+        let effects = match self.peek() {
+            Some(&Token::Arrow) => Effects::pure(),
+            Some(&Token::EffectArrow) => {
+                self.pos += 1;
+                let mut effects = Effects::pure().with(&self.name()?.name);
+                while self.peek() == Some(&Token::Comma) {
+                    self.pos += 1;
+                    effects = effects.with(&self.name()?.name);
+                }
+                self.expect(Token::CloseBracket, "']'")?;
+                effects
+            }
+            _ => return Ok(a),
+        };
+        self.expect(Token::Arrow, "'->'")?;
+        Ok(Type::Fn(Box::new(a), Box::new(self.type_()?), effects))
+    }
+
+    /// union := inter ('\/' inter)*
+    fn union(&mut self) -> Result<Type, Diagnostic> {
+        let mut a = self.intersection()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            a = Type::Or(Box::new(a), Box::new(self.intersection()?));
+        }
+        Ok(a)
+    }
+
+    /// inter := prod ('/\' prod)*
+    fn intersection(&mut self) -> Result<Type, Diagnostic> {
+        let mut a = self.product()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            a = Type::And(Box::new(a), Box::new(self.product()?));
+        }
+        Ok(a)
+    }
+
+    /// prod := tapp ('*' tapp)*
+    fn product(&mut self) -> Result<Type, Diagnostic> {
+        let mut a = self.type_app()?;
+        while self.peek() == Some(&Token::Star) {
+            self.pos += 1;
+            a = Type::Sum(Box::new(a), Box::new(self.type_app()?));
+        }
+        Ok(a)
+    }
+
+    /// tapp := tatom tatom*
+    fn type_app(&mut self) -> Result<Type, Diagnostic> {
+        let mut f = self.type_atom()?;
+        loop {
+            match self.peek() {
+                Some(Token::Ident(i)) if !KEYWORDS.contains(&i.as_str()) => {}
+                Some(&Token::Open) => {}
+                _ => return Ok(f),
+            }
+            f = Type::App(Box::new(f), Box::new(self.type_atom()?));
+        }
+    }
+
+    /// tatom := Foo | Bar | Baz | Unit | Dyn | Name | Ref tatom | '(' type ')'
+    fn type_atom(&mut self) -> Result<Type, Diagnostic> {
+        let span = self.span();
+        let t = match self.peek() {
+            Some(Token::Ident(name)) => match name.as_str() {
+                "Foo" => Type::Foo,
+                "Bar" => Type::Bar,
+                "Baz" => Type::Baz,
+                "Unit" => Type::Unit,
+                "Dyn" => Type::Dyn,
+                "Ref" => {
+                    self.pos += 1;
+                    return Ok(Type::Ref(Box::new(self.type_atom()?)));
+                }
+                // resolved once all aliases are known
+                _ if self.type_names.contains(name) => Type::Var(name.clone()),
+                _ => return error(span, format!("unknown type '{}'", name)),
+            },
+            Some(&Token::Open) => {
+                self.pos += 1;
+                let t = self.type_()?;
+                self.expect(Token::Close, "')'")?;
+                return Ok(t);
+            }
+            _ => return error(span, "expected a type".into()),
+        };
+        self.pos += 1;
+        Ok(t)
+    }
+
+    /// An optional annotation ': type'.
+    fn annotation(&mut self) -> Result<Option<Type>, Diagnostic> {
+        if self.peek() == Some(&Token::Colon) {
+            self.pos += 1;
+            return Ok(Some(self.type_()?));
+        }
+        Ok(None)
+    }
+
+    /// term := '\' name+ '.' term | '\' name ':' type '.' term
+    ///       | 'split' term 'as' '(' name (':' type)? ',' name (':' type)? ')' 'in' term
+    ///       | 'try' term 'with' name '=>' term
+    ///       | app (':=' term)?
+    fn term(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.span();
+        if self.peek() == Some(&Token::Lambda) {
+            self.pos += 1;
+            let mut binders = vec![self.name()?];
+            let annotation = self.annotation()?;
+            while annotation.is_none() && self.peek() != Some(&Token::Dot) {
+                binders.push(self.name()?);
+            }
+            self.expect(Token::Dot, "'.'")?;
+            let body = self.term()?;
+            let mut node = body;
+            for (i, x) in binders.into_iter().enumerate().rev() {
+                let span = if i == 0 {
+                    start.to(node.span)
+                } else {
+                    x.span.to(node.span)
+                };
+                node = Node {
+                    expr: Expr::Lam(x, annotation.clone(), Box::new(node)),
+                    span,
+                };
+            }
+            return Ok(node);
+        }
+        if self.keyword("split") {
+            self.pos += 1;
+            let p = self.term()?;
+            self.expect_keyword("as")?;
+            self.expect(Token::Open, "'('")?;
+            let x = self.name()?;
+            let a = self.annotation()?;
+            self.expect(Token::Comma, "','")?;
+            let y = self.name()?;
+            let b = self.annotation()?;
+            self.expect(Token::Close, "')'")?;
+            self.expect_keyword("in")?;
+            let body = self.term()?;
+            let span = start.to(body.span);
+            return Ok(Node {
+                expr: Expr::Split(Box::new(p), x, a, y, b, Box::new(body)),
+                span,
+            });
+        }
+        if self.keyword("try") {
+            self.pos += 1;
+            let body = self.term()?;
+            self.expect_keyword("with")?;
+            let x = self.name()?;
+            self.expect(Token::FatArrow, "'=>'")?;
+            let handler = self.term()?;
+            let span = start.to(handler.span);
+            return Ok(Node {
+                expr: Expr::Try(Box::new(body), x.name, Box::new(handler)),
+                span,
+            });
+        }
+        let cell = self.app()?;
+        if self.peek() == Some(&Token::Assign) {
+            self.pos += 1;
+            let value = self.term()?;
+            let span = cell.span.to(value.span);
+            return Ok(Node {
+                expr: Expr::Assign(Box::new(cell), Box::new(value)),
+                span,
+            });
+        }
+        Ok(cell)
+    }
+
+    fn starts_prefix(&self) -> bool {
+        match self.peek() {
+            Some(Token::Ident(i)) => i == "ref" || i == "raise" || !KEYWORDS.contains(&i.as_str()),
+            Some(&Token::Hole(_)) | Some(&Token::Open) | Some(&Token::Bang) => true,
+            _ => false,
+        }
+    }
+
+    /// app := prefix prefix*
+    fn app(&mut self) -> Result<Node, Diagnostic> {
+        let mut f = self.prefix()?;
+        while self.starts_prefix() {
+            let a = self.prefix()?;
+            let span = f.span.to(a.span);
+            f = Node {
+                expr: Expr::App(Box::new(f), Box::new(a)),
+                span,
+            };
+        }
+        Ok(f)
+    }
+
+    /// prefix := '!' prefix | 'ref' prefix | 'raise' name | atom
+    fn prefix(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.span();
+        if self.keyword("raise") {
+            self.pos += 1;
+            let x = self.name()?;
+            return Ok(Node {
+                expr: Expr::Raise(x.name),
+                span: start.to(x.span),
+            });
+        }
+        if self.peek() == Some(&Token::Bang) || self.keyword("ref") {
+            let deref = self.peek() == Some(&Token::Bang);
+            self.pos += 1;
+            let e = self.prefix()?;
+            let span = start.to(e.span);
+            let expr = match deref {
+                true => Expr::Deref(Box::new(e)),
+                false => Expr::Ref(Box::new(e)),
+            };
+            return Ok(Node { expr, span });
+        }
+        self.atom()
+    }
+
+    /// atom := name | hole | '(' ')' | '(' term ')' | '(' term ',' term ')' | '(' term ':' type ')'
+    fn atom(&mut self) -> Result<Node, Diagnostic> {
+        let start = self.span();
+        match self.peek().cloned() {
+            Some(Token::Hole(name)) => {
+                self.pos += 1;
+                Ok(Node {
+                    expr: Expr::Hole(name),
+                    span: start,
+                })
+            }
+            Some(Token::Ident(_)) => {
+                let x = self.name()?;
+                Ok(Node {
+                    expr: Expr::Var(x.name),
+                    span: x.span,
+                })
+            }
+            Some(Token::Open) => {
+                self.pos += 1;
+                if self.peek() == Some(&Token::Close) {
+                    self.pos += 1;
+                    return Ok(Node {
+                        expr: Expr::Unit,
+                        span: start.to(self.last()),
+                    });
+                }
+                let a = self.term()?;
+                let expr = match self.peek() {
+                    Some(&Token::Comma) => {
+                        self.pos += 1;
+                        Expr::Pair(Box::new(a), Box::new(self.term()?))
+                    }
+                    Some(&Token::Colon) => {
+                        self.pos += 1;
+                        Expr::Ann(Box::new(a), self.type_()?)
+                    }
+                    _ => {
+                        self.expect(Token::Close, "')'")?;
+                        return Ok(a);
+                    }
+                };
+                let end = self.expect(Token::Close, "')'")?;
+                Ok(Node {
+                    expr,
+                    span: start.to(end),
+                })
+            }
+            _ => error(start, "expected a term".into()),
+        }
+    }
+
+    /// def := 'def' name ':' type '=' term
+    fn def(&mut self) -> Result<Def, Diagnostic> {
+        self.expect_keyword("def")?;
+        let name = self.name()?;
+        self.expect(Token::Colon, "':'")?;
+        let t = self.type_()?;
+        self.expect(Token::Equals, "'='")?;
+        let body = self.term()?;
+        Ok(Def { name, t, body })
+    }
+
+    /// tdef := 'type' name name* '=' type
+    fn type_def(&mut self) -> Result<TypeDef, Diagnostic> {
+        self.expect_keyword("type")?;
+        let name = self.name()?;
+        let mut params = Vec::new();
+        while self.peek() != Some(&Token::Equals) {
+            params.push(self.name()?);
+        }
+        self.pos += 1;
+        let outer = self.type_names.len();
+        self.type_names
+            .extend(params.iter().map(|p| p.name.clone()));
+        let body = self.type_();
+        self.type_names.truncate(outer);
+        Ok(TypeDef {
+            name,
+            params,
+            body: body?,
+        })
+    }
+
+    /// item := def | tdef
+    fn item(&mut self) -> Result<Item, Diagnostic> {
+        match self.keyword("type") {
+            true => Ok(Item::Type(self.type_def()?)),
+            false => Ok(Item::Def(self.def()?)),
+        }
+    }
+
+    fn starts_item(&self) -> bool {
+        self.keyword("def") || self.keyword("type")
+    }
+}
+
+/// Parse a source file. After an error the parser skips
+/// to the next definition, so every error is reported.
+pub fn parse(source: &str) -> (Vec<Item>, Vec<Diagnostic>) {
+    let tokens = match tokenize(source) {
+        Ok(tokens) => tokens,
+        Err(e) => return (Vec::new(), vec![e]),
+    };
+    // aliases may be used before their declaration
+    let type_names = tokens
+        .windows(2)
+        .filter_map(|w| match (&w[0].0, &w[1].0) {
+            (Token::Ident(k), Token::Ident(name)) if k == "type" => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: source.len(),
+        type_names,
+    };
+    let (mut items, mut errors) = (Vec::new(), Vec::new());
+    while parser.peek().is_some() {
+        match parser.item() {
+            Ok(item) => {
+                if parser.peek().is_some() && !parser.starts_item() {
+                    errors.push(Diagnostic {
+                        span: parser.span(),
+                        message: "expected 'def' or 'type'".into(),
+                    });
+                } else {
+                    items.push(item);
+                    continue;
+                }
+            }
+            Err(e) => errors.push(e),
+        }
+        parser.pos += 1;
+        while parser.peek().is_some() && !parser.starts_item() {
+            parser.pos += 1;
+        }
+    }
+    (items, errors)
+}
+
+/// Resolve the aliases of a source file. The aliases on a cycle and
+/// the ones referring to them are reported and left out, their names
+/// are returned together with the aliases.
+fn resolve_aliases(types: &[TypeDef], diagnostics: &mut Vec<Diagnostic>) -> (Aliases, Vec<String>) {
+    let mut declarations: Vec<(String, Type)> = Vec::new();
+    for t in types {
+        if declarations.iter().any(|d| d.0 == t.name.name) {
+            diagnostics.push(Diagnostic {
+                span: t.name.span,
+                message: format!("'{}' is already defined", t.name.name),
+            });
+            continue;
+        }
+        declarations.push((t.name.name.clone(), t.definition()));
+    }
+    let span = |name: &str| {
+        types
+            .iter()
+            .find(|t| t.name.name == name)
+            .unwrap()
+            .name
+            .span
+    };
+    let mut removed: Vec<String> = Vec::new();
+    loop {
+        match Aliases::new(&declarations) {
+            Ok(aliases) => return (aliases, removed),
+            Err(alias::Error::Cycle(cycle)) => {
+                diagnostics.push(Diagnostic {
+                    span: span(&cycle[0]),
+                    message: format!("{}", alias::Error::Cycle(cycle.clone())),
+                });
+                declarations.retain(|d| !cycle.contains(&d.0));
+                removed.extend(cycle);
+            }
+            Err(alias::Error::Duplicate(_)) => unreachable!("duplicates are left out"),
+        }
+        // the aliases using a removed one have no expansion either
+        while let Some(i) = declarations
+            .iter()
+            .position(|d| removed.iter().any(|r| free_in_type(r, &d.1)))
+        {
+            let (name, definition) = declarations.remove(i);
+            let cyclic = removed
+                .iter()
+                .find(|r| free_in_type(r, &definition))
+                .unwrap();
+            diagnostics.push(Diagnostic {
+                span: span(&name),
+                message: format!("'{}' refers to the cyclic alias '{}'", name, cyclic),
+            });
+            removed.push(name);
+        }
+    }
+}
+
+/// A hole together with the type it has to fill
+/// and the variables in scope.
+#[derive(Debug, Clone)]
+pub struct Hole {
+    pub name: String,
+    pub span: Span,
+    pub goal: Type,
+    pub context: Vec<(String, Type)>,
+}
+
+/// Everything known about a source file after checking it.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    /// The checked definitions, without the ones containing
+    /// errors or holes.
+    pub defs: Vec<(String, Term, Type)>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// The type of every checked subterm.
+    pub types: Vec<(Span, Type)>,
+    /// The binder of every variable occurrence.
+    pub bindings: Vec<(Span, Span)>,
+    pub holes: Vec<Hole>,
+}
+
+impl Analysis {
+    /// The type of the smallest subterm around an offset.
+    pub fn type_at(&self, offset: usize) -> Option<&(Span, Type)> {
+        self.types
+            .iter()
+            .filter(|t| t.0.contains(offset))
+            .min_by_key(|t| t.0.end - t.0.start)
+    }
+
+    /// The checked definition `name` as a closed term, the earlier
+    /// definitions it refers to are substituted into it.
+    pub fn program(&self, name: &str) -> Option<Term> {
+        let i = self.defs.iter().position(|d| d.0 == name)?;
+        let term = self.defs[..i]
+            .iter()
+            .rev()
+            .fold(self.defs[i].1.clone(), |t, d| subst(&t, &d.0, &d.1));
+        Some(term)
+    }
+
+    /// The binder of the variable at an offset.
+    pub fn binder_at(&self, offset: usize) -> Option<Span> {
+        self.bindings
+            .iter()
+            .find(|b| b.0.contains(offset))
+            .map(|b| b.1)
+    }
+}
+
+/// The variables in scope with the place they are bound.
+type Env = Vec<(String, Type, Span)>;
+
+struct Elab<'a> {
+    source: &'a str,
+    analysis: &'a mut Analysis,
+    aliases: &'a Aliases,
+    /// The aliases which have no expansion.
+    cyclic: &'a [String],
+    /// The exceptions the term being elaborated may raise.
+    effects: Effects,
+}
+
+impl<'a> Elab<'a> {
+    fn fail<T>(&mut self, span: Span, message: String) -> Option<T> {
+        self.analysis.diagnostics.push(Diagnostic { span, message });
+        None
+    }
+
+    /// Resolve the aliases within a type written at `span`
+    /// and test if it is the type of a term.
+    fn resolve(&mut self, span: Span, t: &Type) -> Option<Type> {
+        if let Some(name) = self.cyclic.iter().find(|c| free_in_type(c, t)) {
+            return self.fail(span, format!("'{}' is a cyclic alias", name));
+        }
+        let t = self.aliases.resolve(t);
+        if !judgment(&t) {
+            return self.fail(span, format!("{} is not the type of a term", t));
+        }
+        Some(t)
+    }
+
+    /// Elaborate a part of a term which may raise other exceptions.
+    fn with_effects<T>(&mut self, effects: Effects, f: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = std::mem::replace(&mut self.effects, effects);
+        let result = f(self);
+        self.effects = outer;
+        result
+    }
+
+    /// Elaborate one of several alternatives, forgetting everything
+    /// it recorded if it fails.
+    fn attempt<T>(&mut self, f: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        let a = &self.analysis;
+        let lengths = (
+            a.diagnostics.len(),
+            a.types.len(),
+            a.bindings.len(),
+            a.holes.len(),
+        );
+        let result = f(self);
+        if result.is_none() {
+            let a = &mut self.analysis;
+            a.diagnostics.truncate(lengths.0);
+            a.types.truncate(lengths.1);
+            a.bindings.truncate(lengths.2);
+            a.holes.truncate(lengths.3);
+        }
+        result
+    }
+
+    fn typed(&mut self, span: Span, t: &Type) {
+        self.analysis.types.push((span, t.clone()));
+    }
+
+    /// Cast a term of the subterm at `span` between consistent types,
+    /// labelled with the place of the subterm.
+    fn cast(&self, term: Term, from: &Type, to: &Type, span: Span) -> Term {
+        let (line, column) = line_column(self.source, span.start);
+        let (from, to) = (normalize_type(from), normalize_type(to));
+        Term::Cast(Box::new(term), from, to, format!("{}:{}", line, column))
+    }
+
+    fn bind(env: &Env, x: &Binder, t: &Type) -> Env {
+        let mut env = env.clone();
+        env.push((x.name.clone(), t.clone(), x.span));
+        env
+    }
+
+    fn infer(&mut self, env: &Env, e: &Node) -> Option<(Term, Type)> {
+        let (term, t) = match &e.expr {
+            Expr::Var(x) => match env.iter().rev().find(|b| &b.0 == x) {
+                Some((_, t, binder)) => {
+                    self.analysis.bindings.push((e.span, *binder));
+                    (Term::Var(x.clone()), t.clone())
+                }
+                None => return self.fail(e.span, format!("unbound variable '{}'", x)),
+            },
+            Expr::Lam(x, Some(a), body) => {
+                let a = &self.resolve(x.span, a)?;
+                // the inferred function is pure
+                let env = Self::bind(env, x, a);
+                let (body, b) =
+                    self.with_effects(Effects::pure(), |elab| elab.infer(&env, body))?;
+                self.typed(x.span, a);
+                let t = Type::Fn(Box::new(a.clone()), Box::new(b), Effects::pure());
+                (Term::Lam(x.name.clone(), Box::new(body)), t)
+            }
+            Expr::Lam(x, None, _) => {
+                return self.fail(
+                    e.span,
+                    format!("can't infer the type of '{}', add an annotation", x.name),
+                )
+            }
+            Expr::App(f, a) => {
+                let (f_term, f_type) = self.infer(env, f)?;
+                match unfold(&f_type) {
+                    // the first function of an intersection which takes the argument
+                    Type::And(_, _) => {
+                        let allowed = self.effects.clone();
+                        let found = conjuncts(&f_type).into_iter().find_map(|part| match part {
+                            Type::Fn(i, o, effects) if effects.within(&allowed) => {
+                                let a = self.attempt(|elab| elab.check(env, a, &i))?;
+                                Some((Term::App(Box::new(f_term.clone()), Box::new(a), *i), *o))
+                            }
+                            _ => None,
+                        });
+                        match found {
+                            Some(app) => app,
+                            None => {
+                                let message =
+                                    format!("the argument fits no function of {}", f_type);
+                                return self.fail(a.span, message);
+                            }
+                        }
+                    }
+                    Type::Fn(i, o, effects) if effects.within(&self.effects) => {
+                        let a = self.check(env, a, &i)?;
+                        (Term::App(Box::new(f_term), Box::new(a), *i), *o)
+                    }
+                    Type::Fn(_, _, effects) => {
+                        let message = format!(
+                            "this call may raise {}, which is not allowed here",
+                            effects.without(&self.effects)
+                        );
+                        return self.fail(e.span, message);
+                    }
+                    // a dynamic function is cast to one taking and returning `Dyn`
+                    Type::Dyn => {
+                        let fun =
+                            Type::Fn(Box::new(Type::Dyn), Box::new(Type::Dyn), Effects::pure());
+                        let f_term = self.cast(f_term, &f_type, &fun, f.span);
+                        let a = self.check(env, a, &Type::Dyn)?;
+                        (
+                            Term::App(Box::new(f_term), Box::new(a), Type::Dyn),
+                            Type::Dyn,
+                        )
+                    }
+                    _ => {
+                        return self.fail(f.span, format!("expected a function, found {}", f_type))
+                    }
+                }
+            }
+            Expr::Pair(a, b) => {
+                let (a, ta) = self.infer(env, a)?;
+                let (b, tb) = self.infer(env, b)?;
+                (
+                    Term::Pair(Box::new(a), Box::new(b)),
+                    Type::Sum(Box::new(ta), Box::new(tb)),
+                )
+            }
+            Expr::Split(p, x, a, y, b, body) => {
+                let (p, ta, tb) = self.split(env, p, a, b)?;
+                let env = Self::bind(&Self::bind(env, x, &ta), y, &tb);
+                let (body, t) = self.infer(&env, body)?;
+                let (x, y) = (x.name.clone(), y.name.clone());
+                (Term::Split(Box::new(p), x, ta, y, tb, Box::new(body)), t)
+            }
+            Expr::Unit => (Term::Unit, Type::Unit),
+            Expr::Ann(inner, t) => {
+                let t = self.resolve(e.span, t)?;
+                (self.check(env, inner, &t)?, t)
+            }
+            Expr::Hole(name) => {
+                return self.fail(
+                    e.span,
+                    format!("can't infer the goal of the hole ?{}", name),
+                )
+            }
+            Expr::Ref(init) => {
+                let (init, t) = self.infer(env, init)?;
+                (Term::Ref(Box::new(init), t.clone()), Type::Ref(Box::new(t)))
+            }
+            Expr::Deref(cell) => {
+                let (cell, t) = self.infer(env, cell)?;
+                match unfold(&t) {
+                    Type::Ref(t) => (Term::Deref(Box::new(cell)), *t),
+                    _ => return self.fail(e.span, format!("expected a reference, found {}", t)),
+                }
+            }
+            Expr::Assign(cell, value) => {
+                let (c, t) = self.infer(env, cell)?;
+                match unfold(&t) {
+                    Type::Ref(t) => {
+                        let value = self.check(env, value, &t)?;
+                        (Term::Assign(Box::new(c), Box::new(value), *t), Type::Unit)
+                    }
+                    _ => return self.fail(cell.span, format!("expected a reference, found {}", t)),
+                }
+            }
+            Expr::Raise(x) => {
+                return self.fail(
+                    e.span,
+                    format!("can't infer the type of raise {}, add an annotation", x),
+                )
+            }
+            Expr::Try(body, x, handler) => {
+                let effects = self.effects.with(x);
+                let (body, t) = self.with_effects(effects, |elab| elab.infer(env, body))?;
+                let handler = self.check(env, handler, &t)?;
+                (Term::Try(Box::new(body), x.clone(), Box::new(handler)), t)
+            }
+        };
+        self.typed(e.span, &t);
+        Some((term, t))
+    }
+
+    /// Check a lambda against a function type.
+    #[allow(clippy::too_many_arguments)]
+    fn lam(
+        &mut self,
+        env: &Env,
+        x: &Binder,
+        a: &Option<Type>,
+        body: &Node,
+        i: &Type,
+        o: &Type,
+        f: &Effects,
+    ) -> Option<Term> {
+        if let Some(a) = a {
+            let a = self.resolve(x.span, a)?;
+            if !type_equality(&a, i) {
+                return self.fail(x.span, format!("expected {}, found {}", i, a));
+            }
+        }
+        self.typed(x.span, i);
+        let env = Self::bind(env, x, i);
+        let body = self.with_effects(f.clone(), |elab| elab.check(&env, body, o))?;
+        Some(Term::Lam(x.name.clone(), Box::new(body)))
+    }
+
+    /// Elaborate the scrutinee of a split, checking it
+    /// if both component types are given.
+    fn split(
+        &mut self,
+        env: &Env,
+        p: &Node,
+        a: &Option<Type>,
+        b: &Option<Type>,
+    ) -> Option<(Term, Type, Type)> {
+        let a = match a {
+            Some(a) => Some(self.resolve(p.span, a)?),
+            None => None,
+        };
+        let b = match b {
+            Some(b) => Some(self.resolve(p.span, b)?),
+            None => None,
+        };
+        if let (Some(a), Some(b)) = (&a, &b) {
+            let pair = Type::Sum(Box::new(a.clone()), Box::new(b.clone()));
+            return Some((self.check(env, p, &pair)?, a.clone(), b.clone()));
+        }
+        let (p_term, t) = self.infer(env, p)?;
+        match unfold(&t) {
+            // a dynamic pair is cast to the given component types
+            Type::Dyn => {
+                let (a, b) = (a.unwrap_or(Type::Dyn), b.unwrap_or(Type::Dyn));
+                let pair = Type::Sum(Box::new(a.clone()), Box::new(b.clone()));
+                Some((self.cast(p_term, &t, &pair, p.span), a, b))
+            }
+            Type::Sum(ta, tb) => {
+                for (given, t) in [(&a, &ta), (&b, &tb)] {
+                    match given {
+                        Some(given) if !type_equality(given, t) => {
+                            return self.fail(p.span, format!("expected {}, found {}", given, t))
+                        }
+                        _ => {}
+                    }
+                }
+                Some((p_term, *ta, *tb))
+            }
+            _ => self.fail(p.span, format!("expected a pair, found {}", t)),
+        }
+    }
+
+    fn check(&mut self, env: &Env, e: &Node, t: &Type) -> Option<Term> {
+        // a term has a union if it has one of its parts, or else by the
+        // rules below, a function has an intersection if it has both parts
+        if let Type::Or(l, r) = unfold(t) {
+            let term = self
+                .attempt(|elab| elab.check(env, e, &l))
+                .or_else(|| self.attempt(|elab| elab.check(env, e, &r)));
+            if term.is_some() {
+                return term;
+            }
+        }
+        let term = match (&e.expr, &unfold(t)) {
+            (Expr::Lam(_, _, _), Type::And(l, r)) => {
+                let (l, r) = (self.check(env, e, l), self.check(env, e, r));
+                r?;
+                l?
+            }
+            // an argument of a union may be taken apart into its cases
+            (Expr::Lam(x, a, body), Type::Fn(i, o, f)) => match unfold(i) {
+                Type::Or(l, r) => match self.attempt(|elab| elab.lam(env, x, a, body, i, o, f)) {
+                    Some(term) => term,
+                    None => {
+                        let case = |i: Box<Type>| Type::Fn(i, o.clone(), f.clone());
+                        let (l, r) = (self.check(env, e, &case(l)), self.check(env, e, &case(r)));
+                        r?;
+                        l?
+                    }
+                },
+                _ => self.lam(env, x, a, body, i, o, f)?,
+            },
+            // a term of a dynamic type is checked at its ground type
+            (Expr::Lam(_, _, _), Type::Dyn) => {
+                let fun = Type::Fn(Box::new(Type::Dyn), Box::new(Type::Dyn), Effects::pure());
+                let term = self.check(env, e, &fun)?;
+                self.cast(term, &fun, t, e.span)
+            }
+            (Expr::Lam(_, _, _), _) => {
+                return self.fail(e.span, format!("expected {}, found a function", t))
+            }
+            (Expr::Pair(a, b), Type::Sum(l, r)) => {
+                // check both components to report all errors
+                let (a, b) = (self.check(env, a, l), self.check(env, b, r));
+                Term::Pair(Box::new(a?), Box::new(b?))
+            }
+            (Expr::Pair(_, _), Type::Dyn) => {
+                let pair = Type::Sum(Box::new(Type::Dyn), Box::new(Type::Dyn));
+                let term = self.check(env, e, &pair)?;
+                self.cast(term, &pair, t, e.span)
+            }
+            (Expr::Pair(_, _), _) => {
+                return self.fail(e.span, format!("expected {}, found a pair", t))
+            }
+            (Expr::Split(p, x, a, y, b, body), _) => {
+                let (p, ta, tb) = self.split(env, p, a, b)?;
+                let env = Self::bind(&Self::bind(env, x, &ta), y, &tb);
+                let body = self.check(&env, body, t)?;
+                let (x, y) = (x.name.clone(), y.name.clone());
+                Term::Split(Box::new(p), x, ta, y, tb, Box::new(body))
+            }
+            (Expr::Hole(name), _) => {
+                self.analysis.holes.push(Hole {
+                    name: name.clone(),
+                    span: e.span,
+                    goal: t.clone(),
+                    context: env.iter().map(|b| (b.0.clone(), b.1.clone())).collect(),
+                });
+                // the definition is incomplete, but this is no error
+                self.typed(e.span, t);
+                return None;
+            }
+            (Expr::Ref(init), Type::Ref(inner)) => {
+                Term::Ref(Box::new(self.check(env, init, inner)?), (**inner).clone())
+            }
+            (Expr::Raise(x), _) if self.effects.contains(x) => Term::Raise(x.clone()),
+            (Expr::Raise(x), _) => {
+                return self.fail(
+                    e.span,
+                    format!("the exception {} may not be raised here", x),
+                )
+            }
+            (Expr::Try(body, x, handler), _) => {
+                let effects = self.effects.with(x);
+                let body = self.with_effects(effects, |elab| elab.check(env, body, t));
+                let handler = self.check(env, handler, t);
+                Term::Try(Box::new(body?), x.clone(), Box::new(handler?))
+            }
+            _ => {
+                let (term, found) = self.infer(env, e)?;
+                if subtype(&found, t) {
+                    term
+                } else if consistent(&found, t) {
+                    self.cast(term, &found, t, e.span)
+                } else {
+                    return self.fail(e.span, format!("expected {}, found {}", t, found));
+                }
+            }
+        };
+        self.typed(e.span, t);
+        Some(term)
+    }
+}
+
+/// The parts of a nested intersection, from left to right.
+fn conjuncts(t: &Type) -> Vec<Type> {
+    match unfold(t) {
+        Type::And(l, r) => {
+            let mut parts = conjuncts(&l);
+            parts.extend(conjuncts(&r));
+            parts
+        }
+        t => vec![t],
+    }
+}
+
+/// Parse and check a source file.
+pub fn analyze(source: &str) -> Analysis {
+    let (items, errors) = parse(source);
+    let mut analysis = Analysis {
+        diagnostics: errors,
+        ..Analysis::default()
+    };
+    let (mut types, mut defs) = (Vec::new(), Vec::new());
+    for item in items {
+        match item {
+            Item::Type(t) => types.push(t),
+            Item::Def(def) => defs.push(def),
+        }
+    }
+    let (aliases, cyclic) = resolve_aliases(&types, &mut analysis.diagnostics);
+    let (mut env, mut ctx) = (Env::new(), Context::Empty());
+    for def in defs {
+        if env.iter().any(|b| b.0 == def.name.name) {
+            analysis.diagnostics.push(Diagnostic {
+                span: def.name.span,
+                message: format!("'{}' is already defined", def.name.name),
+            });
+            continue;
+        }
+        let mut elab = Elab {
+            source,
+            analysis: &mut analysis,
+            aliases: &aliases,
+            cyclic: &cyclic,
+            effects: Effects::pure(),
+        };
+        let t = match elab.resolve(def.name.span, &def.t) {
+            Some(t) => t,
+            None => continue,
+        };
+        let term = elab.check(&env, &def.body, &t);
+        elab.typed(def.name.span, &t);
+        if let Some(term) = term {
+            if judgment_check(&ctx, &term, &t) {
+                analysis.defs.push((def.name.name.clone(), term, t.clone()));
+            } else {
+                analysis.diagnostics.push(Diagnostic {
+                    span: def.body.span,
+                    message: "the elaborated term is rejected by the checker".into(),
+                });
+            }
+        }
+        // later definitions may refer to this one even if it is incomplete
+        env.push((def.name.name.clone(), t.clone(), def.name.span));
+        ctx = Context::Snoc(Box::new(ctx), def.name.name, t);
+    }
+    analysis
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::eval::{run, Store};
+    use super::*;
+
+    fn messages(a: &Analysis) -> Vec<&str> {
+        a.diagnostics.iter().map(|d| d.message.as_str()).collect()
+    }
+
+    #[test]
+    fn parse_and_check_definitions() {
+        let source = "
+            -- the examples from main
+            def identity : Foo -> Foo = \\x. x
+            def fst : Foo * Bar -> Foo = \\p. split p as (x, y) in x
+            def const : Foo -> Bar -> Foo = \\x y. x
+            def apply : (Foo -> Bar) -> Foo -> Bar = \\f x. f x
+            def swap : Foo * Bar -> Bar * Foo = \\p. split p as (x : Foo, y) in (y, x)
+            def twice : Foo -> Foo = \\x. identity (identity x)
+            def cell : Unit = (\\r : Ref Foo. r := !r) (ref (identity (fst (?a, ?b))))
+        ";
+        let a = analyze(source);
+        assert!(a.diagnostics.is_empty(), "{:?}", a.diagnostics);
+        let names: Vec<&str> = a.defs.iter().map(|d| d.0.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["identity", "fst", "const", "apply", "swap", "twice"]
+        );
+        // the hole goals
+        let goals: Vec<String> = a.holes.iter().map(|h| format!("{}", h.goal)).collect();
+        assert_eq!(goals, vec!["Foo", "Bar"]);
+        assert_eq!(a.holes[0].context.len(), 6);
+    }
+
+    #[test]
+    fn located_type_errors() {
+        let source = "def f : Foo -> Bar = \\x. x\ndef g : Foo = (f, f)\ndef h : Foo = y";
+        let a = analyze(source);
+        assert_eq!(
+            messages(&a),
+            vec![
+                "expected Bar, found Foo",
+                "expected Foo, found a pair",
+                "unbound variable 'y'"
+            ]
+        );
+        // the error points at the body of the lambda
+        assert_eq!(
+            &source[a.diagnostics[0].span.start..a.diagnostics[0].span.end],
+            "x"
+        );
+        assert!(a.defs.is_empty());
+    }
+
+    #[test]
+    fn parse_errors_skip_to_the_next_definition() {
+        let source = "def f : Foo -> = \\x. x\ndef g : Foo -> Foo = \\x. x\ndef h : Quux = ()";
+        let a = analyze(source);
+        assert_eq!(messages(&a), vec!["expected a type", "unknown type 'Quux'"]);
+        assert_eq!(a.defs.len(), 1);
+    }
+
+    #[test]
+    fn type_aliases() {
+        let source = "
+            def swap : Swap Foo Bar = \\p. split p as (x, y) in (y, x)
+            type Swap A B = Pair A B -> Pair B A
+            type Pair A B = A * B
+            def pair : Pair Foo Bar -> Foo * Bar = \\p : Pair Foo Bar. p
+        ";
+        let a = analyze(source);
+        assert!(a.diagnostics.is_empty(), "{:?}", a.diagnostics);
+        assert_eq!(a.defs.len(), 2);
+        // the printer keeps the names
+        assert_eq!(format!("{}", a.defs[0].2), "Swap Foo Bar");
+        let p = source.find("(y, x)").unwrap();
+        assert_eq!(format!("{}", a.type_at(p).unwrap().1), "Pair Bar Foo");
+        assert_eq!(format!("{}", a.type_at(p + 4).unwrap().1), "Foo");
+    }
+
+    #[test]
+    fn exceptions() {
+        let source = "
+            def fail : Foo -[Fail]-> Foo = \\x. raise Fail
+            def safe : Foo -> Foo = \\x. try fail x with Fail => x
+            def unsafe : Foo -> Foo = \\x. fail x
+            def escape : Foo -> Foo = \\x. raise Fail
+        ";
+        let a = analyze(source);
+        assert_eq!(
+            messages(&a),
+            vec![
+                "this call may raise Fail, which is not allowed here",
+                "the exception Fail may not be raised here",
+            ]
+        );
+        assert_eq!(a.defs.len(), 2);
+        assert_eq!(format!("{}", a.defs[0].2), "Foo -[Fail]-> Foo");
+        let p = source.find("fail x with").unwrap();
+        assert_eq!(format!("{}", a.type_at(p).unwrap().1), "Foo -[Fail]-> Foo");
+    }
+
+    #[test]
+    fn intersections_and_unions() {
+        let source = "
+            def id : (Foo -> Foo) /\\ (Bar -> Bar) = \\x. x
+            def foo : Foo -> Foo = \\x. id x
+            def bar : Bar -> Bar = \\x. id x
+            def dup : Foo \\/ Bar -> Foo * Foo \\/ Bar * Bar = \\x. (x, x)
+            def baz : Baz -> Baz = \\x. id x
+            def pick : Baz -> Foo \\/ Bar = \\x. x
+        ";
+        let a = analyze(source);
+        assert_eq!(
+            messages(&a),
+            vec![
+                "the argument fits no function of (Foo -> Foo) /\\ (Bar -> Bar)",
+                "expected Foo \\/ Bar, found Baz",
+            ]
+        );
+        assert_eq!(a.defs.len(), 4);
+        assert_eq!(
+            format!("{}", a.defs[3].2),
+            "Foo \\/ Bar -> (Foo * Foo) \\/ (Bar * Bar)"
+        );
+    }
+
+    #[test]
+    fn cyclic_aliases() {
+        let source = "
+            type A = Foo * B
+            type B = A -> Foo
+            type C = Unit * B
+            type D = D
+            def f : C = ((), \\a. a)
+            def g : Pair Foo = ()
+            type Pair X Y = X * Y
+        ";
+        let a = analyze(source);
+        assert_eq!(
+            messages(&a),
+            vec![
+                "type alias cycle A -> B -> A",
+                "'C' refers to the cyclic alias 'B'",
+                "type alias cycle D -> D",
+                "'C' is a cyclic alias",
+                "Pair Foo is not the type of a term",
+            ]
+        );
+        assert!(a.defs.is_empty());
+    }
+
+    #[test]
+    fn types_and_bindings() {
+        let source = "def k : Foo -> Bar -> Foo = \\x y. x";
+        let a = analyze(source);
+        let use_of_x = source.rfind('x').unwrap();
+        let binder = a.binder_at(use_of_x).unwrap();
+        assert_eq!(binder.start, source.find("x y").unwrap());
+        assert_eq!(format!("{}", a.type_at(use_of_x).unwrap().1), "Foo");
+        let lam = source.find("y.").unwrap();
+        assert_eq!(format!("{}", a.type_at(lam).unwrap().1), "Bar");
+    }
+
+    /// Run a definition of a source without errors.
+    fn run_def(source: &str, name: &str) -> Result<String, String> {
+        let a = analyze(source);
+        assert!(a.diagnostics.is_empty(), "{:?}", a.diagnostics);
+        run(&a.program(name).unwrap(), &mut Store::new())
+            .map(|v| v.to_string())
+            .map_err(|b| b.to_string())
+    }
+
+    #[test]
+    fn casts_blame_their_place_in_the_source() {
+        let source = "def ok : Unit = (\\x. x : Dyn -> Unit) ()
+def bad : Foo = (\\x. x : Dyn -> Foo) ()";
+        assert_eq!(run_def(source, "ok"), Ok("()".into()));
+        // the body x on the second line is cast to Foo
+        assert_eq!(
+            run_def(source, "bad"),
+            Err("blame 2:22: expected Foo, found Unit".into())
+        );
+        assert_eq!(line_column(source, source.rfind("x :").unwrap()), (2, 22));
+    }
+
+    #[test]
+    fn dynamic_functions_and_pairs() {
+        let source = "
+            def swap : Dyn -> Dyn = \\p. split p as (x, y) in (y, x)
+            def call : Dyn -> Dyn = \\f. f ()
+            def pair : Unit = split swap ((), (\\x. x : Dyn -> Dyn)) as (f, u) in call f
+            def broken : Unit = split swap ((), ()) as (f, u) in call f
+        ";
+        assert_eq!(run_def(source, "pair"), Ok("()".into()));
+        let a = analyze(source);
+        let label = match run(&a.program("broken").unwrap(), &mut Store::new()) {
+            Err(blame) => blame.label,
+            Ok(v) => panic!("{} is no blame", v),
+        };
+        // the function f of call is a unit
+        let f = source.find("f ()").unwrap();
+        let (line, column) = line_column(source, f);
+        assert_eq!(label, format!("{}:{}", line, column));
+        // inconsistent types are still errors
+        let a = analyze("def f : Dyn -> Foo = \\x. ()");
+        assert_eq!(messages(&a), vec!["expected Foo, found Unit"]);
+    }
+}
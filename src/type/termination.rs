@@ -0,0 +1,406 @@
+//! Termination of the recursive functions of the dependent calculus.
+//!
+//! A recursive function `fix f. \x1. ... \xn. e` is accepted by the
+//! size-change principle: every call `f a1 ... an` in `e` is summarized
+//! by a graph which tells for each argument which parameter it is
+//! smaller than or equal to. An argument is smaller than a parameter if
+//! it is the predecessor bound by `case x of ... | succ k => ...`, or
+//! the predecessor of a smaller variable. Composing the graphs along
+//! every sequence of calls gives finitely many graphs, and if each of
+//! them which stays the same when repeated decreases some parameter,
+//! no infinite chain of calls exists.
+//!
+//! This accepts structural recursion on one argument, lexicographic
+//! orders like the one of the Ackermann function and arguments which
+//! swap places, but not a call whose argument is computed.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::dependent::Expr;
+
+/// The parameter an argument is compared to and the argument,
+/// mapped to true if it is strictly smaller and false if it may be equal.
+type Graph = BTreeMap<(usize, usize), bool>;
+
+/// A recursive call and its size-change graph.
+struct Call {
+    expr: Expr,
+    graph: Graph,
+}
+
+/// A recursive function which may not terminate and the calls of
+/// a path through it which repeat without decreasing an argument.
+#[derive(Debug, Clone)]
+pub struct NonTermination {
+    pub function: String,
+    pub calls: Vec<Expr>,
+}
+
+impl fmt::Display for NonTermination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let calls: Vec<String> = self.calls.iter().map(|c| c.to_string()).collect();
+        write!(
+            f,
+            "{} may not terminate, no argument decreases along the calls {}",
+            self.function,
+            calls.join(" -> ")
+        )
+    }
+}
+
+/// Check the recursive functions of an expression.
+pub fn check(e: &Expr) -> Result<(), NonTermination> {
+    match e {
+        Expr::Fix(f, body) => {
+            check_fix(f, body)?;
+            check(body)
+        }
+        Expr::Var(_) | Expr::Universe(_) | Expr::Nat | Expr::Zero | Expr::Refl => Ok(()),
+        Expr::Lam(_, a) | Expr::Succ(a) => check(a),
+        Expr::Pi(_, a, b)
+        | Expr::Sigma(_, a, b)
+        | Expr::Split(a, _, _, b)
+        | Expr::App(a, b)
+        | Expr::Pair(a, b)
+        | Expr::Ann(a, b) => check(a).and_then(|_| check(b)),
+        Expr::Eq(a, b, c) | Expr::Transport(a, b, c) | Expr::Case(a, b, _, c) => {
+            [a, b, c].iter().try_for_each(|e| check(e))
+        }
+        Expr::NatRec(a, b, c, d) => [a, b, c, d].iter().try_for_each(|e| check(e)),
+    }
+}
+
+/// Check the calls of `fix f. body` with the size-change principle.
+fn check_fix(f: &str, body: &Expr) -> Result<(), NonTermination> {
+    // the parameters are the leading lambdas
+    let mut params = Vec::new();
+    let mut e = body;
+    while let Expr::Lam(x, inner) = e {
+        params.push(x.clone());
+        e = inner;
+    }
+    // a parameter may shadow the function
+    if params.iter().any(|x| x == f) {
+        return Ok(());
+    }
+    // a parameter shadowed by a later one is not compared
+    let mut sizes: Vec<(String, Option<(usize, bool)>)> = Vec::new();
+    for (i, x) in params.iter().enumerate() {
+        sizes.push((x.clone(), Some((i, false))));
+    }
+    let mut calls = Vec::new();
+    collect(e, f, &mut sizes, &mut calls);
+
+    // the graphs of all paths, in the order of their length
+    let mut seen: BTreeSet<Vec<((usize, usize), bool)>> = BTreeSet::new();
+    let mut paths: Vec<(Graph, Vec<usize>)> = Vec::new();
+    for (i, c) in calls.iter().enumerate() {
+        if seen.insert(c.graph.clone().into_iter().collect()) {
+            paths.push((c.graph.clone(), vec![i]));
+        }
+    }
+    let mut next = 0;
+    while next < paths.len() {
+        let (g, path) = paths[next].clone();
+        next += 1;
+        for (i, c) in calls.iter().enumerate() {
+            let h = compose(&g, &c.graph);
+            if seen.insert(h.clone().into_iter().collect()) {
+                let mut path = path.clone();
+                path.push(i);
+                paths.push((h, path));
+            }
+        }
+    }
+    match paths.iter().find(|(g, _)| {
+        compose(g, g) == *g && !(0..params.len()).any(|i| g.get(&(i, i)) == Some(&true))
+    }) {
+        Some((_, path)) => Err(NonTermination {
+            function: f.into(),
+            calls: path.iter().map(|&i| calls[i].expr.clone()).collect(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// The graph of the call `g` followed by the call `h`.
+fn compose(g: &Graph, h: &Graph) -> Graph {
+    let mut composed = Graph::new();
+    for (&(i, j), &s1) in g {
+        for (&(_, k), &s2) in h.range((j, 0)..(j + 1, 0)) {
+            let strict = composed.entry((i, k)).or_insert(false);
+            *strict = *strict || s1 || s2;
+        }
+    }
+    composed
+}
+
+/// Collect the calls of `f` in `e`. `sizes` maps variables in scope to
+/// the parameter they are compared to, the innermost binding counts.
+fn collect(
+    e: &Expr,
+    f: &str,
+    sizes: &mut Vec<(String, Option<(usize, bool)>)>,
+    calls: &mut Vec<Call>,
+) {
+    fn under(
+        bound: &[(&String, Option<(usize, bool)>)],
+        e: &Expr,
+        f: &str,
+        sizes: &mut Vec<(String, Option<(usize, bool)>)>,
+        calls: &mut Vec<Call>,
+    ) {
+        // the function itself may be shadowed
+        if bound.iter().any(|b| b.0 == f) {
+            return;
+        }
+        let n = sizes.len();
+        sizes.extend(bound.iter().map(|b| (b.0.clone(), b.1)));
+        collect(e, f, sizes, calls);
+        sizes.truncate(n);
+    }
+    let size = |sizes: &[(String, Option<(usize, bool)>)], e: &Expr| match e {
+        Expr::Var(x) => sizes.iter().rev().find(|s| s.0 == *x).and_then(|s| s.1),
+        _ => None,
+    };
+
+    // a call is the function applied to its arguments
+    let mut args = Vec::new();
+    let mut head = e;
+    while let Expr::App(g, a) = head {
+        args.push(&**a);
+        head = g;
+    }
+    args.reverse();
+    match head {
+        Expr::Var(x) if x == f => {
+            let mut graph = Graph::new();
+            for (j, a) in args.iter().enumerate() {
+                if let Some((i, strict)) = size(sizes, a) {
+                    graph.insert((i, j), strict);
+                }
+            }
+            calls.push(Call {
+                expr: e.clone(),
+                graph,
+            });
+            for a in args {
+                collect(a, f, sizes, calls);
+            }
+            return;
+        }
+        _ if !args.is_empty() => {
+            collect(head, f, sizes, calls);
+            for a in args {
+                collect(a, f, sizes, calls);
+            }
+            return;
+        }
+        _ => {}
+    }
+    match e {
+        Expr::Var(_) | Expr::Universe(_) | Expr::Nat | Expr::Zero | Expr::Refl => {}
+        Expr::Pi(x, a, b) | Expr::Sigma(x, a, b) => {
+            collect(a, f, sizes, calls);
+            under(&[(x, None)], b, f, sizes, calls);
+        }
+        Expr::Lam(x, body) | Expr::Fix(x, body) => under(&[(x, None)], body, f, sizes, calls),
+        Expr::Split(p, x, y, body) => {
+            collect(p, f, sizes, calls);
+            under(&[(x, None), (y, None)], body, f, sizes, calls);
+        }
+        // the predecessor is smaller than the number
+        Expr::Case(n, z, k, s) => {
+            collect(n, f, sizes, calls);
+            collect(z, f, sizes, calls);
+            let smaller = size(sizes, n).map(|(i, _)| (i, true));
+            under(&[(k, smaller)], s, f, sizes, calls);
+        }
+        Expr::App(a, b) | Expr::Pair(a, b) | Expr::Ann(a, b) => {
+            collect(a, f, sizes, calls);
+            collect(b, f, sizes, calls);
+        }
+        Expr::Succ(a) => collect(a, f, sizes, calls),
+        Expr::Eq(a, b, c) | Expr::Transport(a, b, c) => {
+            for e in &[a, b, c] {
+                collect(e, f, sizes, calls);
+            }
+        }
+        Expr::NatRec(a, b, c, d) => {
+            for e in &[a, b, c, d] {
+                collect(e, f, sizes, calls);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::dependent::{
+        ann, app, arrow, case, cong, eq, fix, judgment_check, lam, nat, normalize, pi, var, Context,
+    };
+    use super::*;
+
+    fn succ(e: Expr) -> Expr {
+        Expr::Succ(Box::new(e))
+    }
+
+    fn binary() -> Expr {
+        arrow(Expr::Nat, arrow(Expr::Nat, Expr::Nat))
+    }
+
+    /// plus = fix plus. \m. \n. case m of 0 => n | succ k => succ (plus k n)
+    fn plus() -> Expr {
+        let body = case(
+            var("m"),
+            var("n"),
+            "k",
+            succ(app(var("plus"), vec![var("k"), var("n")])),
+        );
+        ann(fix("plus", lam("m", lam("n", body))), binary())
+    }
+
+    /// The calls of a function which may not terminate.
+    fn rejected(e: &Expr) -> Vec<String> {
+        let error = check(e).unwrap_err();
+        error.calls.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn decreasing_recursion_is_accepted() {
+        let g = Context::new();
+        assert!(check(&plus()).is_ok());
+        assert!(judgment_check(&g, &plus(), &binary()));
+        // 2 + 2 = 4 by unfolding the recursion
+        let four = app(plus(), vec![nat(2), nat(2)]);
+        assert!(judgment_check(
+            &g,
+            &Expr::Refl,
+            &eq(Expr::Nat, four, nat(4))
+        ));
+
+        // ack m n decreases m, or keeps it and decreases n
+        let ack = fix(
+            "ack",
+            lam(
+                "m",
+                lam(
+                    "n",
+                    case(
+                        var("m"),
+                        succ(var("n")),
+                        "p",
+                        case(
+                            var("n"),
+                            app(var("ack"), vec![var("p"), nat(1)]),
+                            "q",
+                            app(
+                                var("ack"),
+                                vec![var("p"), app(var("ack"), vec![var("m"), var("q")])],
+                            ),
+                        ),
+                    ),
+                ),
+            ),
+        );
+        assert!(judgment_check(&g, &ack, &binary()));
+        let ack = ann(ack, binary());
+        let t = eq(Expr::Nat, app(ack, vec![nat(2), nat(1)]), nat(5));
+        assert!(judgment_check(&g, &Expr::Refl, &t));
+
+        // the arguments swap places, each of them decreases every other call
+        let swap = fix(
+            "f",
+            lam(
+                "m",
+                lam(
+                    "n",
+                    case(
+                        var("n"),
+                        var("m"),
+                        "k",
+                        app(var("f"), vec![var("k"), var("m")]),
+                    ),
+                ),
+            ),
+        );
+        assert!(judgment_check(&g, &swap, &binary()));
+    }
+
+    #[test]
+    fn proofs_by_recursion() {
+        // (n : Nat) -> n + 0 = n by recursion on n, which is only a proof
+        // because the recursion terminates
+        let t = pi(
+            "n",
+            Expr::Nat,
+            eq(Expr::Nat, app(plus(), vec![var("n"), nat(0)]), var("n")),
+        );
+        let step = app(
+            cong(),
+            vec![
+                lam("z", succ(var("z"))),
+                app(plus(), vec![var("k"), nat(0)]),
+                var("k"),
+                app(var("ih"), vec![var("k")]),
+            ],
+        );
+        let proof = fix("ih", lam("n", case(var("n"), Expr::Refl, "k", step)));
+        assert!(judgment_check(&Context::new(), &proof, &t));
+
+        // the same proof by a call which does not decrease is rejected
+        let proof = fix("ih", lam("n", app(var("ih"), vec![var("n")])));
+        assert!(!judgment_check(&Context::new(), &proof, &t));
+    }
+
+    #[test]
+    fn non_decreasing_recursion_is_rejected() {
+        // fix f. \n. f n
+        let e = fix("f", lam("n", app(var("f"), vec![var("n")])));
+        assert_eq!(rejected(&e), ["f n"]);
+        assert_eq!(
+            check(&e).unwrap_err().to_string(),
+            "f may not terminate, no argument decreases along the calls f n"
+        );
+        // fix f. \n. f (succ n)
+        let e = fix("f", lam("n", app(var("f"), vec![succ(var("n"))])));
+        assert_eq!(rejected(&e), ["f (succ n)"]);
+
+        // the swap alone is no decrease, the path shows it twice
+        let e = fix(
+            "f",
+            lam("m", lam("n", app(var("f"), vec![var("n"), var("m")]))),
+        );
+        assert_eq!(rejected(&e), ["f n m", "f n m"]);
+
+        // one of two calls decreases, the other one does not
+        let e = fix(
+            "f",
+            lam(
+                "n",
+                case(
+                    var("n"),
+                    Expr::Zero,
+                    "k",
+                    app(var("f"), vec![app(var("f"), vec![var("k")])]),
+                ),
+            ),
+        );
+        assert_eq!(rejected(&e), ["f (f k)"]);
+        assert!(!judgment_check(
+            &Context::new(),
+            &e,
+            &arrow(Expr::Nat, Expr::Nat)
+        ));
+        // a shadowed function is no recursive call
+        let e = fix("f", lam("f", app(var("f"), vec![Expr::Zero])));
+        assert!(check(&e).is_ok());
+    }
+
+    #[test]
+    fn recursion_on_unknown_numbers_stays_stuck() {
+        // \n. n + 0 unfolds plus once and stops at the case on n
+        let e = normalize(&lam("n", app(plus(), vec![var("n"), nat(0)])));
+        assert!(matches!(e, Expr::Lam(_, ref body) if matches!(**body, Expr::Case(_, _, _, _))));
+    }
+}
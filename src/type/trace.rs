@@ -0,0 +1,434 @@
+//! Tracing and stepping through the evaluation of a term.
+//!
+//! `trace` records every reduction step of the evaluator with its
+//! redex and rule. The `Stepper` runs a term under the control of a
+//! debugger: single steps, `step_over` which reduces the subterm at the
+//! next redex to the end, and `resume` which runs until a breakpoint
+//! on a variable is hit, that is until a step substitutes for it.
+//! Both stop after a budget of steps, since a term with references
+//! or casts may diverge. Both evaluate call-by-value unless another
+//! `Strategy` is given.
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::eval::{is_value, reduce_with, Step, Store, Strategy};
+use super::Term;
+
+/// How an evaluation ended.
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Value(Term),
+    /// An uncaught exception or the blame of a failed cast.
+    Aborted(Term),
+    /// A term which is no value but can't step, this only happens
+    /// to terms which are not well-typed.
+    Stuck(Term),
+    /// The budget ran out, the term may diverge.
+    OutOfSteps(usize, Term),
+}
+
+impl Outcome {
+    /// The outcome of a term which can't step.
+    fn of(t: Term) -> Outcome {
+        match t {
+            Term::Raise(_) | Term::Blame(_, _, _) => Outcome::Aborted(t),
+            _ if is_value(&t) => Outcome::Value(t),
+            _ => Outcome::Stuck(t),
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Value(t) => write!(f, "value {}", t),
+            Outcome::Aborted(t) => write!(f, "aborted with {}", t),
+            Outcome::Stuck(t) => write!(f, "stuck at {}", t),
+            Outcome::OutOfSteps(n, t) => {
+                write!(f, "stopped after {} steps, the term may diverge: {}", n, t)
+            }
+        }
+    }
+}
+
+/// The steps of an evaluation and how it ended.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+    pub outcome: Outcome,
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, s) in self.steps.iter().enumerate() {
+            writeln!(f, "{} {}: {}", i + 1, s.rule, s.redex)?;
+        }
+        write!(f, "{}", self.outcome)
+    }
+}
+
+/// Evaluate a term with at most `budget` steps and record them.
+pub fn trace(t: &Term, store: &mut Store, budget: usize) -> Trace {
+    trace_with(t, store, budget, Strategy::CallByValue)
+}
+
+/// Trace the evaluation of a term under a strategy.
+pub fn trace_with(t: &Term, store: &mut Store, budget: usize, strategy: Strategy) -> Trace {
+    let mut steps: Vec<Step> = Vec::new();
+    loop {
+        let t = steps.last().map_or(t, |s| &s.term).clone();
+        if steps.len() == budget {
+            let outcome = match reduce_with(&t, &mut store.clone(), strategy) {
+                Some(_) => Outcome::OutOfSteps(budget, t),
+                None => Outcome::of(t),
+            };
+            return Trace { steps, outcome };
+        }
+        match reduce_with(&t, store, strategy) {
+            Some(s) => steps.push(s),
+            None => {
+                let outcome = Outcome::of(t);
+                return Trace { steps, outcome };
+            }
+        }
+    }
+}
+
+/// The part of a term at a path of the `json` module.
+fn subterm<'a>(t: &'a Term, path: &str) -> Option<&'a Term> {
+    let mut t = t;
+    for field in path.split('/').skip(1) {
+        t = match (field, t) {
+            ("fun", Term::App(f, _, _)) => f,
+            ("arg", Term::App(_, a, _)) => a,
+            ("left", Term::Pair(a, _)) => a,
+            ("right", Term::Pair(_, b)) => b,
+            ("pair", Term::Split(p, _, _, _, _, _)) => p,
+            ("init", Term::Ref(init, _)) => init,
+            ("cell", Term::Deref(cell)) | ("cell", Term::Assign(cell, _, _)) => cell,
+            ("value", Term::Assign(_, value, _)) => value,
+            ("body", Term::Pack(_, body, _)) | ("body", Term::Try(body, _, _)) => body,
+            ("package", Term::Unpack(p, _, _, _, _)) => p,
+            ("term", Term::Cast(e, _, _, _)) => e,
+            _ => return None,
+        };
+    }
+    Some(t)
+}
+
+/// Why the stepper stopped.
+#[derive(Debug, Clone)]
+pub enum Stop {
+    /// The command is done.
+    Done,
+    /// The last step substituted for a variable with a breakpoint.
+    Breakpoint(String),
+    /// No step is left.
+    Finished(Outcome),
+}
+
+/// An evaluation under the control of a debugger.
+#[derive(Debug, Clone)]
+pub struct Stepper {
+    term: Term,
+    store: Store,
+    steps: usize,
+    budget: usize,
+    strategy: Strategy,
+    breakpoints: BTreeSet<String>,
+}
+
+impl Stepper {
+    /// Evaluate a closed term with at most `budget` steps.
+    pub fn new(term: Term, budget: usize) -> Stepper {
+        Stepper {
+            term,
+            store: Store::new(),
+            steps: 0,
+            budget,
+            strategy: Strategy::CallByValue,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    /// Evaluate under another strategy than call-by-value.
+    pub fn with_strategy(self, strategy: Strategy) -> Stepper {
+        Stepper { strategy, ..self }
+    }
+
+    pub fn term(&self) -> &Term {
+        &self.term
+    }
+
+    /// The number of steps done so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn add_breakpoint(&mut self, x: &str) {
+        self.breakpoints.insert(x.into());
+    }
+
+    /// Remove a breakpoint, returns if there was one.
+    pub fn remove_breakpoint(&mut self, x: &str) -> bool {
+        self.breakpoints.remove(x)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &str> {
+        self.breakpoints.iter().map(|x| x.as_str())
+    }
+
+    /// Do one step.
+    pub fn step(&mut self) -> (Vec<Step>, Stop) {
+        self.run(|_| false)
+    }
+
+    /// Reduce the subterm at the next redex until it is done,
+    /// which steps over the body of a function call.
+    pub fn step_over(&mut self) -> (Vec<Step>, Stop) {
+        let path = self.redex_path().unwrap_or_default();
+        self.run(|stepper| match subterm(&stepper.term, &path) {
+            Some(Term::Raise(_)) | Some(Term::Blame(_, _, _)) | None => false,
+            Some(t) => !is_value(t),
+        })
+    }
+
+    /// Run until a breakpoint is hit or no step is left.
+    pub fn resume(&mut self) -> (Vec<Step>, Stop) {
+        self.run(|_| true)
+    }
+
+    /// The path of the next redex.
+    fn redex_path(&self) -> Option<String> {
+        reduce_with(&self.term, &mut self.store.clone(), self.strategy).map(|s| s.path)
+    }
+
+    /// Do a step and more while `more` holds.
+    fn run(&mut self, mut more: impl FnMut(&Stepper) -> bool) -> (Vec<Step>, Stop) {
+        let mut steps = Vec::new();
+        loop {
+            if self.steps == self.budget {
+                let stop = match self.redex_path() {
+                    Some(_) => Stop::Finished(Outcome::OutOfSteps(self.steps, self.term.clone())),
+                    None => Stop::Finished(Outcome::of(self.term.clone())),
+                };
+                return (steps, stop);
+            }
+            let step = match reduce_with(&self.term, &mut self.store, self.strategy) {
+                Some(step) => step,
+                None => return (steps, Stop::Finished(Outcome::of(self.term.clone()))),
+            };
+            self.steps += 1;
+            self.term = step.term.clone();
+            let hit = step
+                .rule
+                .binds()
+                .into_iter()
+                .find(|x| self.breakpoints.contains(*x))
+                .map(|x| x.to_string());
+            steps.push(step);
+            if let Some(x) = hit {
+                return (steps, Stop::Breakpoint(x));
+            }
+            if !more(self) {
+                return (steps, Stop::Done);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::eval::Rule;
+    use super::super::Type;
+    use super::*;
+
+    fn unit_to_unit() -> Type {
+        Type::arrow(Type::Unit, Type::Unit)
+    }
+
+    /// let r = ref (\z. z) in r := (\y. !r y); !r ()
+    fn knot() -> Term {
+        let body = Term::app(
+            Term::lam(
+                "u",
+                Term::app(Term::deref(Term::var("r")), Term::Unit, Type::Unit),
+            ),
+            Term::assign(
+                Term::var("r"),
+                Term::lam(
+                    "y",
+                    Term::app(Term::deref(Term::var("r")), Term::var("y"), Type::Unit),
+                ),
+                unit_to_unit(),
+            ),
+            Type::Unit,
+        );
+        let cell = Term::reference(Term::lam("z", Term::var("z")), unit_to_unit());
+        Term::app(Term::lam("r", body), cell, Type::reference(unit_to_unit()))
+    }
+
+    #[test]
+    fn steps_record_redex_rule_and_path() {
+        // try (\x. raise E) () with E => ()
+        let call = Term::app(Term::lam("x", Term::raise("E")), Term::Unit, Type::Unit);
+        let term = Term::pair(Term::Unit, Term::try_with(call, "E", Term::Unit));
+        let t = trace(&term, &mut Store::new(), 10);
+        let rules: Vec<_> = t.steps.iter().map(|s| s.rule.clone()).collect();
+        assert_eq!(
+            rules,
+            vec![Rule::Beta("x".into()), Rule::Handle("E".into())]
+        );
+        let paths: Vec<_> = t.steps.iter().map(|s| s.path.as_str()).collect();
+        assert_eq!(paths, vec!["/right/body", "/right"]);
+        // the redex is the part of the term at the path
+        assert_eq!(subterm(&term, paths[0]), Some(&t.steps[0].redex));
+        assert!(matches!(t.outcome, Outcome::Value(Term::Pair(_, _))));
+        assert_eq!(
+            format!("{}", t),
+            "1 beta x: (\\x. raise E) ()\n2 handle E: try raise E with E => ()\nvalue ((), ())"
+        );
+
+        // an exception without handler aborts the whole term
+        let term = Term::pair(Term::Unit, Term::raise("E"));
+        let t = trace(&term, &mut Store::new(), 10);
+        assert_eq!(t.steps[0].rule, Rule::Unwind);
+        assert!(matches!(t.outcome, Outcome::Aborted(Term::Raise(_))));
+    }
+
+    #[test]
+    fn diverging_terms_run_out_of_steps() {
+        let t = trace(&knot(), &mut Store::new(), 50);
+        assert_eq!(t.steps.len(), 50);
+        assert!(matches!(t.outcome, Outcome::OutOfSteps(50, _)));
+        // a terminating term ends within the budget, even if it is used up
+        let id = Term::app(Term::lam("x", Term::var("x")), Term::Unit, Type::Unit);
+        let t = trace(&id, &mut Store::new(), 1);
+        assert!(matches!(t.outcome, Outcome::Value(Term::Unit)));
+    }
+
+    #[test]
+    fn stepping_over_and_breakpoints() {
+        // (\f. f (f ())) (\x. x)
+        let twice = Term::lam(
+            "f",
+            Term::app(
+                Term::var("f"),
+                Term::app(Term::var("f"), Term::Unit, Type::Unit),
+                Type::Unit,
+            ),
+        );
+        let term = Term::app(twice, Term::lam("x", Term::var("x")), unit_to_unit());
+        let mut stepper = Stepper::new(term.clone(), 100);
+        let (steps, _) = stepper.step();
+        assert_eq!(steps[0].rule, Rule::Beta("f".into()));
+        // the inner call is reduced, the outer one is left
+        let (steps, stop) = stepper.step_over();
+        assert_eq!(steps.len(), 1);
+        assert!(matches!(stop, Stop::Done));
+        assert_eq!(format!("{}", stepper.term()), "(\\x. x) ()");
+
+        let mut stepper = Stepper::new(term, 100);
+        stepper.add_breakpoint("x");
+        let (steps, stop) = stepper.resume();
+        assert_eq!(steps.len(), 2);
+        assert!(matches!(stop, Stop::Breakpoint(ref x) if x == "x"));
+        assert!(stepper.remove_breakpoint("x"));
+        let (_, stop) = stepper.resume();
+        assert!(matches!(stop, Stop::Finished(Outcome::Value(Term::Unit))));
+        assert_eq!(stepper.steps(), 3);
+
+        let mut stepper = Stepper::new(knot(), 20);
+        let (steps, stop) = stepper.resume();
+        assert_eq!(steps.len(), 20);
+        assert!(matches!(stop, Stop::Finished(Outcome::OutOfSteps(20, _))));
+    }
+
+    /// (\a. \b. \c. ()) () () (), an argument which takes 3 steps
+    fn costly() -> Term {
+        let f = Term::lam("a", Term::lam("b", Term::lam("c", Term::Unit)));
+        let app = |f: Term, t: Type| Term::app(f, Term::Unit, t);
+        let t = Type::arrow(Type::Unit, unit_to_unit());
+        app(app(app(f, t), unit_to_unit()), Type::Unit)
+    }
+
+    /// The knot sequenced by a split, which is strict under every strategy:
+    /// let r = ref (\z. z) in split (r := (\y. !r y), ()) as (_, _) in !r ()
+    fn strict_knot() -> Term {
+        let assign = Term::assign(
+            Term::var("r"),
+            Term::lam(
+                "y",
+                Term::app(Term::deref(Term::var("r")), Term::var("y"), Type::Unit),
+            ),
+            unit_to_unit(),
+        );
+        let body = Term::split(
+            Term::pair(assign, Term::Unit),
+            "_",
+            Type::Unit,
+            "_",
+            Type::Unit,
+            Term::app(Term::deref(Term::var("r")), Term::Unit, Type::Unit),
+        );
+        let cell = Term::reference(Term::lam("z", Term::var("z")), unit_to_unit());
+        Term::app(Term::lam("r", body), cell, Type::reference(unit_to_unit()))
+    }
+
+    fn steps(term: &Term, strategy: Strategy) -> usize {
+        let t = trace_with(term, &mut Store::new(), 100, strategy);
+        assert!(matches!(t.outcome, Outcome::Value(_)), "{}", t);
+        t.steps.len()
+    }
+
+    #[test]
+    fn strategies_differ_in_steps() {
+        // (\x. (x, (x, x))) costly uses the argument three times: call-by-name
+        // evaluates it three times, call-by-need once and reads the thunk twice
+        let body = Term::pair(Term::var("x"), Term::pair(Term::var("x"), Term::var("x")));
+        let term = Term::app(Term::lam("x", body), costly(), Type::Unit);
+        assert_eq!(steps(&term, Strategy::CallByValue), 1 + 3);
+        assert_eq!(steps(&term, Strategy::CallByName), 1 + 3 * 3);
+        assert_eq!(steps(&term, Strategy::CallByNeed), 1 + 3 + 3);
+        let t = trace_with(&term, &mut Store::new(), 100, Strategy::CallByNeed);
+        let rules: Vec<String> = t.steps.iter().map(|s| s.rule.to_string()).collect();
+        assert_eq!(
+            rules,
+            ["beta x", "beta a", "beta b", "beta c", "deref", "deref", "deref"]
+        );
+
+        // an unused argument is never evaluated by the lazy strategies
+        let term = Term::app(Term::lam("x", Term::Unit), costly(), Type::Unit);
+        assert_eq!(steps(&term, Strategy::CallByValue), 4);
+        assert_eq!(steps(&term, Strategy::CallByName), 1);
+        assert_eq!(steps(&term, Strategy::CallByNeed), 1);
+    }
+
+    #[test]
+    fn lazy_strategies_skip_diverging_arguments() {
+        // (\x. ()) knot loops under call-by-value only
+        let term = Term::app(Term::lam("x", Term::Unit), strict_knot(), Type::Unit);
+        let t = trace_with(&term, &mut Store::new(), 50, Strategy::CallByValue);
+        assert!(matches!(t.outcome, Outcome::OutOfSteps(50, _)));
+        for strategy in [Strategy::CallByName, Strategy::CallByNeed] {
+            let t = trace_with(&term, &mut Store::new(), 50, strategy);
+            assert!(matches!(t.outcome, Outcome::Value(Term::Unit)));
+        }
+        // the same holds for an exception
+        let term = Term::app(Term::lam("x", Term::Unit), Term::raise("E"), Type::Unit);
+        let t = trace_with(&term, &mut Store::new(), 50, Strategy::CallByValue);
+        assert!(matches!(t.outcome, Outcome::Aborted(Term::Raise(_))));
+        let t = trace_with(&term, &mut Store::new(), 50, Strategy::CallByNeed);
+        assert!(matches!(t.outcome, Outcome::Value(Term::Unit)));
+
+        // the knot needs its cell to be shared, call-by-name allocates
+        // a new cell for every use of r and the loop is never tied
+        let t = trace_with(&strict_knot(), &mut Store::new(), 50, Strategy::CallByValue);
+        assert!(matches!(t.outcome, Outcome::OutOfSteps(50, _)));
+        let t = trace_with(&strict_knot(), &mut Store::new(), 50, Strategy::CallByName);
+        assert!(matches!(t.outcome, Outcome::Value(Term::Unit)));
+        let mut stepper = Stepper::new(strict_knot(), 50).with_strategy(Strategy::CallByNeed);
+        let (_, stop) = stepper.resume();
+        assert!(matches!(stop, Stop::Finished(Outcome::OutOfSteps(50, _))));
+    }
+}
@@ -0,0 +1,491 @@
+//! A compiler from checked terms to a small stack machine.
+//!
+//! Variables are resolved to de Bruijn indices at compile time and
+//! every lambda body becomes its own block of code. At runtime a
+//! lambda evaluates to a closure which pairs the block with the
+//! environment it was created in, so no substitution is needed.
+//! Evaluation is call-by-value and left to right like in `eval`.
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use super::eval::{self, Store};
+use super::{judgment_check, Context, Effects, Term, Type};
+
+/// The instructions of the machine.
+#[derive(Debug, Clone)]
+pub enum Instr {
+    Access(usize),  // push the n-th variable of the environment
+    Closure(usize), // push a closure of the given code block
+    Apply,          // call the closure below the argument
+    Return,         // return to the caller
+    Pair,           // build a pair from the two topmost values
+    Unpair,         // bind both components of a pair in the environment
+    Drop(usize),    // remove bindings from the environment
+    Unit,           // push ()
+    Alloc,          // allocate a cell with the topmost value
+    Load,           // read a cell
+    Store,          // write a cell
+}
+
+/// A compiled program is a list of code blocks, the first one is the entry point.
+#[derive(Debug, Clone)]
+pub struct Program {
+    blocks: Vec<Rc<Vec<Instr>>>,
+}
+
+/// The environment is a linked list so closures can share it.
+#[derive(Debug)]
+pub enum Env {
+    Empty,
+    Cons(Value, Rc<Env>),
+}
+
+/// Runtime values of the machine.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Closure(usize, Rc<Env>),
+    Pair(Rc<Value>, Rc<Value>),
+    Unit,
+    Loc(usize),
+}
+
+impl Value {
+    /// Convert a first-order value back into a term.
+    /// Closures have no term representation.
+    pub fn to_term(&self) -> Option<Term> {
+        match self {
+            Value::Closure(_, _) => None,
+            Value::Pair(a, b) => Some(Term::Pair(Box::new(a.to_term()?), Box::new(b.to_term()?))),
+            Value::Unit => Some(Term::Unit),
+            Value::Loc(l) => Some(Term::Loc(*l)),
+        }
+    }
+}
+
+/// Translate a term into instructions of the current block.
+/// The scope holds the names of all bound variables, innermost last.
+fn emit(
+    t: &Term,
+    scope: &mut Vec<String>,
+    code: &mut Vec<Instr>,
+    blocks: &mut Vec<Rc<Vec<Instr>>>,
+) -> Option<()> {
+    match t {
+        Term::Var(x) => {
+            let i = scope.iter().rev().position(|n| n == x)?;
+            code.push(Instr::Access(i));
+        }
+        Term::Lam(x, body) => {
+            let mut inner = Vec::new();
+            scope.push(x.clone());
+            let r = emit(body, scope, &mut inner, blocks);
+            scope.pop();
+            r?;
+            inner.push(Instr::Return);
+            blocks.push(Rc::new(inner));
+            code.push(Instr::Closure(blocks.len() - 1));
+        }
+        Term::App(f, a, _) => {
+            emit(f, scope, code, blocks)?;
+            emit(a, scope, code, blocks)?;
+            code.push(Instr::Apply);
+        }
+        Term::Pair(a, b) => {
+            emit(a, scope, code, blocks)?;
+            emit(b, scope, code, blocks)?;
+            code.push(Instr::Pair);
+        }
+        Term::Split(p, na, _, nb, _, body) => {
+            emit(p, scope, code, blocks)?;
+            code.push(Instr::Unpair);
+            scope.push(na.clone());
+            scope.push(nb.clone());
+            let r = emit(body, scope, code, blocks);
+            scope.truncate(scope.len() - 2);
+            r?;
+            code.push(Instr::Drop(2));
+        }
+        Term::Unit => code.push(Instr::Unit),
+        Term::Ref(init, _) => {
+            emit(init, scope, code, blocks)?;
+            code.push(Instr::Alloc);
+        }
+        Term::Deref(cell) => {
+            emit(cell, scope, code, blocks)?;
+            code.push(Instr::Load);
+        }
+        Term::Assign(cell, value, _) => {
+            emit(cell, scope, code, blocks)?;
+            emit(value, scope, code, blocks)?;
+            code.push(Instr::Store);
+        }
+        // types are erased, so a package is its content
+        Term::Pack(_, body, _) => emit(body, scope, code, blocks)?,
+        Term::Unpack(p, _, _, x, body) => {
+            let bind = Term::App(
+                Box::new(Term::Lam(x.clone(), body.clone())),
+                p.clone(),
+                Type::Unit,
+            );
+            emit(&bind, scope, code, blocks)?;
+        }
+        // locations only exist at runtime
+        Term::Loc(_) => return None,
+        // the machine has no tagged values to check casts against
+        Term::Cast(_, _, _, _) | Term::Blame(_, _, _) => return None,
+        // nor handlers to unwind the stack to
+        Term::Raise(_) | Term::Try(_, _, _) => return None,
+    }
+    Some(())
+}
+
+/// Compile a closed term after checking it against its type.
+pub fn compile(term: &Term, t: &Type) -> Option<Program> {
+    if !judgment_check(&Context::Empty(), term, t) {
+        return None;
+    }
+    let mut blocks = vec![Rc::new(Vec::new())];
+    let mut main = Vec::new();
+    emit(term, &mut Vec::new(), &mut main, &mut blocks)?;
+    main.push(Instr::Return);
+    blocks[0] = Rc::new(main);
+    Some(Program { blocks })
+}
+
+/// A saved caller to continue with after a return.
+struct Frame {
+    block: usize,
+    pc: usize,
+    env: Rc<Env>,
+}
+
+/// Run a program. The heap plays the role of the store
+/// and holds the values of all allocated cells.
+pub fn run(p: &Program, heap: &mut Vec<Value>) -> Option<Value> {
+    let mut stack: Vec<Value> = Vec::new();
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut env = Rc::new(Env::Empty);
+    let (mut block, mut pc) = (0, 0);
+    loop {
+        let instr = p.blocks[block].get(pc)?;
+        pc += 1;
+        match *instr {
+            Instr::Access(i) => {
+                let mut e = &env;
+                for _ in 0..i {
+                    e = match **e {
+                        Env::Cons(_, ref rest) => rest,
+                        Env::Empty => return None,
+                    };
+                }
+                match **e {
+                    Env::Cons(ref v, _) => stack.push(v.clone()),
+                    Env::Empty => return None,
+                }
+            }
+            Instr::Closure(b) => stack.push(Value::Closure(b, env.clone())),
+            Instr::Apply => {
+                let arg = stack.pop()?;
+                match stack.pop()? {
+                    Value::Closure(b, captured) => {
+                        frames.push(Frame { block, pc, env });
+                        env = Rc::new(Env::Cons(arg, captured));
+                        block = b;
+                        pc = 0;
+                    }
+                    _ => return None,
+                }
+            }
+            Instr::Return => match frames.pop() {
+                Some(f) => {
+                    block = f.block;
+                    pc = f.pc;
+                    env = f.env;
+                }
+                None => return stack.pop(),
+            },
+            Instr::Pair => {
+                let b = stack.pop()?;
+                let a = stack.pop()?;
+                stack.push(Value::Pair(Rc::new(a), Rc::new(b)));
+            }
+            Instr::Unpair => match stack.pop()? {
+                Value::Pair(a, b) => {
+                    let inner = Rc::new(Env::Cons((*a).clone(), env));
+                    env = Rc::new(Env::Cons((*b).clone(), inner));
+                }
+                _ => return None,
+            },
+            Instr::Drop(n) => {
+                for _ in 0..n {
+                    env = match *env {
+                        Env::Cons(_, ref rest) => rest.clone(),
+                        Env::Empty => return None,
+                    };
+                }
+            }
+            Instr::Unit => stack.push(Value::Unit),
+            Instr::Alloc => {
+                let v = stack.pop()?;
+                heap.push(v);
+                stack.push(Value::Loc(heap.len() - 1));
+            }
+            Instr::Load => match stack.pop()? {
+                Value::Loc(l) => stack.push(heap.get(l)?.clone()),
+                _ => return None,
+            },
+            Instr::Store => {
+                let v = stack.pop()?;
+                match stack.pop()? {
+                    Value::Loc(l) if l < heap.len() => {
+                        heap[l] = v;
+                        stack.push(Value::Unit);
+                    }
+                    _ => return None,
+                }
+            }
+        }
+    }
+}
+
+/// Church numerals over `Unit` have type `(Unit -> Unit) -> Unit -> Unit`.
+fn church_type() -> Type {
+    let tick = Type::Fn(Box::new(Type::Unit), Box::new(Type::Unit), Effects::pure());
+    Type::Fn(Box::new(tick.clone()), Box::new(tick), Effects::pure())
+}
+
+/// The numeral \f. \x. f (f ... x).
+pub fn church(n: usize) -> Term {
+    let body = (0..n).fold(Term::Var("x".into()), |x, _| {
+        Term::App(Box::new(Term::Var("f".into())), Box::new(x), Type::Unit)
+    });
+    Term::Lam("f".into(), Box::new(Term::Lam("x".into(), Box::new(body))))
+}
+
+/// \m. \n. \f. \x. m f (n f x)
+pub fn church_plus() -> Term {
+    let tick = Type::Fn(Box::new(Type::Unit), Box::new(Type::Unit), Effects::pure());
+    let var = |x: &str| Box::new(Term::Var(x.into()));
+    let nfx = Term::App(
+        Box::new(Term::App(var("n"), var("f"), tick.clone())),
+        var("x"),
+        Type::Unit,
+    );
+    let body = Term::App(
+        Box::new(Term::App(var("m"), var("f"), tick.clone())),
+        Box::new(nfx),
+        Type::Unit,
+    );
+    ["m", "n", "f", "x"]
+        .iter()
+        .rev()
+        .fold(body, |b, x| Term::Lam((*x).into(), Box::new(b)))
+}
+
+/// \m. \n. \f. m (n f)
+pub fn church_mult() -> Term {
+    let tick = Type::Fn(Box::new(Type::Unit), Box::new(Type::Unit), Effects::pure());
+    let var = |x: &str| Box::new(Term::Var(x.into()));
+    let body = Term::App(
+        var("m"),
+        Box::new(Term::App(var("n"), var("f"), tick.clone())),
+        tick,
+    );
+    ["m", "n", "f"]
+        .iter()
+        .rev()
+        .fold(body, |b, x| Term::Lam((*x).into(), Box::new(b)))
+}
+
+/// Apply a binary operation on numerals to `a` and `b`, and run the result
+/// with a function which allocates a cell on every call. The size of the
+/// store at the end is the resulting number.
+pub fn church_program(op: Term, a: usize, b: usize) -> Term {
+    let nat = church_type();
+    let tick = Term::Lam(
+        "u".into(),
+        Box::new(Term::App(
+            Box::new(Term::Lam("c".into(), Box::new(Term::Unit))),
+            Box::new(Term::Ref(Box::new(Term::Unit), Type::Unit)),
+            Type::Ref(Box::new(Type::Unit)),
+        )),
+    );
+    let result = Term::App(
+        Box::new(Term::App(Box::new(op), Box::new(church(a)), nat.clone())),
+        Box::new(church(b)),
+        nat,
+    );
+    Term::App(
+        Box::new(Term::App(
+            Box::new(result),
+            Box::new(tick),
+            Type::Fn(Box::new(Type::Unit), Box::new(Type::Unit), Effects::pure()),
+        )),
+        Box::new(Term::Unit),
+        Type::Unit,
+    )
+}
+
+/// The timings of one program of the benchmark.
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub program: String,
+    /// The number of cells the program allocates, one per tick.
+    pub ticks: usize,
+    /// The time of the substitution based evaluator.
+    pub eval: Duration,
+    /// The time of compiling and running the program on the machine.
+    pub vm: Duration,
+}
+
+/// Compare the substitution based evaluator with the machine
+/// on Church numeral arithmetic.
+pub fn bench() -> Vec<Measurement> {
+    let cases = vec![
+        ("plus", church_plus(), 50, 50),
+        ("mult", church_mult(), 10, 10),
+        ("mult", church_mult(), 20, 20),
+        ("mult", church_mult(), 30, 30),
+    ];
+    let mut measurements = Vec::new();
+    for (name, op, a, b) in cases {
+        let term = church_program(op, a, b);
+
+        let start = Instant::now();
+        let mut store = Store::new();
+        eval::eval(&term, &mut store);
+        let tree = start.elapsed();
+
+        let start = Instant::now();
+        let program = compile(&term, &Type::Unit).expect("benchmark program is well-typed");
+        let mut heap = Vec::new();
+        run(&program, &mut heap);
+        let machine = start.elapsed();
+
+        assert_eq!(store.len(), heap.len());
+        measurements.push(Measurement {
+            program: format!("{} {} {}", name, a, b),
+            ticks: heap.len(),
+            eval: tree,
+            vm: machine,
+        });
+    }
+    measurements
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn var(x: &str) -> Box<Term> {
+        Box::new(Term::Var(x.into()))
+    }
+
+    /// Run a term with both evaluators and compare the results.
+    fn agree(term: &Term, t: &Type) -> (Term, usize) {
+        let program = compile(term, t).expect("term is not well-typed");
+        let mut heap = Vec::new();
+        let value = run(&program, &mut heap).expect("machine went wrong");
+        let mut store = Store::new();
+        let expected = eval::eval(term, &mut store);
+        assert_eq!(store.len(), heap.len());
+        let value = value.to_term().expect("result is not first-order");
+        assert!(super::super::nbe::alpha_equal(&value, &expected));
+        (value, heap.len())
+    }
+
+    #[test]
+    fn church_arithmetic() {
+        assert_eq!(
+            agree(&church_program(church_plus(), 3, 4), &Type::Unit).1,
+            7
+        );
+        assert_eq!(
+            agree(&church_program(church_mult(), 3, 4), &Type::Unit).1,
+            12
+        );
+        assert_eq!(
+            agree(&church_program(church_mult(), 0, 4), &Type::Unit).1,
+            0
+        );
+    }
+
+    #[test]
+    fn pairs_and_split() {
+        // split (ref (), ()) as (x, y) in (y, x)
+        let cell = Type::Ref(Box::new(Type::Unit));
+        let term = Term::Split(
+            Box::new(Term::Pair(
+                Box::new(Term::Ref(Box::new(Term::Unit), Type::Unit)),
+                Box::new(Term::Unit),
+            )),
+            "x".into(),
+            cell.clone(),
+            "y".into(),
+            Type::Unit,
+            Box::new(Term::Pair(var("y"), var("x"))),
+        );
+        let t = Type::Sum(Box::new(Type::Unit), Box::new(cell));
+        agree(&term, &t);
+    }
+
+    #[test]
+    fn closures_capture_their_environment() {
+        // (\x. \y. x) (ref ()) (ref ()) returns the first cell
+        let cell = Type::Ref(Box::new(Type::Unit));
+        let k = Term::Lam("x".into(), Box::new(Term::Lam("y".into(), var("x"))));
+        let alloc = || Box::new(Term::Ref(Box::new(Term::Unit), Type::Unit));
+        let term = Term::App(
+            Box::new(Term::App(Box::new(k), alloc(), cell.clone())),
+            alloc(),
+            cell.clone(),
+        );
+        let (value, cells) = agree(&term, &cell);
+        assert!(matches!(value, Term::Loc(0)));
+        assert_eq!(cells, 2);
+    }
+
+    #[test]
+    fn shadowing_in_split() {
+        // split ((), ref ()) as (x, x) in x refers to the second component
+        let cell = Type::Ref(Box::new(Type::Unit));
+        let term = Term::Split(
+            Box::new(Term::Pair(
+                Box::new(Term::Unit),
+                Box::new(Term::Ref(Box::new(Term::Unit), Type::Unit)),
+            )),
+            "x".into(),
+            Type::Unit,
+            "x".into(),
+            cell.clone(),
+            var("x"),
+        );
+        assert!(matches!(agree(&term, &cell).0, Term::Loc(0)));
+    }
+
+    #[test]
+    fn assignment() {
+        // (\r. (\u. !r) (r := ())) (ref ())
+        let cell = Type::Ref(Box::new(Type::Unit));
+        let term = Term::App(
+            Box::new(Term::Lam(
+                "r".into(),
+                Box::new(Term::App(
+                    Box::new(Term::Lam("u".into(), Box::new(Term::Deref(var("r"))))),
+                    Box::new(Term::Assign(var("r"), Box::new(Term::Unit), Type::Unit)),
+                    Type::Unit,
+                )),
+            )),
+            Box::new(Term::Ref(Box::new(Term::Unit), Type::Unit)),
+            cell,
+        );
+        agree(&term, &Type::Unit);
+    }
+
+    #[test]
+    fn ill_typed_terms_are_not_compiled() {
+        let term = Term::App(Box::new(Term::Unit), Box::new(Term::Unit), Type::Unit);
+        assert!(compile(&term, &Type::Unit).is_none());
+        assert!(compile(&Term::Var("x".into()), &Type::Unit).is_none());
+    }
+}
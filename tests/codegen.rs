@@ -0,0 +1,62 @@
+//! Compile the rust code generated for the type checker
+//! examples and run the curry and uncurry functions.
+use std::env;
+use std::fs;
+use std::process::Command;
+
+const MAIN: &str = r#"
+mod examples;
+
+use examples::*;
+use std::rc::Rc;
+
+fn main() {
+    println!("{}", identity()(7));
+    println!("{}", fst()((1, "one".to_string())));
+    println!("{}", const_()(2)("two".to_string()));
+    let len: Rc<dyn Fn(i64) -> String> = Rc::new(|n| "x".repeat(n as usize));
+    println!("{}", apply()(len.clone())(3));
+    println!("{}", continuize()(4)(len));
+
+    // f (n, s) tests if s has length n
+    let f: Rc<dyn Fn((i64, String)) -> bool> = Rc::new(|p: (i64, String)| p.0 as usize == p.1.len());
+    let curried = curry()(f);
+    println!("{}", curried(3)("abc".to_string()));
+    println!("{}", curried(2)("abc".to_string()));
+    let uncurried = uncurry()(curried);
+    println!("{}", uncurried((3, "abc".to_string())));
+    println!("{}", uncurried((4, "abc".to_string())));
+}
+"#;
+
+#[test]
+fn generated_examples_compile_and_run() {
+    let out = Command::new(env!("CARGO_BIN_EXE_type"))
+        .args(["codegen", "i64", "String", "bool"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+
+    let dir = env::temp_dir().join(format!("type-codegen-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("examples.rs"), &out.stdout).unwrap();
+    fs::write(dir.join("main.rs"), MAIN).unwrap();
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let status = Command::new(rustc)
+        .arg("--edition=2018")
+        .arg("-o")
+        .arg(dir.join("main"))
+        .arg(dir.join("main.rs"))
+        .status()
+        .unwrap();
+    assert!(status.success(), "generated code does not compile");
+
+    let out = Command::new(dir.join("main")).output().unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "7\n1\n2\nxxx\nxxxx\ntrue\nfalse\ntrue\nfalse\n"
+    );
+}